@@ -0,0 +1,62 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use markdown_viewer_lib::highlight::HighlightEngine;
+use markdown_viewer_lib::{render_markdown, MarkdownOptions};
+use markdown_viewer_lib::sanitize::SanitizeLevel;
+
+// A design-doc sized sample: prose, tables and several fenced code blocks
+fn sample_document() -> String {
+    let section = r#"
+## Component overview
+
+The watcher re-renders the document on every save, so render time matters.
+
+| Module | Responsibility |
+|--------|----------------|
+| parser | Markdown to HTML |
+| watcher | Live reload |
+
+```rust
+fn main() {
+    let greeting = "Hello";
+    println!("{}, world!", greeting);
+}
+```
+
+```js
+const answer = [1, 2, 3].map((n) => n * 2);
+console.log(answer);
+```
+
+```python
+def fib(n):
+    return n if n < 2 else fib(n - 1) + fib(n - 2)
+```
+"#;
+    let mut doc = String::from("# Design document\n");
+    for _ in 0..20 {
+        doc.push_str(section);
+    }
+    doc
+}
+
+fn bench_render(c: &mut Criterion) {
+    let doc = sample_document();
+
+    // Previous behaviour: syntect resources loaded for every render
+    c.bench_function("render_cold_engine", |b| {
+        b.iter(|| {
+            let engine = HighlightEngine::new();
+            render_markdown(black_box(&doc), &engine, SanitizeLevel::Standard, MarkdownOptions::default()).unwrap()
+        })
+    });
+
+    // Shared engine held in Tauri state
+    let engine = HighlightEngine::new();
+    engine.warm_up();
+    c.bench_function("render_shared_engine", |b| {
+        b.iter(|| render_markdown(black_box(&doc), &engine, SanitizeLevel::Standard, MarkdownOptions::default()).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_render);
+criterion_main!(benches);
@@ -0,0 +1,316 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+use pulldown_cmark::{BlockQuoteKind, CowStr, Event, Tag, TagEnd};
+
+use crate::markdown::{LineIndex, SourceEvent};
+
+// Callout kinds, following GitHub's `> [!NOTE]` alert syntax
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    Note,
+    Tip,
+    Important,
+    Warning,
+    Caution,
+}
+
+impl AlertKind {
+    fn from_block_quote(kind: BlockQuoteKind) -> Self {
+        match kind {
+            BlockQuoteKind::Note => Self::Note,
+            BlockQuoteKind::Tip => Self::Tip,
+            BlockQuoteKind::Important => Self::Important,
+            BlockQuoteKind::Warning => Self::Warning,
+            BlockQuoteKind::Caution => Self::Caution,
+        }
+    }
+
+    // Docusaurus admonition names mapped onto the closest GitHub alert
+    fn from_container_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "note" | "info" => Some(Self::Note),
+            "tip" => Some(Self::Tip),
+            "important" => Some(Self::Important),
+            "warning" => Some(Self::Warning),
+            "caution" | "danger" => Some(Self::Caution),
+            _ => None,
+        }
+    }
+
+    fn marker(self) -> &'static str {
+        match self {
+            Self::Note => "[!NOTE]",
+            Self::Tip => "[!TIP]",
+            Self::Important => "[!IMPORTANT]",
+            Self::Warning => "[!WARNING]",
+            Self::Caution => "[!CAUTION]",
+        }
+    }
+
+    fn class_name(self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Tip => "tip",
+            Self::Important => "important",
+            Self::Warning => "warning",
+            Self::Caution => "caution",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::Note => "Note",
+            Self::Tip => "Tip",
+            Self::Important => "Important",
+            Self::Warning => "Warning",
+            Self::Caution => "Caution",
+        }
+    }
+
+    // Plain text glyphs survive every sanitize level and the DOCX/PDF exports
+    fn icon(self) -> &'static str {
+        match self {
+            Self::Note => "ℹ️",
+            Self::Tip => "💡",
+            Self::Important => "💬",
+            Self::Warning => "⚠️",
+            Self::Caution => "🛑",
+        }
+    }
+}
+
+// Custom alert titles from `:::note[Title]` containers, keyed by 1-based source line
+pub type AlertTitles = HashMap<usize, String>;
+
+// The title an alert shows when its container doesn't give one
+pub fn alert_title(kind: BlockQuoteKind) -> &'static str {
+    AlertKind::from_block_quote(kind).title()
+}
+
+// Rewrite Docusaurus `:::note` containers into GitHub alert blockquotes.
+// Every line is rewritten in place so line numbers stay the same.
+pub fn expand_containers(body: &str, first_line: usize) -> (Cow<'_, str>, AlertTitles) {
+    let mut titles = AlertTitles::new();
+    if !body.contains(":::") {
+        return (Cow::Borrowed(body), titles);
+    }
+
+    let mut output = String::with_capacity(body.len() + body.len() / 8);
+    let mut depth = 0usize;
+    let mut code_fence: Option<(char, usize)> = None;
+    let mut changed = false;
+
+    for (index, line) in body.split_inclusive('\n').enumerate() {
+        let (content, ending) = split_line_ending(line);
+        let trimmed = content.trim_start();
+        let indent = content.len() - trimmed.len();
+
+        if let Some(fence) = code_fence {
+            if closes_code_fence(trimmed, fence) {
+                code_fence = None;
+            }
+        } else if let Some(fence) = opens_code_fence(trimmed) {
+            code_fence = Some(fence);
+        } else if indent <= 3 {
+            if let Some((kind, title)) = parse_container_open(trimmed) {
+                output.push_str(&"> ".repeat(depth));
+                output.push_str("> ");
+                output.push_str(kind.marker());
+                output.push_str(ending);
+                if let Some(title) = title {
+                    titles.insert(first_line + index, title);
+                }
+                depth += 1;
+                changed = true;
+                continue;
+            }
+            if depth > 0 && is_container_close(trimmed) {
+                depth -= 1;
+                output.push_str(">".repeat(depth).as_str());
+                output.push_str(ending);
+                continue;
+            }
+        }
+
+        output.push_str(&"> ".repeat(depth));
+        output.push_str(line);
+    }
+
+    if changed { (Cow::Owned(output), titles) } else { (Cow::Borrowed(body), titles) }
+}
+
+fn split_line_ending(line: &str) -> (&str, &str) {
+    let content = line.trim_end_matches(['\n', '\r']);
+    (content, &line[content.len()..])
+}
+
+fn opens_code_fence(trimmed: &str) -> Option<(char, usize)> {
+    let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = trimmed.chars().take_while(|c| *c == fence_char).count();
+    (length >= 3).then_some((fence_char, length))
+}
+
+fn closes_code_fence(trimmed: &str, (fence_char, length): (char, usize)) -> bool {
+    let run = trimmed.chars().take_while(|c| *c == fence_char).count();
+    run >= length && trimmed[run * fence_char.len_utf8()..].trim().is_empty()
+}
+
+// `:::note`, `:::tip Title` or `:::warning[Title]`
+fn parse_container_open(trimmed: &str) -> Option<(AlertKind, Option<String>)> {
+    let rest = trimmed.trim_start_matches(':');
+    if trimmed.len() - rest.len() < 3 {
+        return None;
+    }
+
+    let name_end = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+    let (name, rest) = rest.split_at(name_end);
+    let kind = AlertKind::from_container_name(name)?;
+
+    let rest = rest.trim();
+    let title = match rest.strip_prefix('[').and_then(|inner| inner.strip_suffix(']')) {
+        Some(bracketed) => bracketed.trim(),
+        None => rest,
+    };
+
+    // Keep the Docusaurus name as the title when it differs from the alert kind
+    let title = if !title.is_empty() {
+        Some(title.to_string())
+    } else if !name.eq_ignore_ascii_case(kind.class_name()) {
+        let mut chars = name.chars();
+        chars.next().map(|first| first.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase())
+    } else {
+        None
+    };
+
+    Some((kind, title))
+}
+
+fn is_container_close(trimmed: &str) -> bool {
+    let trimmed = trimmed.trim_end();
+    trimmed.len() >= 3 && trimmed.chars().all(|c| c == ':')
+}
+
+// Replace alert blockquotes with titled <aside> callouts
+pub fn render_alerts<'a>(
+    events: Vec<SourceEvent<'a>>,
+    titles: &AlertTitles,
+    line_index: &LineIndex,
+) -> Vec<SourceEvent<'a>> {
+    events.into_iter()
+        .map(|(event, range)| match event {
+            Event::Start(Tag::BlockQuote(Some(kind))) => {
+                let title = resolve_title(kind, &range, titles, line_index);
+                let start_tag = alert_start_tag(AlertKind::from_block_quote(kind), title);
+                (Event::Html(CowStr::from(start_tag)), range)
+            }
+            Event::End(TagEnd::BlockQuote(Some(_))) => (Event::Html(CowStr::from("</aside>\n")), range),
+            other => (other, range),
+        })
+        .collect()
+}
+
+// The titles of a document's alerts, keyed by the index of their start event, for
+// exporters that lay alerts out themselves
+pub fn alert_event_titles(
+    events: &[SourceEvent<'_>],
+    titles: &AlertTitles,
+    line_index: &LineIndex,
+) -> HashMap<usize, String> {
+    events.iter()
+        .enumerate()
+        .filter_map(|(index, (event, range))| match event {
+            Event::Start(Tag::BlockQuote(Some(kind))) => {
+                Some((index, resolve_title(*kind, range, titles, line_index).to_string()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn resolve_title<'t>(kind: BlockQuoteKind, range: &Range<usize>, titles: &'t AlertTitles, line_index: &LineIndex) -> &'t str {
+    titles.get(&line_index.line_of(range.start))
+        .map(String::as_str)
+        .unwrap_or(alert_title(kind))
+}
+
+fn alert_start_tag(kind: AlertKind, title: &str) -> String {
+    format!(
+        "<aside class=\"markdown-alert markdown-alert-{}\" role=\"note\">\n<p class=\"markdown-alert-title\"><span class=\"markdown-alert-icon\" aria-hidden=\"true\">{}</span>{}</p>\n",
+        kind.class_name(),
+        kind.icon(),
+        html_escape::encode_text(title)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Options, Parser};
+
+    fn render(markdown: &str) -> String {
+        let (text, titles) = expand_containers(markdown, 1);
+        let events = Parser::new_ext(&text, Options::ENABLE_GFM).into_offset_iter().collect();
+        let events = render_alerts(events, &titles, &LineIndex::new(&text));
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter().map(|(event, _)| event));
+        output
+    }
+
+    #[test]
+    fn renders_github_alerts() {
+        let html = render("> [!WARNING]\n> Careful & quick\n");
+        assert!(html.starts_with("<aside class=\"markdown-alert markdown-alert-warning\" role=\"note\">"));
+        assert!(html.contains("<span class=\"markdown-alert-icon\" aria-hidden=\"true\">⚠️</span>Warning</p>"));
+        assert!(html.contains("<p>Careful &amp; quick</p>"));
+        assert!(html.trim_end().ends_with("</aside>"));
+        assert_eq!(render("> plain quote\n"), "<blockquote>\n<p>plain quote</p>\n</blockquote>\n");
+    }
+
+    #[test]
+    fn expands_admonitions_line_for_line() {
+        let (text, titles) = expand_containers("a\n:::tip\nbody\n:::\nb\n", 1);
+        assert_eq!(text, "a\n> [!TIP]\n> body\n\nb\n");
+        assert!(titles.is_empty());
+        assert_eq!(text.lines().count(), 5);
+    }
+
+    #[test]
+    fn keeps_admonition_titles() {
+        let (_, titles) = expand_containers(":::warning[Mind the gap]\nx\n:::\n:::danger\ny\n:::\n", 10);
+        assert_eq!(titles.get(&10).map(String::as_str), Some("Mind the gap"));
+        assert_eq!(titles.get(&13).map(String::as_str), Some("Danger"));
+
+        let html = render(":::info Heads up\nRead this\n:::\n");
+        assert!(html.contains("markdown-alert-note"));
+        assert!(html.contains("</span>Heads up</p>"));
+    }
+
+    #[test]
+    fn titles_alert_events_for_exporters() {
+        let (text, titles) = expand_containers("> [!TIP]\n> a\n\n:::note[Read me]\nb\n:::\n", 1);
+        let events: Vec<SourceEvent> = Parser::new_ext(&text, Options::ENABLE_GFM).into_offset_iter().collect();
+        let mut event_titles: Vec<(usize, String)> = alert_event_titles(&events, &titles, &LineIndex::new(&text)).into_iter().collect();
+        event_titles.sort();
+        let labels: Vec<&str> = event_titles.iter().map(|(_, title)| title.as_str()).collect();
+        assert_eq!(labels, ["Tip", "Read me"]);
+        assert!(matches!(events[event_titles[0].0].0, Event::Start(Tag::BlockQuote(Some(BlockQuoteKind::Tip)))));
+        assert_eq!(alert_title(BlockQuoteKind::Caution), "Caution");
+    }
+
+    #[test]
+    fn nests_admonitions() {
+        let (text, _) = expand_containers(":::note\n::::caution\ninner\n::::\n:::\n", 1);
+        assert_eq!(text, "> [!NOTE]\n> > [!CAUTION]\n> > inner\n>\n\n");
+    }
+
+    #[test]
+    fn leaves_code_fences_and_unknown_containers_alone() {
+        let source = "```\n:::note\n```\n:::unknown\n::\n";
+        let (text, titles) = expand_containers(source, 1);
+        assert!(matches!(text, Cow::Borrowed(_)));
+        assert_eq!(text, source);
+        assert!(titles.is_empty());
+        assert!(matches!(expand_containers("    :::note\n", 1).0, Cow::Borrowed(_)));
+    }
+}
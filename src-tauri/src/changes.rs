@@ -0,0 +1,254 @@
+use serde::Serialize;
+
+use crate::front_matter::FrontMatter;
+use crate::markdown::{RenderedBlock, RenderedDocument};
+use crate::sanitize::SanitizeReport;
+use crate::source_map::strip_source_lines;
+use crate::toc::TocEntry;
+
+// Above this many old × new items in the changed region, skip matching and replace it all
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+// One block of the new document: either an old block to keep, or new HTML to insert.
+// Kept blocks may have moved, so their data-source-line attributes are shifted to start_line.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockUpdate {
+    pub old_index: Option<usize>,
+    pub html: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+// A run of changed lines in the new document; removals point at the line where content was
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockChange {
+    pub kind: ChangeKind,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+// What the viewer needs to patch an open document in place
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentUpdate {
+    // True when there was nothing to diff against and every block must be replaced
+    pub full: bool,
+    pub blocks: Vec<BlockUpdate>,
+    pub changes: Vec<BlockChange>,
+    pub toc: Vec<TocEntry>,
+    pub front_matter: Option<FrontMatter>,
+    pub sanitize_report: SanitizeReport,
+    pub dependencies: Vec<String>,
+}
+
+// Diff a block render against the previous render of the same document
+pub fn document_update(document: RenderedDocument, previous: Option<&[RenderedBlock]>) -> DocumentUpdate {
+    let blocks = document.blocks.unwrap_or_default();
+    let matches = previous.map(|previous| match_blocks(previous, &blocks));
+
+    let updates = blocks.iter().enumerate()
+        .map(|(index, block)| {
+            let old_index = matches.as_ref().and_then(|matches| matches[index]);
+            BlockUpdate {
+                old_index,
+                html: old_index.is_none().then(|| block.html.clone()),
+                start_line: block.start_line,
+                end_line: block.end_line,
+            }
+        })
+        .collect();
+    let changes = match (&matches, previous) {
+        (Some(matches), Some(previous)) => change_map(matches, previous.len(), &blocks),
+        _ => Vec::new(),
+    };
+
+    DocumentUpdate {
+        full: previous.is_none(),
+        blocks: updates,
+        changes,
+        toc: document.toc,
+        front_matter: document.front_matter,
+        sanitize_report: document.sanitize_report,
+        dependencies: document.dependencies,
+    }
+}
+
+// For each new block, the old block with identical HTML it corresponds to. Source line
+// numbers are ignored, or every block after an inserted line would count as changed.
+fn match_blocks(old: &[RenderedBlock], new: &[RenderedBlock]) -> Vec<Option<usize>> {
+    let old: Vec<_> = old.iter().map(|block| strip_source_lines(&block.html)).collect();
+    let new: Vec<_> = new.iter().map(|block| strip_source_lines(&block.html)).collect();
+    match_sequences(&old, &new)
+}
+
+// For each new item, the equal old item it corresponds to, keeping order (a longest
+// common subsequence). Unchanged runs at either end are matched directly, so typical
+// edits only diff a few items; a middle too large to diff is left unmatched.
+pub fn match_sequences<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; new.len()];
+
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    for (index, matched) in matches.iter_mut().enumerate().take(prefix) {
+        *matched = Some(index);
+    }
+    for offset in 1..=suffix {
+        matches[new.len() - offset] = Some(old.len() - offset);
+    }
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    if old_middle.is_empty() || new_middle.is_empty() || old_middle.len() * new_middle.len() > MAX_DIFF_CELLS {
+        return matches;
+    }
+
+    // lengths[i][j]: longest common subsequence of old_middle[i..] and new_middle[j..]
+    let columns = new_middle.len() + 1;
+    let mut lengths = vec![0u32; (old_middle.len() + 1) * columns];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lengths[i * columns + j] = if old_middle[i] == new_middle[j] {
+                lengths[(i + 1) * columns + j + 1] + 1
+            } else {
+                lengths[(i + 1) * columns + j].max(lengths[i * columns + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() && j < new_middle.len() {
+        if old_middle[i] == new_middle[j] {
+            matches[prefix + j] = Some(prefix + i);
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * columns + j] >= lengths[i * columns + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+// Walk the gaps between kept blocks: replaced blocks count as modified, the rest as added or removed
+fn change_map(matches: &[Option<usize>], old_len: usize, new: &[RenderedBlock]) -> Vec<BlockChange> {
+    let mut changes = Vec::new();
+    let mut old_next = 0;
+    let mut new_next = 0;
+    let kept = matches.iter().enumerate()
+        .filter_map(|(new_index, old_index)| old_index.map(|old_index| (old_index, new_index)))
+        .chain(std::iter::once((old_len, new.len())));
+
+    for (old_index, new_index) in kept {
+        let removed = old_index - old_next;
+        let inserted = &new[new_next..new_index];
+        let (modified, added) = inserted.split_at(removed.min(inserted.len()));
+
+        for (kind, run) in [(ChangeKind::Modified, modified), (ChangeKind::Added, added)] {
+            if let (Some(first), Some(last)) = (run.first(), run.last()) {
+                changes.push(BlockChange { kind, start_line: first.start_line, end_line: last.end_line });
+            }
+        }
+        if removed > inserted.len() {
+            let line = match new.get(new_index) {
+                Some(next) => next.start_line,
+                None => new.last().map_or(1, |last| last.end_line),
+            };
+            changes.push(BlockChange { kind: ChangeKind::Removed, start_line: line, end_line: line });
+        }
+
+        old_next = old_index + 1;
+        new_next = new_index + 1;
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One block per paragraph text, one source line each starting at `first_line`
+    fn blocks(texts: &[&str], first_line: usize) -> Vec<RenderedBlock> {
+        texts.iter().enumerate()
+            .map(|(index, text)| {
+                let line = first_line + index;
+                RenderedBlock { html: format!("<p data-source-line=\"{}\">{}</p>\n", line, text), start_line: line, end_line: line }
+            })
+            .collect()
+    }
+
+    fn reload(old: &[RenderedBlock], new: Vec<RenderedBlock>) -> DocumentUpdate {
+        let document = RenderedDocument {
+            html: String::new(),
+            toc: Vec::new(),
+            front_matter: None,
+            sanitize_report: SanitizeReport::default(),
+            dependencies: Vec::new(),
+            blocks: Some(new),
+        };
+        document_update(document, Some(old))
+    }
+
+    fn kinds(update: &DocumentUpdate) -> Vec<(ChangeKind, usize, usize)> {
+        update.changes.iter().map(|change| (change.kind, change.start_line, change.end_line)).collect()
+    }
+
+    #[test]
+    fn matches_longest_common_subsequence() {
+        assert_eq!(match_sequences(&[1, 2, 3, 4], &[1, 3, 5, 4]), vec![Some(0), Some(2), None, Some(3)]);
+        assert_eq!(match_sequences(&[1, 2], &[2, 1]).iter().flatten().count(), 1);
+        assert_eq!(match_sequences::<u8>(&[], &[1]), vec![None]);
+        assert_eq!(match_sequences(&[1, 2], &[]), Vec::<Option<usize>>::new());
+    }
+
+    #[test]
+    fn keeps_blocks_that_only_moved() {
+        let old = blocks(&["a", "b", "c"], 1);
+        let update = reload(&old, blocks(&["new", "a", "b", "c"], 1));
+        assert!(!update.full);
+        let kept: Vec<_> = update.blocks.iter().map(|block| block.old_index).collect();
+        assert_eq!(kept, vec![None, Some(0), Some(1), Some(2)]);
+        assert_eq!(update.blocks[0].html.as_deref(), Some("<p data-source-line=\"1\">new</p>\n"));
+        assert!(update.blocks[1].html.is_none());
+        assert_eq!(update.blocks[3].start_line, 4);
+        assert_eq!(kinds(&update), vec![(ChangeKind::Added, 1, 1)]);
+    }
+
+    #[test]
+    fn reports_modified_and_removed_blocks() {
+        let old = blocks(&["a", "b", "c", "d", "e"], 1);
+        let update = reload(&old, blocks(&["a", "B", "e"], 1));
+        assert_eq!(kinds(&update), vec![(ChangeKind::Modified, 2, 2), (ChangeKind::Removed, 3, 3)]);
+
+        let update = reload(&old, blocks(&["a", "b"], 1));
+        assert_eq!(kinds(&update), vec![(ChangeKind::Removed, 2, 2)]);
+    }
+
+    #[test]
+    fn replaces_everything_without_a_previous_render() {
+        let document = RenderedDocument {
+            html: String::new(),
+            toc: Vec::new(),
+            front_matter: None,
+            sanitize_report: SanitizeReport::default(),
+            dependencies: Vec::new(),
+            blocks: Some(blocks(&["a"], 1)),
+        };
+        let update = document_update(document, None);
+        assert!(update.full);
+        assert!(update.changes.is_empty());
+        assert_eq!(update.blocks[0].old_index, None);
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::docx::export_docx;
+use crate::epub::{export_epub, BookSource};
+use crate::highlight::HighlightEngine;
+use crate::pdf::{export_pdf, PdfOptions};
+use crate::standalone::inline_images;
+use crate::sanitize::SanitizeLevel;
+use crate::slides::slide_deck_html;
+use crate::toc::TOC_MARKER;
+use crate::markdown::render_markdown_blocks;
+use crate::{export_html_document, post_process_image_paths, read_file_with_size_limit, render_markdown, ExportTheme, MarkdownOptions};
+
+// Process exit codes for the headless renderer
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_RENDER_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+const USAGE: &str = "\
+Usage: markdown-viewer render <INPUT>... [options]
+
+Renders markdown files to standalone HTML, Word, PDF or EPUB documents, or to HTML
+slide decks, without opening a window.
+INPUT may be a file or a glob pattern such as \"docs/**/*.md\".
+
+Options:
+  -o, --output <PATH>     Output file, or directory when rendering several files.
+                          Use - for stdout. Defaults to INPUT with the format's extension.
+  -f, --format <FORMAT>   html (default), docx, pdf, epub or slides
+      --theme <THEME>     light (default), dark or auto (HTML only)
+      --code-theme <NAME> Code highlighting theme, or a .tmTheme file
+      --toc               Insert a table of contents (at [TOC], or at the top; HTML only).
+                          DOCX, PDF and EPUB output always fill in [TOC].
+      --sanitize <LEVEL>  strict, standard (default) or relaxed (HTML, EPUB and slides)
+      --self-contained    Embed local images as data URIs (HTML only)
+  -h, --help              Show this help
+
+Options given for a format they don't apply to are an error.
+";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    #[default]
+    Html,
+    Docx,
+    Pdf,
+    Epub,
+    Slides,
+}
+
+impl OutputFormat {
+    fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Html => "html",
+            OutputFormat::Docx => "docx",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Epub => "epub",
+            OutputFormat::Slides => "slides",
+        }
+    }
+
+    // DOCX and PDF drop raw HTML instead of sanitizing it, and only HTML output has
+    // themes, an optional table of contents or linked images
+    fn supports(self, option: &str) -> bool {
+        match option {
+            "--theme" | "--toc" | "--self-contained" => self == OutputFormat::Html,
+            "--sanitize" => matches!(self, OutputFormat::Html | OutputFormat::Epub | OutputFormat::Slides),
+            _ => true,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Html | OutputFormat::Slides => "html",
+            OutputFormat::Docx => "docx",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Epub => "epub",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct RenderArgs {
+    inputs: Vec<String>,
+    output: Option<String>,
+    format: OutputFormat,
+    // None when not given, so options a format doesn't use can be rejected
+    theme: Option<ExportTheme>,
+    toc: bool,
+    sanitize_level: Option<SanitizeLevel>,
+    self_contained: bool,
+    code_theme: Option<String>,
+}
+
+// Run the command-line renderer when the first argument is `render`.
+// Returns the process exit code, or None to start the GUI as usual.
+pub fn run_from_args(args: &[String]) -> Option<i32> {
+    if args.get(1).map(String::as_str) != Some("render") {
+        return None;
+    }
+    attach_console();
+
+    let render_args = match parse_render_args(&args[2..]) {
+        Ok(Some(render_args)) => render_args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return Some(EXIT_SUCCESS);
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return Some(EXIT_USAGE);
+        }
+    };
+
+    Some(render_all(&render_args))
+}
+
+// Ok(None) means help was requested
+fn parse_render_args(args: &[String]) -> Result<Option<RenderArgs>, String> {
+    let mut render_args = RenderArgs::default();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => {
+                render_args.output = Some(iter.next().ok_or("--output needs a path")?.clone());
+            }
+            "-f" | "--format" => {
+                let value = iter.next().ok_or("--format needs a value")?;
+                render_args.format = parse_choice(value, "--format")?;
+            }
+            "--theme" => {
+                let value = iter.next().ok_or("--theme needs a value")?;
+                render_args.theme = Some(parse_choice(value, "--theme")?);
+            }
+            "--code-theme" => {
+                render_args.code_theme = Some(iter.next().ok_or("--code-theme needs a name or file")?.clone());
+            }
+            "--sanitize" => {
+                let value = iter.next().ok_or("--sanitize needs a value")?;
+                render_args.sanitize_level = Some(parse_choice(value, "--sanitize")?);
+            }
+            "--toc" => render_args.toc = true,
+            "--self-contained" => render_args.self_contained = true,
+            flag if flag.starts_with('-') && flag != "-" => return Err(format!("unknown option {}", flag)),
+            input => render_args.inputs.push(input.to_string()),
+        }
+    }
+
+    if render_args.inputs.is_empty() {
+        return Err("no input files given".to_string());
+    }
+    let given = [
+        ("--theme", render_args.theme.is_some()),
+        ("--toc", render_args.toc),
+        ("--sanitize", render_args.sanitize_level.is_some()),
+        ("--self-contained", render_args.self_contained),
+    ];
+    if let Some((option, _)) = given.iter().find(|(option, given)| *given && !render_args.format.supports(option)) {
+        return Err(format!("{} does not apply to --format {}", option, render_args.format.name()));
+    }
+    Ok(Some(render_args))
+}
+
+// Reuse the serde names so the CLI accepts exactly what the commands accept
+fn parse_choice<T: serde::de::DeserializeOwned>(value: &str, option: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_lowercase()))
+        .map_err(|_| format!("invalid value '{}' for {}", value, option))
+}
+
+fn render_all(render_args: &RenderArgs) -> i32 {
+    let inputs = match expand_inputs(&render_args.inputs) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    // Several inputs (or a glob) always write into a directory
+    let batch = inputs.len() > 1 || render_args.inputs.iter().any(|input| is_glob(input));
+    if batch && render_args.output.as_deref() == Some("-") {
+        eprintln!("error: --output - only works with a single input file");
+        return EXIT_USAGE;
+    }
+
+    let highlighter = HighlightEngine::new();
+    if let Some(code_theme) = &render_args.code_theme {
+        if let Err(e) = select_code_theme(&highlighter, code_theme) {
+            eprintln!("error: {}", e);
+            return EXIT_USAGE;
+        }
+    }
+    let mut written = HashSet::new();
+    let mut failures = 0;
+
+    for input in &inputs {
+        let result = render_file(input, render_args, &highlighter).and_then(|rendered| {
+            let output = output_path(input, render_args.output.as_deref(), render_args.format, batch);
+            if let Some(output) = &output {
+                if !written.insert(output.clone()) {
+                    return Err(format!("would overwrite {} from another input", output.display()));
+                }
+            }
+            write_output(output.as_deref(), &rendered)
+        });
+
+        if let Err(e) = result {
+            eprintln!("error: {}: {}", input.display(), e);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        eprintln!("{} of {} file(s) failed", failures, inputs.len());
+        EXIT_RENDER_FAILED
+    } else {
+        EXIT_SUCCESS
+    }
+}
+
+// Use one theme for light and dark output, from a .tmTheme file or by name
+fn select_code_theme(highlighter: &HighlightEngine, code_theme: &str) -> Result<(), String> {
+    let name = if code_theme.to_lowercase().ends_with(".tmtheme") {
+        highlighter.load_theme_file(Path::new(code_theme))?
+    } else {
+        code_theme.to_string()
+    };
+    highlighter.select_themes(Some(&name), Some(&name)).map_err(|e| {
+        let names: Vec<String> = highlighter.list_themes().into_iter().map(|theme| theme.name).collect();
+        format!("{} (available: {})", e, names.join(", "))
+    })
+}
+
+fn is_glob(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+// Expand glob patterns ourselves, since Windows shells don't
+fn expand_inputs(patterns: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut inputs = Vec::new();
+    for pattern in patterns {
+        if !is_glob(pattern) {
+            inputs.push(PathBuf::from(pattern));
+            continue;
+        }
+
+        let paths = glob::glob(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+        let matches: Vec<PathBuf> = paths.flatten().filter(|path| path.is_file()).collect();
+        if matches.is_empty() {
+            return Err(format!("no files match '{}'", pattern));
+        }
+        inputs.extend(matches);
+    }
+    Ok(inputs)
+}
+
+// The same pipeline as read_markdown_file followed by export_html, or one of the other exporters
+fn render_file(input: &Path, render_args: &RenderArgs, highlighter: &HighlightEngine) -> Result<Vec<u8>, String> {
+    let content = read_file_with_size_limit(input)?;
+    let sanitize_level = render_args.sanitize_level.unwrap_or_default();
+    let title = input.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Untitled".to_string());
+    match render_args.format {
+        OutputFormat::Docx => return export_docx(&content, Some(input), &title, highlighter, MarkdownOptions::default()),
+        OutputFormat::Pdf => {
+            return export_pdf(&content, Some(input), &title, highlighter, MarkdownOptions::default(), &PdfOptions::default());
+        }
+        OutputFormat::Epub => {
+            let sources = [BookSource { path: Some(input.to_path_buf()), content }];
+            return export_epub(&sources, &title, highlighter, sanitize_level, MarkdownOptions::default());
+        }
+        OutputFormat::Slides => {
+            let document = render_markdown_blocks(&content, highlighter, sanitize_level, MarkdownOptions::default())?;
+            let deck = slide_deck_html(document, Some(input), &title, &[], highlighter)?;
+            for src in deck.skipped_images {
+                eprintln!("warning: {}: image {} was not embedded", input.display(), src);
+            }
+            return Ok(deck.html.into_bytes());
+        }
+        OutputFormat::Html => {}
+    }
+
+    let mut document = render_markdown(&content, highlighter, sanitize_level, MarkdownOptions::default())?;
+    document.html = if render_args.self_contained {
+        let (html, skipped_images) = inline_images(&document.html, input.parent().unwrap_or(Path::new(".")))?;
+        for src in skipped_images {
+            eprintln!("warning: {}: image {} was not embedded", input.display(), src);
+        }
+        html
+    } else {
+        post_process_image_paths(&document.html, &input.to_string_lossy())?
+    };
+
+    let (html, toc) = if render_args.toc {
+        let html = if document.html.contains(TOC_MARKER) {
+            document.html
+        } else {
+            format!("{}\n{}", TOC_MARKER, document.html)
+        };
+        (html, Some(document.toc))
+    } else {
+        (document.html, None)
+    };
+
+    export_html_document(html, title, toc, document.front_matter, render_args.theme, highlighter).map(String::into_bytes)
+}
+
+// None means stdout
+fn output_path(input: &Path, output: Option<&str>, format: OutputFormat, batch: bool) -> Option<PathBuf> {
+    let output_name = input.with_extension(format.extension());
+    match output {
+        Some("-") => None,
+        Some(output) if batch || Path::new(output).is_dir() => {
+            Some(Path::new(output).join(output_name.file_name().unwrap_or_default()))
+        }
+        Some(output) => Some(PathBuf::from(output)),
+        None => Some(output_name),
+    }
+}
+
+fn write_output(output: Option<&Path>, rendered: &[u8]) -> Result<(), String> {
+    let Some(output) = output else {
+        return std::io::stdout().write_all(rendered)
+            .map_err(|e| format!("Failed to write to stdout: {}", e));
+    };
+
+    if let Some(parent) = output.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(output, rendered).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    println!("{}", output.display());
+    Ok(())
+}
+
+// Release builds use the Windows GUI subsystem, so reattach to the calling console for output
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+fn attach_console() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<RenderArgs>, String> {
+        parse_render_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parses_options() {
+        let render_args = parse(&["a.md", "-f", "HTML", "--theme", "dark", "--toc", "--sanitize", "strict", "-o", "-"]).unwrap().unwrap();
+        assert_eq!(render_args.inputs, ["a.md"]);
+        assert_eq!(render_args.format, OutputFormat::Html);
+        assert_eq!(render_args.theme, Some(ExportTheme::Dark));
+        assert_eq!(render_args.sanitize_level, Some(SanitizeLevel::Strict));
+        assert_eq!(render_args.output.as_deref(), Some("-"));
+        assert!(render_args.toc);
+        assert!(parse(&["--help", "a.md"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert_eq!(parse(&[]).err().as_deref(), Some("no input files given"));
+        assert_eq!(parse(&["a.md", "--bogus"]).err().as_deref(), Some("unknown option --bogus"));
+        assert_eq!(parse(&["a.md", "-f", "rtf"]).err().as_deref(), Some("invalid value 'rtf' for --format"));
+        assert_eq!(parse(&["a.md", "--theme"]).err().as_deref(), Some("--theme needs a value"));
+    }
+
+    #[test]
+    fn rejects_options_the_format_ignores() {
+        assert_eq!(parse(&["a.md", "-f", "pdf", "--sanitize", "strict"]).err().as_deref(), Some("--sanitize does not apply to --format pdf"));
+        assert_eq!(parse(&["a.md", "--toc", "-f", "docx"]).err().as_deref(), Some("--toc does not apply to --format docx"));
+        assert_eq!(parse(&["a.md", "-f", "epub", "--theme", "dark"]).err().as_deref(), Some("--theme does not apply to --format epub"));
+        assert_eq!(parse(&["a.md", "-f", "slides", "--self-contained"]).err().as_deref(), Some("--self-contained does not apply to --format slides"));
+        assert!(parse(&["a.md", "-f", "epub", "--sanitize", "relaxed", "--code-theme", "x"]).is_ok());
+        assert!(parse(&["a.md", "-f", "slides", "--sanitize", "strict"]).is_ok());
+    }
+
+    #[test]
+    fn names_outputs_after_inputs() {
+        let input = Path::new("docs/guide.md");
+        assert_eq!(output_path(input, None, OutputFormat::Slides, false), Some(PathBuf::from("docs/guide.html")));
+        assert_eq!(output_path(input, Some("out"), OutputFormat::Pdf, true), Some(PathBuf::from("out/guide.pdf")));
+        assert_eq!(output_path(input, Some("book.epub"), OutputFormat::Epub, false), Some(PathBuf::from("book.epub")));
+        assert_eq!(output_path(input, Some("-"), OutputFormat::Html, false), None);
+    }
+}
@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+
+use crate::check_forbidden_path;
+use crate::links::percent_decode;
+
+// Keep documents with huge image galleries from exhausting the OS watch limits
+const MAX_DEPENDENCIES: usize = 500;
+
+// Local files a rendered document embeds, which should trigger a reload when they change.
+// Runs after post_process_image_paths, so local images are absolute `file://` URLs by now.
+pub fn collect_dependencies(html: &str, base_file: &Path) -> Result<Vec<PathBuf>, String> {
+    let base_dir = base_file.parent().unwrap_or(Path::new("."));
+    let dependencies = RefCell::new(Vec::new());
+
+    rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("img[src]", |el| {
+                let src = el.get_attribute("src").unwrap_or_default();
+                if let Some(path) = local_path(&html_escape::decode_html_entities(&src), base_dir) {
+                    let mut dependencies = dependencies.borrow_mut();
+                    if !dependencies.contains(&path) && dependencies.len() < MAX_DEPENDENCIES {
+                        dependencies.push(path);
+                    }
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| format!("Failed to collect dependencies: {}", e))?;
+
+    Ok(dependencies.into_inner())
+}
+
+// Existing local file behind a `file://` URL or a relative reference that stays inside
+// the document's folder; remote URLs and data: URIs have no file. The renderer
+// percent-encodes references (spaces in file names), so they are also tried decoded.
+fn local_path(src: &str, base_dir: &Path) -> Option<PathBuf> {
+    let resolve = |src: &str| {
+        match src.split_once("://") {
+            Some((scheme, path)) if scheme.eq_ignore_ascii_case("file") => PathBuf::from(path).canonicalize().ok(),
+            Some(_) => None,
+            None if src.starts_with("data:") => None,
+            None => {
+                let path = base_dir.join(src).canonicalize().ok()?;
+                path.starts_with(base_dir.canonicalize().ok()?).then_some(path)
+            }
+        }
+    };
+
+    let canonical = resolve(src).or_else(|| resolve(&percent_decode(src)))?;
+    (canonical.is_file() && check_forbidden_path(&canonical).is_ok()).then_some(canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A folder with `docs/notes.md`, `docs/My Image.png`, `docs/a.png` and `outside.png`
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-dependencies-{}-{}", name, std::process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        for file in ["docs/notes.md", "docs/My Image.png", "docs/a.png", "outside.png"] {
+            fs::write(dir.join(file), "x").unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn resolves_local_references() {
+        let dir = fixture("local");
+        let docs = dir.join("docs");
+
+        assert_eq!(local_path("a.png", &docs), Some(docs.join("a.png")));
+        assert_eq!(local_path("./a.png", &docs), Some(docs.join("a.png")));
+        assert_eq!(local_path("My%20Image.png", &docs), Some(docs.join("My Image.png")));
+        let file_url = format!("FILE://{}", dir.join("outside.png").to_string_lossy());
+        assert_eq!(local_path(&file_url, &docs), Some(dir.join("outside.png")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn skips_remote_missing_and_escaping_references() {
+        let dir = fixture("skipped");
+        let docs = dir.join("docs");
+
+        assert_eq!(local_path("https://example.com/a.png", &docs), None);
+        assert_eq!(local_path("data:image/png;base64,AAAA", &docs), None);
+        assert_eq!(local_path("missing.png", &docs), None);
+        assert_eq!(local_path("../outside.png", &docs), None);
+        assert_eq!(local_path("%2E%2E/outside.png", &docs), None);
+        assert_eq!(local_path(".", &docs), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn collects_each_image_once() {
+        let dir = fixture("collect");
+        let docs = dir.join("docs");
+        let html = format!(
+            "<img src=\"a.png\"><img src=\"file://{}\"><img src=\"My%20Image.png\"><img src=\"https://example.com/b.png\"><img alt=\"none\">",
+            docs.join("a.png").to_string_lossy()
+        );
+
+        let dependencies = collect_dependencies(&html, &docs.join("notes.md")).unwrap();
+        assert_eq!(dependencies, vec![docs.join("a.png"), docs.join("My Image.png")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use serde::Serialize;
+
+use crate::changes::match_sequences;
+use crate::markdown::RenderedBlock;
+use crate::source_map::strip_source_lines;
+
+// Paired blocks sharing less than this share of their words are shown as a removal and an addition
+const MIN_WORD_SIMILARITY: f64 = 0.4;
+// Changed regions with more old × new block pairs than this are paired in order instead of aligned
+const MAX_ALIGNED_PAIRS: usize = 2_500;
+// Old × new tokens the word diffs of one changed region may compare in total. Past it the
+// region is paired in order, and pairs that no longer fit are shown as removed and added.
+const MAX_WORD_DIFF_CELLS: usize = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RowKind {
+    Unchanged,
+    Modified,
+    Added,
+    Removed,
+}
+
+impl RowKind {
+    fn class_name(self) -> &'static str {
+        match self {
+            RowKind::Unchanged => "diff-unchanged",
+            RowKind::Modified => "diff-modified",
+            RowKind::Added => "diff-added",
+            RowKind::Removed => "diff-removed",
+        }
+    }
+}
+
+// Number of blocks of each kind
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffStats {
+    pub unchanged: usize,
+    pub modified: usize,
+    pub added: usize,
+    pub removed: usize,
+}
+
+// Both documents side by side, one row per block, with changed words marked by <del> and <ins>
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedDiff {
+    pub html: String,
+    pub stats: DiffStats,
+}
+
+// Align the blocks of two renders (unchanged blocks match exactly), then diff the words
+// of the blocks left between them pairwise
+pub fn diff_documents(old: &[RenderedBlock], new: &[RenderedBlock], old_title: &str, new_title: &str) -> RenderedDiff {
+    let old_html: Vec<String> = old.iter().map(|block| strip_source_lines(&block.html).into_owned()).collect();
+    let new_html: Vec<String> = new.iter().map(|block| strip_source_lines(&block.html).into_owned()).collect();
+    let matches = match_sequences(&old_html, &new_html);
+
+    let mut html = format!(
+        "<div class=\"markdown-diff\">\n<div class=\"diff-header\"><div class=\"diff-side\">{}</div><div class=\"diff-side\">{}</div></div>\n",
+        html_escape::encode_text(old_title),
+        html_escape::encode_text(new_title),
+    );
+    let mut stats = DiffStats::default();
+    let mut push_row = |kind: RowKind, old: &str, new: &str| {
+        html.push_str(&format!(
+            "<div class=\"diff-row {}\">\n<div class=\"diff-side diff-old\">{}</div>\n<div class=\"diff-side diff-new\">{}</div>\n</div>\n",
+            kind.class_name(), old, new,
+        ));
+        match kind {
+            RowKind::Unchanged => stats.unchanged += 1,
+            RowKind::Modified => stats.modified += 1,
+            RowKind::Added => stats.added += 1,
+            RowKind::Removed => stats.removed += 1,
+        }
+    };
+
+    let mut old_next = 0;
+    let mut new_next = 0;
+    let kept = matches.iter().enumerate()
+        .filter_map(|(new_index, old_index)| old_index.map(|old_index| (old_index, new_index)))
+        .chain(std::iter::once((old.len(), new.len())));
+
+    for (old_index, new_index) in kept {
+        let removed = &old_html[old_next..old_index];
+        let inserted = &new_html[new_next..new_index];
+        for row in align_blocks(removed, inserted) {
+            match row {
+                AlignedRow::Modified(diff) => push_row(RowKind::Modified, &diff.old, &diff.new),
+                AlignedRow::Removed(index) => push_row(RowKind::Removed, &removed[index], ""),
+                AlignedRow::Added(index) => push_row(RowKind::Added, "", &inserted[index]),
+            }
+        }
+        if let (Some(old), Some(new)) = (old_html.get(old_index), new_html.get(new_index)) {
+            push_row(RowKind::Unchanged, old, new);
+        }
+        old_next = old_index + 1;
+        new_next = new_index + 1;
+    }
+
+    html.push_str("</div>\n");
+    RenderedDiff { html, stats }
+}
+
+enum AlignedRow {
+    Modified(WordDiff),
+    Removed(usize),
+    Added(usize),
+}
+
+// Pair up the blocks of a changed region so that edited blocks line up with their old
+// version: the order-preserving pairing with the most words in common
+fn align_blocks(removed: &[String], inserted: &[String]) -> Vec<AlignedRow> {
+    let removed: Vec<BlockTokens> = removed.iter().map(|html| BlockTokens::new(html)).collect();
+    let inserted: Vec<BlockTokens> = inserted.iter().map(|html| BlockTokens::new(html)).collect();
+    let mut budget = MAX_WORD_DIFF_CELLS;
+
+    // Pairs sharing too few words can't pass diff_words, so only the rest are diffed
+    let candidates: Vec<(usize, usize)> = if removed.len() * inserted.len() > MAX_ALIGNED_PAIRS {
+        Vec::new()
+    } else {
+        (0..removed.len())
+            .flat_map(|i| (0..inserted.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| removed[i].overlap(&inserted[j]) >= MIN_WORD_SIMILARITY)
+            .collect()
+    };
+    let cost = candidates.iter().fold(0usize, |total, &(i, j)| total.saturating_add(removed[i].diff_cost(&inserted[j])));
+    if removed.len() * inserted.len() > MAX_ALIGNED_PAIRS || cost > budget {
+        return pair_in_order(&removed, &inserted, &mut budget);
+    }
+
+    let mut pairs: Vec<Vec<Option<WordDiff>>> = removed.iter()
+        .map(|_| inserted.iter().map(|_| None).collect())
+        .collect();
+    for (i, j) in candidates {
+        pairs[i][j] = diff_words(&removed[i], &inserted[j]);
+    }
+
+    // scores[i][j]: best total similarity of removed[i..] paired with inserted[j..]
+    let columns = inserted.len() + 1;
+    let mut scores = vec![0.0f64; (removed.len() + 1) * columns];
+    for i in (0..removed.len()).rev() {
+        for j in (0..inserted.len()).rev() {
+            let paired = pairs[i][j].as_ref().map_or(0.0, |diff| diff.similarity + scores[(i + 1) * columns + j + 1]);
+            scores[i * columns + j] = paired.max(scores[(i + 1) * columns + j]).max(scores[i * columns + j + 1]);
+        }
+    }
+
+    let mut rows = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < removed.len() && j < inserted.len() {
+        let best = scores[i * columns + j];
+        if pairs[i][j].as_ref().is_some_and(|diff| diff.similarity + scores[(i + 1) * columns + j + 1] >= best) {
+            rows.push(AlignedRow::Modified(pairs[i][j].take().unwrap()));
+            i += 1;
+            j += 1;
+        } else if scores[(i + 1) * columns + j] >= best {
+            rows.push(AlignedRow::Removed(i));
+            i += 1;
+        } else {
+            rows.push(AlignedRow::Added(j));
+            j += 1;
+        }
+    }
+    rows.extend((i..removed.len()).map(AlignedRow::Removed));
+    rows.extend((j..inserted.len()).map(AlignedRow::Added));
+    rows
+}
+
+// Pair the n-th removed block with the n-th inserted one, diffing words while the budget lasts
+fn pair_in_order(removed: &[BlockTokens], inserted: &[BlockTokens], budget: &mut usize) -> Vec<AlignedRow> {
+    let mut rows = Vec::new();
+    for index in 0..removed.len().max(inserted.len()) {
+        match (removed.get(index), inserted.get(index)) {
+            (Some(old), Some(new)) => {
+                let cost = old.diff_cost(new);
+                let diff = if old.overlap(new) >= MIN_WORD_SIMILARITY && cost <= *budget {
+                    *budget -= cost;
+                    diff_words(old, new)
+                } else {
+                    None
+                };
+                match diff {
+                    Some(diff) => rows.push(AlignedRow::Modified(diff)),
+                    None => rows.extend([AlignedRow::Removed(index), AlignedRow::Added(index)]),
+                }
+            }
+            (Some(_), None) => rows.push(AlignedRow::Removed(index)),
+            (None, Some(_)) => rows.push(AlignedRow::Added(index)),
+            (None, None) => {}
+        }
+    }
+    rows
+}
+
+// A block split into tokens, with its words counted so pairs can be scored without a diff
+struct BlockTokens<'a> {
+    tokens: Vec<&'a str>,
+    word_counts: HashMap<&'a str, usize>,
+    words: usize,
+}
+
+impl<'a> BlockTokens<'a> {
+    fn new(html: &'a str) -> Self {
+        let tokens = tokenize(html);
+        let mut word_counts = HashMap::new();
+        for token in tokens.iter().filter(|token| is_word(token)) {
+            *word_counts.entry(*token).or_insert(0) += 1;
+        }
+        let words = word_counts.values().sum();
+        Self { tokens, word_counts, words }
+    }
+
+    // The share of words both blocks have, ignoring order. diff_words only counts words
+    // that also line up, so its similarity is never higher.
+    fn overlap(&self, other: &BlockTokens) -> f64 {
+        if self.words + other.words == 0 {
+            return 1.0;
+        }
+        let common: usize = self.word_counts.iter()
+            .map(|(word, count)| other.word_counts.get(word).map_or(0, |other_count| (*count).min(*other_count)))
+            .sum();
+        (2 * common) as f64 / (self.words + other.words) as f64
+    }
+
+    // Cells the word diff of the two blocks fills at most
+    fn diff_cost(&self, other: &BlockTokens) -> usize {
+        self.tokens.len().saturating_mul(other.tokens.len())
+    }
+}
+
+// Both sides of a block pair with their changed words marked
+struct WordDiff {
+    similarity: f64,
+    old: String,
+    new: String,
+}
+
+// Mark the words only one side has. Tags are compared too but never wrapped, so each
+// side keeps its own well-formed markup. None when the blocks have too little in common.
+fn diff_words(old: &BlockTokens, new: &BlockTokens) -> Option<WordDiff> {
+    let old_tokens = &old.tokens;
+    let new_tokens = &new.tokens;
+    let matches = match_sequences(old_tokens, new_tokens);
+
+    let mut old_kept = vec![false; old_tokens.len()];
+    for old_index in matches.iter().flatten() {
+        old_kept[*old_index] = true;
+    }
+    let new_kept: Vec<bool> = matches.iter().map(Option::is_some).collect();
+
+    let words = |tokens: &[&str], kept: &[bool]| tokens.iter().zip(kept)
+        .filter(|(token, _)| is_word(token))
+        .fold((0, 0), |(total, kept_words), (_, kept)| (total + 1, kept_words + usize::from(*kept)));
+    let (old_words, common) = words(old_tokens, &old_kept);
+    let (new_words, _) = words(new_tokens, &new_kept);
+    let similarity = if old_words + new_words == 0 {
+        1.0
+    } else {
+        (2 * common) as f64 / (old_words + new_words) as f64
+    };
+    if similarity < MIN_WORD_SIMILARITY {
+        return None;
+    }
+
+    Some(WordDiff {
+        similarity,
+        old: mark_tokens(old_tokens, &old_kept, "del"),
+        new: mark_tokens(new_tokens, &new_kept, "ins"),
+    })
+}
+
+// Wrap each run of unmatched text in the given tag; a tag in between ends the run
+fn mark_tokens(tokens: &[&str], kept: &[bool], tag: &str) -> String {
+    let mut html = String::new();
+    let mut run = String::new();
+    let flush = |html: &mut String, run: &mut String| {
+        if run.trim().is_empty() {
+            html.push_str(run);
+        } else {
+            html.push_str(&format!("<{} class=\"diff-{}\">{}</{}>", tag, tag, run, tag));
+        }
+        run.clear();
+    };
+
+    for (token, kept) in tokens.iter().zip(kept) {
+        if *kept || token.starts_with('<') {
+            flush(&mut html, &mut run);
+            html.push_str(token);
+        } else {
+            run.push_str(token);
+        }
+    }
+    flush(&mut html, &mut run);
+    html
+}
+
+// Split HTML into tags, runs of whitespace and words
+fn tokenize(html: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while let Some(first) = rest.chars().next() {
+        let end = if first == '<' {
+            rest.find('>').map_or(rest.len(), |end| end + 1)
+        } else if first.is_whitespace() {
+            rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len())
+        } else {
+            rest.find(|c: char| c.is_whitespace() || c == '<').unwrap_or(rest.len())
+        };
+        tokens.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    tokens
+}
+
+fn is_word(token: &str) -> bool {
+    !token.starts_with('<') && !token.trim().is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(texts: &[&str]) -> Vec<RenderedBlock> {
+        texts.iter().enumerate()
+            .map(|(index, text)| RenderedBlock {
+                html: format!("<p data-source-line=\"{0}-{0}\">{1}</p>\n", index + 1, text),
+                start_line: index + 1,
+                end_line: index + 1,
+            })
+            .collect()
+    }
+
+    fn row_kinds(old: &[&str], new: &[&str]) -> Vec<&'static str> {
+        let html = diff_documents(&blocks(old), &blocks(new), "a", "b").html;
+        html.match_indices("<div class=\"diff-row ")
+            .map(|(index, prefix)| {
+                let rest = &html[index + prefix.len()..];
+                ["diff-unchanged", "diff-modified", "diff-added", "diff-removed"].into_iter()
+                    .find(|kind| rest.starts_with(kind))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_unchanged_blocks_side_by_side() {
+        let diff = diff_documents(&blocks(&["one", "two"]), &blocks(&["one", "two"]), "old <a>", "new");
+        assert_eq!(diff.stats.unchanged, 2);
+        assert!(diff.html.contains("<div class=\"diff-side\">old &lt;a&gt;</div>"));
+        assert!(!diff.html.contains("data-source-line"));
+    }
+
+    #[test]
+    fn marks_changed_words_in_edited_blocks() {
+        let diff = diff_documents(&blocks(&["the quick brown fox"]), &blocks(&["the quick red fox"]), "a", "b");
+        assert_eq!(diff.stats.modified, 1);
+        assert!(diff.html.contains("<p>the quick <del class=\"diff-del\">brown</del> fox</p>"));
+        assert!(diff.html.contains("<p>the quick <ins class=\"diff-ins\">red</ins> fox</p>"));
+    }
+
+    #[test]
+    fn shows_rewritten_blocks_as_removed_and_added() {
+        assert_eq!(row_kinds(&["alpha beta gamma"], &["one two three"]), ["diff-removed", "diff-added"]);
+    }
+
+    #[test]
+    fn aligns_edited_blocks_with_their_old_version() {
+        let old = ["intro", "first point about parsing markdown", "second point about rendering html", "end"];
+        let new = ["intro", "a brand new paragraph", "second point about rendering clean html", "end"];
+        assert_eq!(row_kinds(&old, &new), ["diff-unchanged", "diff-removed", "diff-added", "diff-modified", "diff-unchanged"]);
+    }
+
+    #[test]
+    fn overlap_never_underestimates_word_similarity() {
+        for (old, new) in [("a b c d", "d c b a"), ("a a b", "a b b"), ("x y z", "x y z"), ("<b>a</b> c", "a <i>c</i>")] {
+            let (old, new) = (BlockTokens::new(old), BlockTokens::new(new));
+            let similarity = diff_words(&old, &new).map_or(0.0, |diff| diff.similarity);
+            assert!(old.overlap(&new) >= similarity, "{:?} {:?}", old.tokens, new.tokens);
+        }
+    }
+
+    #[test]
+    fn pairs_in_order_until_the_budget_runs_out() {
+        let old = [BlockTokens::new("a b c"), BlockTokens::new("d e f")];
+        let new = [BlockTokens::new("a b x"), BlockTokens::new("d e y")];
+        let mut budget = old[0].diff_cost(&new[0]);
+        let rows = pair_in_order(&old, &new, &mut budget);
+        assert!(matches!(rows.as_slice(), [AlignedRow::Modified(_), AlignedRow::Removed(1), AlignedRow::Added(1)]));
+        assert_eq!(budget, 0);
+    }
+
+    #[test]
+    fn bounds_the_work_for_large_changed_regions() {
+        let words: Vec<String> = (0..1_000).map(|word| format!("w{}", word % 50)).collect();
+        let old: Vec<String> = (0..40).map(|block| format!("old{} {}", block, words.join(" "))).collect();
+        let new: Vec<String> = (0..40).map(|block| format!("new{} {}", block, words.join(" "))).collect();
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+
+        let diff = diff_documents(&blocks(&old), &blocks(&new), "a", "b");
+        let stats = diff.stats;
+        assert!(stats.modified > 0);
+        assert_eq!(stats.modified + stats.removed, 40);
+        assert_eq!(stats.modified + stats.added, 40);
+    }
+}
@@ -0,0 +1,1040 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Tag, TagEnd};
+use syntect::highlighting::FontStyle;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::alerts::alert_title;
+use crate::front_matter::FrontMatter;
+use crate::gfm::MarkdownOptions;
+use crate::highlight::{CodeBlockInfo, HighlightEngine};
+use crate::images::{read_header, read_local_image, ImageHeader};
+use crate::markdown::{document_events, is_line_break, is_toc_marker, link_target};
+
+// Page content width (US Letter, 1" margins) in EMU and twentieths of a point
+const CONTENT_WIDTH_EMU: u64 = 5_943_600;
+const CONTENT_WIDTH_TWIPS: u32 = 9_360;
+// Images are laid out at 96 DPI
+const EMU_PER_PIXEL: u64 = 9_525;
+// Indentation per level of list or quote nesting, in twentieths of a point
+const INDENT_STEP: u32 = 720;
+// Word supports nine list levels
+const MAX_LIST_LEVEL: usize = 8;
+// Bullet lists share one numbering instance; ordered lists each get their own after it
+const BULLET_NUM_ID: usize = 1;
+// Background of code lines picked out with `{3-5}` in the info string
+const HIGHLIGHTED_LINE_FILL: &str = "FFF3C4";
+
+const WORD_NAMESPACES: &str = concat!(
+    "xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\" ",
+    "xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\" ",
+    "xmlns:wp=\"http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing\" ",
+    "xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\" ",
+    "xmlns:pic=\"http://schemas.openxmlformats.org/drawingml/2006/picture\"",
+);
+const RELATIONSHIP_TYPES: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+
+// Render a markdown document to the bytes of a .docx file. Images are read relative
+// to `base_file`; without one (unsaved content) they are replaced by their alt text.
+pub fn export_docx(
+    markdown_content: &str,
+    base_file: Option<&Path>,
+    title: &str,
+    highlighter: &HighlightEngine,
+    options: MarkdownOptions,
+) -> Result<Vec<u8>, String> {
+    let document = document_events(markdown_content, options);
+    let slugs = document.headings.iter().map(|heading| heading.slug.clone()).collect();
+    let mut writer = DocxWriter::new(highlighter, base_file.and_then(Path::parent), slugs);
+    if let Some(title) = document.front_matter.as_ref().and_then(|front_matter| front_matter.title.as_deref()) {
+        writer.title_paragraph(title);
+    }
+    writer.write_events(&document.events, &document.alert_titles);
+    writer.finish(title, document.front_matter.as_ref())
+}
+
+struct Relationship {
+    id: String,
+    kind: &'static str,
+    target: String,
+    external: bool,
+}
+
+struct Footnote {
+    id: usize,
+    // The document body, set aside while the footnote is written
+    body: String,
+    needs_reference_mark: bool,
+}
+
+struct TableContext {
+    alignments: Vec<Alignment>,
+    in_head: bool,
+    cell: usize,
+}
+
+#[derive(Default)]
+struct ParagraphProps<'s> {
+    style: Option<&'s str>,
+    numbering: Option<(usize, usize)>,
+    bottom_border: bool,
+    fill: Option<&'s str>,
+    indent: u32,
+    align: Option<&'s str>,
+}
+
+impl ParagraphProps<'_> {
+    // Child elements must follow the order of the schema, or Word refuses the file
+    fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        if let Some(style) = self.style {
+            xml.push_str(&format!("<w:pStyle w:val=\"{}\"/>", style));
+        }
+        if let Some((num_id, level)) = self.numbering {
+            xml.push_str(&format!("<w:numPr><w:ilvl w:val=\"{}\"/><w:numId w:val=\"{}\"/></w:numPr>", level, num_id));
+        }
+        if self.bottom_border {
+            xml.push_str("<w:pBdr><w:bottom w:val=\"single\" w:sz=\"6\" w:space=\"1\" w:color=\"D0D7DE\"/></w:pBdr>");
+        }
+        if let Some(fill) = self.fill {
+            xml.push_str(&format!("<w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"{}\"/>", fill));
+        }
+        if self.indent > 0 {
+            xml.push_str(&format!("<w:ind w:left=\"{}\"/>", self.indent));
+        }
+        if let Some(align) = self.align {
+            xml.push_str(&format!("<w:jc w:val=\"{}\"/>", align));
+        }
+        if xml.is_empty() {
+            xml
+        } else {
+            format!("<w:pPr>{}</w:pPr>", xml)
+        }
+    }
+}
+
+// Walks the event stream once, writing WordprocessingML into the document body
+// (or the footnote being defined) and collecting the parts the body refers to
+struct DocxWriter<'h> {
+    highlighter: &'h HighlightEngine,
+    base_dir: Option<&'h Path>,
+    out: String,
+    paragraph_open: bool,
+
+    bold: usize,
+    italic: usize,
+    strike: usize,
+    links: usize,
+
+    lists: Vec<usize>,
+    item_needs_number: bool,
+    quote_depth: usize,
+    table: Option<TableContext>,
+    // The ids the HTML view gives the headings, in document order
+    heading_slugs: std::vec::IntoIter<String>,
+    image: Option<(String, String)>,
+    // Inside a `[TOC]` paragraph, replaced by a field as a whole
+    in_toc_marker: bool,
+    code_block: Option<(CodeBlockInfo, String)>,
+
+    footnote: Option<Footnote>,
+    footnote_ids: HashMap<String, usize>,
+    footnotes: Vec<(usize, String)>,
+
+    relationships: Vec<Relationship>,
+    footnote_relationships: Vec<Relationship>,
+    media: Vec<(String, Vec<u8>)>,
+    // (start, level) of each ordered list, numbered from BULLET_NUM_ID + 1
+    ordered_lists: Vec<(u64, usize)>,
+    next_id: usize,
+    has_toc: bool,
+}
+
+impl<'h> DocxWriter<'h> {
+    fn new(highlighter: &'h HighlightEngine, base_dir: Option<&'h Path>, heading_slugs: Vec<String>) -> Self {
+        Self {
+            highlighter,
+            base_dir,
+            out: String::new(),
+            paragraph_open: false,
+            bold: 0,
+            italic: 0,
+            strike: 0,
+            links: 0,
+            lists: Vec::new(),
+            item_needs_number: false,
+            quote_depth: 0,
+            table: None,
+            heading_slugs: heading_slugs.into_iter(),
+            image: None,
+            in_toc_marker: false,
+            code_block: None,
+            footnote: None,
+            footnote_ids: HashMap::new(),
+            footnotes: Vec::new(),
+            relationships: Vec::new(),
+            footnote_relationships: Vec::new(),
+            media: Vec::new(),
+            ordered_lists: Vec::new(),
+            next_id: 1,
+            has_toc: false,
+        }
+    }
+
+    fn write_events(&mut self, events: &[Event], alert_titles: &HashMap<usize, String>) {
+        for (index, event) in events.iter().enumerate() {
+            // Alt text is collected, not written
+            if let Some((_, alt)) = self.image.as_mut() {
+                match event {
+                    Event::End(TagEnd::Image) => self.end_image(),
+                    Event::Text(text) | Event::Code(text) => alt.push_str(text),
+                    _ => {}
+                }
+                continue;
+            }
+            if self.in_toc_marker {
+                self.in_toc_marker = !matches!(event, Event::End(TagEnd::Paragraph));
+                continue;
+            }
+            if let Some((_, code)) = self.code_block.as_mut() {
+                match event {
+                    Event::End(TagEnd::CodeBlock) => self.end_code_block(),
+                    Event::Text(text) => code.push_str(text),
+                    _ => {}
+                }
+                continue;
+            }
+
+            match event {
+                Event::Start(tag) => self.start_tag(tag, &events[index + 1..], alert_titles.get(&index)),
+                Event::End(tag) => self.end_tag(tag),
+                Event::Text(text) => self.text_run(text, false),
+                Event::Code(code) => self.text_run(code, true),
+                Event::InlineMath(latex) | Event::DisplayMath(latex) => self.text_run(latex, true),
+                Event::SoftBreak => self.text_run(" ", false),
+                Event::HardBreak => self.raw_run("<w:br/>"),
+                Event::InlineHtml(html) if is_line_break(html) => self.raw_run("<w:br/>"),
+                Event::Rule => {
+                    self.close_paragraph();
+                    self.open_paragraph(ParagraphProps { bottom_border: true, ..ParagraphProps::default() });
+                    self.close_paragraph();
+                }
+                Event::FootnoteReference(label) => {
+                    let id = self.footnote_id(label);
+                    self.raw_run(&format!(
+                        "<w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteReference w:id=\"{}\"/>",
+                        id
+                    ));
+                }
+                Event::TaskListMarker(checked) => self.text_run(if *checked { "\u{2612} " } else { "\u{2610} " }, false),
+                // Raw HTML has no Word equivalent
+                Event::Html(_) | Event::InlineHtml(_) => {}
+            }
+        }
+        self.close_paragraph();
+    }
+
+    // `title` is the title of the alert a blockquote starting here stands for
+    fn start_tag(&mut self, tag: &Tag, rest: &[Event], title: Option<&String>) {
+        match tag {
+            Tag::Paragraph => {
+                self.close_paragraph();
+                if is_toc_marker(rest) {
+                    self.toc_field();
+                    self.in_toc_marker = true;
+                } else {
+                    self.open_paragraph(ParagraphProps::default());
+                }
+            }
+            Tag::Heading { level, .. } => {
+                self.close_paragraph();
+                let style = heading_style(*level);
+                self.open_paragraph(ParagraphProps { style: Some(style), ..ParagraphProps::default() });
+                if let Some(slug) = self.heading_slugs.next() {
+                    let id = self.next_id();
+                    self.out.push_str(&format!(
+                        "<w:bookmarkStart w:id=\"{}\" w:name=\"{}\"/><w:bookmarkEnd w:id=\"{}\"/>",
+                        id, xml_escape(&bookmark_name(&slug)), id
+                    ));
+                }
+            }
+            Tag::BlockQuote(kind) => {
+                self.close_paragraph();
+                self.quote_depth += 1;
+                if let Some(kind) = kind {
+                    self.open_paragraph(ParagraphProps::default());
+                    self.bold += 1;
+                    self.text_run(title.map_or(alert_title(*kind), String::as_str), false);
+                    self.bold -= 1;
+                    self.close_paragraph();
+                }
+            }
+            Tag::CodeBlock(kind) => {
+                self.close_paragraph();
+                let info = match kind {
+                    CodeBlockKind::Fenced(info) => CodeBlockInfo::parse(info),
+                    CodeBlockKind::Indented => CodeBlockInfo::default(),
+                };
+                self.code_block = Some((info, String::new()));
+            }
+            Tag::List(start) => {
+                self.close_paragraph();
+                let num_id = match start {
+                    Some(start) => {
+                        self.ordered_lists.push((*start, self.lists.len().min(MAX_LIST_LEVEL)));
+                        BULLET_NUM_ID + self.ordered_lists.len()
+                    }
+                    None => BULLET_NUM_ID,
+                };
+                self.lists.push(num_id);
+            }
+            Tag::Item => {
+                self.close_paragraph();
+                self.item_needs_number = true;
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.close_paragraph();
+                let id = self.footnote_id(label);
+                let body = std::mem::take(&mut self.out);
+                self.footnote = Some(Footnote { id, body, needs_reference_mark: true });
+            }
+            Tag::Table(alignments) => {
+                self.close_paragraph();
+                let column_width = CONTENT_WIDTH_TWIPS / alignments.len().max(1) as u32;
+                self.out.push_str(concat!(
+                    "<w:tbl><w:tblPr><w:tblStyle w:val=\"MarkdownTable\"/><w:tblW w:w=\"5000\" w:type=\"pct\"/>",
+                    "<w:tblLook w:val=\"04A0\" w:firstRow=\"1\" w:lastRow=\"0\" w:firstColumn=\"0\" w:lastColumn=\"0\" w:noHBand=\"0\" w:noVBand=\"1\"/>",
+                    "</w:tblPr><w:tblGrid>",
+                ));
+                for _ in alignments {
+                    self.out.push_str(&format!("<w:gridCol w:w=\"{}\"/>", column_width));
+                }
+                self.out.push_str("</w:tblGrid>");
+                self.table = Some(TableContext { alignments: alignments.clone(), in_head: false, cell: 0 });
+            }
+            Tag::TableHead => {
+                self.out.push_str("<w:tr><w:trPr><w:tblHeader/></w:trPr>");
+                if let Some(table) = self.table.as_mut() {
+                    table.in_head = true;
+                    table.cell = 0;
+                }
+            }
+            Tag::TableRow => {
+                self.out.push_str("<w:tr>");
+                if let Some(table) = self.table.as_mut() {
+                    table.cell = 0;
+                }
+            }
+            Tag::TableCell => {
+                let Some(table) = self.table.as_ref() else { return };
+                let width = CONTENT_WIDTH_TWIPS / table.alignments.len().max(1) as u32;
+                let align = match table.alignments.get(table.cell) {
+                    Some(Alignment::Center) => Some("center"),
+                    Some(Alignment::Right) => Some("right"),
+                    _ => None,
+                };
+                if table.in_head {
+                    self.bold += 1;
+                }
+                self.out.push_str(&format!("<w:tc><w:tcPr><w:tcW w:w=\"{}\" w:type=\"dxa\"/></w:tcPr>", width));
+                self.open_paragraph(ParagraphProps { align, ..ParagraphProps::default() });
+            }
+            Tag::Emphasis => self.italic += 1,
+            Tag::Strong => self.bold += 1,
+            Tag::Strikethrough => self.strike += 1,
+            Tag::Link { link_type, dest_url, .. } => {
+                self.ensure_paragraph();
+                let target = link_target(*link_type, dest_url);
+                let start = match target.strip_prefix('#') {
+                    Some(anchor) => format!("<w:hyperlink w:anchor=\"{}\">", xml_escape(&bookmark_name(anchor))),
+                    None => {
+                        let id = self.add_relationship("hyperlink", target.to_string(), true);
+                        format!("<w:hyperlink r:id=\"{}\">", id)
+                    }
+                };
+                self.out.push_str(&start);
+                self.links += 1;
+            }
+            Tag::Image { dest_url, .. } => self.image = Some((dest_url.to_string(), String::new())),
+            _ => {}
+        }
+    }
+
+    fn end_tag(&mut self, tag: &TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item => self.close_paragraph(),
+            TagEnd::BlockQuote(_) => {
+                self.close_paragraph();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            }
+            TagEnd::List(_) => {
+                self.close_paragraph();
+                self.lists.pop();
+            }
+            TagEnd::FootnoteDefinition => {
+                self.close_paragraph();
+                if let Some(footnote) = self.footnote.take() {
+                    let mut content = std::mem::replace(&mut self.out, footnote.body);
+                    if content.ends_with("</w:tbl>") {
+                        content.push_str("<w:p/>");
+                    }
+                    self.footnotes.push((footnote.id, content));
+                }
+            }
+            TagEnd::TableCell => {
+                self.close_paragraph();
+                self.out.push_str("</w:tc>");
+                if let Some(table) = self.table.as_mut() {
+                    if table.in_head {
+                        self.bold -= 1;
+                    }
+                    table.cell += 1;
+                }
+            }
+            TagEnd::TableHead => {
+                self.out.push_str("</w:tr>");
+                if let Some(table) = self.table.as_mut() {
+                    table.in_head = false;
+                }
+            }
+            TagEnd::TableRow => self.out.push_str("</w:tr>"),
+            TagEnd::Table => {
+                self.out.push_str("</w:tbl>");
+                self.table = None;
+            }
+            TagEnd::Emphasis => self.italic -= 1,
+            TagEnd::Strong => self.bold -= 1,
+            TagEnd::Strikethrough => self.strike -= 1,
+            TagEnd::Link if self.links > 0 => {
+                self.out.push_str("</w:hyperlink>");
+                self.links -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn open_paragraph(&mut self, mut props: ParagraphProps) {
+        if self.footnote.is_some() && props.style.is_none() {
+            props.style = Some("FootnoteText");
+        } else if self.quote_depth > 0 && props.style.is_none() {
+            props.style = Some("Quote");
+        }
+        if self.table.is_none() {
+            props.indent += INDENT_STEP * (self.lists.len() + self.quote_depth) as u32;
+        }
+        if let Some(&num_id) = self.lists.last() {
+            if std::mem::take(&mut self.item_needs_number) {
+                // The numbering definition supplies the indentation of numbered paragraphs
+                props.numbering = Some((num_id, (self.lists.len() - 1).min(MAX_LIST_LEVEL)));
+                props.indent = INDENT_STEP * self.quote_depth as u32;
+            }
+        }
+
+        self.out.push_str("<w:p>");
+        self.out.push_str(&props.to_xml());
+        self.paragraph_open = true;
+
+        if let Some(footnote) = self.footnote.as_mut() {
+            if std::mem::take(&mut footnote.needs_reference_mark) {
+                self.out.push_str("<w:r><w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteRef/></w:r><w:r><w:t xml:space=\"preserve\"> </w:t></w:r>");
+            }
+        }
+    }
+
+    // Tight list items and table cells hold text without a paragraph event
+    fn ensure_paragraph(&mut self) {
+        if !self.paragraph_open {
+            self.open_paragraph(ParagraphProps::default());
+        }
+    }
+
+    fn close_paragraph(&mut self) {
+        if self.paragraph_open {
+            // Links never span paragraphs in markdown, but don't leave one open if they did
+            for _ in 0..self.links {
+                self.out.push_str("</w:hyperlink>");
+            }
+            self.links = 0;
+            self.out.push_str("</w:p>");
+            self.paragraph_open = false;
+        }
+    }
+
+    fn title_paragraph(&mut self, title: &str) {
+        self.open_paragraph(ParagraphProps { style: Some("Title"), ..ParagraphProps::default() });
+        self.text_run(title, false);
+        self.close_paragraph();
+    }
+
+    // A Word table of contents field, filled in when the document is opened
+    fn toc_field(&mut self) {
+        self.open_paragraph(ParagraphProps::default());
+        self.out.push_str(concat!(
+            "<w:r><w:fldChar w:fldCharType=\"begin\" w:dirty=\"true\"/></w:r>",
+            "<w:r><w:instrText xml:space=\"preserve\"> TOC \\o \"1-3\" \\h \\z \\u </w:instrText></w:r>",
+            "<w:r><w:fldChar w:fldCharType=\"separate\"/></w:r>",
+            "<w:r><w:t>Right-click to update the table of contents.</w:t></w:r>",
+            "<w:r><w:fldChar w:fldCharType=\"end\"/></w:r>",
+        ));
+        self.close_paragraph();
+        self.has_toc = true;
+    }
+
+    fn run_properties(&self, code: bool) -> String {
+        let mut properties = String::new();
+        if self.links > 0 {
+            properties.push_str("<w:rStyle w:val=\"Hyperlink\"/>");
+            if code {
+                properties.push_str("<w:rFonts w:ascii=\"Consolas\" w:hAnsi=\"Consolas\" w:cs=\"Consolas\"/>");
+            }
+        } else if code {
+            properties.push_str("<w:rStyle w:val=\"VerbatimChar\"/>");
+        }
+        if self.bold > 0 {
+            properties.push_str("<w:b/><w:bCs/>");
+        }
+        if self.italic > 0 {
+            properties.push_str("<w:i/><w:iCs/>");
+        }
+        if self.strike > 0 {
+            properties.push_str("<w:strike/>");
+        }
+        if properties.is_empty() {
+            properties
+        } else {
+            format!("<w:rPr>{}</w:rPr>", properties)
+        }
+    }
+
+    fn text_run(&mut self, text: &str, code: bool) {
+        self.ensure_paragraph();
+        let properties = self.run_properties(code);
+        self.out.push_str(&format!("<w:r>{}<w:t xml:space=\"preserve\">{}</w:t></w:r>", properties, xml_escape(text)));
+    }
+
+    fn raw_run(&mut self, content: &str) {
+        self.ensure_paragraph();
+        self.out.push_str(&format!("<w:r>{}</w:r>", content));
+    }
+
+    // One paragraph per line, coloured like the HTML view
+    fn end_code_block(&mut self) {
+        let Some((info, code)) = self.code_block.take() else { return };
+        let code = code.strip_suffix('\n').unwrap_or(&code);
+
+        if let Some(title) = &info.title {
+            self.open_paragraph(ParagraphProps { style: Some("SourceCodeTitle"), ..ParagraphProps::default() });
+            self.text_run(title, false);
+            self.close_paragraph();
+        }
+
+        let styled = self.highlighter.styled_lines(code, info.language.as_deref());
+        for (index, line) in code.split('\n').enumerate() {
+            self.open_paragraph(ParagraphProps {
+                style: Some("SourceCode"),
+                fill: info.is_line_highlighted(index + 1).then_some(HIGHLIGHTED_LINE_FILL),
+                ..ParagraphProps::default()
+            });
+            match styled.as_ref().and_then(|lines| lines.get(index)) {
+                Some(regions) => {
+                    for (style, text) in regions {
+                        let text = text.trim_end_matches(['\n', '\r']);
+                        if text.is_empty() {
+                            continue;
+                        }
+                        let mut properties = format!(
+                            "<w:color w:val=\"{:02X}{:02X}{:02X}\"/>",
+                            style.foreground.r, style.foreground.g, style.foreground.b
+                        );
+                        if style.font_style.contains(FontStyle::ITALIC) {
+                            properties.insert_str(0, "<w:i/>");
+                        }
+                        if style.font_style.contains(FontStyle::BOLD) {
+                            properties.insert_str(0, "<w:b/>");
+                        }
+                        self.code_run(text, &properties);
+                    }
+                }
+                None => self.code_run(line.trim_end_matches('\r'), ""),
+            }
+            self.close_paragraph();
+        }
+    }
+
+    // Tabs need their own element inside a run
+    fn code_run(&mut self, text: &str, properties: &str) {
+        let properties = if properties.is_empty() { String::new() } else { format!("<w:rPr>{}</w:rPr>", properties) };
+        self.out.push_str(&format!("<w:r>{}", properties));
+        for (index, part) in text.split('\t').enumerate() {
+            if index > 0 {
+                self.out.push_str("<w:tab/>");
+            }
+            if !part.is_empty() {
+                self.out.push_str(&format!("<w:t xml:space=\"preserve\">{}</w:t>", xml_escape(part)));
+            }
+        }
+        self.out.push_str("</w:r>");
+    }
+
+    fn end_image(&mut self) {
+        let Some((source, alt)) = self.image.take() else { return };
+        match self.load_image(&source) {
+            Some((header, data)) => {
+                let media_name = format!("image{}.{}", self.media.len() + 1, header.format.extension());
+                let relationship = self.add_relationship("image", format!("media/{}", media_name), false);
+                self.media.push((media_name.clone(), data));
+
+                // Shrink to the page width, keeping the aspect ratio
+                let mut cx = u64::from(header.width) * EMU_PER_PIXEL;
+                let mut cy = u64::from(header.height) * EMU_PER_PIXEL;
+                if cx > CONTENT_WIDTH_EMU {
+                    cy = cy * CONTENT_WIDTH_EMU / cx;
+                    cx = CONTENT_WIDTH_EMU;
+                }
+                let id = self.next_id();
+                self.raw_run(&format!(
+                    concat!(
+                        "<w:drawing><wp:inline distT=\"0\" distB=\"0\" distL=\"0\" distR=\"0\">",
+                        "<wp:extent cx=\"{cx}\" cy=\"{cy}\"/><wp:docPr id=\"{id}\" name=\"{name}\" descr=\"{alt}\"/>",
+                        "<wp:cNvGraphicFramePr><a:graphicFrameLocks noChangeAspect=\"1\"/></wp:cNvGraphicFramePr>",
+                        "<a:graphic><a:graphicData uri=\"http://schemas.openxmlformats.org/drawingml/2006/picture\">",
+                        "<pic:pic><pic:nvPicPr><pic:cNvPr id=\"{id}\" name=\"{name}\"/><pic:cNvPicPr/></pic:nvPicPr>",
+                        "<pic:blipFill><a:blip r:embed=\"{rel}\"/><a:stretch><a:fillRect/></a:stretch></pic:blipFill>",
+                        "<pic:spPr><a:xfrm><a:off x=\"0\" y=\"0\"/><a:ext cx=\"{cx}\" cy=\"{cy}\"/></a:xfrm>",
+                        "<a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom></pic:spPr></pic:pic>",
+                        "</a:graphicData></a:graphic></wp:inline></w:drawing>",
+                    ),
+                    cx = cx, cy = cy, id = id, name = media_name, alt = xml_escape(&alt), rel = relationship,
+                ));
+            }
+            None => {
+                // Remote, missing or unsupported images keep their alt text
+                let alt = if alt.is_empty() { source } else { alt };
+                self.italic += 1;
+                self.text_run(&format!("[{}]", alt), false);
+                self.italic -= 1;
+            }
+        }
+    }
+
+    // Local PNG, JPEG and GIF images with their pixel size
+    fn load_image(&self, source: &str) -> Option<(ImageHeader, Vec<u8>)> {
+        let data = read_local_image(source, self.base_dir?)?;
+        let header = read_header(&data)?;
+        Some((header, data))
+    }
+
+    fn footnote_id(&mut self, label: &str) -> usize {
+        let next = self.footnote_ids.len() + 1;
+        *self.footnote_ids.entry(label.to_string()).or_insert(next)
+    }
+
+    fn next_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    // Footnotes are a separate part with their own relationships
+    fn add_relationship(&mut self, kind: &'static str, target: String, external: bool) -> String {
+        let id = format!("rIdx{}", self.next_id());
+        let relationship = Relationship { id: id.clone(), kind, target, external };
+        if self.footnote.is_some() {
+            self.footnote_relationships.push(relationship);
+        } else {
+            self.relationships.push(relationship);
+        }
+        id
+    }
+
+    fn finish(mut self, title: &str, front_matter: Option<&FrontMatter>) -> Result<Vec<u8>, String> {
+        self.close_paragraph();
+        // A table can't be the last thing before the section properties
+        if self.out.is_empty() || self.out.ends_with("</w:tbl>") {
+            self.out.push_str("<w:p/>");
+        }
+
+        let document = format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+                "<w:document {}><w:body>{}",
+                "<w:sectPr><w:pgSz w:w=\"12240\" w:h=\"15840\"/>",
+                "<w:pgMar w:top=\"1440\" w:right=\"1440\" w:bottom=\"1440\" w:left=\"1440\" w:header=\"720\" w:footer=\"720\" w:gutter=\"0\"/>",
+                "</w:sectPr></w:body></w:document>",
+            ),
+            WORD_NAMESPACES, self.out
+        );
+
+        let mut footnotes = format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:footnotes {}>",
+                "<w:footnote w:type=\"separator\" w:id=\"-1\"><w:p><w:pPr><w:spacing w:after=\"0\" w:line=\"240\" w:lineRule=\"auto\"/></w:pPr><w:r><w:separator/></w:r></w:p></w:footnote>",
+                "<w:footnote w:type=\"continuationSeparator\" w:id=\"0\"><w:p><w:pPr><w:spacing w:after=\"0\" w:line=\"240\" w:lineRule=\"auto\"/></w:pPr><w:r><w:continuationSeparator/></w:r></w:p></w:footnote>",
+            ),
+            WORD_NAMESPACES
+        );
+        for (id, content) in &self.footnotes {
+            let content = if content.is_empty() { "<w:p/>" } else { content };
+            footnotes.push_str(&format!("<w:footnote w:id=\"{}\">{}</w:footnote>", id, content));
+        }
+        footnotes.push_str("</w:footnotes>");
+
+        let mut document_relationships = vec![
+            Relationship { id: "rId1".into(), kind: "styles", target: "styles.xml".into(), external: false },
+            Relationship { id: "rId2".into(), kind: "numbering", target: "numbering.xml".into(), external: false },
+            Relationship { id: "rId3".into(), kind: "footnotes", target: "footnotes.xml".into(), external: false },
+            Relationship { id: "rId4".into(), kind: "settings", target: "settings.xml".into(), external: false },
+        ];
+        document_relationships.append(&mut self.relationships);
+
+        let mut parts: Vec<(String, Vec<u8>)> = vec![
+            ("[Content_Types].xml".into(), CONTENT_TYPES.into()),
+            ("_rels/.rels".into(), PACKAGE_RELATIONSHIPS.into()),
+            ("docProps/core.xml".into(), core_properties(title, front_matter).into_bytes()),
+            ("word/document.xml".into(), document.into_bytes()),
+            ("word/_rels/document.xml.rels".into(), relationships_xml(&document_relationships).into_bytes()),
+            ("word/styles.xml".into(), STYLES.into()),
+            ("word/numbering.xml".into(), numbering_xml(&self.ordered_lists).into_bytes()),
+            ("word/footnotes.xml".into(), footnotes.into_bytes()),
+            ("word/settings.xml".into(), settings_xml(self.has_toc).into_bytes()),
+        ];
+        if !self.footnote_relationships.is_empty() {
+            parts.push(("word/_rels/footnotes.xml.rels".into(), relationships_xml(&self.footnote_relationships).into_bytes()));
+        }
+        for (name, data) in self.media {
+            parts.push((format!("word/media/{}", name), data));
+        }
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, data) in parts {
+            zip.start_file(name, options).map_err(|e| format!("Failed to write DOCX: {}", e))?;
+            zip.write_all(&data).map_err(|e| format!("Failed to write DOCX: {}", e))?;
+        }
+        let cursor = zip.finish().map_err(|e| format!("Failed to write DOCX: {}", e))?;
+        Ok(cursor.into_inner())
+    }
+}
+
+// Word bookmark names may only hold letters, digits and underscores; a leading
+// underscore hides them from the bookmark list
+fn bookmark_name(slug: &str) -> String {
+    let name: String = slug.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .take(39)
+        .collect();
+    format!("_{}", name)
+}
+
+fn heading_style(level: HeadingLevel) -> &'static str {
+    match level {
+        HeadingLevel::H1 => "Heading1",
+        HeadingLevel::H2 => "Heading2",
+        HeadingLevel::H3 => "Heading3",
+        HeadingLevel::H4 => "Heading4",
+        HeadingLevel::H5 => "Heading5",
+        HeadingLevel::H6 => "Heading6",
+    }
+}
+
+// Escape text for XML, dropping control characters XML 1.0 can't represent
+pub fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 || c == '\u{FFFE}' || c == '\u{FFFF}' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn relationships_xml(relationships: &[Relationship]) -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+        "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    ));
+    for relationship in relationships {
+        xml.push_str(&format!(
+            "<Relationship Id=\"{}\" Type=\"{}/{}\" Target=\"{}\"{}/>",
+            relationship.id,
+            RELATIONSHIP_TYPES,
+            relationship.kind,
+            xml_escape(&relationship.target),
+            if relationship.external { " TargetMode=\"External\"" } else { "" },
+        ));
+    }
+    xml.push_str("</Relationships>");
+    xml
+}
+
+// One numbering instance for bullets, then one per ordered list so each restarts at its own start
+fn numbering_xml(ordered_lists: &[(u64, usize)]) -> String {
+    let mut xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:numbering {}>", WORD_NAMESPACES);
+    let bullets = ["\u{2022}", "\u{25E6}", "\u{25AA}"];
+    let formats = ["decimal", "lowerLetter", "lowerRoman"];
+
+    for (abstract_id, ordered) in [(0, false), (1, true)] {
+        xml.push_str(&format!("<w:abstractNum w:abstractNumId=\"{}\"><w:multiLevelType w:val=\"hybridMultilevel\"/>", abstract_id));
+        for level in 0..=MAX_LIST_LEVEL {
+            let (format, text) = if ordered {
+                (formats[level % 3], format!("%{}.", level + 1))
+            } else {
+                ("bullet", bullets[level % 3].to_string())
+            };
+            xml.push_str(&format!(
+                concat!(
+                    "<w:lvl w:ilvl=\"{}\"><w:start w:val=\"1\"/><w:numFmt w:val=\"{}\"/><w:lvlText w:val=\"{}\"/>",
+                    "<w:lvlJc w:val=\"left\"/><w:pPr><w:ind w:left=\"{}\" w:hanging=\"360\"/></w:pPr></w:lvl>",
+                ),
+                level, format, text, INDENT_STEP as usize * (level + 1),
+            ));
+        }
+        xml.push_str("</w:abstractNum>");
+    }
+
+    xml.push_str(&format!("<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"0\"/></w:num>", BULLET_NUM_ID));
+    for (index, (start, level)) in ordered_lists.iter().enumerate() {
+        xml.push_str(&format!(
+            "<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"1\"/><w:lvlOverride w:ilvl=\"{}\"><w:startOverride w:val=\"{}\"/></w:lvlOverride></w:num>",
+            BULLET_NUM_ID + index + 1, level, start,
+        ));
+    }
+    xml.push_str("</w:numbering>");
+    xml
+}
+
+fn settings_xml(update_fields: bool) -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<w:settings {}>{}",
+            "<w:footnotePr><w:footnote w:id=\"-1\"/><w:footnote w:id=\"0\"/></w:footnotePr></w:settings>",
+        ),
+        WORD_NAMESPACES,
+        if update_fields { "<w:updateFields w:val=\"true\"/>" } else { "" },
+    )
+}
+
+fn core_properties(title: &str, front_matter: Option<&FrontMatter>) -> String {
+    let title = front_matter.and_then(|front_matter| front_matter.title.as_deref()).unwrap_or(title);
+    let mut xml = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+            "<cp:coreProperties xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" ",
+            "xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:dcterms=\"http://purl.org/dc/terms/\" ",
+            "xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\"><dc:title>{}</dc:title>",
+        ),
+        xml_escape(title)
+    );
+    if let Some(author) = front_matter.and_then(|front_matter| front_matter.author.as_deref()) {
+        xml.push_str(&format!("<dc:creator>{}</dc:creator>", xml_escape(author)));
+    }
+    if let Some(front_matter) = front_matter.filter(|front_matter| !front_matter.tags.is_empty()) {
+        xml.push_str(&format!("<cp:keywords>{}</cp:keywords>", xml_escape(&front_matter.tags.join(", "))));
+    }
+    xml.push_str("</cp:coreProperties>");
+    xml
+}
+
+const CONTENT_TYPES: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">",
+    "<Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>",
+    "<Default Extension=\"xml\" ContentType=\"application/xml\"/>",
+    "<Default Extension=\"png\" ContentType=\"image/png\"/>",
+    "<Default Extension=\"jpeg\" ContentType=\"image/jpeg\"/>",
+    "<Default Extension=\"gif\" ContentType=\"image/gif\"/>",
+    "<Override PartName=\"/word/document.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml\"/>",
+    "<Override PartName=\"/word/styles.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml\"/>",
+    "<Override PartName=\"/word/numbering.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml\"/>",
+    "<Override PartName=\"/word/footnotes.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.footnotes+xml\"/>",
+    "<Override PartName=\"/word/settings.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.settings+xml\"/>",
+    "<Override PartName=\"/docProps/core.xml\" ContentType=\"application/vnd.openxmlformats-package.core-properties+xml\"/>",
+    "</Types>",
+);
+
+const PACKAGE_RELATIONSHIPS: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    "<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"word/document.xml\"/>",
+    "<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties\" Target=\"docProps/core.xml\"/>",
+    "</Relationships>",
+);
+
+// Styles modelled on the HTML view: GitHub-like headings, shaded code and bordered tables
+const STYLES: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<w:styles xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">",
+    "<w:docDefaults><w:rPrDefault><w:rPr><w:rFonts w:ascii=\"Calibri\" w:hAnsi=\"Calibri\" w:eastAsia=\"Calibri\" w:cs=\"Calibri\"/>",
+    "<w:sz w:val=\"22\"/><w:szCs w:val=\"22\"/><w:lang w:val=\"en-US\"/></w:rPr></w:rPrDefault>",
+    "<w:pPrDefault><w:pPr><w:spacing w:after=\"160\" w:line=\"264\" w:lineRule=\"auto\"/></w:pPr></w:pPrDefault></w:docDefaults>",
+    "<w:style w:type=\"paragraph\" w:default=\"1\" w:styleId=\"Normal\"><w:name w:val=\"Normal\"/><w:qFormat/></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Title\"><w:name w:val=\"Title\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:spacing w:after=\"240\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"48\"/><w:szCs w:val=\"48\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading1\"><w:name w:val=\"heading 1\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:pBdr><w:bottom w:val=\"single\" w:sz=\"4\" w:space=\"4\" w:color=\"D0D7DE\"/></w:pBdr><w:spacing w:before=\"360\" w:after=\"120\"/><w:outlineLvl w:val=\"0\"/></w:pPr>",
+    "<w:rPr><w:b/><w:sz w:val=\"40\"/><w:szCs w:val=\"40\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading2\"><w:name w:val=\"heading 2\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:pBdr><w:bottom w:val=\"single\" w:sz=\"4\" w:space=\"4\" w:color=\"D0D7DE\"/></w:pBdr><w:spacing w:before=\"320\" w:after=\"120\"/><w:outlineLvl w:val=\"1\"/></w:pPr>",
+    "<w:rPr><w:b/><w:sz w:val=\"32\"/><w:szCs w:val=\"32\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading3\"><w:name w:val=\"heading 3\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"280\" w:after=\"80\"/><w:outlineLvl w:val=\"2\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"26\"/><w:szCs w:val=\"26\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading4\"><w:name w:val=\"heading 4\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"240\" w:after=\"80\"/><w:outlineLvl w:val=\"3\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"22\"/><w:szCs w:val=\"22\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading5\"><w:name w:val=\"heading 5\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"240\" w:after=\"80\"/><w:outlineLvl w:val=\"4\"/></w:pPr><w:rPr><w:b/><w:sz w:val=\"20\"/><w:szCs w:val=\"20\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Heading6\"><w:name w:val=\"heading 6\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:keepNext/><w:spacing w:before=\"240\" w:after=\"80\"/><w:outlineLvl w:val=\"5\"/></w:pPr><w:rPr><w:b/><w:color w:val=\"57606A\"/><w:sz w:val=\"20\"/><w:szCs w:val=\"20\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"Quote\"><w:name w:val=\"Quote\"/><w:basedOn w:val=\"Normal\"/><w:qFormat/>",
+    "<w:pPr><w:pBdr><w:left w:val=\"single\" w:sz=\"24\" w:space=\"8\" w:color=\"D0D7DE\"/></w:pBdr></w:pPr><w:rPr><w:color w:val=\"57606A\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"SourceCode\"><w:name w:val=\"Source Code\"/><w:basedOn w:val=\"Normal\"/>",
+    "<w:pPr><w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"F6F8FA\"/><w:spacing w:after=\"0\" w:line=\"240\" w:lineRule=\"auto\"/></w:pPr>",
+    "<w:rPr><w:rFonts w:ascii=\"Consolas\" w:hAnsi=\"Consolas\" w:cs=\"Consolas\"/><w:sz w:val=\"19\"/><w:szCs w:val=\"19\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"SourceCodeTitle\"><w:name w:val=\"Source Code Title\"/><w:basedOn w:val=\"Normal\"/><w:next w:val=\"SourceCode\"/>",
+    "<w:pPr><w:keepNext/><w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"E9ECEF\"/><w:spacing w:before=\"120\" w:after=\"0\"/></w:pPr>",
+    "<w:rPr><w:rFonts w:ascii=\"Consolas\" w:hAnsi=\"Consolas\" w:cs=\"Consolas\"/><w:sz w:val=\"18\"/><w:szCs w:val=\"18\"/></w:rPr></w:style>",
+    "<w:style w:type=\"paragraph\" w:styleId=\"FootnoteText\"><w:name w:val=\"footnote text\"/><w:basedOn w:val=\"Normal\"/>",
+    "<w:pPr><w:spacing w:after=\"0\" w:line=\"240\" w:lineRule=\"auto\"/></w:pPr><w:rPr><w:sz w:val=\"20\"/><w:szCs w:val=\"20\"/></w:rPr></w:style>",
+    "<w:style w:type=\"character\" w:default=\"1\" w:styleId=\"DefaultParagraphFont\"><w:name w:val=\"Default Paragraph Font\"/><w:uiPriority w:val=\"1\"/></w:style>",
+    "<w:style w:type=\"character\" w:styleId=\"VerbatimChar\"><w:name w:val=\"Verbatim Char\"/><w:basedOn w:val=\"DefaultParagraphFont\"/>",
+    "<w:rPr><w:rFonts w:ascii=\"Consolas\" w:hAnsi=\"Consolas\" w:cs=\"Consolas\"/><w:sz w:val=\"19\"/><w:szCs w:val=\"19\"/><w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"EFF1F3\"/></w:rPr></w:style>",
+    "<w:style w:type=\"character\" w:styleId=\"Hyperlink\"><w:name w:val=\"Hyperlink\"/><w:basedOn w:val=\"DefaultParagraphFont\"/>",
+    "<w:rPr><w:color w:val=\"0969DA\"/><w:u w:val=\"single\"/></w:rPr></w:style>",
+    "<w:style w:type=\"character\" w:styleId=\"FootnoteReference\"><w:name w:val=\"footnote reference\"/><w:basedOn w:val=\"DefaultParagraphFont\"/>",
+    "<w:rPr><w:vertAlign w:val=\"superscript\"/></w:rPr></w:style>",
+    "<w:style w:type=\"table\" w:default=\"1\" w:styleId=\"TableNormal\"><w:name w:val=\"Normal Table\"/><w:uiPriority w:val=\"99\"/>",
+    "<w:tblPr><w:tblInd w:w=\"0\" w:type=\"dxa\"/><w:tblCellMar><w:top w:w=\"0\" w:type=\"dxa\"/><w:left w:w=\"108\" w:type=\"dxa\"/>",
+    "<w:bottom w:w=\"0\" w:type=\"dxa\"/><w:right w:w=\"108\" w:type=\"dxa\"/></w:tblCellMar></w:tblPr></w:style>",
+    "<w:style w:type=\"table\" w:styleId=\"MarkdownTable\"><w:name w:val=\"Markdown Table\"/><w:basedOn w:val=\"TableNormal\"/>",
+    "<w:pPr><w:spacing w:before=\"60\" w:after=\"60\"/></w:pPr>",
+    "<w:tblPr><w:tblBorders>",
+    "<w:top w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/><w:left w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/>",
+    "<w:bottom w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/><w:right w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/>",
+    "<w:insideH w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/><w:insideV w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"D0D7DE\"/>",
+    "</w:tblBorders></w:tblPr>",
+    "<w:tblStylePr w:type=\"firstRow\"><w:rPr><w:b/></w:rPr><w:tcPr><w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"F6F8FA\"/></w:tcPr></w:tblStylePr>",
+    "</w:style>",
+    "</w:styles>",
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    // The text parts of the package, by name
+    fn export(markdown: &str, base_file: Option<&Path>) -> HashMap<String, String> {
+        let bytes = export_docx(markdown, base_file, "doc", &HighlightEngine::new(), MarkdownOptions::default()).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut parts = HashMap::new();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).unwrap();
+            let mut data = Vec::new();
+            file.read_to_end(&mut data).unwrap();
+            parts.insert(file.name().to_string(), String::from_utf8_lossy(&data).into_owned());
+        }
+        parts
+    }
+
+    #[test]
+    fn numbers_nested_and_ordered_lists() {
+        let parts = export("- a\n  - b\n\ntext\n\n3. c\n4. d\n\n<!-- -->\n\n1. e\n", None);
+        let document = &parts["word/document.xml"];
+        assert!(document.contains("<w:numPr><w:ilvl w:val=\"0\"/><w:numId w:val=\"1\"/></w:numPr>"));
+        assert!(document.contains("<w:numPr><w:ilvl w:val=\"1\"/><w:numId w:val=\"1\"/></w:numPr>"));
+        assert_eq!(document.matches("<w:numId w:val=\"2\"/>").count(), 2);
+        assert_eq!(document.matches("<w:numId w:val=\"3\"/>").count(), 1);
+
+        // Each ordered list restarts at its own start
+        let numbering = &parts["word/numbering.xml"];
+        assert!(numbering.contains("<w:num w:numId=\"2\"><w:abstractNumId w:val=\"1\"/><w:lvlOverride w:ilvl=\"0\"><w:startOverride w:val=\"3\"/>"));
+        assert!(numbering.contains("<w:num w:numId=\"3\"><w:abstractNumId w:val=\"1\"/><w:lvlOverride w:ilvl=\"0\"><w:startOverride w:val=\"1\"/>"));
+    }
+
+    #[test]
+    fn writes_tables_with_header_rows_and_alignment() {
+        let document = &export("| a | b |\n|:-|-:|\n| 1 | 2 |\n", None)["word/document.xml"];
+        assert_eq!(document.matches("<w:tr>").count(), 2);
+        assert_eq!(document.matches("<w:tr><w:trPr><w:tblHeader/></w:trPr>").count(), 1);
+        assert_eq!(document.matches("<w:tc>").count(), 4);
+        assert_eq!(document.matches("<w:jc w:val=\"right\"/>").count(), 2);
+        assert!(document.contains("<w:r><w:rPr><w:b/><w:bCs/></w:rPr><w:t xml:space=\"preserve\">a</w:t></w:r>"));
+        // A table can't end the body
+        assert!(document.contains("</w:tbl><w:p/><w:sectPr>"));
+    }
+
+    #[test]
+    fn embeds_local_images_and_keeps_alt_text_for_others() {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-docx-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(1600u32.to_be_bytes());
+        png.extend(100u32.to_be_bytes());
+        fs::write(dir.join("wide.png"), &png).unwrap();
+
+        let parts = export("![Wide](wide.png) ![Remote](https://example.com/a.png)\n", Some(&dir.join("doc.md")));
+        let document = &parts["word/document.xml"];
+        // Shrunk to the page width
+        assert!(document.contains(&format!("<wp:extent cx=\"{}\" cy=\"{}\"/>", CONTENT_WIDTH_EMU, CONTENT_WIDTH_EMU / 16)));
+        assert!(document.contains("descr=\"Wide\""));
+        assert!(document.contains("[Remote]"));
+        assert_eq!(parts["word/media/image1.png"].len(), String::from_utf8_lossy(&png).len());
+        assert!(parts["word/_rels/document.xml.rels"].contains("Target=\"media/image1.png\"/>"));
+
+        // Without a file to resolve against, images keep their alt text
+        assert!(export("![Wide](wide.png)\n", None)["word/document.xml"].contains("[Wide]"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_code_lines_as_paragraphs() {
+        let document = &export("```rust {2}\nfn main() {}\n\tlet x = 1;\n```\n", None)["word/document.xml"];
+        assert_eq!(document.matches("<w:pStyle w:val=\"SourceCode\"/>").count(), 2);
+        assert_eq!(document.matches(&format!("w:fill=\"{}\"", HIGHLIGHTED_LINE_FILL)).count(), 1);
+        assert!(document.contains("<w:tab/>"));
+        assert!(document.contains("<w:color w:val=\""));
+    }
+
+    #[test]
+    fn moves_footnotes_to_their_own_part() {
+        let parts = export("Text[^note].\n\n[^note]: See [the site](https://example.com).\n", None);
+        assert!(parts["word/document.xml"].contains("<w:footnoteReference w:id=\"1\"/>"));
+        assert!(!parts["word/document.xml"].contains("See "));
+
+        let footnotes = &parts["word/footnotes.xml"];
+        assert!(footnotes.contains("<w:footnote w:id=\"1\"><w:p><w:pPr><w:pStyle w:val=\"FootnoteText\"/></w:pPr>"));
+        assert!(footnotes.contains("<w:footnoteRef/>"));
+        // Links in footnotes are relationships of the footnotes part
+        assert!(parts["word/_rels/footnotes.xml.rels"].contains("Target=\"https://example.com\" TargetMode=\"External\""));
+        assert!(!parts["word/_rels/document.xml.rels"].contains("example.com"));
+    }
+
+    #[test]
+    fn links_email_addresses_with_mailto() {
+        let parts = export("Write to a@b.com or <c@d.org>, or [mail](mailto:e@f.net).\n", None);
+        let relationships = &parts["word/_rels/document.xml.rels"];
+        for target in ["mailto:a@b.com", "mailto:c@d.org", "mailto:e@f.net"] {
+            assert!(relationships.contains(&format!("Target=\"{}\" TargetMode=\"External\"", target)), "{}", target);
+        }
+        assert!(!relationships.contains("mailto:mailto:"));
+        assert_eq!(parts["word/document.xml"].matches("<w:hyperlink r:id=").count(), 3);
+    }
+
+    #[test]
+    fn titles_alerts() {
+        let document = &export("> [!IMPORTANT]\n> a\n\n:::warning Mind the gap\nb\n:::\n\n:::danger\nc\n:::\n", None)["word/document.xml"];
+        for title in ["Important", "Mind the gap", "Danger"] {
+            assert!(document.contains(&format!("<w:b/><w:bCs/></w:rPr><w:t xml:space=\"preserve\">{}</w:t>", title)), "{}", title);
+        }
+        assert!(!document.contains(">Warning<"));
+    }
+}
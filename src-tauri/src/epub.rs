@@ -0,0 +1,705 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lol_html::html_content::ContentType;
+use lol_html::{doc_comments, doc_text, element, rewrite_str, RewriteStrSettings};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::docx::xml_escape;
+use crate::front_matter::FrontMatter;
+use crate::gfm::MarkdownOptions;
+use crate::highlight::HighlightEngine;
+use crate::images::{resolve_local_image, sniff_mime_type};
+use crate::links::{percent_decode, resolve_link, LinkKind};
+use crate::markdown::render_markdown;
+use crate::sanitize::SanitizeLevel;
+use crate::source_map::strip_source_lines;
+use crate::toc::{render_toc_html, TocEntry, TOC_MARKER};
+use crate::workspace::WorkspaceEntry;
+use crate::ExportTheme;
+
+// Images past this total are left out of a book and read as their alt text
+const MAX_BOOK_IMAGES_SIZE: u64 = 200 * 1024 * 1024;
+// Elements without content, which XHTML has to close as `<br />`
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+// Files read first in each folder of a book, like the page GitHub shows for a folder
+const INDEX_FILE_STEMS: &[&str] = &["readme", "index"];
+const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+const MATHML_NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+
+// One markdown file of a book
+pub struct BookSource {
+    // Where the file lives, for resolving images and links; None for unsaved content
+    pub path: Option<PathBuf>,
+    pub content: String,
+}
+
+struct Chapter {
+    file_name: String,
+    title: String,
+    // Whether the title is the chapter's only top-level heading, which the navigation
+    // then doesn't repeat below it
+    title_from_heading: bool,
+    toc: Vec<TocEntry>,
+    front_matter: Option<FrontMatter>,
+    body: String,
+    // Manifest properties: "mathml" and "svg" when the chapter contains them
+    properties: Vec<&'static str>,
+}
+
+struct BookImage {
+    href: String,
+    media_type: &'static str,
+    data: Vec<u8>,
+}
+
+// Images copied into the book, each file once however often it is referenced
+#[derive(Default)]
+struct BookImages {
+    images: Vec<BookImage>,
+    by_path: HashMap<PathBuf, usize>,
+    total_size: u64,
+}
+
+impl BookImages {
+    // Package path of the image at `path`, adding it to the book on first use
+    fn add(&mut self, path: PathBuf) -> Option<String> {
+        if let Some(&index) = self.by_path.get(&path) {
+            return Some(self.images[index].href.clone());
+        }
+        let data = std::fs::read(&path).ok()?;
+        let media_type = sniff_mime_type(&data)?;
+        if self.total_size + data.len() as u64 > MAX_BOOK_IMAGES_SIZE {
+            return None;
+        }
+        self.total_size += data.len() as u64;
+
+        let href = format!("images/image-{}.{}", self.images.len() + 1, image_extension(media_type));
+        self.by_path.insert(path, self.images.len());
+        self.images.push(BookImage { href: href.clone(), media_type, data });
+        Some(href)
+    }
+}
+
+// Render markdown files to the bytes of an EPUB 3 book, one chapter per file in the
+// given order. Headings make up the navigation document. The first file's front matter
+// supplies the book title and language, and the first author found is the book's author.
+// Local images are resolved like the viewer resolves them and copied into the book;
+// other images are replaced by their alt text.
+pub fn export_epub(
+    sources: &[BookSource],
+    title: &str,
+    highlighter: &HighlightEngine,
+    sanitize_level: SanitizeLevel,
+    options: MarkdownOptions,
+) -> Result<Vec<u8>, String> {
+    if sources.is_empty() {
+        return Err("Nothing to export".to_string());
+    }
+
+    // Links between the files of the book point at their chapters instead
+    let chapter_files: HashMap<String, String> = sources.iter()
+        .enumerate()
+        .filter_map(|(index, source)| {
+            let path = source.path.as_ref()?;
+            Some((path.to_string_lossy().to_string(), chapter_file_name(index)))
+        })
+        .collect();
+
+    let mut images = BookImages::default();
+    let mut chapters = Vec::with_capacity(sources.len());
+    for (index, source) in sources.iter().enumerate() {
+        let document = render_markdown(&source.content, highlighter, sanitize_level, options)?;
+        let html = strip_source_lines(&document.html).replace(TOC_MARKER, &render_toc_html(&document.toc));
+        let (body, properties) = to_xhtml(&html, source.path.as_deref(), &chapter_files, &mut images)?;
+
+        let front_matter_title = document.front_matter.as_ref().and_then(|front_matter| front_matter.title.clone());
+        let (chapter_title, title_from_heading) = match (front_matter_title, document.toc.as_slice()) {
+            (Some(chapter_title), _) => (chapter_title, false),
+            (None, [heading]) if !heading.text.is_empty() => (heading.text.clone(), true),
+            (None, _) => {
+                let file_stem = source.path.as_deref().and_then(Path::file_stem);
+                (file_stem.map_or_else(|| title.to_string(), |stem| stem.to_string_lossy().to_string()), false)
+            }
+        };
+
+        chapters.push(Chapter {
+            file_name: chapter_file_name(index),
+            title: chapter_title,
+            title_from_heading,
+            toc: document.toc,
+            front_matter: document.front_matter,
+            body,
+            properties,
+        });
+    }
+
+    let first_front_matter = chapters[0].front_matter.as_ref();
+    let book_title = first_front_matter
+        .and_then(|front_matter| front_matter.title.as_deref())
+        .unwrap_or(title);
+    let language = first_front_matter
+        .and_then(|front_matter| front_matter.fields.get("lang").or_else(|| front_matter.fields.get("language")))
+        .and_then(|language| language.as_str())
+        .unwrap_or("en");
+
+    let mut parts: Vec<(String, Vec<u8>)> = vec![
+        ("mimetype".into(), b"application/epub+zip".to_vec()),
+        ("META-INF/container.xml".into(), CONTAINER_XML.into()),
+        ("OEBPS/content.opf".into(), package_document(book_title, language, sources, &chapters, &images).into_bytes()),
+        ("OEBPS/nav.xhtml".into(), navigation_document(book_title, language, &chapters).into_bytes()),
+        ("OEBPS/style.css".into(), format!("{}{}", EPUB_CSS, highlighter.theme_css(ExportTheme::Light)).into_bytes()),
+    ];
+    for chapter in &chapters {
+        let xhtml = xhtml_document(&chapter.title, language, &chapter.body);
+        parts.push((format!("OEBPS/{}", chapter.file_name), xhtml.into_bytes()));
+    }
+    for image in images.images {
+        parts.push((format!("OEBPS/{}", image.href), image.data));
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // Readers recognise a book by an uncompressed mimetype entry at the very start
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (index, (name, data)) in parts.into_iter().enumerate() {
+        let options = if index == 0 { stored } else { deflated };
+        zip.start_file(name, options).map_err(|e| format!("Failed to write EPUB: {}", e))?;
+        zip.write_all(&data).map_err(|e| format!("Failed to write EPUB: {}", e))?;
+    }
+    let cursor = zip.finish().map_err(|e| format!("Failed to write EPUB: {}", e))?;
+    Ok(cursor.into_inner())
+}
+
+// Markdown files of a folder in reading order: in each folder its README or index
+// first, then the other files by name, then its subfolders
+pub fn book_files(tree: &WorkspaceEntry) -> Vec<PathBuf> {
+    let is_index = |entry: &WorkspaceEntry| {
+        let stem = Path::new(&entry.name).file_stem().map(|stem| stem.to_string_lossy().to_lowercase());
+        stem.is_some_and(|stem| INDEX_FILE_STEMS.contains(&stem.as_str()))
+    };
+    let mut files: Vec<&WorkspaceEntry> = tree.children.iter().filter(|child| !child.is_dir).collect();
+    // The tree is sorted by name already, and the sort is stable
+    files.sort_by_key(|file| !is_index(file));
+
+    let mut paths: Vec<PathBuf> = files.iter().map(|file| PathBuf::from(&file.path)).collect();
+    for directory in tree.children.iter().filter(|child| child.is_dir) {
+        paths.extend(book_files(directory));
+    }
+    paths
+}
+
+fn chapter_file_name(index: usize) -> String {
+    format!("chapter-{}.xhtml", index + 1)
+}
+
+// Turn rendered HTML into well-formed XHTML for the book: void elements closed,
+// attributes and text re-escaped for XML (HTML entities such as `&nbsp;` don't exist
+// there), images copied into the book and links between its files pointed at chapters.
+// Returns the XHTML and the manifest properties the chapter needs.
+fn to_xhtml(
+    html: &str,
+    base_file: Option<&Path>,
+    chapter_files: &HashMap<String, String>,
+    images: &mut BookImages,
+) -> Result<(String, Vec<&'static str>), String> {
+    let base_dir = base_file.and_then(Path::parent);
+    let images = RefCell::new(images);
+    let has_math = Cell::new(false);
+    let has_svg = Cell::new(false);
+
+    let xhtml = rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("img", |el| {
+                let src = el.get_attribute("src").unwrap_or_default();
+                let src = html_escape::decode_html_entities(&src).to_string();
+                // The renderer percent-encodes paths, which the file system doesn't
+                let href = base_dir
+                    .and_then(|base_dir| {
+                        resolve_local_image(&src, base_dir).or_else(|| resolve_local_image(&percent_decode(&src), base_dir))
+                    })
+                    .and_then(|path| images.borrow_mut().add(path));
+                match href {
+                    Some(href) => el.set_attribute("src", &href)?,
+                    None => {
+                        let alt = el.get_attribute("alt").unwrap_or_default();
+                        el.replace(&html_escape::decode_html_entities(&alt), ContentType::Text);
+                    }
+                }
+                Ok(())
+            }),
+            element!("a[href]", |el| {
+                let href = el.get_attribute("href").unwrap_or_default();
+                let link = resolve_link(&html_escape::decode_html_entities(&href), base_file);
+                match link.kind {
+                    LinkKind::Anchor | LinkKind::External => {}
+                    LinkKind::Markdown => match link.path.as_ref().and_then(|path| chapter_files.get(path)) {
+                        Some(file_name) => {
+                            let target = match &link.anchor {
+                                Some(anchor) => format!("{}#{}", file_name, anchor),
+                                None => file_name.clone(),
+                            };
+                            el.set_attribute("href", &html_escape::encode_double_quoted_attribute(&target))?;
+                        }
+                        None => el.remove_attribute("href"),
+                    },
+                    // Files outside the book can't be opened from a reader, so the link text stays plain
+                    LinkKind::File | LinkKind::Broken => el.remove_attribute("href"),
+                }
+                Ok(())
+            }),
+            element!("*", |el| {
+                if el.removed() {
+                    return Ok(());
+                }
+                let tag_name = el.tag_name();
+                // XHTML element names are lowercase; SVG and MathML ones keep their case
+                if el.namespace_uri() == XHTML_NAMESPACE && el.tag_name_preserve_case() != tag_name {
+                    el.set_tag_name(&tag_name)?;
+                }
+
+                let attributes: Vec<(String, String)> = el.attributes().iter()
+                    .map(|attribute| {
+                        let value = html_escape::decode_html_entities(&attribute.value()).to_string();
+                        (attribute.name_preserve_case(), xml_escape(&value))
+                    })
+                    .collect();
+                for (name, value) in &attributes {
+                    el.set_attribute(name, value)?;
+                }
+
+                match tag_name.as_str() {
+                    "math" => {
+                        has_math.set(true);
+                        if !el.has_attribute("xmlns") {
+                            el.set_attribute("xmlns", MATHML_NAMESPACE)?;
+                        }
+                    }
+                    "svg" => {
+                        has_svg.set(true);
+                        if !el.has_attribute("xmlns") {
+                            el.set_attribute("xmlns", SVG_NAMESPACE)?;
+                        }
+                    }
+                    _ => {}
+                }
+
+                if VOID_ELEMENTS.contains(&tag_name.as_str()) && !el.is_self_closing() {
+                    let attributes: String = attributes.iter()
+                        .map(|(name, value)| format!(" {}=\"{}\"", name, value))
+                        .collect();
+                    el.replace(&format!("<{}{} />", tag_name, attributes), ContentType::Html);
+                }
+                Ok(())
+            }),
+        ],
+        document_content_handlers: vec![
+            doc_comments!(|comment| {
+                comment.remove();
+                Ok(())
+            }),
+            doc_text!(|text| {
+                let decoded = html_escape::decode_html_entities(text.as_str());
+                let escaped = xml_escape(&decoded);
+                if escaped != text.as_str() {
+                    text.replace(&escaped, ContentType::Html);
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| format!("Failed to convert chapter to XHTML: {}", e))?;
+
+    let mut properties = Vec::new();
+    if has_math.get() {
+        properties.push("mathml");
+    }
+    if has_svg.get() {
+        properties.push("svg");
+    }
+    Ok((xhtml, properties))
+}
+
+fn image_extension(media_type: &str) -> &'static str {
+    match media_type {
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/svg+xml" => "svg",
+        _ => "png",
+    }
+}
+
+fn xhtml_document(title: &str, language: &str, body: &str) -> String {
+    let language = xml_escape(language);
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n",
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{}\" xml:lang=\"{}\">\n",
+            "<head>\n<meta charset=\"UTF-8\" />\n<title>{}</title>\n",
+            "<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\" />\n</head>\n",
+            "<body>\n{}</body>\n</html>\n",
+        ),
+        language, language, xml_escape(title), body
+    )
+}
+
+// The table of contents readers show. A single file lists its headings; a book of
+// several files lists each chapter with its headings nested below.
+fn navigation_document(book_title: &str, language: &str, chapters: &[Chapter]) -> String {
+    let mut items = String::new();
+    if let [chapter] = chapters {
+        navigation_items(&chapter.file_name, &chapter.toc, &mut items);
+    }
+    if items.is_empty() {
+        for chapter in chapters {
+            items.push_str(&format!("<li><a href=\"{}\">{}</a>", chapter.file_name, xml_escape(&chapter.title)));
+            let headings = match chapter.toc.as_slice() {
+                [heading] if chapter.title_from_heading => heading.children.as_slice(),
+                headings => headings,
+            };
+            if !headings.is_empty() {
+                items.push_str("\n<ol>\n");
+                navigation_items(&chapter.file_name, headings, &mut items);
+                items.push_str("</ol>\n");
+            }
+            items.push_str("</li>\n");
+        }
+    }
+
+    let body = format!(
+        "<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n{}</ol>\n</nav>\n",
+        items
+    );
+    xhtml_document(book_title, language, &body)
+}
+
+fn navigation_items(file_name: &str, entries: &[TocEntry], items: &mut String) {
+    for entry in entries {
+        // Every entry needs a label; headings made of an image alone have no text
+        let text = if entry.text.is_empty() { &entry.slug } else { &entry.text };
+        items.push_str(&format!(
+            "<li><a href=\"{}#{}\">{}</a>",
+            file_name,
+            xml_escape(&entry.slug),
+            xml_escape(text)
+        ));
+        if !entry.children.is_empty() {
+            items.push_str("\n<ol>\n");
+            navigation_items(file_name, &entry.children, items);
+            items.push_str("</ol>\n");
+        }
+        items.push_str("</li>\n");
+    }
+}
+
+fn package_document(
+    book_title: &str,
+    language: &str,
+    sources: &[BookSource],
+    chapters: &[Chapter],
+    images: &BookImages,
+) -> String {
+    let mut metadata = format!(
+        concat!(
+            "<dc:identifier id=\"book-id\">{}</dc:identifier>\n",
+            "<dc:title>{}</dc:title>\n<dc:language>{}</dc:language>\n",
+            "<meta property=\"dcterms:modified\">{}</meta>\n",
+        ),
+        book_identifier(book_title, sources),
+        xml_escape(book_title),
+        xml_escape(language),
+        utc_timestamp(SystemTime::now())
+    );
+    let front_matter = chapters.iter().filter_map(|chapter| chapter.front_matter.as_ref());
+    if let Some(author) = front_matter.clone().find_map(|front_matter| front_matter.author.as_deref()) {
+        metadata.push_str(&format!("<dc:creator>{}</dc:creator>\n", xml_escape(author)));
+    }
+    if let Some(front_matter) = chapters[0].front_matter.as_ref() {
+        for tag in &front_matter.tags {
+            metadata.push_str(&format!("<dc:subject>{}</dc:subject>\n", xml_escape(tag)));
+        }
+    }
+
+    let mut manifest = String::from(concat!(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+        "<item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    ));
+    let mut spine = String::new();
+    for (index, chapter) in chapters.iter().enumerate() {
+        let properties = if chapter.properties.is_empty() {
+            String::new()
+        } else {
+            format!(" properties=\"{}\"", chapter.properties.join(" "))
+        };
+        manifest.push_str(&format!(
+            "<item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"{}/>\n",
+            index + 1, chapter.file_name, properties
+        ));
+        spine.push_str(&format!("<itemref idref=\"chapter-{}\"/>\n", index + 1));
+    }
+    for (index, image) in images.images.iter().enumerate() {
+        manifest.push_str(&format!(
+            "<item id=\"image-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            index + 1, image.href, image.media_type
+        ));
+    }
+
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n",
+            "<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}</metadata>\n",
+            "<manifest>\n{}</manifest>\n<spine>\n{}</spine>\n</package>\n",
+        ),
+        metadata, manifest, spine
+    )
+}
+
+// A UUID derived from the title and files, so exporting the same book again replaces
+// it in a reader's library instead of adding a copy
+fn book_identifier(book_title: &str, sources: &[BookSource]) -> String {
+    let half = |seed: u8| {
+        let mut hasher = DefaultHasher::new();
+        seed.hash(&mut hasher);
+        book_title.hash(&mut hasher);
+        for source in sources {
+            source.path.hash(&mut hasher);
+        }
+        hasher.finish()
+    };
+    let (high, low) = (half(0), half(1));
+    // Version 8 (custom) and the RFC 9562 variant bits
+    let high = (high & !0xF000) | 0x8000;
+    let low = (low & !(0b11 << 62)) | (0b10 << 62);
+    format!(
+        "urn:uuid:{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32, (high >> 16) & 0xFFFF, high & 0xFFFF, low >> 48, low & 0xFFFF_FFFF_FFFF
+    )
+}
+
+// `2024-05-01T12:00:00Z`, the only form dcterms:modified accepts
+fn utc_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+    let (days, time_of_day) = (seconds / 86_400, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's days_to_civil)
+    let shifted = days as i64 + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, time_of_day / 3_600, time_of_day / 60 % 60, time_of_day % 60
+    )
+}
+
+const CONTAINER_XML: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n",
+    "<rootfiles><rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/></rootfiles>\n",
+    "</container>\n",
+);
+
+// Readers apply their own fonts and page colours, so this only shapes the content
+const EPUB_CSS: &str = "\
+h1, h2, h3, h4, h5, h6 { line-height: 1.25; margin: 1.2em 0 0.5em; page-break-after: avoid; }
+p, ul, ol, blockquote, table, pre { margin: 0 0 1em; }
+img { max-width: 100%; }
+blockquote { margin-left: 0; padding-left: 1em; border-left: 0.25em solid #ccc; }
+code, kbd, pre { font-family: monospace; font-size: 0.9em; }
+pre { white-space: pre-wrap; padding: 0.75em; background: #f6f8fa; border-radius: 0.25em; }
+pre .highlighted-line { display: block; background: rgba(255, 213, 79, 0.25); }
+.code-block-title { font-family: monospace; font-size: 0.8em; padding: 0.3em 0.75em; background: #e9ecef; }
+.code-block pre { margin-top: 0; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+li > input[type=\"checkbox\"] { margin-right: 0.4em; }
+.math-display { display: block; margin: 1em 0; text-align: center; }
+.markdown-alert { padding: 0.5em 1em; margin-bottom: 1em; border-left: 0.25em solid #888; }
+.markdown-alert-title { font-weight: bold; margin-bottom: 0.25em; }
+.markdown-alert-note { border-color: #0969da; }
+.markdown-alert-tip { border-color: #1a7f37; }
+.markdown-alert-important { border-color: #8250df; }
+.markdown-alert-warning { border-color: #9a6700; }
+.markdown-alert-caution { border-color: #cf222e; }
+.table-of-contents ul { padding-left: 1.25em; }
+.footnote-definition { font-size: 0.9em; }
+nav#toc ol { list-style: none; padding-left: 1em; }
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_matter::FrontMatterFormat;
+    use std::fs;
+
+    fn file(path: &str) -> WorkspaceEntry {
+        let name = Path::new(path).file_name().unwrap().to_string_lossy().to_string();
+        WorkspaceEntry { name, path: path.to_string(), is_dir: false, title: None, children: Vec::new(), truncated: false }
+    }
+
+    fn folder(path: &str, children: Vec<WorkspaceEntry>) -> WorkspaceEntry {
+        WorkspaceEntry { is_dir: true, children, ..file(path) }
+    }
+
+    fn heading(level: u8, text: &str, children: Vec<TocEntry>) -> TocEntry {
+        let slug = text.to_lowercase().replace(' ', "-");
+        TocEntry { level, text: text.to_string(), slug, line: 1, children }
+    }
+
+    fn chapter(index: usize, title: &str, title_from_heading: bool, toc: Vec<TocEntry>) -> Chapter {
+        Chapter {
+            file_name: chapter_file_name(index),
+            title: title.to_string(),
+            title_from_heading,
+            toc,
+            front_matter: None,
+            body: String::new(),
+            properties: Vec::new(),
+        }
+    }
+
+    // A folder with `guide.md`, `other.md`, `docs/picture.png` and `outside.png` one level up
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-epub-{}-{}", name, std::process::id()));
+        fs::create_dir_all(dir.join("book").join("docs")).unwrap();
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x01\0\0\0\x01";
+        fs::write(dir.join("book").join("docs").join("picture.png"), png).unwrap();
+        fs::write(dir.join("outside.png"), png).unwrap();
+        for name in ["guide.md", "other.md"] {
+            fs::write(dir.join("book").join(name), "# x\n").unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn reads_index_files_then_files_then_folders() {
+        let tree = folder("/book", vec![
+            folder("/book/guide", vec![file("/book/guide/intro.md"), file("/book/guide/Readme.md")]),
+            file("/book/alpha.md"),
+            file("/book/index.md"),
+            file("/book/zeta.md"),
+        ]);
+        let order: Vec<PathBuf> = ["index.md", "alpha.md", "zeta.md", "guide/Readme.md", "guide/intro.md"].iter()
+            .map(|path| Path::new("/book").join(path))
+            .collect();
+        assert_eq!(book_files(&tree), order);
+    }
+
+    #[test]
+    fn closes_void_elements_and_escapes_for_xml() {
+        let html = "<P>a&nbsp;b &amp; c<br>d</P><hr><img src=\"https://example.com/a.png\" alt=\"A &amp; B\"><!-- note -->";
+        let (xhtml, properties) = to_xhtml(html, None, &HashMap::new(), &mut BookImages::default()).unwrap();
+        assert_eq!(xhtml, "<p>a\u{a0}b &amp; c<br />d</p><hr />A &amp; B");
+        assert!(properties.is_empty());
+
+        let (xhtml, properties) = to_xhtml("<math display=\"block\"><mi>x</mi></math>", None, &HashMap::new(), &mut BookImages::default()).unwrap();
+        assert!(xhtml.starts_with(&format!("<math display=\"block\" xmlns=\"{}\">", MATHML_NAMESPACE)));
+        assert_eq!(properties, vec!["mathml"]);
+    }
+
+    #[test]
+    fn points_links_between_files_at_chapters() {
+        let dir = fixture("links");
+        let guide = dir.join("book").join("guide.md");
+        let chapter_files = HashMap::from([
+            (guide.to_string_lossy().to_string(), chapter_file_name(0)),
+            (dir.join("book").join("other.md").to_string_lossy().to_string(), chapter_file_name(1)),
+        ]);
+
+        let html = concat!(
+            "<a href=\"other.md#set-up\">a</a><a href=\"#top\">b</a><a href=\"https://example.com\">c</a>",
+            "<a href=\"docs/picture.png\">d</a><a href=\"missing.md\">e</a>",
+        );
+        let (xhtml, _) = to_xhtml(html, Some(&guide), &chapter_files, &mut BookImages::default()).unwrap();
+        assert_eq!(xhtml, concat!(
+            "<a href=\"chapter-2.xhtml#set-up\">a</a><a href=\"#top\">b</a><a href=\"https://example.com\">c</a>",
+            "<a>d</a><a>e</a>",
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn copies_local_images_and_replaces_others_by_alt_text() {
+        let dir = fixture("images");
+        let guide = dir.join("book").join("guide.md");
+        let mut images = BookImages::default();
+
+        let html = concat!(
+            "<img src=\"docs/picture.png\" alt=\"inside\"><img src=\"./docs/picture.png\" alt=\"again\">",
+            "<img src=\"../outside.png\" alt=\"outside\"><img src=\"missing.png\" alt=\"missing\">",
+        );
+        let (xhtml, _) = to_xhtml(html, Some(&guide), &HashMap::new(), &mut images).unwrap();
+        assert_eq!(xhtml, concat!(
+            "<img src=\"images/image-1.png\" alt=\"inside\" /><img src=\"images/image-1.png\" alt=\"again\" />",
+            "outsidemissing",
+        ));
+        assert_eq!(images.images.len(), 1);
+        assert_eq!(images.images[0].media_type, "image/png");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lists_chapters_images_and_metadata_in_the_package() {
+        let mut first = chapter(0, "Intro", false, Vec::new());
+        first.front_matter = Some(FrontMatter {
+            format: FrontMatterFormat::Yaml,
+            title: Some("Book".to_string()),
+            author: Some("Ada & Co".to_string()),
+            date: None,
+            tags: vec!["notes".to_string()],
+            fields: Default::default(),
+        });
+        let mut second = chapter(1, "Math", false, Vec::new());
+        second.properties = vec!["mathml", "svg"];
+        let images = BookImages {
+            images: vec![BookImage { href: "images/image-1.png".to_string(), media_type: "image/png", data: Vec::new() }],
+            ..BookImages::default()
+        };
+        let sources = [BookSource { path: None, content: String::new() }];
+
+        let package = package_document("Book", "de", &sources, &[first, second], &images);
+        assert!(package.contains(&format!("<dc:identifier id=\"book-id\">{}</dc:identifier>", book_identifier("Book", &sources))));
+        assert!(package.contains("<dc:title>Book</dc:title>\n<dc:language>de</dc:language>"));
+        assert!(package.contains("<dc:creator>Ada &amp; Co</dc:creator>\n<dc:subject>notes</dc:subject>"));
+        assert!(package.contains("<item id=\"chapter-1\" href=\"chapter-1.xhtml\" media-type=\"application/xhtml+xml\"/>"));
+        assert!(package.contains("<item id=\"chapter-2\" href=\"chapter-2.xhtml\" media-type=\"application/xhtml+xml\" properties=\"mathml svg\"/>"));
+        assert!(package.contains("<item id=\"image-1\" href=\"images/image-1.png\" media-type=\"image/png\"/>"));
+        assert!(package.contains("<spine>\n<itemref idref=\"chapter-1\"/>\n<itemref idref=\"chapter-2\"/>\n</spine>"));
+    }
+
+    #[test]
+    fn nests_headings_below_chapters_in_the_navigation() {
+        let chapters = [
+            // Titled by its only top-level heading, which isn't repeated below it
+            chapter(0, "Guide", true, vec![heading(1, "Guide", vec![heading(2, "Set up", Vec::new())])]),
+            chapter(1, "notes", false, Vec::new()),
+        ];
+        let navigation = navigation_document("Book", "en", &chapters);
+        assert!(navigation.contains(concat!(
+            "<ol>\n<li><a href=\"chapter-1.xhtml\">Guide</a>\n<ol>\n",
+            "<li><a href=\"chapter-1.xhtml#set-up\">Set up</a></li>\n</ol>\n</li>\n",
+            "<li><a href=\"chapter-2.xhtml\">notes</a></li>\n</ol>",
+        )));
+
+        // A single file lists its headings directly
+        let navigation = navigation_document("Book", "en", &chapters[..1]);
+        assert!(navigation.contains("<ol>\n<li><a href=\"chapter-1.xhtml#guide\">Guide</a>\n<ol>\n<li><a href=\"chapter-1.xhtml#set-up\">Set up</a></li>\n</ol>\n</li>\n</ol>"));
+        assert!(navigation.contains("<title>Book</title>"));
+    }
+}
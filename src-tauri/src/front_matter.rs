@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// Front matter larger than this is treated as ordinary content
+const MAX_FRONT_MATTER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontMatterFormat {
+    Yaml,
+    Toml,
+}
+
+// Metadata block from the top of a document, with the common fields pulled out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrontMatter {
+    pub format: FrontMatterFormat,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub fields: Map<String, Value>,
+}
+
+// Split `---` YAML or `+++` TOML front matter from the document body.
+// Returns the parsed metadata and the byte offset where the body starts.
+pub fn extract_front_matter(content: &str) -> (Option<FrontMatter>, usize) {
+    let bom = if content.starts_with('\u{feff}') { '\u{feff}'.len_utf8() } else { 0 };
+    let text = &content[bom..];
+
+    let (format, fence, closing_fences): (_, _, &[&str]) = if text.starts_with("---") {
+        (FrontMatterFormat::Yaml, "---", &["---", "..."])
+    } else if text.starts_with("+++") {
+        (FrontMatterFormat::Toml, "+++", &["+++"])
+    } else {
+        return (None, 0);
+    };
+
+    // The opening fence must be alone on the first line
+    let Some(first_line_end) = text.find('\n') else { return (None, 0) };
+    if text[..first_line_end].trim_end() != fence {
+        return (None, 0);
+    }
+
+    // Find the closing fence line
+    let mut offset = first_line_end + 1;
+    let mut body_start = None;
+    let mut block_end = offset;
+    for line in text[offset..].split_inclusive('\n') {
+        if closing_fences.contains(&line.trim_end()) {
+            block_end = offset;
+            body_start = Some(offset + line.len());
+            break;
+        }
+        offset += line.len();
+        if offset > MAX_FRONT_MATTER_SIZE {
+            return (None, 0);
+        }
+    }
+    let Some(body_start) = body_start else { return (None, 0) };
+
+    let block = &text[first_line_end + 1..block_end];
+    let fields = match format {
+        FrontMatterFormat::Yaml => serde_yaml::from_str::<Value>(block).ok(),
+        FrontMatterFormat::Toml => block.parse::<toml::Table>().ok().map(|table| toml_to_json(toml::Value::Table(table))),
+    };
+
+    // Only strip the block if it really was a metadata map; `---` on the first line
+    // can also be a thematic break
+    match fields {
+        Some(Value::Object(fields)) => (Some(FrontMatter::from_fields(format, fields)), bom + body_start),
+        _ => (None, 0),
+    }
+}
+
+impl FrontMatter {
+    fn from_fields(format: FrontMatterFormat, fields: Map<String, Value>) -> Self {
+        let title = fields.get("title").and_then(value_to_string);
+        let date = fields.get("date").and_then(value_to_string);
+        let author = fields.get("author")
+            .or_else(|| fields.get("authors"))
+            .and_then(|value| match value {
+                Value::Array(items) => {
+                    let names: Vec<String> = items.iter().filter_map(author_name).collect();
+                    if names.is_empty() { None } else { Some(names.join(", ")) }
+                }
+                other => author_name(other),
+            });
+        let tags = fields.get("tags")
+            .or_else(|| fields.get("keywords"))
+            .map(|value| match value {
+                Value::Array(items) => items.iter().filter_map(value_to_string).collect(),
+                Value::String(list) => list.split(',')
+                    .map(|tag| tag.trim().to_string())
+                    .filter(|tag| !tag.is_empty())
+                    .collect(),
+                _ => Vec::new(),
+            })
+            .unwrap_or_default();
+
+        Self { format, title, author, date, tags, fields }
+    }
+}
+
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) if !text.trim().is_empty() => Some(text.trim().to_string()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+// Authors may be plain strings or `{ name = "..." }` tables
+fn author_name(value: &Value) -> Option<String> {
+    match value {
+        Value::Object(author) => author.get("name").and_then(value_to_string),
+        other => value_to_string(other),
+    }
+}
+
+// TOML datetimes have no JSON equivalent, so they become strings
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(text) => Value::String(text),
+        toml::Value::Integer(number) => Value::from(number),
+        toml::Value::Float(number) => Value::from(number),
+        toml::Value::Boolean(flag) => Value::Bool(flag),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_yaml_front_matter() {
+        let content = "---\ntitle: Guide\nauthor: Ada\ndate: 2024-05-01\ntags: [a, b]\n---\n# Body\n";
+        let (front_matter, body_start) = extract_front_matter(content);
+        let front_matter = front_matter.unwrap();
+        assert_eq!(front_matter.format, FrontMatterFormat::Yaml);
+        assert_eq!(front_matter.title.as_deref(), Some("Guide"));
+        assert_eq!(front_matter.author.as_deref(), Some("Ada"));
+        assert_eq!(front_matter.date.as_deref(), Some("2024-05-01"));
+        assert_eq!(front_matter.tags, ["a", "b"]);
+        assert_eq!(&content[body_start..], "# Body\n");
+    }
+
+    #[test]
+    fn parses_toml_front_matter() {
+        let content = "+++\ntitle = \"Notes\"\ndate = 2024-05-01T10:00:00Z\nkeywords = \"x, y\"\n[[authors]]\nname = \"Ada\"\n[[authors]]\nname = \"Grace\"\n+++\nBody";
+        let (front_matter, body_start) = extract_front_matter(content);
+        let front_matter = front_matter.unwrap();
+        assert_eq!(front_matter.format, FrontMatterFormat::Toml);
+        assert_eq!(front_matter.title.as_deref(), Some("Notes"));
+        assert_eq!(front_matter.date.as_deref(), Some("2024-05-01T10:00:00Z"));
+        assert_eq!(front_matter.author.as_deref(), Some("Ada, Grace"));
+        assert_eq!(front_matter.tags, ["x", "y"]);
+        assert_eq!(&content[body_start..], "Body");
+    }
+
+    #[test]
+    fn accepts_byte_order_mark_and_yaml_end_marker() {
+        let content = "\u{feff}---\r\ntitle: 1\r\n...\r\nBody";
+        let (front_matter, body_start) = extract_front_matter(content);
+        assert_eq!(front_matter.unwrap().title.as_deref(), Some("1"));
+        assert_eq!(&content[body_start..], "Body");
+    }
+
+    #[test]
+    fn leaves_malformed_front_matter_as_content() {
+        for content in [
+            "---\ntitle: [unclosed\n---\nBody",
+            "+++\ntitle = \n+++\nBody",
+            "---\ntitle: Never closed\n",
+            "--- title: x\n---\n",
+            "---\njust a sentence\n---\n",
+            "Text\n---\ntitle: x\n---\n",
+        ] {
+            assert_eq!(extract_front_matter(content).1, 0, "{:?}", content);
+            assert!(extract_front_matter(content).0.is_none());
+        }
+    }
+
+    #[test]
+    fn ignores_oversized_front_matter() {
+        let content = format!("---\n{}---\nBody", "key: value\n".repeat(MAX_FRONT_MATTER_SIZE / 10));
+        assert!(extract_front_matter(&content).0.is_none());
+    }
+
+    #[test]
+    fn keeps_every_field() {
+        let (front_matter, _) = extract_front_matter("---\ntitle: \"  \"\ndraft: true\n---\n");
+        let front_matter = front_matter.unwrap();
+        assert_eq!(front_matter.title, None);
+        assert_eq!(front_matter.fields.get("draft"), Some(&Value::Bool(true)));
+    }
+}
@@ -0,0 +1,368 @@
+use std::ops::Range;
+use pulldown_cmark::{CowStr, Event, LinkType, Options, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::markdown::SourceEvent;
+
+// Raw HTML tags GitHub neutralises with its tagfilter extension
+const FILTERED_TAGS: &[&str] = &[
+    "title", "textarea", "style", "xmp", "iframe", "noembed", "noframes", "script", "plaintext",
+];
+
+// Characters trimmed from the end of an autolink, as in the GFM spec
+const TRAILING_PUNCTUATION: &[char] = &['?', '!', '.', ',', ':', '*', '_', '~'];
+
+// Optional markdown extensions. Defaults match GitHub's README rendering;
+// a document can override them with a `markdown:` table in its front matter,
+// written either as `smartPunctuation` or `smart_punctuation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MarkdownOptions {
+    // Link bare `https://`, `www.` and email addresses
+    pub autolinks: bool,
+    // Curly quotes, en/em dashes and ellipses
+    pub smart_punctuation: bool,
+    // `# Heading {#id .class}` syntax
+    pub heading_attributes: bool,
+    // Escape the raw HTML tags in FILTERED_TAGS instead of passing them through
+    pub tag_filter: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self { autolinks: true, smart_punctuation: false, heading_attributes: false, tag_filter: true }
+    }
+}
+
+impl MarkdownOptions {
+    // Apply per-document overrides; unknown keys and invalid values are ignored
+    pub fn with_overrides(self, overrides: Option<&Value>) -> Self {
+        let Some(Value::Object(overrides)) = overrides else { return self };
+        let Ok(Value::Object(mut merged)) = serde_json::to_value(self) else { return self };
+
+        for (key, value) in overrides {
+            if value.is_boolean() {
+                merged.insert(camel_case(key), value.clone());
+            }
+        }
+        serde_json::from_value(Value::Object(merged)).unwrap_or(self)
+    }
+
+    // pulldown-cmark flags for this configuration. New parser extensions should be
+    // wired up here so that upgrading pulldown-cmark only touches this function.
+    pub fn parser_options(&self) -> Options {
+        let mut options = Options::empty();
+        options.insert(Options::ENABLE_STRIKETHROUGH);
+        options.insert(Options::ENABLE_TABLES);
+        options.insert(Options::ENABLE_FOOTNOTES);
+        options.insert(Options::ENABLE_TASKLISTS);
+        options.insert(Options::ENABLE_MATH);
+        options.insert(Options::ENABLE_GFM);
+        options.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        options.set(Options::ENABLE_HEADING_ATTRIBUTES, self.heading_attributes);
+        options
+    }
+}
+
+fn camel_case(key: &str) -> String {
+    let mut parts = key.split(['_', '-']);
+    let mut output = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            output.push(first.to_ascii_uppercase());
+            output.push_str(chars.as_str());
+        }
+    }
+    output
+}
+
+// Turn bare URLs and email addresses in text into links (GFM "extended autolinks")
+pub fn autolink_events(events: Vec<SourceEvent<'_>>) -> Vec<SourceEvent<'_>> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut link_depth = 0usize;
+    let mut in_code_block = false;
+    let mut pending: Option<(String, Range<usize>)> = None;
+
+    for (event, range) in events {
+        if let Event::Text(text) = &event {
+            if link_depth == 0 && !in_code_block {
+                // pulldown-cmark splits text at delimiters, so join runs before scanning
+                match pending.as_mut() {
+                    Some((buffer, pending_range)) => {
+                        buffer.push_str(text);
+                        pending_range.end = range.end;
+                    }
+                    None => pending = Some((text.to_string(), range)),
+                }
+                continue;
+            }
+        }
+
+        if let Some((text, text_range)) = pending.take() {
+            push_linkified(&mut output, text, text_range);
+        }
+
+        match &event {
+            Event::Start(Tag::Link { .. } | Tag::Image { .. }) => link_depth += 1,
+            Event::End(TagEnd::Link | TagEnd::Image) => link_depth = link_depth.saturating_sub(1),
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            _ => {}
+        }
+        output.push((event, range));
+    }
+
+    if let Some((text, text_range)) = pending {
+        push_linkified(&mut output, text, text_range);
+    }
+    output
+}
+
+fn push_linkified<'a>(output: &mut Vec<SourceEvent<'a>>, text: String, range: Range<usize>) {
+    let mut last = 0;
+    let mut search = 0;
+
+    while let Some((start, end, link_type)) = find_autolink(&text, search) {
+        if start > last {
+            output.push((Event::Text(CowStr::from(text[last..start].to_string())), range.clone()));
+        }
+
+        let label = &text[start..end];
+        // The HTML writer adds `mailto:` to email links itself
+        let url = if link_type == LinkType::Autolink && label[..4].eq_ignore_ascii_case("www.") {
+            format!("http://{}", label)
+        } else {
+            label.to_string()
+        };
+        output.push((Event::Start(Tag::Link {
+            link_type,
+            dest_url: CowStr::from(url),
+            title: CowStr::from(""),
+            id: CowStr::from(""),
+        }), range.clone()));
+        output.push((Event::Text(CowStr::from(label.to_string())), range.clone()));
+        output.push((Event::End(TagEnd::Link), range.clone()));
+
+        last = end;
+        search = end;
+    }
+
+    if last == 0 {
+        output.push((Event::Text(CowStr::from(text)), range));
+    } else if last < text.len() {
+        output.push((Event::Text(CowStr::from(text[last..].to_string())), range));
+    }
+}
+
+// Find the next autolink at or after `from`, returning its byte span and kind
+fn find_autolink(text: &str, from: usize) -> Option<(usize, usize, LinkType)> {
+    let mut index = from;
+    while index < text.len() {
+        let rest = &text[index..];
+        let boundary = text[..index].chars().next_back()
+            .is_none_or(|c| c.is_whitespace() || matches!(c, '*' | '_' | '~' | '('));
+
+        if boundary {
+            let scheme_len = ["https://", "http://", "www."].iter()
+                .find(|scheme| rest.get(..scheme.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme)))
+                .map(|scheme| scheme.len());
+            if let Some(scheme_len) = scheme_len {
+                if let Some(end) = url_end(text, index, scheme_len) {
+                    return Some((index, end, LinkType::Autolink));
+                }
+            }
+        }
+
+        if rest.starts_with('@') {
+            if let Some((start, end)) = email_span(text, index) {
+                if start >= from {
+                    return Some((start, end, LinkType::Email));
+                }
+            }
+        }
+
+        index += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+// End of a URL whose scheme (or `www.`) starts at `start`
+fn url_end(text: &str, start: usize, scheme_len: usize) -> Option<usize> {
+    let domain_start = start + scheme_len;
+    let domain_len = text[domain_start..]
+        .find(|c: char| !(c.is_alphanumeric() || matches!(c, '.' | '-' | '_')))
+        .unwrap_or(text.len() - domain_start);
+    let domain = &text[domain_start..domain_start + domain_len];
+    if !valid_domain(domain, text[start..domain_start].eq_ignore_ascii_case("www.")) {
+        return None;
+    }
+
+    let path_len = text[domain_start..]
+        .find(|c: char| c.is_whitespace() || c == '<')
+        .unwrap_or(text.len() - domain_start);
+    let end = trim_trailing(&text[start..domain_start + path_len]);
+    (end > scheme_len).then_some(start + end)
+}
+
+// At least one dot (for `www.` the prefix counts) and no underscores in the last two labels
+fn valid_domain(domain: &str, is_www: bool) -> bool {
+    let domain = domain.trim_end_matches(TRAILING_PUNCTUATION);
+    if domain.is_empty() || (!is_www && !domain.contains('.')) {
+        return false;
+    }
+    domain.rsplit('.').take(2).all(|label| !label.contains('_'))
+}
+
+// Apply the GFM trailing punctuation, parenthesis and entity rules; returns the kept length
+fn trim_trailing(candidate: &str) -> usize {
+    let mut end = candidate.len();
+    loop {
+        let current = &candidate[..end];
+        let unbalanced_paren = current.ends_with(')') && current.matches(')').count() > current.matches('(').count();
+        if current.ends_with(TRAILING_PUNCTUATION) || unbalanced_paren {
+            end -= 1;
+            continue;
+        }
+
+        // Drop something that looks like an entity reference, e.g. `&amp;`
+        let Some(without) = current.strip_suffix(';') else { break };
+        match without.rfind('&') {
+            Some(amp) if amp + 1 < without.len() && without[amp + 1..].chars().all(|c| c.is_ascii_alphanumeric()) => end = amp,
+            _ => break,
+        }
+    }
+    end
+}
+
+// Span of an email address around the `@` at `at`
+fn email_span(text: &str, at: usize) -> Option<(usize, usize)> {
+    let local_start = text[..at]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '+')))
+        .map_or(0, |(index, c)| index + c.len_utf8());
+    if local_start == at {
+        return None;
+    }
+
+    let domain_len = text[at + 1..]
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_')))
+        .unwrap_or(text.len() - at - 1);
+    let domain = text[at + 1..at + 1 + domain_len].trim_end_matches('.');
+    if !domain.contains('.') || domain.ends_with(['-', '_']) || domain.starts_with('.') {
+        return None;
+    }
+
+    Some((local_start, at + 1 + domain.len()))
+}
+
+// Escape the opening `<` of filtered tags in raw HTML, like GitHub's tagfilter
+pub fn filter_raw_html(events: Vec<SourceEvent<'_>>) -> Vec<SourceEvent<'_>> {
+    events.into_iter()
+        .map(|(event, range)| match event {
+            Event::Html(html) => (Event::Html(filter_tags(html)), range),
+            Event::InlineHtml(html) => (Event::InlineHtml(filter_tags(html)), range),
+            other => (other, range),
+        })
+        .collect()
+}
+
+fn filter_tags(html: CowStr<'_>) -> CowStr<'_> {
+    let mut output = String::with_capacity(html.len());
+    let mut last = 0;
+
+    for (index, _) in html.match_indices('<') {
+        let rest = &html[index + 1..];
+        let name_start = usize::from(rest.starts_with('/'));
+        let name_len = rest[name_start..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len() - name_start);
+        let name = &rest[name_start..name_start + name_len];
+
+        let after = rest[name_start + name_len..].chars().next();
+        let terminated = after.is_none_or(|c| c.is_whitespace() || c == '>' || c == '/');
+        if terminated && FILTERED_TAGS.iter().any(|tag| tag.eq_ignore_ascii_case(name)) {
+            output.push_str(&html[last..index]);
+            output.push_str("&lt;");
+            last = index + 1;
+        }
+    }
+
+    if last == 0 {
+        return html;
+    }
+    output.push_str(&html[last..]);
+    CowStr::from(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Parser};
+
+    fn render(markdown: &str) -> String {
+        let options = MarkdownOptions::default();
+        let events = Parser::new_ext(markdown, options.parser_options()).into_offset_iter().collect();
+        let events = filter_raw_html(autolink_events(events));
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter().map(|(event, _)| event));
+        output
+    }
+
+    #[test]
+    fn links_bare_urls_and_www() {
+        assert_eq!(render("see https://example.com/a?b=1."),
+            "<p>see <a href=\"https://example.com/a?b=1\">https://example.com/a?b=1</a>.</p>\n");
+        assert_eq!(render("www.example.com"),
+            "<p><a href=\"http://www.example.com\">www.example.com</a></p>\n");
+    }
+
+    #[test]
+    fn trims_unbalanced_parens_and_entities() {
+        assert!(render("(https://example.com/wiki/A_(b))").contains("href=\"https://example.com/wiki/A_(b)\""));
+        assert_eq!(trim_trailing("https://example.com&amp;"), "https://example.com".len());
+    }
+
+    #[test]
+    fn ignores_urls_inside_words_links_and_code() {
+        assert_eq!(render("xhttps://example.com"), "<p>xhttps://example.com</p>\n");
+        assert_eq!(render("[https://a.com](https://b.com)").matches("<a ").count(), 1);
+        assert!(!render("```\nhttps://example.com\n```").contains("<a "));
+        assert!(!render("https://nodot").contains("<a "));
+    }
+
+    #[test]
+    fn links_email_addresses() {
+        assert_eq!(render("mail foo.bar+x@example.com."),
+            "<p>mail <a href=\"mailto:foo.bar+x@example.com\">foo.bar+x@example.com</a>.</p>\n");
+        assert!(!render("foo@localhost").contains("<a "));
+        assert!(!render("@example.com").contains("<a "));
+    }
+
+    #[test]
+    fn handles_non_ascii_text_around_autolinks() {
+        assert_eq!(render("café@example.com"), "<p>café@example.com</p>\n");
+        assert_eq!(render("ü@example.com"), "<p>ü@example.com</p>\n");
+        assert!(render("é a@example.com").contains("href=\"mailto:a@example.com\""));
+        assert!(render("naïve https://exämple.com ok").contains(">https://exämple.com</a>"));
+        assert!(!render("éhttps://example.com").contains("<a "));
+    }
+
+    #[test]
+    fn filters_dangerous_raw_tags() {
+        assert_eq!(filter_tags(CowStr::from("<script>x</script>")).as_ref(), "&lt;script>x&lt;/script>");
+        assert_eq!(filter_tags(CowStr::from("<SCRIPT src=a>")).as_ref(), "&lt;SCRIPT src=a>");
+        assert_eq!(filter_tags(CowStr::from("<scripts> <b>")).as_ref(), "<scripts> <b>");
+        assert_eq!(filter_tags(CowStr::from("<iframe/>")).as_ref(), "&lt;iframe/>");
+    }
+
+    #[test]
+    fn applies_front_matter_overrides() {
+        let overrides = serde_json::json!({ "smart_punctuation": true, "autolinks": "no", "unknown": true });
+        let options = MarkdownOptions::default().with_overrides(Some(&overrides));
+        assert!(options.smart_punctuation);
+        assert!(options.autolinks);
+    }
+}
@@ -0,0 +1,261 @@
+use std::path::{Path, PathBuf};
+
+use git2::{Commit, ObjectType, Oid, Repository, Sort, Status};
+use serde::Serialize;
+
+// Upper bounds on how far back history is searched and how many commits are listed
+const MAX_WALKED_COMMITS: usize = 20_000;
+const MAX_HISTORY_ENTRIES: usize = 500;
+
+// The repository a file belongs to and the file's state in it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitFileInfo {
+    pub repo_root: String,
+    pub relative_path: String,
+    // None when HEAD is detached or the repository has no commits yet
+    pub branch: Option<String>,
+    pub tracked: bool,
+    pub modified: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitInfo {
+    pub id: String,
+    pub short_id: String,
+    pub summary: String,
+    pub author: String,
+    pub email: String,
+    // Seconds since the Unix epoch
+    pub time: i64,
+}
+
+// A file inside the working tree of a local repository
+struct RepoFile {
+    repo: Repository,
+    relative_path: PathBuf,
+}
+
+// Find the repository enclosing a file. Only the local .git directory is read;
+// git2 is built without its network transports.
+fn open_repo_file(file_path: &Path) -> Result<Option<RepoFile>, String> {
+    let Some(parent) = file_path.parent() else {
+        return Ok(None);
+    };
+    let repo = match Repository::discover(parent) {
+        Ok(repo) => repo,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to open git repository: {}", e.message())),
+    };
+    // Bare repositories have no working tree for the file to be in
+    let Some(workdir) = repo.workdir() else {
+        return Ok(None);
+    };
+    let workdir = workdir.canonicalize()
+        .map_err(|e| format!("Failed to resolve repository directory: {}", e))?;
+    let Ok(relative_path) = file_path.strip_prefix(&workdir).map(Path::to_path_buf) else {
+        return Ok(None);
+    };
+    Ok(Some(RepoFile { repo, relative_path }))
+}
+
+pub fn file_info(file_path: &Path) -> Result<Option<GitFileInfo>, String> {
+    let Some(RepoFile { repo, relative_path }) = open_repo_file(file_path)? else {
+        return Ok(None);
+    };
+    let status = repo.status_file(&relative_path).unwrap_or(Status::WT_NEW);
+    let branch = repo.head().ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.shorthand().map(str::to_string));
+
+    Ok(Some(GitFileInfo {
+        repo_root: repo.workdir().unwrap_or(Path::new("")).to_string_lossy().to_string(),
+        relative_path: relative_path.to_string_lossy().to_string(),
+        branch,
+        tracked: !status.intersects(Status::WT_NEW | Status::IGNORED),
+        modified: status.intersects(Status::WT_MODIFIED | Status::INDEX_MODIFIED | Status::WT_DELETED),
+    }))
+}
+
+// Commits reachable from HEAD that changed the file, newest first
+pub fn file_history(file_path: &Path, limit: usize) -> Result<Vec<CommitInfo>, String> {
+    let Some(RepoFile { repo, relative_path }) = open_repo_file(file_path)? else {
+        return Ok(Vec::new());
+    };
+    let limit = limit.clamp(1, MAX_HISTORY_ENTRIES);
+    let git_error = |e: git2::Error| format!("Failed to read history: {}", e.message());
+
+    let mut walk = repo.revwalk().map_err(git_error)?;
+    if walk.push_head().is_err() {
+        // No commits yet
+        return Ok(Vec::new());
+    }
+    walk.set_sorting(Sort::TIME).map_err(git_error)?;
+
+    let mut history = Vec::new();
+    for oid in walk.take(MAX_WALKED_COMMITS) {
+        let commit = repo.find_commit(oid.map_err(git_error)?).map_err(git_error)?;
+        let blob = blob_id(&commit, &relative_path);
+        let parent_blob = commit.parents().next().and_then(|parent| blob_id(&parent, &relative_path));
+        if blob.is_some() && blob != parent_blob {
+            history.push(commit_info(&commit));
+            if history.len() == limit {
+                break;
+            }
+        }
+    }
+    Ok(history)
+}
+
+// The file's contents at a revision ("HEAD", a commit id, a branch name...), or an
+// empty document when it did not exist then
+pub fn file_at_revision(file_path: &Path, revision: &str) -> Result<(String, CommitInfo), String> {
+    let Some(RepoFile { repo, relative_path }) = open_repo_file(file_path)? else {
+        return Err("File is not in a git repository".to_string());
+    };
+    let commit = repo.revparse_single(revision)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|e| format!("Unknown revision '{}': {}", revision, e.message()))?;
+
+    let content = match blob_id(&commit, &relative_path) {
+        Some(id) => {
+            let blob = repo.find_blob(id).map_err(|e| format!("Failed to read file from git: {}", e.message()))?;
+            if blob.size() as u64 > crate::MAX_FILE_SIZE {
+                return Err(format!(
+                    "File too large: {:.1} MB (maximum allowed: {:.1} MB)",
+                    blob.size() as f64 / 1024.0 / 1024.0,
+                    crate::MAX_FILE_SIZE as f64 / 1024.0 / 1024.0
+                ));
+            }
+            String::from_utf8_lossy(blob.content()).into_owned()
+        }
+        None => String::new(),
+    };
+    Ok((content, commit_info(&commit)))
+}
+
+fn blob_id(commit: &Commit, relative_path: &Path) -> Option<Oid> {
+    let entry = commit.tree().ok()?.get_path(relative_path).ok()?;
+    (entry.kind() == Some(ObjectType::Blob)).then(|| entry.id())
+}
+
+fn commit_info(commit: &Commit) -> CommitInfo {
+    let author = commit.author();
+    let id = commit.id().to_string();
+    CommitInfo {
+        short_id: id.chars().take(7).collect(),
+        id,
+        summary: commit.summary().unwrap_or_default().to_string(),
+        author: author.name().unwrap_or_default().to_string(),
+        email: author.email().unwrap_or_default().to_string(),
+        time: commit.time().seconds(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Signature, Time};
+    use std::fs;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-git-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    // Write the files and commit them, a minute after the previous commit
+    fn commit(repo: &Repository, files: &[(&str, &str)], message: &str) -> Oid {
+        let workdir = repo.workdir().unwrap();
+        let mut index = repo.index().unwrap();
+        for (name, content) in files {
+            fs::write(workdir.join(name), content).unwrap();
+            index.add_path(Path::new(name)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let time = 1_700_000_000 + 60 * parent.as_ref().map_or(0, |parent| parent.parent_count() as i64 + 1);
+        let signature = Signature::new("Ada", "ada@example.com", &Time::new(time, 0)).unwrap();
+        let parents: Vec<&Commit> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents).unwrap()
+    }
+
+    // A repository where `a.md` changes in the second commit and `b.md` in the third
+    fn fixture(name: &str) -> (PathBuf, [Oid; 3]) {
+        let dir = temp_dir(name);
+        let repo = Repository::init(&dir).unwrap();
+        let first = commit(&repo, &[("a.md", "# One\n"), ("b.md", "# B\n")], "Add notes");
+        let second = commit(&repo, &[("a.md", "# Two\n")], "Edit a");
+        let third = commit(&repo, &[("b.md", "# B2\n")], "Edit b");
+        (dir, [first, second, third])
+    }
+
+    #[test]
+    fn lists_the_commits_that_changed_a_file() {
+        let (dir, [first, second, third]) = fixture("history");
+        let ids = |history: Vec<CommitInfo>| history.into_iter().map(|commit| commit.id).collect::<Vec<_>>();
+
+        let history = file_history(&dir.join("a.md"), 10).unwrap();
+        assert_eq!(history[0].summary, "Edit a");
+        assert_eq!((history[0].author.as_str(), history[0].email.as_str()), ("Ada", "ada@example.com"));
+        assert_eq!(history[0].short_id, second.to_string()[..7]);
+        assert_eq!(ids(history), vec![second.to_string(), first.to_string()]);
+        assert_eq!(ids(file_history(&dir.join("a.md"), 1).unwrap()), vec![second.to_string()]);
+        assert_eq!(ids(file_history(&dir.join("b.md"), 10).unwrap()), vec![third.to_string(), first.to_string()]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_files_at_a_revision() {
+        let (dir, [first, _, third]) = fixture("revision");
+        let file = dir.join("a.md");
+
+        let (content, commit) = file_at_revision(&file, "HEAD").unwrap();
+        assert_eq!((content.as_str(), commit.id), ("# Two\n", third.to_string()));
+        assert_eq!(file_at_revision(&file, &first.to_string()).unwrap().0, "# One\n");
+        assert_eq!(file_at_revision(&file, "HEAD~2").unwrap().0, "# One\n");
+        assert!(file_at_revision(&file, "no-such-branch").unwrap_err().starts_with("Unknown revision"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn handles_untracked_and_modified_files() {
+        let (dir, _) = fixture("untracked");
+        let untracked = dir.join("draft.md");
+        fs::write(&untracked, "# Draft\n").unwrap();
+        fs::write(dir.join("a.md"), "# Three\n").unwrap();
+
+        let info = file_info(&untracked).unwrap().unwrap();
+        assert_eq!((info.relative_path.as_str(), info.tracked, info.modified), ("draft.md", false, false));
+        assert!(file_history(&untracked, 10).unwrap().is_empty());
+        // A file that didn't exist at the revision reads as an empty document
+        assert_eq!(file_at_revision(&untracked, "HEAD").unwrap().0, "");
+
+        let info = file_info(&dir.join("a.md")).unwrap().unwrap();
+        assert!(info.tracked && info.modified);
+        assert!(info.branch.is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_files_outside_a_working_tree() {
+        let dir = temp_dir("outside");
+        let file = dir.join("notes.md");
+        fs::write(&file, "# Notes\n").unwrap();
+
+        // Not in any repository
+        assert!(file_info(&file).unwrap().is_none());
+        assert!(file_history(&file, 10).unwrap().is_empty());
+        assert_eq!(file_at_revision(&file, "HEAD").unwrap_err(), "File is not in a git repository");
+
+        // Inside a bare repository, which has no working tree
+        let bare = dir.join("bare.git");
+        Repository::init_bare(&bare).unwrap();
+        fs::write(bare.join("notes.md"), "# Notes\n").unwrap();
+        assert!(file_info(&bare.join("notes.md")).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
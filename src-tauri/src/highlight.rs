@@ -0,0 +1,517 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use serde::Serialize;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Style, Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, line_tokens_to_classed_spans, ClassStyle};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+use crate::ExportTheme;
+
+// Limit language identifier length
+const MAX_LANGUAGE_LENGTH: usize = 50;
+
+// Themes used until the user picks others
+const DEFAULT_LIGHT_THEME: &str = "InspiredGitHub";
+const DEFAULT_DARK_THEME: &str = "base16-ocean.dark";
+
+// Code blocks are marked up with scope classes and coloured by the stylesheet from
+// theme_css, so switching themes restyles them without rendering again
+const CLASS_PREFIX: &str = "syn-";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: CLASS_PREFIX };
+
+// Shared highlighting engine, managed as Tauri state next to TabState
+pub type HighlightState = Arc<HighlightEngine>;
+
+// Metadata parsed from a fenced code block info string,
+// e.g. `rust title="main.rs" {3-5}`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CodeBlockInfo {
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub highlight_lines: Vec<(usize, usize)>,
+    pub metadata: String,
+}
+
+impl CodeBlockInfo {
+    pub fn parse(info: &str) -> Self {
+        let info = info.trim();
+        let lang_end = info.find(|c: char| c.is_whitespace() || c == '{').unwrap_or(info.len());
+        let (lang_token, metadata) = info.split_at(lang_end);
+
+        // `rust,ignore` style tokens only use the part before the comma
+        let language = lang_token.split(',').next().unwrap_or("");
+        let language = if language.is_empty() || language.len() > MAX_LANGUAGE_LENGTH {
+            None
+        } else {
+            Some(language.to_string())
+        };
+
+        let metadata = metadata.trim().to_string();
+        let title = parse_title(&metadata);
+        let highlight_lines = parse_line_ranges(&metadata);
+
+        Self { language, title, highlight_lines, metadata }
+    }
+
+    pub fn is_line_highlighted(&self, line: usize) -> bool {
+        self.highlight_lines.iter().any(|&(start, end)| line >= start && line <= end)
+    }
+
+    // Attributes carried over to the rendered <pre> element
+    fn pre_attributes(&self) -> String {
+        let mut attrs = String::new();
+        if let Some(language) = &self.language {
+            attrs.push_str(&format!(" data-lang=\"{}\"", html_escape::encode_double_quoted_attribute(language)));
+        }
+        if let Some(title) = &self.title {
+            attrs.push_str(&format!(" data-title=\"{}\"", html_escape::encode_double_quoted_attribute(title)));
+        }
+        if !self.highlight_lines.is_empty() {
+            let ranges: Vec<String> = self.highlight_lines.iter()
+                .map(|&(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+                .collect();
+            attrs.push_str(&format!(" data-highlight-lines=\"{}\"", ranges.join(",")));
+        }
+        attrs
+    }
+}
+
+// Extract `title="..."` (or unquoted `title=...`) from code block metadata
+fn parse_title(metadata: &str) -> Option<String> {
+    let start = metadata.find("title=")? + "title=".len();
+    let rest = &metadata[start..];
+    let title = if let Some(quoted) = rest.strip_prefix('"') {
+        quoted.split('"').next()?
+    } else if let Some(quoted) = rest.strip_prefix('\'') {
+        quoted.split('\'').next()?
+    } else {
+        rest.split_whitespace().next()?
+    };
+
+    if title.is_empty() { None } else { Some(title.to_string()) }
+}
+
+// Extract line ranges such as `{3-5}` or `{1,4-6}` from code block metadata
+fn parse_line_ranges(metadata: &str) -> Vec<(usize, usize)> {
+    let Some(open) = metadata.find('{') else { return Vec::new() };
+    let Some(close) = metadata[open..].find('}') else { return Vec::new() };
+
+    metadata[open + 1..open + close]
+        .split(',')
+        .filter_map(|part| {
+            let part = part.trim();
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let start = start.trim().parse().ok()?;
+                    let end = end.trim().parse().ok()?;
+                    if start <= end { Some((start, end)) } else { None }
+                }
+                None => part.parse().ok().map(|line| (line, line)),
+            }
+        })
+        .collect()
+}
+
+// Syntect resources are expensive to deserialize, so they are loaded once on
+// first use and reused by every render afterwards
+#[derive(Default)]
+pub struct HighlightEngine {
+    syntax_set: OnceLock<SyntaxSet>,
+    themes: OnceLock<RwLock<BTreeMap<String, LoadedTheme>>>,
+    // Folder of the user's .tmTheme files, read together with the built-in themes
+    custom_theme_dir: OnceLock<PathBuf>,
+    selection: RwLock<ThemeSelection>,
+}
+
+// A highlighting theme as offered to the user
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightTheme {
+    pub name: String,
+    // Dark background, so pickers can suggest it for dark mode
+    pub dark: bool,
+    // Loaded from a .tmTheme file rather than built in
+    pub custom: bool,
+}
+
+struct LoadedTheme {
+    theme: Arc<Theme>,
+    custom: bool,
+}
+
+// Names of the themes used in light and dark colour schemes
+struct ThemeSelection {
+    light: String,
+    dark: String,
+}
+
+impl Default for ThemeSelection {
+    fn default() -> Self {
+        Self { light: DEFAULT_LIGHT_THEME.to_string(), dark: DEFAULT_DARK_THEME.to_string() }
+    }
+}
+
+impl HighlightEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn syntax_set(&self) -> &SyntaxSet {
+        self.syntax_set.get_or_init(SyntaxSet::load_defaults_newlines)
+    }
+
+    fn themes(&self) -> &RwLock<BTreeMap<String, LoadedTheme>> {
+        self.themes.get_or_init(|| {
+            let mut themes: BTreeMap<String, LoadedTheme> = ThemeSet::load_defaults().themes.into_iter()
+                .map(|(name, theme)| (name, LoadedTheme { theme: Arc::new(theme), custom: false }))
+                .collect();
+            if let Some(dir) = self.custom_theme_dir.get() {
+                load_theme_folder(dir, &mut themes);
+            }
+            RwLock::new(themes)
+        })
+    }
+
+    // Read custom themes from this folder as well. Only takes effect before the themes
+    // are first loaded, so call it before warm_up.
+    pub fn set_custom_theme_dir(&self, dir: PathBuf) {
+        let _ = self.custom_theme_dir.set(dir);
+    }
+
+    // Force loading of all resources (used to warm the cache off the UI thread)
+    pub fn warm_up(&self) {
+        self.syntax_set();
+        self.themes();
+    }
+
+    pub fn list_themes(&self) -> Vec<HighlightTheme> {
+        self.themes().read().unwrap().iter()
+            .map(|(name, loaded)| HighlightTheme {
+                name: name.clone(),
+                dark: is_dark(&loaded.theme),
+                custom: loaded.custom,
+            })
+            .collect()
+    }
+
+    // Choose the themes for light and dark colour schemes; None restores the default
+    pub fn select_themes(&self, light: Option<&str>, dark: Option<&str>) -> Result<(), String> {
+        let light = light.unwrap_or(DEFAULT_LIGHT_THEME);
+        let dark = dark.unwrap_or(DEFAULT_DARK_THEME);
+        let themes = self.themes().read().unwrap();
+        for name in [light, dark] {
+            if !themes.contains_key(name) {
+                return Err(format!("Unknown highlighting theme: {}", name));
+            }
+        }
+
+        let mut selection = self.selection.write().unwrap();
+        selection.light = light.to_string();
+        selection.dark = dark.to_string();
+        Ok(())
+    }
+
+    // Add a .tmTheme file to the available themes under its file name; returns that name
+    pub fn load_theme_file(&self, path: &Path) -> Result<String, String> {
+        let (name, theme) = read_theme_file(path)?;
+        self.themes().write().unwrap().insert(name.clone(), LoadedTheme { theme: Arc::new(theme), custom: true });
+        Ok(name)
+    }
+
+    // The selected theme for a colour scheme. Auto gets the light theme: it is used where
+    // only one set of colours can be written, such as DOCX and PDF.
+    pub fn theme(&self, scheme: ExportTheme) -> Arc<Theme> {
+        let selection = self.selection.read().unwrap();
+        let (name, default) = match scheme {
+            ExportTheme::Dark => (&selection.dark, DEFAULT_DARK_THEME),
+            ExportTheme::Light | ExportTheme::Auto => (&selection.light, DEFAULT_LIGHT_THEME),
+        };
+
+        let themes = self.themes().read().unwrap();
+        themes.get(name.as_str())
+            .or_else(|| themes.get(default))
+            .map(|loaded| loaded.theme.clone())
+            .unwrap_or_default()
+    }
+
+    // Stylesheet colouring highlighted code for a colour scheme. Auto holds both themes
+    // and lets the reader's system preference pick one.
+    pub fn theme_css(&self, scheme: ExportTheme) -> String {
+        match scheme {
+            ExportTheme::Light | ExportTheme::Dark => css_for_theme(&self.theme(scheme)),
+            ExportTheme::Auto => format!(
+                "@media not all and (prefers-color-scheme: dark) {{\n{}}}\n@media (prefers-color-scheme: dark) {{\n{}}}\n",
+                css_for_theme(&self.theme(ExportTheme::Light)),
+                css_for_theme(&self.theme(ExportTheme::Dark))
+            ),
+        }
+    }
+
+    fn find_syntax(&self, language: &str) -> Option<&SyntaxReference> {
+        let syntax_set = self.syntax_set();
+
+        // Try multiple language variations to improve matching
+        let lowercase = language.to_lowercase();
+        let language_variants = [
+            language,
+            lowercase.as_str(),
+            match lowercase.as_str() {
+                "js" => "javascript",
+                "ts" => "typescript",
+                "py" => "python",
+                "rb" => "ruby",
+                "sh" => "bash",
+                "yml" => "yaml",
+                "md" => "markdown",
+                _ => language
+            }
+        ];
+
+        language_variants.iter().find_map(|variant| syntax_set.find_syntax_by_token(variant))
+    }
+
+    // Render a fenced or indented code block to HTML
+    pub fn render_code_block(&self, code: &str, info: &CodeBlockInfo) -> String {
+        let highlighted = info.language.as_deref()
+            .and_then(|language| self.find_syntax(language))
+            .and_then(|syntax| self.highlight_with_syntax(code, syntax, info));
+
+        let pre = highlighted.unwrap_or_else(|| {
+            // No syntax highlighting available for this language
+            let class = info.language.as_ref()
+                .map(|language| format!(" class=\"language-{}\"", html_escape::encode_double_quoted_attribute(language)))
+                .unwrap_or_default();
+            format!("<pre{}><code{}>{}</code></pre>\n", info.pre_attributes(), class, html_escape::encode_text(code))
+        });
+
+        match &info.title {
+            Some(title) => format!(
+                "<div class=\"code-block\"><div class=\"code-block-title\">{}</div>{}</div>\n",
+                html_escape::encode_text(title),
+                pre
+            ),
+            None => pre,
+        }
+    }
+
+    // Coloured regions of each line in the light theme, for exporters that don't write
+    // HTML. None when the language is unknown or highlighting fails.
+    pub fn styled_lines<'c>(&self, code: &'c str, language: Option<&str>) -> Option<Vec<Vec<(Style, &'c str)>>> {
+        let syntax = self.find_syntax(language?)?;
+        let theme = self.theme(ExportTheme::Light);
+        let mut highlighter = HighlightLines::new(syntax, &theme);
+        LinesWithEndings::from(code)
+            .map(|line| highlighter.highlight_line(line, self.syntax_set()).ok())
+            .collect()
+    }
+
+    fn highlight_with_syntax(&self, code: &str, syntax: &SyntaxReference, info: &CodeBlockInfo) -> Option<String> {
+        let syntax_set = self.syntax_set();
+        let mut parse_state = ParseState::new(syntax);
+        let mut scope_stack = ScopeStack::new();
+        let mut output = format!("<pre class=\"{}code\"{}>\n", CLASS_PREFIX, info.pre_attributes());
+
+        for (index, line) in LinesWithEndings::from(code).enumerate() {
+            let ops = parse_state.parse_line(line, syntax_set).ok()?;
+            // Scopes still open from earlier lines are reopened and closed again at the
+            // end, so each line is balanced markup that can be wrapped on its own
+            let mut line_html = open_scope_spans(&scope_stack);
+            let (spans, _) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut scope_stack).ok()?;
+            line_html.push_str(&spans);
+            line_html.push_str(&"</span>".repeat(scope_stack.len()));
+
+            if info.is_line_highlighted(index + 1) {
+                output.push_str("<span class=\"highlighted-line\">");
+                output.push_str(&line_html);
+                output.push_str("</span>");
+            } else {
+                output.push_str(&line_html);
+            }
+        }
+
+        output.push_str("</pre>\n");
+        Some(output)
+    }
+}
+
+fn open_scope_spans(scope_stack: &ScopeStack) -> String {
+    scope_stack.as_slice().iter()
+        .map(|scope| {
+            let classes: Vec<String> = scope.build_string().split('.')
+                .map(|atom| format!("{}{}", CLASS_PREFIX, atom))
+                .collect();
+            format!("<span class=\"{}\">", classes.join(" "))
+        })
+        .collect()
+}
+
+fn css_for_theme(theme: &Theme) -> String {
+    // Without its name, which syntect writes into a header comment that is dropped here
+    let theme = Theme { name: None, ..theme.clone() };
+    let css = css_for_theme_with_class_style(&theme, CLASS_STYLE).unwrap_or_default();
+    let css = css.split_once("*/").map_or(css.as_str(), |(_, rules)| rules).trim_start();
+    // `pre.` outranks the page's own `pre` backgrounds; custom themes must not end the
+    // <style> element they are written into
+    css.replacen(&format!(".{}code {{", CLASS_PREFIX), &format!("pre.{}code {{", CLASS_PREFIX), 1)
+        .replace("</", "<\\/")
+}
+
+fn is_dark(theme: &Theme) -> bool {
+    theme.settings.background.is_some_and(|color| {
+        u32::from(color.r) * 299 + u32::from(color.g) * 587 + u32::from(color.b) * 114 < 128_000
+    })
+}
+
+// A theme file with the name it is listed under: its file name without extension
+fn read_theme_file(path: &Path) -> Result<(String, Theme), String> {
+    let name = path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .ok_or_else(|| format!("Invalid theme file: {}", path.display()))?;
+    let theme = ThemeSet::get_theme(path)
+        .map_err(|e| format!("Failed to load theme {}: {}", path.display(), e))?;
+    Ok((name, theme))
+}
+
+// Every .tmTheme file in a folder; files that fail to parse are reported and skipped
+fn load_theme_folder(dir: &Path, themes: &mut BTreeMap<String, LoadedTheme>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if !path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("tmtheme")) {
+            continue;
+        }
+        match read_theme_file(&path) {
+            Ok((name, theme)) => {
+                themes.insert(name, LoadedTheme { theme: Arc::new(theme), custom: true });
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_language_title_and_ranges() {
+        let info = CodeBlockInfo::parse("rust,ignore title=\"src/main.rs\" {3-5}");
+        assert_eq!(info.language.as_deref(), Some("rust"));
+        assert_eq!(info.title.as_deref(), Some("src/main.rs"));
+        assert_eq!(info.highlight_lines, vec![(3, 5)]);
+        assert!(info.is_line_highlighted(4));
+        assert!(!info.is_line_highlighted(6));
+    }
+
+    #[test]
+    fn parses_title_quoting_styles() {
+        assert_eq!(CodeBlockInfo::parse("js title='a b.js'").title.as_deref(), Some("a b.js"));
+        assert_eq!(CodeBlockInfo::parse("js title=app.js {1}").title.as_deref(), Some("app.js"));
+        assert_eq!(CodeBlockInfo::parse("js title=\"\"").title, None);
+        assert_eq!(CodeBlockInfo::parse("js").title, None);
+    }
+
+    #[test]
+    fn parses_range_lists_and_skips_invalid_ranges() {
+        assert_eq!(CodeBlockInfo::parse("py {1,4-6, 9}").highlight_lines, vec![(1, 1), (4, 6), (9, 9)]);
+        assert_eq!(CodeBlockInfo::parse("py {5-3,x,2}").highlight_lines, vec![(2, 2)]);
+        assert!(CodeBlockInfo::parse("py {3-5").highlight_lines.is_empty());
+    }
+
+    #[test]
+    fn handles_missing_and_overlong_languages() {
+        let info = CodeBlockInfo::parse("{2}");
+        assert_eq!(info.language, None);
+        assert_eq!(info.highlight_lines, vec![(2, 2)]);
+        assert_eq!(CodeBlockInfo::parse("").language, None);
+        assert_eq!(CodeBlockInfo::parse(&"x".repeat(MAX_LANGUAGE_LENGTH + 1)).language, None);
+    }
+
+    // A dark theme whose name would end a CSS comment
+    const CUSTOM_THEME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+  <key>name</key><string>Brand */ theme</string>
+  <key>settings</key>
+  <array>
+    <dict><key>settings</key><dict>
+      <key>background</key><string>#101820</string>
+      <key>foreground</key><string>#F2AA4C</string>
+    </dict></dict>
+    <dict><key>scope</key><string>keyword</string>
+      <key>settings</key><dict><key>foreground</key><string>#FF0066</string></dict></dict>
+  </array>
+</dict>
+</plist>
+"#;
+
+    #[test]
+    fn writes_balanced_classed_lines() {
+        let engine = HighlightEngine::new();
+        let html = engine.render_code_block("/* a\n b */\nfn main() {}\n", &CodeBlockInfo::parse("rust {2}"));
+        assert!(html.starts_with("<pre class=\"syn-code\" data-lang=\"rust\" data-highlight-lines=\"2\">"));
+        assert!(html.contains("syn-comment"));
+        assert!(!html.contains("style="));
+        assert_eq!(html.matches("<span").count(), html.matches("</span>").count());
+
+        // The comment spans both lines, and the highlighted line reopens it
+        let highlighted = html.split("<span class=\"highlighted-line\">").nth(1).unwrap();
+        assert!(highlighted.starts_with("<span class=\"syn-source syn-rust\"><span class=\"syn-comment"));
+    }
+
+    #[test]
+    fn falls_back_to_escaped_plain_code() {
+        let engine = HighlightEngine::new();
+        let html = engine.render_code_block("a < b\n", &CodeBlockInfo::parse("no\"such title=x.txt"));
+        assert_eq!(html, "<div class=\"code-block\"><div class=\"code-block-title\">x.txt</div>\
+<pre data-lang=\"no&quot;such\" data-title=\"x.txt\"><code class=\"language-no&quot;such\">a &lt; b\n</code></pre>\n</div>\n");
+    }
+
+    #[test]
+    fn selects_themes_per_colour_scheme() {
+        let engine = HighlightEngine::new();
+        assert!(engine.select_themes(Some("No such theme"), None).is_err());
+        engine.select_themes(Some("Solarized (light)"), Some("base16-mocha.dark")).unwrap();
+        assert_eq!(engine.theme(ExportTheme::Light).name.as_deref(), Some("Solarized (light)"));
+        assert_eq!(engine.theme(ExportTheme::Auto).name.as_deref(), Some("Solarized (light)"));
+        assert!(is_dark(&engine.theme(ExportTheme::Dark)));
+
+        engine.select_themes(None, None).unwrap();
+        assert_eq!(engine.theme(ExportTheme::Dark).name, engine.themes().read().unwrap()[DEFAULT_DARK_THEME].theme.name);
+    }
+
+    #[test]
+    fn writes_theme_css_for_both_schemes() {
+        let engine = HighlightEngine::new();
+        let light = engine.theme_css(ExportTheme::Light);
+        assert!(light.starts_with("pre.syn-code {"));
+        assert!(!light.contains("/*"));
+
+        let auto = engine.theme_css(ExportTheme::Auto);
+        assert!(auto.starts_with("@media not all and (prefers-color-scheme: dark) {\npre.syn-code {"));
+        assert!(auto.contains(&format!("@media (prefers-color-scheme: dark) {{\n{}}}", engine.theme_css(ExportTheme::Dark))));
+    }
+
+    #[test]
+    fn imports_tm_theme_files() {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-themes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Brand.tmTheme"), CUSTOM_THEME).unwrap();
+        std::fs::write(dir.join("Broken.tmTheme"), "not a plist").unwrap();
+
+        let engine = HighlightEngine::new();
+        assert_eq!(engine.load_theme_file(&dir.join("Brand.tmTheme")), Ok("Brand".to_string()));
+        assert!(engine.load_theme_file(&dir.join("Broken.tmTheme")).is_err());
+        let brand = engine.list_themes().into_iter().find(|theme| theme.name == "Brand").unwrap();
+        assert!(brand.custom && brand.dark);
+
+        engine.select_themes(Some("Brand"), None).unwrap();
+        let css = engine.theme_css(ExportTheme::Light);
+        assert!(css.contains("#ff0066"), "{}", css);
+        assert!(!css.contains("*/"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
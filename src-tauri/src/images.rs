@@ -0,0 +1,186 @@
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Gif => "gif",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+        }
+    }
+}
+
+// What the header of an image file says about it
+#[derive(Debug, Clone, Copy)]
+pub struct ImageHeader {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    // Colour components of a JPEG frame; PNG and GIF images report 0
+    pub components: u8,
+}
+
+// Find an image referenced by a document on disk, following the viewer's rules in
+// post_process_image_paths: a file:// URL, or a relative path that stays inside the
+// document's directory. Remote, forbidden and oversized images give None.
+pub fn resolve_local_image(source: &str, base_dir: &Path) -> Option<PathBuf> {
+    if source.contains("://") && !source.starts_with("file://") {
+        return None;
+    }
+    let path = match source.strip_prefix("file://") {
+        Some(path) => Path::new(path).canonicalize().ok()?,
+        None => {
+            let path = base_dir.join(source).canonicalize().ok()?;
+            if !path.starts_with(base_dir.canonicalize().ok()?) {
+                return None;
+            }
+            path
+        }
+    };
+    if crate::check_forbidden_path(&path).is_err() || !path.is_file() {
+        return None;
+    }
+    if path.metadata().ok()?.len() > crate::MAX_FILE_SIZE {
+        return None;
+    }
+    Some(path)
+}
+
+// Read an image referenced by a document, resolved by resolve_local_image
+pub fn read_local_image(source: &str, base_dir: &Path) -> Option<Vec<u8>> {
+    std::fs::read(resolve_local_image(source, base_dir)?).ok()
+}
+
+// Format and pixel size of a PNG, JPEG or GIF image
+pub fn read_header(data: &[u8]) -> Option<ImageHeader> {
+    let be16 = |at: usize| Some(u32::from(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?)));
+    let le16 = |at: usize| Some(u32::from(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?)));
+    let be32 = |at: usize| Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?));
+    let header = |format, width, height, components| Some(ImageHeader { format, width, height, components });
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return header(ImageFormat::Png, be32(16)?, be32(20)?, 0);
+    }
+    if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        return header(ImageFormat::Gif, le16(6)?, le16(8)?, 0);
+    }
+    if data.starts_with(&[0xFF, 0xD8]) {
+        // Walk the segments up to the start-of-frame marker, which holds the size
+        let mut at = 2;
+        while at + 4 <= data.len() {
+            if data[at] != 0xFF {
+                return None;
+            }
+            let marker = data[at + 1];
+            if marker == 0xFF {
+                at += 1;
+                continue;
+            }
+            if matches!(marker, 0x01 | 0xD0..=0xD9) {
+                at += 2;
+                continue;
+            }
+            if matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                return header(ImageFormat::Jpeg, be16(at + 7)?, be16(at + 5)?, *data.get(at + 9)?);
+            }
+            at += 2 + be16(at + 2)? as usize;
+        }
+    }
+    None
+}
+
+// Media type of an image file judged by its contents, including formats read_header
+// can't size
+pub fn sniff_mime_type(data: &[u8]) -> Option<&'static str> {
+    if let Some(header) = read_header(data) {
+        return Some(header.format.mime_type());
+    }
+    if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    let start = String::from_utf8_lossy(&data[..data.len().min(1024)]).to_ascii_lowercase();
+    let start = start.trim_start_matches('\u{FEFF}').trim_start();
+    if start.starts_with('<') && start.contains("<svg") {
+        return Some("image/svg+xml");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend(width.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn reads_png_and_gif_sizes() {
+        let header = read_header(&png(640, 480)).unwrap();
+        assert_eq!((header.format, header.width, header.height), (ImageFormat::Png, 640, 480));
+
+        let header = read_header(b"GIF89a\x10\x00\x20\x00").unwrap();
+        assert_eq!((header.format, header.width, header.height), (ImageFormat::Gif, 16, 32));
+        assert!(read_header(&png(1, 1)[..20]).is_none());
+    }
+
+    #[test]
+    fn walks_jpeg_segments_to_the_frame_header() {
+        let mut jpeg = vec![0xFF, 0xD8];
+        // APP0 segment, then fill bytes, then a baseline frame: 8-bit, 200 high, 300 wide, 3 components
+        jpeg.extend([0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00]);
+        jpeg.extend([0xFF, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0xC8, 0x01, 0x2C, 0x03]);
+        let header = read_header(&jpeg).unwrap();
+        assert_eq!((header.format, header.width, header.height, header.components), (ImageFormat::Jpeg, 300, 200, 3));
+
+        // A Huffman table marker is not a frame
+        assert!(read_header(&[0xFF, 0xD8, 0xFF, 0xC4, 0x00, 0x02]).is_none());
+        assert!(read_header(&[0xFF, 0xD8, 0x00, 0x00, 0x00, 0x00]).is_none());
+    }
+
+    #[test]
+    fn sniffs_formats_without_a_size() {
+        assert_eq!(sniff_mime_type(&png(1, 1)), Some("image/png"));
+        assert_eq!(sniff_mime_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_mime_type("\u{FEFF}<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>".as_bytes()), Some("image/svg+xml"));
+        assert_eq!(sniff_mime_type(b"<html><body></body></html>"), None);
+        assert_eq!(sniff_mime_type(b""), None);
+    }
+
+    #[test]
+    fn resolves_images_inside_the_document_folder() {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-images-{}", std::process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        fs::write(dir.join("outside.png"), png(1, 1)).unwrap();
+        fs::write(dir.join("docs").join("inside.png"), png(1, 1)).unwrap();
+        let docs = dir.join("docs");
+
+        assert_eq!(resolve_local_image("inside.png", &docs), Some(docs.join("inside.png").canonicalize().unwrap()));
+        assert_eq!(resolve_local_image("../outside.png", &docs), None);
+        assert_eq!(resolve_local_image("missing.png", &docs), None);
+        assert_eq!(resolve_local_image("https://example.com/a.png", &docs), None);
+
+        let file_url = format!("file://{}", dir.join("outside.png").to_string_lossy());
+        assert_eq!(read_local_image(&file_url, &docs), Some(png(1, 1)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
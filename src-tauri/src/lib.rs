@@ -1,106 +1,95 @@
-use pulldown_cmark::{Parser, Options, html};
 use std::fs;
 use std::env;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use notify::{Watcher, RecommendedWatcher, RecursiveMode, Event, EventKind};
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter, Manager};
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 use tauri::RunEvent;
-use syntect::parsing::SyntaxSet;
-use syntect::highlighting::ThemeSet;
-use syntect::html::highlighted_html_for_string;
-use regex;
 
+mod alerts;
+mod changes;
+pub mod cli;
+mod dependencies;
+mod diff;
+mod docx;
+mod epub;
+mod front_matter;
+mod gfm;
+mod git;
+pub mod highlight;
+mod images;
+mod links;
+mod markdown;
+mod math;
+mod pdf;
+pub mod sanitize;
+mod slides;
+mod source_map;
+mod standalone;
+mod tabs;
+mod toc;
+mod workspace;
+
+use front_matter::FrontMatter;
+use highlight::{HighlightEngine, HighlightState, HighlightTheme};
+use changes::DocumentUpdate;
+use diff::RenderedDiff;
+use epub::BookSource;
+use git::{CommitInfo, GitFileInfo};
+pub use markdown::{render_markdown, RenderedDocument};
+use markdown::{render_markdown_blocks, RenderedBlock};
+use pdf::PdfOptions;
+use sanitize::SanitizeLevel;
+use slides::Slide;
+use source_map::SourceLocation;
+use standalone::StandaloneHtml;
+pub use gfm::MarkdownOptions;
+use tabs::{TabId, TabInfo, TabList, TabManager, TabState};
+use toc::{render_toc_html, TocEntry, TOC_MARKER};
+use workspace::{validate_directory_path, WorkspaceEntry, WorkspaceState};
 
 // Security constants
 const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024; // 50MB limit
 const MAX_REGEX_SIZE: usize = 10 * 1024 * 1024; // 10MB regex limit
-const MAX_HTML_SIZE: usize = 100 * 1024 * 1024; // 100MB HTML limit for temp files
-// const MAX_LANGUAGE_LENGTH: usize = 50; // Limit language identifier length
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdown", "mkd"];
+// Folder inside the app's config directory holding imported .tmTheme files
+const CUSTOM_THEME_DIR: &str = "themes";
+// Placeholder emitted in place of images that fail URL or path validation
+const BLOCKED_IMAGE_TAG: &str = "<img src=\"data:image/svg+xml;base64,PHN2ZyB3aWR0aD0iMjAwIiBoZWlnaHQ9IjEwMCIgeG1sbnM9Imh0dHA6Ly93d3cudzMub3JnLzIwMDAvc3ZnIj48cmVjdCB3aWR0aD0iMTAwJSIgaGVpZ2h0PSIxMDAlIiBmaWxsPSIjZGRkIi8+PHRleHQgeD0iNTAlIiB5PSI1MCUiIGZvbnQtZmFtaWx5PSJBcmlhbCwgc2Fucy1zZXJpZiIgZm9udC1zaXplPSIxNCIgZmlsbD0iIzk5OSIgdGV4dC1hbmNob3I9Im1pZGRsZSIgZHk9Ii4zZW0iPkJsb2NrZWQgSW1hZ2U8L3RleHQ+PC9zdmc+\" alt=\"Blocked unsafe image\"";
 
-// Global state for file watcher
-type WatcherState = Arc<Mutex<Option<RecommendedWatcher>>>;
+// A file to open, optionally at a source line (`notes.md:120`) or heading (`notes.md#setup`)
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OpenedFile {
+    file_path: String,
+    line: Option<usize>,
+    anchor: Option<String>,
+}
 
 // App state to store file opened via "Open With" on macOS
 #[derive(Default)]
 struct OpenedFileState {
-    file_path: Arc<Mutex<Option<String>>>,
+    opened_file: Arc<Mutex<Option<OpenedFile>>>,
 }
 
 impl OpenedFileState {
-    fn set_file(&self, path: String) {
-        let mut file_path = self.file_path.lock().unwrap();
-        *file_path = Some(path);
+    fn set_file(&self, file: OpenedFile) {
+        let mut opened_file = self.opened_file.lock().unwrap();
+        *opened_file = Some(file);
     }
     
-    fn get_file(&self) -> Option<String> {
-        let file_path = self.file_path.lock().unwrap();
-        file_path.clone()
+    fn get_file(&self) -> Option<OpenedFile> {
+        let opened_file = self.opened_file.lock().unwrap();
+        opened_file.clone()
     }
     
     fn clear_file(&self) {
-        let mut file_path = self.file_path.lock().unwrap();
-        *file_path = None;
+        let mut opened_file = self.opened_file.lock().unwrap();
+        *opened_file = None;
     }
 }
 
-// Security utilities for temp file handling
-fn create_secure_temp_file(content: &str) -> Result<PathBuf, String> {
-    // Basic size validation only
-    if content.len() > MAX_HTML_SIZE {
-        return Err("Content too large".to_string());
-    }
-    
-    let temp_dir = std::env::temp_dir();
-    
-    // Use secure random for filename
-    use std::time::{SystemTime, UNIX_EPOCH};
-    
-    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
-    let pid = std::process::id();
-    let tid = std::thread::current().id();
-    
-    // Create unpredictable filename combining multiple entropy sources
-    let filename = format!("md_{}_{:?}_{}.html", nanos, tid, pid);
-    let temp_file = temp_dir.join(filename);
-    
-    // Create file with secure permissions on Unix from the start
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::OpenOptionsExt;
-        use std::io::Write;
-        
-        let mut file = std::fs::OpenOptions::new()
-            .create_new(true)
-            .write(true)
-            .mode(0o600)  // Owner read/write only
-            .open(&temp_file)
-            .map_err(|_| "Failed to create temp file".to_string())?;
-        
-        file.write_all(content.as_bytes())
-            .map_err(|_| "Failed to write content".to_string())?;
-    }
-    
-    #[cfg(windows)]
-    {
-        // On Windows, use a more secure approach
-        std::fs::write(&temp_file, content)
-            .map_err(|_| "Failed to create temp file".to_string())?;
-        
-        // Windows doesn't have from_readonly, skip permissions
-    }
-    
-    #[cfg(not(any(unix, windows)))]
-    {
-        std::fs::write(&temp_file, content)
-            .map_err(|_| "Failed to create temp file".to_string())?;
-    }
-    
-    Ok(temp_file)
-}
-
-
 // Secure file reading with size limits
 fn read_file_with_size_limit(path: &Path) -> Result<String, String> {
     let metadata = path.metadata()
@@ -152,10 +141,9 @@ fn validate_file_path(file_path: &str) -> Result<PathBuf, String> {
         .map_err(|e| format!("Invalid file path: {}", e))?;
     
     // Check file extension - only allow markdown files
-    let allowed_extensions = ["md", "markdown", "mdown", "mkd"];
     if let Some(extension) = canonical_path.extension() {
         if let Some(ext_str) = extension.to_str() {
-            if !allowed_extensions.contains(&ext_str.to_lowercase().as_str()) {
+            if !MARKDOWN_EXTENSIONS.contains(&ext_str.to_lowercase().as_str()) {
                 return Err(format!("Invalid file extension: {}. Only markdown files are allowed.", ext_str));
             }
         } else {
@@ -166,6 +154,23 @@ fn validate_file_path(file_path: &str) -> Result<PathBuf, String> {
     }
     
     // Prevent access to system directories
+    check_forbidden_path(&canonical_path)?;
+    
+    // Check if file actually exists
+    if !canonical_path.exists() {
+        return Err(format!("File does not exist: {}", canonical_path.display()));
+    }
+    
+    // Check if it's actually a file (not a directory)
+    if !canonical_path.is_file() {
+        return Err(format!("Path is not a file: {}", canonical_path.display()));
+    }
+    
+    Ok(canonical_path)
+}
+
+// Reject paths inside system directories
+fn check_forbidden_path(canonical_path: &Path) -> Result<(), String> {
     let canonical_str = canonical_path.to_string_lossy().to_lowercase();
     let forbidden_paths = if cfg!(windows) {
         vec![
@@ -193,39 +198,13 @@ fn validate_file_path(file_path: &str) -> Result<PathBuf, String> {
         }
     }
     
-    // Check if file actually exists
-    if !canonical_path.exists() {
-        return Err(format!("File does not exist: {}", canonical_path.display()));
-    }
-    
-    // Check if it's actually a file (not a directory)
-    if !canonical_path.is_file() {
-        return Err(format!("Path is not a file: {}", canonical_path.display()));
-    }
-    
-    Ok(canonical_path)
+    Ok(())
 }
 
-fn sanitize_markdown_content(content: &str) -> String {
-    // Basic content validation - check for suspicious patterns
-    let suspicious_patterns = [
-        "<script",
-        "javascript:",
-        "data:text/html",
-        "vbscript:",
-        "on[a-z]*=", // onerror, onclick, onload, etc.
-    ];
-    
-    let content_lower = content.to_lowercase();
-    for pattern in &suspicious_patterns {
-        if content_lower.contains(pattern) {
-            eprintln!("Warning: Suspicious content detected in markdown: {}", pattern);
-        }
-    }
-    
-    // Return content as-is for now - client-side sanitization will handle the rest
-    // In a future version, we could implement server-side sanitization here
-    content.to_string()
+fn has_markdown_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| MARKDOWN_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
 }
 
 fn validate_image_url(url: &str) -> bool {
@@ -263,100 +242,241 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-fn parse_markdown(markdown_content: &str) -> Result<String, String> {
-    // Sanitize content first
-    let sanitized_content = sanitize_markdown_content(markdown_content);
-    
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
-    options.insert(Options::ENABLE_TASKLISTS);
-    
-    let parser = Parser::new_ext(&sanitized_content, options);
-    let mut html_output = String::new();
-    html::push_html(&mut html_output, parser);
-    
-    // Post-process HTML to add syntax highlighting
-    let html_with_syntax = post_process_syntax_highlighting(&html_output)?;
-    Ok(html_with_syntax)
-}
-
-fn post_process_syntax_highlighting(html: &str) -> Result<String, String> {
-    // Initialize syntax highlighting resources
-    let syntax_set = SyntaxSet::load_defaults_newlines();
-    let theme_set = ThemeSet::load_defaults();
-    
-    // Use a lighter theme that works better for markdown viewers
-    let theme = theme_set.themes.get("InspiredGitHub")
-        .or_else(|| theme_set.themes.get("Solarized (light)"))
-        .or_else(|| theme_set.themes.get("base16-ocean.light"))
-        .unwrap_or(&theme_set.themes["base16-ocean.dark"]);
-    
-    // Pattern to match fenced code blocks with language - secured against ReDoS
-    let re_with_lang = create_secure_regex(r#"<pre><code class="language-([^"]{1,50})">(.*?)</code></pre>"#)
-        .map_err(|e| format!("Failed to create syntax highlighting regex: {}", e))?;
-    
-    // Process code blocks with language specification
-    let result = re_with_lang.replace_all(html, |caps: &regex::Captures| {
-        let language = &caps[1];
-        let code = html_escape::decode_html_entities(&caps[2]).to_string();
-        
-        // Try multiple language variations to improve matching
-        let language_variants = [
-            language,
-            &language.to_lowercase(),
-            match language.to_lowercase().as_str() {
-                "js" => "javascript",
-                "ts" => "typescript", 
-                "py" => "python",
-                "rb" => "ruby",
-                "sh" => "bash",
-                "yml" => "yaml",
-                "md" => "markdown",
-                _ => language
-            }
-        ];
-        
-        for lang_variant in &language_variants {
-            if let Some(syntax) = syntax_set.find_syntax_by_token(lang_variant) {
-                match highlighted_html_for_string(&code, &syntax_set, syntax, theme) {
-                    Ok(highlighted) => {
-                        return highlighted;
-                    },
-                    Err(_) => {
-                        // Continue trying other variants
-                    }
-                }
+fn parse_markdown(
+    markdown_content: &str,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<RenderedDocument, String> {
+    let mut document = render_markdown(markdown_content, &highlighter, sanitize_level.unwrap_or_default(), markdown_options.unwrap_or_default())?;
+    
+    // Without a file there is nothing to resolve relative links against
+    document.html = links::annotate_links(&document.html, None)?;
+    Ok(document)
+}
+
+// Split a document into slides for presenting, rendered like read_markdown_file (or
+// parse_markdown for unsaved content)
+#[tauri::command]
+fn render_slides(
+    file_path: Option<String>,
+    markdown_content: Option<String>,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<Vec<Slide>, String> {
+    let document = match (markdown_content, file_path) {
+        (Some(content), _) => {
+            let mut document = render_markdown_blocks(&content, &highlighter, sanitize_level.unwrap_or_default(), markdown_options.unwrap_or_default())?;
+            for block in document.blocks.iter_mut().flatten() {
+                block.html = links::annotate_links(&block.html, None)?;
             }
+            document
         }
-        
-        // No syntax highlighting available for this language
-        
-        // Fallback to original format with proper escaping
-        format!("<pre><code class=\"language-{}\">{}</code></pre>", 
-               language, html_escape::encode_text(&code))
-    });
-    
-    Ok(result.to_string())
+        (None, Some(file_path)) => render_file(&file_path, sanitize_level, markdown_options, true, &highlighter)?.1,
+        (None, None) => return Err("Nothing to present".to_string()),
+    };
+    Ok(slides::split_slides(document.blocks.as_deref().unwrap_or_default()))
 }
 
+// Open the presenter view of the running slideshow, or bring it forward if it is open.
+// It is a page of its own that follows the main window through events.
 #[tauri::command]
-fn read_markdown_file(file_path: String) -> Result<String, String> {
-    // Validate file path for security
+fn open_presenter_window(app_handle: AppHandle) -> Result<(), String> {
+    if let Some(window) = app_handle.get_webview_window("presenter") {
+        return window.set_focus().map_err(|e| format!("Failed to focus presenter view: {}", e));
+    }
+    tauri::WebviewWindowBuilder::new(&app_handle, "presenter", tauri::WebviewUrl::App("presenter.html".into()))
+        .title("Presenter View")
+        .inner_size(1100.0, 700.0)
+        .min_inner_size(700.0, 450.0)
+        .build()
+        .map_err(|e| format!("Failed to open presenter view: {}", e))?;
+    Ok(())
+}
+
+// Resolve a source line of a file to the rendered element it ended up in
+#[tauri::command]
+fn locate_source_line(
+    file_path: String,
+    line: usize,
+    markdown_options: Option<MarkdownOptions>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<Option<SourceLocation>, String> {
     let validated_path = validate_file_path(&file_path)?;
+    let content = read_file_with_size_limit(&validated_path)?;
+    let document = render_markdown(&content, &highlighter, SanitizeLevel::default(), markdown_options.unwrap_or_default())?;
+    source_map::locate_line(&document.html, line)
+}
+
+#[tauri::command]
+fn get_table_of_contents(markdown_content: &str, markdown_options: Option<MarkdownOptions>) -> Vec<TocEntry> {
+    markdown::table_of_contents(markdown_content, markdown_options.unwrap_or_default())
+}
+
+#[tauri::command]
+fn read_markdown_file(
+    file_path: String,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    tab_id: Option<TabId>,
+    highlighter: tauri::State<HighlightState>,
+    tab_state: tauri::State<TabState>,
+) -> Result<RenderedDocument, String> {
+    let (document, _) = render_file_for_tab(&file_path, sanitize_level, markdown_options, tab_id, &highlighter, &tab_state)?;
+    Ok(document)
+}
+
+// Re-render a tab's file after it changed, sending only the blocks that differ from the last render
+#[tauri::command]
+fn refresh_markdown_file(
+    file_path: String,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    tab_id: TabId,
+    highlighter: tauri::State<HighlightState>,
+    tab_state: tauri::State<TabState>,
+) -> Result<DocumentUpdate, String> {
+    let (document, previous) = render_file_for_tab(&file_path, sanitize_level, markdown_options, Some(tab_id), &highlighter, &tab_state)?;
+    Ok(changes::document_update(document, previous.as_deref()))
+}
+
+// Render two files side by side with the blocks and words that differ marked
+#[tauri::command]
+fn diff_markdown_files(
+    old_path: String,
+    new_path: String,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<RenderedDiff, String> {
+    let (old_path, old) = render_file(&old_path, sanitize_level, markdown_options, true, &highlighter)?;
+    let (new_path, new) = render_file(&new_path, sanitize_level, markdown_options, true, &highlighter)?;
+    let title = |path: &Path| path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(diff::diff_documents(
+        old.blocks.as_deref().unwrap_or_default(),
+        new.blocks.as_deref().unwrap_or_default(),
+        &title(&old_path),
+        &title(&new_path),
+    ))
+}
+
+// The git repository a file is in, if any
+#[tauri::command]
+fn get_git_info(file_path: String) -> Result<Option<GitFileInfo>, String> {
+    let validated_path = validate_file_path(&file_path)?;
+    git::file_info(&validated_path)
+}
+
+#[tauri::command]
+fn get_file_history(file_path: String, limit: Option<usize>) -> Result<Vec<CommitInfo>, String> {
+    let validated_path = validate_file_path(&file_path)?;
+    git::file_history(&validated_path, limit.unwrap_or(100))
+}
+
+// Compare the file on disk with its committed version at a revision (HEAD by default)
+#[tauri::command]
+fn diff_with_revision(
+    file_path: String,
+    revision: Option<String>,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<RenderedDiff, String> {
+    let revision = revision.unwrap_or_else(|| "HEAD".to_string());
+    let (new_path, new) = render_file(&file_path, sanitize_level, markdown_options, true, &highlighter)?;
+    let (old_content, commit) = git::file_at_revision(&new_path, &revision)?;
+    // Images in the old version resolve against the file's current location
+    let old = render_content(&old_content, &new_path, sanitize_level, markdown_options, true, &highlighter)?;
+    
+    let name = new_path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(diff::diff_documents(
+        old.blocks.as_deref().unwrap_or_default(),
+        new.blocks.as_deref().unwrap_or_default(),
+        &format!("{} @ {} {}", name, commit.short_id, commit.summary),
+        &format!("{} (working copy)", name),
+    ))
+}
+
+// Read and render a file. Documents shown in a tab are rendered block by block; the tab's
+// watched dependencies and stored blocks are updated, and the blocks they replace returned.
+fn render_file_for_tab(
+    file_path: &str,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    tab_id: Option<TabId>,
+    highlighter: &HighlightEngine,
+    tab_state: &TabState,
+) -> Result<(RenderedDocument, Option<Vec<RenderedBlock>>), String> {
+    let (validated_path, mut document) = render_file(file_path, sanitize_level, markdown_options, tab_id.is_some(), highlighter)?;
+    
+    // Reload the tab when an embedded file changes, not just the document itself
+    let dependencies = dependencies::collect_dependencies(&document.html, &validated_path)?;
+    let previous_blocks = match tab_id {
+        Some(tab_id) => {
+            let mut tabs = tab_state.lock().unwrap();
+            tabs.set_dependencies(tab_id, &validated_path, &dependencies);
+            tabs.replace_rendered_blocks(tab_id, &validated_path, document.blocks.clone())
+        }
+        None => None,
+    };
+    document.dependencies = dependencies.iter().map(|path| path.to_string_lossy().to_string()).collect();
+    Ok((document, previous_blocks))
+}
+
+// Validate, read and render a file, optionally split into blocks, with images and links
+// resolved relative to it
+fn render_file(
+    file_path: &str,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    split_blocks: bool,
+    highlighter: &HighlightEngine,
+) -> Result<(PathBuf, RenderedDocument), String> {
+    // Validate file path for security
+    let validated_path = validate_file_path(file_path)?;
     
     // Use secure file reading with size limits
     let content = read_file_with_size_limit(&validated_path)?;
     
-    // Sanitize content
-    let sanitized_content = sanitize_markdown_content(&content);
-    
-    // Parse markdown
-    let html = parse_markdown(&sanitized_content)?;
+    let document = render_content(&content, &validated_path, sanitize_level, markdown_options, split_blocks, highlighter)?;
+    Ok((validated_path, document))
+}
+
+// Render markdown that belongs to a file, so its images and links resolve relative to it
+fn render_content(
+    content: &str,
+    validated_path: &Path,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    split_blocks: bool,
+    highlighter: &HighlightEngine,
+) -> Result<RenderedDocument, String> {
+    // Parse and sanitize markdown
+    let sanitize_level = sanitize_level.unwrap_or_default();
+    let markdown_options = markdown_options.unwrap_or_default();
+    let mut document = if split_blocks {
+        render_markdown_blocks(content, highlighter, sanitize_level, markdown_options)?
+    } else {
+        render_markdown(content, highlighter, sanitize_level, markdown_options)?
+    };
     
-    // Process images with file path context
-    post_process_image_paths(&html, &validated_path.to_string_lossy())
+    // Process images and resolve links to other documents relative to this file
+    let path_context = validated_path.to_string_lossy();
+    let process = |html: &str| -> Result<String, String> {
+        let html = post_process_image_paths(html, &path_context)?;
+        links::annotate_links(&html, Some(validated_path))
+    };
+    match &mut document.blocks {
+        Some(blocks) => {
+            for block in blocks.iter_mut() {
+                block.html = process(&block.html)?;
+            }
+            document.html = blocks.iter().map(|block| block.html.as_str()).collect();
+        }
+        None => document.html = process(&document.html)?,
+    }
+    Ok(document)
 }
 
 
@@ -373,7 +493,7 @@ fn post_process_image_paths(html: &str, file_path: &str) -> Result<String, Strin
         // Validate URL for security
         if !validate_image_url(src) {
             eprintln!("Blocked unsafe image URL: {}", src);
-            return format!("<img src=\"data:image/svg+xml;base64,PHN2ZyB3aWR0aD0iMjAwIiBoZWlnaHQ9IjEwMCIgeG1sbnM9Imh0dHA6Ly93d3cudzMub3JnLzIwMDAvc3ZnIj48cmVjdCB3aWR0aD0iMTAwJSIgaGVpZ2h0PSIxMDAlIiBmaWxsPSIjZGRkIi8+PHRleHQgeD0iNTAlIiB5PSI1MCUiIGZvbnQtZmFtaWx5PSJBcmlhbCwgc2Fucy1zZXJpZiIgZm9udC1zaXplPSIxNCIgZmlsbD0iIzk5OSIgdGV4dC1hbmNob3I9Im1pZGRsZSIgZHk9Ii4zZW0iPkJsb2NrZWQgSW1hZ2U8L3RleHQ+PC9zdmc+\" alt=\"Blocked unsafe image\"");
+            return BLOCKED_IMAGE_TAG.to_string();
         }
         
         // Skip URLs that are already absolute (http/https/data)
@@ -389,7 +509,7 @@ fn post_process_image_paths(html: &str, file_path: &str) -> Result<String, Strin
             if let Ok(canonical_base) = base_path.canonicalize() {
                 if !canonical_full.starts_with(&canonical_base) {
                     eprintln!("Blocked path traversal attempt in image: {}", src);
-                    return format!("<img src=\"data:image/svg+xml;base64,PHN2ZyB3aWR0aD0iMjAwIiBoZWlnaHQ9IjEwMCIgeG1sbnM9Imh0dHA6Ly93d3cudzMub3JnLzIwMDAvc3ZnIj48cmVjdCB3aWR0aD0iMTAwJSIgaGVpZ2h0PSIxMDAlIiBmaWxsPSIjZGRkIi8+PHRleHQgeD0iNTAlIiB5PSI1MCUiIGZvbnQtZmFtaWx5PSJBcmlhbCwgc2Fucy1zZXJpZiIgZm9udC1zaXplPSIxNCIgZmlsbD0iIzk5OSIgdGV4dC1hbmNob3I9Im1pZGRsZSIgZHk9Ii4zZW0iPkJsb2NrZWQgSW1hZ2U8L3RleHQ+PC9zdmc+\" alt=\"Blocked unsafe image\"");
+                    return BLOCKED_IMAGE_TAG.to_string();
                 }
             }
         }
@@ -412,7 +532,7 @@ fn get_launch_args() -> Vec<String> {
 }
 
 #[tauri::command]
-fn get_opened_file(state: tauri::State<OpenedFileState>) -> Option<String> {
+fn get_opened_file(state: tauri::State<OpenedFileState>) -> Option<OpenedFile> {
     let file = state.get_file();
     if file.is_some() {
         // Clear the file after retrieving it so it's only opened once
@@ -422,12 +542,51 @@ fn get_opened_file(state: tauri::State<OpenedFileState>) -> Option<String> {
 }
 
 #[tauri::command]
-fn export_html(content: String, title: String) -> Result<String, String> {
+fn export_html(
+    content: String,
+    title: String,
+    toc: Option<Vec<TocEntry>>,
+    front_matter: Option<FrontMatter>,
+    theme: Option<ExportTheme>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<String, String> {
+    export_html_document(content, title, toc, front_matter, theme, &highlighter)
+}
+
+// Wrap rendered HTML in the export template, with the code colours of the export theme
+pub fn export_html_document(
+    content: String,
+    title: String,
+    toc: Option<Vec<TocEntry>>,
+    front_matter: Option<FrontMatter>,
+    theme: Option<ExportTheme>,
+    highlighter: &HighlightEngine,
+) -> Result<String, String> {
+    // Replace `[TOC]` marker paragraphs with the document outline when requested
+    let content = match toc {
+        Some(toc) => content.replace(TOC_MARKER, &render_toc_html(&toc)),
+        None => content,
+    };
+    
+    // Front matter title wins over the file name
+    let title = front_matter.as_ref()
+        .and_then(|front_matter| front_matter.title.clone())
+        .unwrap_or(title);
+    let title = html_escape::encode_text(&title);
+    let meta_tags = front_matter.as_ref().map(front_matter_meta_tags).unwrap_or_default();
+    let theme = theme.unwrap_or_default();
+    let theme_css = match theme {
+        ExportTheme::Light => String::new(),
+        ExportTheme::Dark => DARK_THEME_CSS.to_string(),
+        ExportTheme::Auto => format!("@media (prefers-color-scheme: dark) {{\n{}\n        }}", DARK_THEME_CSS),
+    };
+    let theme_css = format!("{}\n{}", theme_css, highlighter.theme_css(theme));
+    
     let html_template = format!(r#"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">{}
     <title>{}</title>
     <style>
         :root {{
@@ -489,6 +648,33 @@ fn export_html(content: String, title: String) -> Result<String, String> {
             font-style: italic;
         }}
         
+        .markdown-alert {{
+            margin: 1rem 0;
+            padding: 0.5rem 1rem;
+            border-left: 4px solid var(--alert-color);
+            border-radius: 0 0.375rem 0.375rem 0;
+            background: color-mix(in srgb, var(--alert-color) 8%, transparent);
+        }}
+        
+        .markdown-alert > :last-child {{
+            margin-bottom: 0;
+        }}
+        
+        .markdown-alert-title {{
+            display: flex;
+            align-items: center;
+            gap: 0.5rem;
+            margin: 0 0 0.5rem;
+            font-weight: 600;
+            color: var(--alert-color);
+        }}
+        
+        .markdown-alert-note {{ --alert-color: #0969da; }}
+        .markdown-alert-tip {{ --alert-color: #1a7f37; }}
+        .markdown-alert-important {{ --alert-color: #8250df; }}
+        .markdown-alert-warning {{ --alert-color: #9a6700; }}
+        .markdown-alert-caution {{ --alert-color: #d1242f; }}
+        
         code {{
             background: #f8f9fa;
             padding: 0.125rem 0.25rem;
@@ -510,6 +696,46 @@ fn export_html(content: String, title: String) -> Result<String, String> {
             padding: 0;
         }}
         
+        .code-block {{ margin: 1rem 0; }}
+        
+        .code-block-title {{
+            font-family: 'Monaco', 'Consolas', 'Courier New', monospace;
+            font-size: 0.8125rem;
+            padding: 0.375rem 1rem;
+            background: #e9ecef;
+            border-radius: 0.5rem 0.5rem 0 0;
+        }}
+        
+        .code-block pre {{
+            margin-top: 0;
+            border-top-left-radius: 0;
+            border-top-right-radius: 0;
+        }}
+        
+        pre .highlighted-line {{
+            display: block;
+            margin: 0 -1rem;
+            padding: 0 1rem;
+            background: rgba(255, 213, 79, 0.25);
+        }}
+        
+        .math-display {{
+            display: block;
+            margin: 1rem 0;
+            overflow-x: auto;
+            text-align: center;
+        }}
+        
+        .mermaid-diagram-container {{
+            margin: 1rem 0;
+            text-align: center;
+        }}
+        
+        .mermaid-diagram-container svg {{
+            max-width: 100%;
+            height: auto;
+        }}
+        
         table {{
             width: 100%;
             border-collapse: collapse;
@@ -559,6 +785,38 @@ fn export_html(content: String, title: String) -> Result<String, String> {
             text-decoration: underline;
         }}
         
+        .table-of-contents {{
+            margin: 1rem 0;
+            padding: 1rem 1.5rem;
+            background: #f8f9fa;
+            border-radius: 0.5rem;
+        }}
+        
+        .table-of-contents ul {{
+            margin-bottom: 0;
+            padding-left: 1.25rem;
+        }}
+        
+        body:has(.markdown-diff) {{ max-width: none; }}
+        
+        .diff-header, .diff-row {{
+            display: grid;
+            grid-template-columns: 1fr 1fr;
+            gap: 1.5rem;
+        }}
+        
+        .diff-header {{
+            font-weight: 600;
+            border-bottom: 1px solid #e9ecef;
+            padding-bottom: 0.5rem;
+        }}
+        
+        .diff-side {{ min-width: 0; }}
+        .diff-removed .diff-old, .diff-modified .diff-old {{ background: rgba(220, 53, 69, 0.06); }}
+        .diff-added .diff-new, .diff-modified .diff-new {{ background: rgba(40, 167, 69, 0.06); }}
+        del.diff-del {{ background: rgba(220, 53, 69, 0.25); text-decoration: line-through; }}
+        ins.diff-ins {{ background: rgba(40, 167, 69, 0.25); text-decoration: none; }}
+        
         @media print {{
             body {{
                 padding: 1rem;
@@ -573,71 +831,344 @@ fn export_html(content: String, title: String) -> Result<String, String> {
                 page-break-inside: avoid;
             }}
         }}
+        
+        {}
     </style>
 </head>
 <body>
 {}
 </body>
-</html>"#, title, content);
+</html>"#, meta_tags, title, theme_css, content);
 
     Ok(html_template)
 }
 
+// Colour scheme of exported HTML
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportTheme {
+    #[default]
+    Light,
+    Dark,
+    // Follow the reader's system preference
+    Auto,
+}
+
+// Overrides applied on top of the light export styles
+const DARK_THEME_CSS: &str = r#"
+        :root { color: #e2e8f0; background-color: #1a202c; }
+        h1, h2 { border-color: #4a5568; }
+        blockquote { border-color: #4a5568; color: #a0aec0; }
+        code, pre, .table-of-contents, .code-block-title { background: #2d3748; color: #e2e8f0; }
+        th, td { border-color: #4a5568; }
+        th { background-color: #2d3748; }
+        tr:nth-child(even) { background-color: #232a36; }
+        tr:hover { background-color: #2d3748; }
+        a { color: #63b3ed; }
+        pre .highlighted-line { background: rgba(255, 213, 79, 0.15); }"#;
+
+// Document metadata from front matter as <meta> tags
+fn front_matter_meta_tags(front_matter: &FrontMatter) -> String {
+    let mut tags = String::new();
+    if let Some(author) = &front_matter.author {
+        tags.push_str(&format!("\n    <meta name=\"author\" content=\"{}\">", html_escape::encode_double_quoted_attribute(author)));
+    }
+    if let Some(date) = &front_matter.date {
+        tags.push_str(&format!("\n    <meta name=\"date\" content=\"{}\">", html_escape::encode_double_quoted_attribute(date)));
+    }
+    if !front_matter.tags.is_empty() {
+        tags.push_str(&format!("\n    <meta name=\"keywords\" content=\"{}\">", html_escape::encode_double_quoted_attribute(&front_matter.tags.join(", "))));
+    }
+    tags
+}
+
+// What the single-document exports work from: the validated file, for resolving images
+// and links, and the markdown to render. Content passed in (unsaved tabs) wins over the
+// file on disk.
+fn export_source(file_path: Option<String>, markdown_content: Option<String>) -> Result<(Option<PathBuf>, String), String> {
+    let validated_path = file_path.as_deref().map(validate_file_path).transpose()?;
+    let content = match (markdown_content, &validated_path) {
+        (Some(content), _) => content,
+        (None, Some(path)) => read_file_with_size_limit(path)?,
+        (None, None) => return Err("Nothing to export".to_string()),
+    };
+    Ok((validated_path, content))
+}
+
+// Export a document as one portable HTML file: local images are embedded as data URIs and
+// mermaid diagrams as the SVG the viewer rendered them to, in document order
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn export_standalone_html(
+    file_path: Option<String>,
+    markdown_content: Option<String>,
+    title: String,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    diagrams: Option<Vec<Option<String>>>,
+    theme: Option<ExportTheme>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<StandaloneHtml, String> {
+    let (validated_path, content) = export_source(file_path, markdown_content)?;
+    let document = render_markdown(&content, &highlighter, sanitize_level.unwrap_or_default(), markdown_options.unwrap_or_default())?;
+    standalone::standalone_html(
+        document,
+        validated_path.as_deref(),
+        &title,
+        theme.unwrap_or_default(),
+        diagrams.as_deref().unwrap_or_default(),
+        &highlighter,
+    )
+}
+
+// Write a document as .docx, read through export_source
+#[tauri::command]
+fn export_docx(
+    file_path: Option<String>,
+    markdown_content: Option<String>,
+    title: String,
+    markdown_options: Option<MarkdownOptions>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<tauri::ipc::Response, String> {
+    let (validated_path, content) = export_source(file_path, markdown_content)?;
+    let bytes = docx::export_docx(&content, validated_path.as_deref(), &title, &highlighter, markdown_options.unwrap_or_default())?;
+    // Sent as raw bytes rather than a JSON array of numbers
+    Ok(tauri::ipc::Response::new(bytes))
+}
+
+// Lay a document out as a paginated PDF
+#[tauri::command]
+fn export_pdf(
+    file_path: Option<String>,
+    markdown_content: Option<String>,
+    title: String,
+    markdown_options: Option<MarkdownOptions>,
+    pdf_options: Option<PdfOptions>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<tauri::ipc::Response, String> {
+    let (validated_path, content) = export_source(file_path, markdown_content)?;
+    let bytes = pdf::export_pdf(
+        &content,
+        validated_path.as_deref(),
+        &title,
+        &highlighter,
+        markdown_options.unwrap_or_default(),
+        &pdf_options.unwrap_or_default(),
+    )?;
+    Ok(tauri::ipc::Response::new(bytes))
+}
+
+// Export the slides of a document as one HTML file that presents itself, with local
+// images and diagrams embedded like export_standalone_html
+#[tauri::command]
+fn export_slide_deck(
+    file_path: Option<String>,
+    markdown_content: Option<String>,
+    title: String,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    diagrams: Option<Vec<Option<String>>>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<StandaloneHtml, String> {
+    let (validated_path, content) = export_source(file_path, markdown_content)?;
+    let document = render_markdown_blocks(&content, &highlighter, sanitize_level.unwrap_or_default(), markdown_options.unwrap_or_default())?;
+    slides::slide_deck_html(document, validated_path.as_deref(), &title, &diagrams.unwrap_or_default(), &highlighter)
+}
+
+// Write a document as an EPUB book with a single chapter
+#[tauri::command]
+fn export_epub(
+    file_path: Option<String>,
+    markdown_content: Option<String>,
+    title: String,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<tauri::ipc::Response, String> {
+    let (validated_path, content) = export_source(file_path, markdown_content)?;
+    let sources = [BookSource { path: validated_path, content }];
+    let bytes = epub::export_epub(&sources, &title, &highlighter, sanitize_level.unwrap_or_default(), markdown_options.unwrap_or_default())?;
+    Ok(tauri::ipc::Response::new(bytes))
+}
+
+// Write every markdown file in a folder as one EPUB book, a chapter per file in
+// reading order (see epub::book_files)
 #[tauri::command]
-fn start_watching_file(
+fn export_folder_epub(
+    dir_path: String,
+    title: String,
+    sanitize_level: Option<SanitizeLevel>,
+    markdown_options: Option<MarkdownOptions>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<tauri::ipc::Response, String> {
+    let root = validate_directory_path(&dir_path)?;
+    let tree = workspace::scan_workspace(&root);
+    if tree.truncated {
+        return Err(format!("This folder has more than {} markdown files; export a smaller folder", workspace::MAX_WORKSPACE_FILES));
+    }
+    let sources = epub::book_files(&tree).into_iter()
+        .map(|path| {
+            let path = validate_file_path(&path.to_string_lossy())?;
+            let content = read_file_with_size_limit(&path)?;
+            Ok(BookSource { path: Some(path), content })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if sources.is_empty() {
+        return Err("No markdown files in this folder".to_string());
+    }
+    let bytes = epub::export_epub(&sources, &title, &highlighter, sanitize_level.unwrap_or_default(), markdown_options.unwrap_or_default())?;
+    Ok(tauri::ipc::Response::new(bytes))
+}
+
+#[tauri::command]
+fn open_tab(
+    file_path: Option<String>,
+    title: String,
+    app_handle: AppHandle,
+    tab_state: tauri::State<TabState>,
+) -> Result<TabInfo, String> {
+    // Validate file path for security
+    let file_path = file_path
+        .map(|path| validate_file_path(&path).map(|validated| validated.to_string_lossy().to_string()))
+        .transpose()?;
+    
+    let mut tabs = tab_state.lock().unwrap();
+    tabs.open(file_path, title, &app_handle)
+}
+
+#[tauri::command]
+fn navigate_tab(
+    tab_id: TabId,
     file_path: String,
+    title: String,
     app_handle: AppHandle,
-    watcher_state: tauri::State<WatcherState>,
-) -> Result<(), String> {
+    tab_state: tauri::State<TabState>,
+) -> Result<TabInfo, String> {
     // Validate file path for security
     let validated_path = validate_file_path(&file_path)?;
-    let validated_path_str = validated_path.to_string_lossy().to_string();
-    
-    // Stop any existing watcher first
-    stop_watching_file(watcher_state.clone()).ok();
-    
-    let app_handle_clone = app_handle.clone();
-    let file_path_clone = validated_path_str.clone();
-    
-    let mut watcher = notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
-        match res {
-            Ok(event) => {
-                // Only respond to write events
-                if matches!(event.kind, EventKind::Modify(_)) {
-                    if let Some(path) = event.paths.first() {
-                        if path.to_string_lossy() == file_path_clone {
-                            // Emit event to frontend
-                            app_handle_clone.emit("file-changed", &file_path_clone).ok();
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("Watch error: {:?}", e);
-            }
-        }
-    }).map_err(|e| format!("Failed to create watcher: {}", e))?;
     
-    // Watch the file's parent directory
-    let path = Path::new(&validated_path_str);
-    if let Some(parent) = path.parent() {
-        watcher.watch(parent, RecursiveMode::NonRecursive)
-            .map_err(|e| format!("Failed to watch directory: {}", e))?;
-    } else {
-        return Err("File has no parent directory".to_string());
+    let mut tabs = tab_state.lock().unwrap();
+    tabs.navigate(tab_id, validated_path.to_string_lossy().to_string(), title, &app_handle)
+}
+
+#[tauri::command]
+fn close_tab(tab_id: TabId, tab_state: tauri::State<TabState>) -> Option<TabId> {
+    let mut tabs = tab_state.lock().unwrap();
+    tabs.close(tab_id)
+}
+
+#[tauri::command]
+fn activate_tab(tab_id: TabId, tab_state: tauri::State<TabState>) -> Result<TabInfo, String> {
+    let mut tabs = tab_state.lock().unwrap();
+    tabs.activate(tab_id)
+}
+
+#[tauri::command]
+fn list_tabs(tab_state: tauri::State<TabState>) -> TabList {
+    let tabs = tab_state.lock().unwrap();
+    tabs.list()
+}
+
+#[tauri::command]
+fn update_tab_view(tab_id: TabId, scroll_top: f64, zoom: f64, tab_state: tauri::State<TabState>) -> Result<(), String> {
+    let mut tabs = tab_state.lock().unwrap();
+    tabs.update_view(tab_id, scroll_top, zoom)
+}
+
+// Highlighting themes to choose from: the built-in ones and imported .tmTheme files
+#[tauri::command]
+fn list_highlight_themes(highlighter: tauri::State<HighlightState>) -> Vec<HighlightTheme> {
+    highlighter.list_themes()
+}
+
+// Pick the code themes for light and dark mode (None for the defaults). Returns the
+// stylesheet colouring code blocks in the viewer, which follows the system colour scheme.
+#[tauri::command]
+fn set_highlight_themes(
+    light: Option<String>,
+    dark: Option<String>,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<String, String> {
+    highlighter.select_themes(light.as_deref(), dark.as_deref())?;
+    Ok(highlighter.theme_css(ExportTheme::Auto))
+}
+
+// Add a .tmTheme file to the highlighting themes. A copy is kept in the config folder so
+// the theme is there again on the next start; returns the name it is listed under.
+#[tauri::command]
+fn import_highlight_theme(
+    file_path: String,
+    app_handle: AppHandle,
+    highlighter: tauri::State<HighlightState>,
+) -> Result<String, String> {
+    let path = Path::new(&file_path).canonicalize()
+        .map_err(|e| format!("Invalid file path: {}", e))?;
+    if !path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("tmtheme")) {
+        return Err("Theme files must have a .tmTheme extension".to_string());
     }
+    check_forbidden_path(&path)?;
+    // Refuse oversized files before handing them to the theme parser
+    read_file_with_size_limit(&path)?;
+
+    let themes_dir = app_handle.path().app_config_dir()
+        .map_err(|e| format!("Failed to locate config folder: {}", e))?
+        .join(CUSTOM_THEME_DIR);
+    fs::create_dir_all(&themes_dir)
+        .map_err(|e| format!("Failed to create themes folder: {}", e))?;
+    let target = themes_dir.join(path.file_name().unwrap_or_default());
+    // Importing a file from the themes folder itself must not truncate it
+    let copied = target.canonicalize().ok().as_deref() != Some(path.as_path());
+    if copied {
+        fs::copy(&path, &target).map_err(|e| format!("Failed to save theme: {}", e))?;
+    }
+
+    // Loaded from the copy, so what is listed now is what the next start loads
+    highlighter.load_theme_file(&target).inspect_err(|_| {
+        // A theme that doesn't parse would only fail again on every start
+        if copied {
+            fs::remove_file(&target).ok();
+        }
+    })
+}
+
+// Quiet period before a burst of file system events is reported; returns the delay in use
+#[tauri::command]
+fn set_watch_debounce(debounce_ms: u64, tab_state: tauri::State<TabState>) -> u64 {
+    let tabs = tab_state.lock().unwrap();
+    tabs.set_watch_debounce(debounce_ms)
+}
+
+#[tauri::command]
+fn open_workspace(
+    dir_path: String,
+    app_handle: AppHandle,
+    workspace_state: tauri::State<WorkspaceState>,
+) -> Result<WorkspaceEntry, String> {
+    // Validate folder path for security
+    let root = validate_directory_path(&dir_path)?;
     
-    // Store the watcher in state
-    let mut watcher_guard = watcher_state.lock().unwrap();
-    *watcher_guard = Some(watcher);
+    // Dropping the previous workspace stops its watcher
+    close_workspace(workspace_state.clone()).ok();
     
-    Ok(())
+    let workspace = workspace::open_workspace(root, app_handle)?;
+    let tree = workspace.tree.clone();
+    
+    let mut workspace_guard = workspace_state.lock().unwrap();
+    *workspace_guard = Some(workspace);
+    
+    Ok(tree)
 }
 
 #[tauri::command]
-fn stop_watching_file(watcher_state: tauri::State<WatcherState>) -> Result<(), String> {
-    let mut watcher_guard = watcher_state.lock().unwrap();
-    *watcher_guard = None;
+fn get_workspace(workspace_state: tauri::State<WorkspaceState>) -> Option<WorkspaceEntry> {
+    let workspace_guard = workspace_state.lock().unwrap();
+    workspace_guard.as_ref().map(|workspace| workspace.tree.clone())
+}
+
+#[tauri::command]
+fn close_workspace(workspace_state: tauri::State<WorkspaceState>) -> Result<(), String> {
+    let mut workspace_guard = workspace_state.lock().unwrap();
+    *workspace_guard = None;
     Ok(())
 }
 
@@ -653,85 +1184,138 @@ fn read_file_content(file_path: String) -> Result<String, String> {
     Ok(content)
 }
 
-#[tauri::command]
-async fn save_temp_html_and_open(html_content: String) -> Result<(), String> {
-    // Create secure temp file (includes validation and permissions)
-    let temp_file = create_secure_temp_file(&html_content)?;
-    
-    // Spawn browser process (browser caches file content immediately)
-    let spawn_result = {
-        #[cfg(target_os = "macos")]
-        {
-            std::process::Command::new("open")
-                .arg(&temp_file)
-                .spawn()
+// Split `path.md:LINE` or `path.md#anchor` into the path and its location. A suffix
+// only counts when what precedes it is a markdown file, so other colons and hashes
+// in a path are left alone.
+fn parse_location_arg(arg: &str) -> (&str, Option<usize>, Option<String>) {
+    if let Some((path, anchor)) = arg.rsplit_once('#') {
+        if has_markdown_extension(Path::new(path)) && !anchor.is_empty() {
+            return (path, None, Some(anchor.to_string()));
         }
-        
-        #[cfg(target_os = "windows")]
-        {
-            std::process::Command::new("cmd")
-                .args(["/C", "start", ""])
-                .arg(&temp_file)
-                .spawn()
+    }
+    if let Some((path, line)) = arg.rsplit_once(':') {
+        if let Ok(line) = line.parse::<usize>() {
+            if has_markdown_extension(Path::new(path)) && line > 0 {
+                return (path, Some(line), None);
+            }
         }
+    }
+    (arg, None, None)
+}
+
+// First markdown file among command line arguments, resolved against the
+// working directory of the process that received them
+fn find_markdown_arg(args: &[String], cwd: &Path) -> Option<OpenedFile> {
+    args.iter()
+        .skip(1)
+        .map(|arg| parse_location_arg(arg))
+        .find(|(path, _, _)| has_markdown_extension(Path::new(path)))
+        .and_then(|(path, line, anchor)| {
+            let file_path = cwd.join(path).canonicalize().ok()?;
+            Some(OpenedFile { file_path: file_path.to_string_lossy().to_string(), line, anchor })
+        })
+}
+
+// Hand a file opened through the OS (file association, second launch) to the frontend
+fn forward_opened_file(app_handle: &AppHandle, file: OpenedFile) {
+    // Validate the file path for security
+    if let Ok(validated_path) = validate_file_path(&file.file_path) {
+        let file = OpenedFile { file_path: validated_path.to_string_lossy().to_string(), ..file };
         
-        #[cfg(target_os = "linux")]
-        {
-            std::process::Command::new("xdg-open")
-                .arg(&temp_file)
-                .spawn()
-        }
-    };
-    
-    // Check if spawn succeeded
-    let _child = spawn_result.map_err(|_| "Failed to open browser".to_string())?;
-    
-    // Give browser 2 seconds to cache the file, then cleanup
-    let temp_file_cleanup = temp_file.clone();
-    std::thread::spawn(move || {
-        std::thread::sleep(std::time::Duration::from_secs(2));
-        let _ = std::fs::remove_file(&temp_file_cleanup);
-    });
-    
-    Ok(())
+        // Store the opened file in app state
+        let opened_file_state = app_handle.state::<OpenedFileState>();
+        opened_file_state.set_file(file.clone());
+        
+        // Also try to emit the event to the frontend if it's ready
+        let _ = app_handle.emit("file-opened-via-os", &file);
+    }
+}
+
+fn focus_main_window(app_handle: &AppHandle) {
+    if let Some(window) = app_handle.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let watcher_state: WatcherState = Arc::new(Mutex::new(None));
+    let tab_state: TabState = Arc::new(Mutex::new(TabManager::default()));
     let opened_file_state = OpenedFileState::default();
+    let highlight_state: HighlightState = Arc::new(HighlightEngine::new());
+    let workspace_state: WorkspaceState = Arc::new(Mutex::new(None));
     
     tauri::Builder::default()
+        // Must be registered first: later launches hand their arguments to this
+        // process and exit before anything else starts
+        .plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+            if let Some(file) = find_markdown_arg(&args, Path::new(&cwd)) {
+                forward_opened_file(app, file);
+            }
+            focus_main_window(app);
+        }))
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
-        .manage(watcher_state)
+        .manage(tab_state)
         .manage(opened_file_state)
+        .manage(highlight_state)
+        .manage(workspace_state)
         .invoke_handler(tauri::generate_handler![
             greet, 
             parse_markdown, 
-            read_markdown_file, 
+            get_table_of_contents,
+            locate_source_line,
+            render_slides,
+            open_presenter_window,
+            read_markdown_file,
+            refresh_markdown_file, 
+            diff_markdown_files,
+            get_git_info,
+            get_file_history,
+            diff_with_revision,
             get_launch_args,
             get_opened_file,
-            start_watching_file,
-            stop_watching_file,
+            open_tab,
+            navigate_tab,
+            close_tab,
+            activate_tab,
+            list_tabs,
+            update_tab_view,
+            set_watch_debounce,
+            list_highlight_themes,
+            set_highlight_themes,
+            import_highlight_theme,
+            open_workspace,
+            get_workspace,
+            close_workspace,
             export_html,
-            read_file_content,
-            save_temp_html_and_open
+            export_standalone_html,
+            export_docx,
+            export_pdf,
+            export_epub,
+            export_slide_deck,
+            export_folder_epub,
+            read_file_content
         ])
         .setup(|app| {
+            // Load syntax definitions and themes in the background so the
+            // first render doesn't pay the deserialization cost
+            let highlighter = app.state::<HighlightState>().inner().clone();
+            if let Ok(config_dir) = app.path().app_config_dir() {
+                highlighter.set_custom_theme_dir(config_dir.join(CUSTOM_THEME_DIR));
+            }
+            std::thread::spawn(move || highlighter.warm_up());
+            
             // Check command line args during setup (fallback for other platforms)
             let setup_args = env::args().collect::<Vec<String>>();
+            let current_dir = env::current_dir().unwrap_or_default();
             
-            // Check for markdown files in args
-            for arg in setup_args.iter().skip(1) {
-                if arg.ends_with(".md") || arg.ends_with(".markdown") || 
-                   arg.ends_with(".mdown") || arg.ends_with(".mkd") {
-                    // For command line arguments, store in the opened file state
-                    let opened_file_state = app.state::<OpenedFileState>();
-                    opened_file_state.set_file(arg.clone());
-                    break;
-                }
+            // For command line arguments, store in the opened file state
+            if let Some(file) = find_markdown_arg(&setup_args, &current_dir) {
+                let opened_file_state = app.state::<OpenedFileState>();
+                opened_file_state.set_file(file);
             }
             
             Ok(())
@@ -754,19 +1338,8 @@ pub fn run() {
                             url_str.to_string()
                         };
                         
-                        if file_path.ends_with(".md") || file_path.ends_with(".markdown") || 
-                           file_path.ends_with(".mdown") || file_path.ends_with(".mkd") {
-                            // Validate the file path for security
-                            if let Ok(validated_path) = validate_file_path(&file_path) {
-                                let validated_str = validated_path.to_string_lossy().to_string();
-                                
-                                // Store the opened file in app state
-                                let opened_file_state = app_handle.state::<OpenedFileState>();
-                                opened_file_state.set_file(validated_str.clone());
-                                
-                                // Also try to emit the event to the frontend if it's ready
-                                let _ = app_handle.emit("file-opened-via-os", &validated_str);
-                            }
+                        if has_markdown_extension(Path::new(&file_path)) {
+                            forward_opened_file(app_handle, OpenedFile { file_path, ..OpenedFile::default() });
                             break;
                         }
                     }
@@ -0,0 +1,237 @@
+use std::path::{Path, PathBuf};
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use serde::Serialize;
+
+use crate::{check_forbidden_path, has_markdown_extension, validate_file_path};
+
+// Files GitHub shows when a link points at a folder
+const DIRECTORY_INDEX_FILES: &[&str] = &["README.md", "readme.md", "index.md"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkKind {
+    // `#section` within the current document
+    Anchor,
+    // Another markdown file, opened in the viewer
+    Markdown,
+    // Any other local file, revealed in the system file manager
+    File,
+    // Web, mail and other URLs, opened in the default handler
+    External,
+    // Relative target that doesn't exist or may not be opened
+    Broken,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedLink {
+    pub kind: LinkKind,
+    pub path: Option<String>,
+    pub anchor: Option<String>,
+}
+
+impl ResolvedLink {
+    fn new(kind: LinkKind, path: Option<PathBuf>, anchor: Option<String>) -> Self {
+        Self { kind, path: path.map(|path| path.to_string_lossy().to_string()), anchor }
+    }
+}
+
+// Classify a link target, resolving relative paths against the document's folder
+pub fn resolve_link(href: &str, base_file: Option<&Path>) -> ResolvedLink {
+    let href = href.trim();
+    if let Some(anchor) = href.strip_prefix('#') {
+        return ResolvedLink::new(LinkKind::Anchor, None, non_empty(percent_decode(anchor)));
+    }
+
+    let (target, anchor) = match href.split_once('#') {
+        Some((target, anchor)) => (target, non_empty(percent_decode(anchor))),
+        None => (href, None),
+    };
+    let target = target.split('?').next().unwrap_or_default();
+
+    let local_path = match url_scheme(target) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("file") => target[scheme.len() + 1..].trim_start_matches("//"),
+        Some(_) => return ResolvedLink::new(LinkKind::External, None, None),
+        None => target,
+    };
+    if local_path.is_empty() {
+        return ResolvedLink::new(LinkKind::Broken, None, anchor);
+    }
+
+    let local_path = PathBuf::from(percent_decode(local_path));
+    let joined = match base_file.and_then(Path::parent) {
+        _ if local_path.is_absolute() => local_path,
+        Some(base_dir) => base_dir.join(local_path),
+        None => return ResolvedLink::new(LinkKind::Broken, None, anchor),
+    };
+
+    // Canonicalizing first resolves `..` segments, which validate_file_path rejects outright
+    let Ok(canonical) = joined.canonicalize() else {
+        return ResolvedLink::new(LinkKind::Broken, None, anchor);
+    };
+    let canonical = if canonical.is_dir() {
+        match DIRECTORY_INDEX_FILES.iter().map(|name| canonical.join(name)).find(|index| index.is_file()) {
+            Some(index) => index,
+            None => return ResolvedLink::new(LinkKind::Broken, None, anchor),
+        }
+    } else {
+        canonical
+    };
+
+    if has_markdown_extension(&canonical) {
+        match validate_file_path(&canonical.to_string_lossy()) {
+            Ok(validated) => ResolvedLink::new(LinkKind::Markdown, Some(validated), anchor),
+            Err(_) => ResolvedLink::new(LinkKind::Broken, None, anchor),
+        }
+    } else if check_forbidden_path(&canonical).is_ok() {
+        ResolvedLink::new(LinkKind::File, Some(canonical), None)
+    } else {
+        ResolvedLink::new(LinkKind::Broken, None, None)
+    }
+}
+
+// Tag every `<a href>` with its resolved kind, path and anchor as data attributes
+pub fn annotate_links(html: &str, base_file: Option<&Path>) -> Result<String, String> {
+    rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("a[href]", |el| {
+                let href = el.get_attribute("href").unwrap_or_default();
+                let link = resolve_link(&html_escape::decode_html_entities(&href), base_file);
+
+                let kind = serde_json::to_value(link.kind)?;
+                el.set_attribute("data-link-kind", kind.as_str().unwrap_or_default())?;
+                if let Some(path) = &link.path {
+                    el.set_attribute("data-link-path", &html_escape::encode_double_quoted_attribute(path))?;
+                }
+                if let Some(anchor) = &link.anchor {
+                    el.set_attribute("data-link-anchor", &html_escape::encode_double_quoted_attribute(anchor))?;
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| format!("Failed to process links: {}", e))
+}
+
+// Scheme of an absolute URL; single letters are Windows drive letters, not schemes
+fn url_scheme(target: &str) -> Option<&str> {
+    let colon = target.find(':')?;
+    let scheme = &target[..colon];
+    let valid = scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then_some(scheme)
+}
+
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes.get(index + 1..index + 3)
+            .and_then(|pair| std::str::from_utf8(pair).ok())
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn non_empty(text: String) -> Option<String> {
+    if text.is_empty() { None } else { Some(text) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // A folder with `guide.md`, `My Notes.md`, `image.png` and `docs/README.md`
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-links-{}-{}", name, std::process::id()));
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        for file in ["guide.md", "My Notes.md", "image.png", "docs/README.md"] {
+            fs::write(dir.join(file), "x").unwrap();
+        }
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn classifies_anchors_and_external_links() {
+        let link = resolve_link("#Install%20Steps", None);
+        assert_eq!(link.kind, LinkKind::Anchor);
+        assert_eq!(link.anchor.as_deref(), Some("Install Steps"));
+
+        for href in ["https://example.com/a.md", "mailto:a@b.org", "vscode://file/x"] {
+            assert_eq!(resolve_link(href, None).kind, LinkKind::External, "{}", href);
+        }
+        assert_eq!(resolve_link("#", None).anchor, None);
+    }
+
+    #[test]
+    fn resolves_relative_markdown_links() {
+        let dir = fixture("relative");
+        let base = dir.join("docs").join("README.md");
+
+        let link = resolve_link("../guide.md#setup", Some(&base));
+        assert_eq!(link.kind, LinkKind::Markdown);
+        assert_eq!(link.path, Some(dir.join("guide.md").to_string_lossy().to_string()));
+        assert_eq!(link.anchor.as_deref(), Some("setup"));
+
+        let link = resolve_link("My%20Notes.md?plain=1", Some(&dir.join("guide.md")));
+        assert_eq!(link.path, Some(dir.join("My Notes.md").to_string_lossy().to_string()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn opens_folder_readmes_and_other_files() {
+        let dir = fixture("folders");
+        let base = dir.join("guide.md");
+
+        let link = resolve_link("docs/", Some(&base));
+        assert_eq!(link.kind, LinkKind::Markdown);
+        assert_eq!(link.path, Some(dir.join("docs").join("README.md").to_string_lossy().to_string()));
+
+        let link = resolve_link("./image.png#ignored", Some(&base));
+        assert_eq!(link.kind, LinkKind::File);
+        assert_eq!(link.anchor, None);
+
+        let file_url = format!("file://{}", dir.join("guide.md").to_string_lossy());
+        assert_eq!(resolve_link(&file_url, None).kind, LinkKind::Markdown);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn marks_missing_targets_broken() {
+        let dir = fixture("broken");
+        let base = dir.join("guide.md");
+        assert_eq!(resolve_link("missing.md", Some(&base)).kind, LinkKind::Broken);
+        assert_eq!(resolve_link("guide.md", None).kind, LinkKind::Broken);
+        assert_eq!(resolve_link("?query", Some(&base)).kind, LinkKind::Broken);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn annotates_link_elements() {
+        let html = annotate_links("<a href=\"#a&amp;b\">x</a><a href=\"https://x.org\">y</a><a>z</a>", None).unwrap();
+        assert_eq!(html, "<a href=\"#a&amp;b\" data-link-kind=\"anchor\" data-link-anchor=\"a&amp;b\">x</a>\
+<a href=\"https://x.org\" data-link-kind=\"external\">y</a><a>z</a>");
+    }
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%e2%82%ac"), "%zz€");
+        assert_eq!(url_scheme("C:\\notes\\a.md"), None);
+        assert_eq!(url_scheme("git+ssh://host"), Some("git+ssh"));
+    }
+}
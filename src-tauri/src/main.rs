@@ -2,5 +2,11 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // `markdown-viewer render ...` runs headless and never opens a window
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(exit_code) = markdown_viewer_lib::cli::run_from_args(&args) {
+        std::process::exit(exit_code);
+    }
+    
     markdown_viewer_lib::run()
 }
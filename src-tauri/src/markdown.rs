@@ -0,0 +1,484 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, LinkType, Parser, Tag, TagEnd};
+use serde::Serialize;
+
+use crate::alerts::{alert_event_titles, expand_containers, render_alerts, AlertTitles};
+use crate::gfm::{autolink_events, filter_raw_html, MarkdownOptions};
+use crate::front_matter::{extract_front_matter, FrontMatter};
+use crate::highlight::{CodeBlockInfo, HighlightEngine};
+use crate::math::render_math;
+use crate::sanitize::{sanitize_html, SanitizeLevel, SanitizeReport};
+use crate::source_map::{annotate_source_lines, line_span};
+use crate::toc::{build_toc, Slugger, TocEntry};
+
+// A parser event together with the byte range of source it came from
+pub type SourceEvent<'a> = (Event<'a>, Range<usize>);
+
+// Marks the boundaries between top-level blocks in the rendered HTML. prepare_source
+// replaces NUL characters in the input, so the document itself can't contain one.
+// The newline keeps the HTML writer from adding one of its own.
+const BLOCK_SEPARATOR: &str = "\0\n";
+
+// Rendered HTML plus everything the frontend needs to know about it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedDocument {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+    pub front_matter: Option<FrontMatter>,
+    pub sanitize_report: SanitizeReport,
+    // Local files the document embeds, such as images; only known when rendering from a file
+    pub dependencies: Vec<String>,
+    // The same HTML split into top-level blocks, when requested
+    pub blocks: Option<Vec<RenderedBlock>>,
+}
+
+// The HTML of one top-level block (paragraph, list, table, ...) and the lines it came from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedBlock {
+    pub html: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+// Maps byte offsets in the source to 1-based line numbers
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self { line_starts }
+    }
+
+    pub fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line + 1,
+            Err(line) => line,
+        }
+    }
+}
+
+// Markdown ready for parsing: front matter split off and `:::` containers expanded.
+// Expansion keeps line numbers intact, so `text` is what ranges and lines refer to.
+struct PreparedSource<'a> {
+    text: Cow<'a, str>,
+    body_offset: usize,
+    front_matter: Option<FrontMatter>,
+    alert_titles: AlertTitles,
+}
+
+impl PreparedSource<'_> {
+    // Front matter `markdown:` settings take priority over the caller's options
+    fn markdown_options(&self, options: MarkdownOptions) -> MarkdownOptions {
+        options.with_overrides(self.front_matter.as_ref().and_then(|front_matter| front_matter.fields.get("markdown")))
+    }
+}
+
+fn prepare_source(markdown_content: &str) -> PreparedSource<'_> {
+    // CommonMark replaces NUL with U+FFFD; pulldown-cmark leaves that to the caller
+    if markdown_content.contains('\0') {
+        let cleaned = markdown_content.replace('\0', "\u{FFFD}");
+        let prepared = prepare_source(&cleaned);
+        return PreparedSource { text: Cow::Owned(prepared.text.into_owned()), ..prepared };
+    }
+
+    let (front_matter, body_offset) = extract_front_matter(markdown_content);
+    let (header, body) = markdown_content.split_at(body_offset);
+    let first_line = header.matches('\n').count() + 1;
+
+    let (body, alert_titles) = expand_containers(body, first_line);
+    let text = match body {
+        Cow::Borrowed(_) => Cow::Borrowed(markdown_content),
+        Cow::Owned(body) => Cow::Owned(format!("{}{}", header, body)),
+    };
+    PreparedSource { text, body_offset, front_matter, alert_titles }
+}
+
+// Parse the body, keeping ranges relative to the whole file
+fn parse_document<'a>(source: &'a PreparedSource<'_>, options: &MarkdownOptions) -> Vec<SourceEvent<'a>> {
+    let body_offset = source.body_offset;
+    Parser::new_ext(&source.text[body_offset..], options.parser_options())
+        .into_offset_iter()
+        .map(|(event, range)| (event, range.start + body_offset..range.end + body_offset))
+        .collect()
+}
+
+// Render markdown to sanitized HTML using a shared highlighting engine
+pub fn render_markdown(
+    markdown_content: &str,
+    highlighter: &HighlightEngine,
+    sanitize_level: SanitizeLevel,
+    options: MarkdownOptions,
+) -> Result<RenderedDocument, String> {
+    render(markdown_content, highlighter, sanitize_level, options, false)
+}
+
+// Like render_markdown, but also returns the top-level blocks so later renders can be diffed
+pub fn render_markdown_blocks(
+    markdown_content: &str,
+    highlighter: &HighlightEngine,
+    sanitize_level: SanitizeLevel,
+    options: MarkdownOptions,
+) -> Result<RenderedDocument, String> {
+    render(markdown_content, highlighter, sanitize_level, options, true)
+}
+
+fn render(
+    markdown_content: &str,
+    highlighter: &HighlightEngine,
+    sanitize_level: SanitizeLevel,
+    options: MarkdownOptions,
+    split_blocks: bool,
+) -> Result<RenderedDocument, String> {
+    let source = prepare_source(markdown_content);
+    let options = source.markdown_options(options);
+    let line_index = LineIndex::new(&source.text);
+    let mut events = parse_document(&source, &options);
+    let block_ranges = if split_blocks { top_level_blocks(&events) } else { Vec::new() };
+
+    if options.autolinks {
+        events = autolink_events(events);
+    }
+    if options.tag_filter {
+        events = filter_raw_html(events);
+    }
+    let (events, headings) = anchor_headings(events, &line_index);
+    let events = render_alerts(events, &source.alert_titles, &line_index);
+    let events = highlight_code_blocks(events, highlighter);
+    let events = render_math_events(events);
+    let mut events = annotate_source_lines(events, &source.text, &line_index);
+    if split_blocks {
+        events = separate_blocks(events, &block_ranges);
+    }
+
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, events.into_iter().map(|(event, _)| event));
+
+    // Raw HTML in the markdown passes straight through pulldown-cmark,
+    // so the whole document is filtered against the allowlist
+    let (html, sanitize_report) = sanitize_html(&html_output, sanitize_level)?;
+
+    let (html, blocks) = if split_blocks {
+        let blocks = split_rendered_blocks(&html, &block_ranges, &source.text, &line_index);
+        (blocks.iter().map(|block| block.html.as_str()).collect(), Some(blocks))
+    } else {
+        (html, None)
+    };
+    Ok(RenderedDocument {
+        html,
+        toc: build_toc(headings),
+        front_matter: source.front_matter,
+        sanitize_report,
+        dependencies: Vec::new(),
+        blocks,
+    })
+}
+
+// A document parsed for exporters that write something other than HTML
+pub struct DocumentEvents {
+    pub events: Vec<Event<'static>>,
+    // Flat, in document order, with the slugs the HTML view uses as heading ids
+    pub headings: Vec<TocEntry>,
+    pub front_matter: Option<FrontMatter>,
+    // Titles of the alert blockquotes, default or from `:::` containers, keyed by the
+    // index of their start event
+    pub alert_titles: HashMap<usize, String>,
+}
+
+// Parse a document with the same options and autolinks as render_markdown
+pub fn document_events(markdown_content: &str, options: MarkdownOptions) -> DocumentEvents {
+    let source = prepare_source(markdown_content);
+    let options = source.markdown_options(options);
+    let mut events = parse_document(&source, &options);
+    if options.autolinks {
+        events = autolink_events(events);
+    }
+    let line_index = LineIndex::new(&source.text);
+    let (_, headings) = anchor_headings(events.clone(), &line_index);
+    let alert_titles = alert_event_titles(&events, &source.alert_titles, &line_index);
+    DocumentEvents {
+        events: events.into_iter().map(|(event, _)| event.into_static()).collect(),
+        headings,
+        front_matter: source.front_matter,
+        alert_titles,
+    }
+}
+
+// Where a link leads. Email autolinks carry the bare address, which the HTML writer
+// turns into a mailto: link; other writers have to do the same.
+pub fn link_target(link_type: LinkType, dest_url: &str) -> Cow<'_, str> {
+    if link_type == LinkType::Email && !dest_url.to_ascii_lowercase().starts_with("mailto:") {
+        Cow::Owned(format!("mailto:{}", dest_url))
+    } else {
+        Cow::Borrowed(dest_url)
+    }
+}
+
+// A paragraph holding nothing but `[TOC]`, given the events after its start
+pub fn is_toc_marker(rest: &[Event]) -> bool {
+    let mut text = String::new();
+    for event in rest {
+        match event {
+            Event::Text(content) => text.push_str(content),
+            Event::End(TagEnd::Paragraph) => return text == "[TOC]",
+            _ => return false,
+        }
+    }
+    false
+}
+
+// Inline `<br>` tags, which exporters turn into line breaks
+pub fn is_line_break(html: &str) -> bool {
+    matches!(html.trim().to_ascii_lowercase().as_str(), "<br>" | "<br/>" | "<br />")
+}
+
+// Source ranges of the top-level blocks, in document order
+fn top_level_blocks(events: &[SourceEvent]) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut depth = 0usize;
+    for (event, range) in events {
+        match event {
+            Event::Start(_) => {
+                if depth == 0 {
+                    blocks.push(range.clone());
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth = depth.saturating_sub(1),
+            // Thematic breaks and the like have no start and end
+            _ if depth == 0 => blocks.push(range.clone()),
+            _ => {}
+        }
+    }
+    blocks
+}
+
+// Put a separator between the events of consecutive top-level blocks. Later stages keep
+// the source ranges of the events they replace, so each event still maps to its block.
+fn separate_blocks<'a>(events: Vec<SourceEvent<'a>>, blocks: &[Range<usize>]) -> Vec<SourceEvent<'a>> {
+    let mut separated = Vec::with_capacity(events.len() + blocks.len());
+    let mut current = 0;
+    for (event, range) in events {
+        let block = blocks.partition_point(|block| block.start <= range.start).saturating_sub(1);
+        while current < block {
+            separated.push((Event::Html(BLOCK_SEPARATOR.into()), range.start..range.start));
+            current += 1;
+        }
+        separated.push((event, range));
+    }
+    separated
+}
+
+// Split the sanitized HTML at the separators. If raw HTML swallowed a separator
+// (say, inside a stripped element) the whole document becomes a single block.
+fn split_rendered_blocks(html: &str, blocks: &[Range<usize>], source: &str, line_index: &LineIndex) -> Vec<RenderedBlock> {
+    let parts: Vec<&str> = html.split(BLOCK_SEPARATOR).collect();
+    if parts.len() == blocks.len() {
+        return parts.into_iter().zip(blocks)
+            .map(|(part, range)| {
+                let (start_line, end_line) = line_span(range, source, line_index);
+                RenderedBlock { html: part.to_string(), start_line, end_line }
+            })
+            .collect();
+    }
+
+    let (Some(first), Some(last)) = (blocks.first(), blocks.last()) else {
+        return Vec::new();
+    };
+    let (start_line, _) = line_span(first, source, line_index);
+    let (_, end_line) = line_span(last, source, line_index);
+    vec![RenderedBlock { html: html.replace(BLOCK_SEPARATOR, ""), start_line, end_line }]
+}
+
+// Build the nested table of contents without rendering the document
+pub fn table_of_contents(markdown_content: &str, options: MarkdownOptions) -> Vec<TocEntry> {
+    let source = prepare_source(markdown_content);
+    let options = source.markdown_options(options);
+    let line_index = LineIndex::new(&source.text);
+    let events = parse_document(&source, &options);
+
+    let (_, headings) = anchor_headings(events, &line_index);
+    build_toc(headings)
+}
+
+// Plain text of the first heading, used as a document title in file listings
+pub fn first_heading(markdown_content: &str) -> Option<String> {
+    let source = prepare_source(markdown_content);
+    let mut events = Parser::new_ext(&source.text[source.body_offset..], MarkdownOptions::default().parser_options());
+
+    events.find(|event| matches!(event, Event::Start(Tag::Heading { .. })))?;
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::Text(content) | Event::Code(content) => text.push_str(&content),
+            Event::End(TagEnd::Heading(_)) => break,
+            _ => {}
+        }
+    }
+
+    let text = text.trim();
+    if text.is_empty() { None } else { Some(text.to_string()) }
+}
+
+// Give every heading a slug id and collect the flat heading outline
+fn anchor_headings<'a>(
+    events: Vec<SourceEvent<'a>>,
+    line_index: &LineIndex,
+) -> (Vec<SourceEvent<'a>>, Vec<TocEntry>) {
+    let mut slugger = Slugger::default();
+    let mut output = Vec::with_capacity(events.len());
+    let mut headings = Vec::new();
+
+    // Explicit ids take priority, so reserve them before generating slugs
+    for (event, _) in &events {
+        if let Event::Start(Tag::Heading { id: Some(id), .. }) = event {
+            slugger.reserve(id);
+        }
+    }
+
+    let mut iter = events.into_iter();
+    while let Some((event, range)) = iter.next() {
+        let Event::Start(Tag::Heading { level, id, classes, attrs }) = event else {
+            output.push((event, range));
+            continue;
+        };
+
+        // Buffer the heading contents so its text is known before the start tag is written
+        let mut inner = Vec::new();
+        let mut text = String::new();
+        for (inner_event, inner_range) in iter.by_ref() {
+            let is_end = matches!(inner_event, Event::End(TagEnd::Heading(_)));
+            if let Event::Text(content) | Event::Code(content) = &inner_event {
+                text.push_str(content);
+            }
+            inner.push((inner_event, inner_range));
+            if is_end {
+                break;
+            }
+        }
+
+        let slug = match id {
+            Some(id) => id.to_string(),
+            None => slugger.slug(&text),
+        };
+
+        let mut start_tag = format!("<{} id=\"{}\"", level, html_escape::encode_double_quoted_attribute(&slug));
+        if !classes.is_empty() {
+            let classes: Vec<&str> = classes.iter().map(|class| class.as_ref()).collect();
+            start_tag.push_str(&format!(" class=\"{}\"", html_escape::encode_double_quoted_attribute(&classes.join(" "))));
+        }
+        for (name, value) in &attrs {
+            let value = value.as_deref().unwrap_or("");
+            start_tag.push_str(&format!(" {}=\"{}\"", html_escape::encode_safe(name), html_escape::encode_double_quoted_attribute(value)));
+        }
+        start_tag.push('>');
+
+        headings.push(TocEntry {
+            level: level as u8,
+            text: text.trim().to_string(),
+            slug,
+            line: line_index.line_of(range.start),
+            children: Vec::new(),
+        });
+
+        output.push((Event::Html(CowStr::from(start_tag)), range));
+        output.extend(inner);
+    }
+
+    (output, headings)
+}
+
+// Replace code block events with pre-rendered, syntax highlighted HTML
+fn highlight_code_blocks<'a>(
+    events: Vec<SourceEvent<'a>>,
+    highlighter: &HighlightEngine,
+) -> Vec<SourceEvent<'a>> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut code_block: Option<(CodeBlockInfo, String, Range<usize>)> = None;
+
+    for (event, range) in events {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let info = match kind {
+                    CodeBlockKind::Fenced(info) => CodeBlockInfo::parse(&info),
+                    CodeBlockKind::Indented => CodeBlockInfo::default(),
+                };
+                code_block = Some((info, String::new(), range));
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, code, _)) = code_block.as_mut() {
+                    code.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((info, code, range)) = code_block.take() {
+                    let rendered = highlighter.render_code_block(&code, &info);
+                    output.push((Event::Html(CowStr::from(rendered)), range));
+                }
+            }
+            other => output.push((other, range)),
+        }
+    }
+
+    output
+}
+
+// Replace `$inline$` and `$$display$$` math with MathML
+fn render_math_events(events: Vec<SourceEvent<'_>>) -> Vec<SourceEvent<'_>> {
+    events.into_iter()
+        .map(|(event, range)| match event {
+            Event::InlineMath(latex) => (Event::InlineHtml(CowStr::from(render_math(&latex, false))), range),
+            Event::DisplayMath(latex) => (Event::InlineHtml(CowStr::from(render_math(&latex, true))), range),
+            other => (other, range),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(markdown: &str) -> String {
+        let engine = HighlightEngine::new();
+        render_markdown(markdown, &engine, SanitizeLevel::default(), MarkdownOptions::default()).unwrap().html
+    }
+
+    #[test]
+    fn replaces_math_events_with_mathml() {
+        let text = "$x$ and\n\n$$y$$";
+        let events: Vec<SourceEvent> = Parser::new_ext(text, MarkdownOptions::default().parser_options()).into_offset_iter().collect();
+        let html: Vec<String> = render_math_events(events).into_iter()
+            .filter_map(|(event, range)| match event {
+                Event::InlineHtml(html) => Some(format!("{:?} {}", range, html)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(html.len(), 2);
+        assert!(html[0].starts_with("0..3 <span class=\"math math-inline\"><math display=\"inline\""), "{}", html[0]);
+        assert!(html[1].starts_with("9..14 <span class=\"math math-display\"><math display=\"block\""), "{}", html[1]);
+    }
+
+    #[test]
+    fn renders_inline_and_display_math() {
+        let html = render("Area $\\pi r^2$ of\n\n$$a<b$$\n");
+        assert!(html.contains("<span class=\"math math-inline\"><math display=\"inline\""), "{}", html);
+        assert!(html.contains("<annotation encoding=\"application/x-tex\">\\pi r^2</annotation>"), "{}", html);
+        assert!(html.contains("<span class=\"math math-display\"><math display=\"block\""), "{}", html);
+        assert!(html.contains(">a&lt;b</annotation>"), "{}", html);
+    }
+
+    #[test]
+    fn leaves_dollars_in_code_alone() {
+        let html = render("`$x$` costs \\$5\n\n```\n$$y$$\n```\n");
+        assert!(!html.contains("<math"), "{}", html);
+        assert!(html.contains("<code>$x$</code> costs $5"), "{}", html);
+        assert!(html.contains("$$y$$"), "{}", html);
+    }
+}
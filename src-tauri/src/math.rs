@@ -0,0 +1,81 @@
+use pulldown_latex::config::DisplayMode;
+use pulldown_latex::{push_mathml, Parser, RenderConfig, Storage};
+
+// Render TeX math to MathML so it displays without any client-side library.
+// The TeX source is kept as an annotation for copy/paste and accessibility.
+pub fn render_math(latex: &str, display: bool) -> String {
+    let storage = Storage::new();
+    let parser = Parser::new(latex, &storage);
+    // pulldown-latex writes the annotation as it is given
+    let annotation = html_escape::encode_text(latex);
+    let config = RenderConfig {
+        display_mode: if display { DisplayMode::Block } else { DisplayMode::Inline },
+        annotation: Some(&annotation),
+        // The namespace lets exported HTML and Word pick up the markup as MathML
+        xml: true,
+        ..RenderConfig::default()
+    };
+
+    let mut mathml = String::new();
+    let rendered = push_mathml(&mut mathml, parser, config).ok().map(|_| escape_operators(&mathml));
+    math_span(latex, display, rendered)
+}
+
+// pulldown-latex writes operators and identifiers such as `<` and `\&` unescaped, as
+// `<mo><</mo>`, which would start a tag. They are always an element's only content,
+// possibly negated with a combining slash.
+fn escape_operators(mathml: &str) -> String {
+    let mut escaped = mathml.to_string();
+    for (character, entity) in [('&', "&amp;"), ('<', "&lt;"), ('>', "&gt;")] {
+        for negation in ["", "\u{338}"] {
+            let raw = format!(">{}{}</", character, negation);
+            if escaped.contains(&raw) {
+                escaped = escaped.replace(&raw, &format!(">{}{}</", entity, negation));
+            }
+        }
+    }
+    escaped
+}
+
+// Unknown commands are rendered inline in the error colour, so `mathml` is only None
+// on writer errors; the source is shown instead
+fn math_span(latex: &str, display: bool, mathml: Option<String>) -> String {
+    // Display math is still a <span> because it can sit inside a paragraph
+    let class = if display { "math math-display" } else { "math math-inline" };
+    let content = mathml.unwrap_or_else(|| format!("<code>{}</code>", html_escape::encode_text(latex)));
+    format!("<span class=\"{}\">{}</span>", class, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_inline_and_display_math() {
+        let inline = render_math("x^2", false);
+        assert!(inline.starts_with("<span class=\"math math-inline\"><math"), "{}", inline);
+        assert!(!inline.contains("display=\"block\""));
+        assert!(inline.contains("<annotation encoding=\"application/x-tex\">x^2</annotation>"), "{}", inline);
+
+        let display = render_math("a < b", true);
+        assert!(display.starts_with("<span class=\"math math-display\"><math"), "{}", display);
+        assert!(display.contains("display=\"block\""));
+        assert!(display.contains("a &lt; b</annotation>"), "{}", display);
+    }
+
+    #[test]
+    fn escapes_operators_and_the_annotation() {
+        let mathml = render_math("a<b \\& c>d", false);
+        assert!(mathml.contains("<mo>&lt;</mo>"), "{}", mathml);
+        assert!(mathml.contains("<mo>&gt;</mo>"), "{}", mathml);
+        assert!(mathml.contains(">&amp;</"), "{}", mathml);
+        assert!(mathml.contains(">a&lt;b \\&amp; c&gt;d</annotation>"), "{}", mathml);
+        assert_eq!(escape_operators("<mo>\u{2264}</mo><mo><\u{338}</mo>"), "<mo>\u{2264}</mo><mo>&lt;\u{338}</mo>");
+    }
+
+    #[test]
+    fn falls_back_to_the_source() {
+        assert_eq!(math_span("a < b", false, None), "<span class=\"math math-inline\"><code>a &lt; b</code></span>");
+        assert_eq!(math_span("x", true, Some("<math></math>".to_string())), "<span class=\"math math-display\"><math></math></span>");
+    }
+}
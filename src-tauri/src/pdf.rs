@@ -0,0 +1,1712 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use pdf_writer::types::{ActionType, AnnotationType, CidFontType, FontFlags, PageMode, SystemInfo, UnicodeCmap};
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use pulldown_cmark::{Alignment, BlockQuoteKind, CodeBlockKind, Event, HeadingLevel, Tag, TagEnd};
+use serde::Deserialize;
+use syntect::highlighting::FontStyle;
+use ttf_parser::{Face, GlyphId};
+
+use crate::alerts::alert_title;
+use crate::front_matter::FrontMatter;
+use crate::gfm::MarkdownOptions;
+use crate::highlight::{CodeBlockInfo, HighlightEngine};
+use crate::images::{read_header, read_local_image, ImageFormat, ImageHeader};
+use crate::markdown::{document_events, is_line_break, is_toc_marker, link_target};
+use crate::toc::TocEntry;
+
+const MARGIN: f32 = 72.0;
+const BODY_SIZE: f32 = 10.5;
+const FOOTNOTE_SIZE: f32 = 9.0;
+const CODE_SIZE: f32 = 8.5;
+const CODE_LINE_HEIGHT: f32 = 11.5;
+const CODE_PADDING: f32 = 6.0;
+const RUNNING_TEXT_SIZE: f32 = 8.5;
+// Line height as a multiple of the font size
+const LINE_SPACING: f32 = 1.4;
+const PARAGRAPH_GAP: f32 = 8.0;
+const LIST_GAP: f32 = 2.0;
+const LIST_INDENT: f32 = 18.0;
+const QUOTE_INDENT: f32 = 14.0;
+const CELL_PADDING: f32 = 4.0;
+const TAB_WIDTH: usize = 4;
+// Images are laid out at 96 DPI
+const POINTS_PER_PIXEL: f32 = 0.75;
+// Larger images would take too much memory to decode
+const MAX_IMAGE_PIXELS: u64 = 40_000_000;
+
+type Color = [f32; 3];
+
+const fn rgb(hex: u32) -> Color {
+    [
+        ((hex >> 16) & 0xFF) as f32 / 255.0,
+        ((hex >> 8) & 0xFF) as f32 / 255.0,
+        (hex & 0xFF) as f32 / 255.0,
+    ]
+}
+
+// The palette of the HTML view
+const TEXT: Color = rgb(0x1F2328);
+const MUTED: Color = rgb(0x57606A);
+const LINK: Color = rgb(0x0969DA);
+const BORDER: Color = rgb(0xD0D7DE);
+const CODE_BACKGROUND: Color = rgb(0xF6F8FA);
+const CODE_TITLE_BACKGROUND: Color = rgb(0xE9ECEF);
+const HIGHLIGHTED_LINE: Color = rgb(0xFFF3C4);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    #[default]
+    Letter,
+    A4,
+}
+
+impl PageSize {
+    // Width and height in points
+    fn dimensions(self) -> (f32, f32) {
+        match self {
+            PageSize::Letter => (612.0, 792.0),
+            PageSize::A4 => (595.28, 841.89),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PdfOptions {
+    pub page_size: PageSize,
+    // Running header and footer. {title}, {page} and {pages} are filled in; an empty
+    // string leaves them out.
+    pub header: String,
+    pub footer: String,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::default(),
+            header: "{title}".to_string(),
+            footer: "Page {page} of {pages}".to_string(),
+        }
+    }
+}
+
+// Lay a markdown document out on pages and write it as a PDF, with bookmarks for its
+// headings. Text is set in the bundled DejaVu fonts, embedded with only the glyphs the
+// document uses; characters none of them has are drawn as a box but can still be
+// searched and copied.
+pub fn export_pdf(
+    markdown_content: &str,
+    base_file: Option<&Path>,
+    title: &str,
+    highlighter: &HighlightEngine,
+    markdown_options: MarkdownOptions,
+    pdf_options: &PdfOptions,
+) -> Result<Vec<u8>, String> {
+    let document = document_events(markdown_content, markdown_options);
+    let mut writer = PdfWriter::new(highlighter, base_file.and_then(Path::parent), pdf_options.page_size, document.headings);
+    if let Some(title) = document.front_matter.as_ref().and_then(|front_matter| front_matter.title.as_deref()) {
+        writer.title_block(title);
+    }
+    writer.write_events(&document.events, &document.alert_titles);
+    writer.finish(title, document.front_matter.as_ref(), pdf_options)
+}
+
+// DejaVu Sans and DejaVu Sans Mono in their book and bold cuts. Oblique text is the
+// upright cut slanted, so those cuts aren't bundled.
+const FONT_FILES: [&[u8]; 4] = [
+    include_bytes!("../fonts/DejaVuSans.ttf"),
+    include_bytes!("../fonts/DejaVuSans-Bold.ttf"),
+    include_bytes!("../fonts/DejaVuSansMono.ttf"),
+    include_bytes!("../fonts/DejaVuSansMono-Bold.ttf"),
+];
+const FONT_NAMES: [&[u8]; 4] = [b"F0", b"F1", b"F2", b"F3"];
+const BASE_FONTS: [&str; 4] = ["DejaVuSans", "DejaVuSans-Bold", "DejaVuSansMono", "DejaVuSansMono-Bold"];
+// Horizontal shear of oblique text
+const OBLIQUE_SLANT: f32 = 0.2;
+
+fn faces() -> &'static [Face<'static>; 4] {
+    static FACES: OnceLock<[Face<'static>; 4]> = OnceLock::new();
+    FACES.get_or_init(|| FONT_FILES.map(|data| Face::parse(data, 0).expect("the bundled fonts are valid")))
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Font {
+    mono: bool,
+    bold: bool,
+    italic: bool,
+}
+
+impl Font {
+    const MONO: Font = Font { mono: true, bold: false, italic: false };
+
+    // Index into FONT_FILES of the cut the text is set in
+    fn face(self) -> usize {
+        usize::from(self.mono) * 2 + usize::from(self.bold)
+    }
+
+    // The face and glyph a character is drawn with. Characters the monospace cuts lack
+    // come from the sans cut of the same weight, then from the book sans cut; ones no
+    // face has get the missing glyph of their own face.
+    fn glyph(self, c: char) -> (usize, u16) {
+        let own = self.face();
+        [own, usize::from(self.bold), 0].into_iter()
+            .find_map(|face| faces()[face].glyph_index(c).map(|glyph| (face, glyph.0)))
+            .unwrap_or((own, 0))
+    }
+
+    // Width of text in points
+    fn text_width(self, text: &str, size: f32) -> f32 {
+        text.chars()
+            .filter(|&c| c != '\n')
+            .map(|c| {
+                let (face, glyph) = self.glyph(c);
+                glyph_width(face, glyph)
+            })
+            .sum::<f32>() * size / 1000.0
+    }
+}
+
+// Advance width of a glyph in thousandths of the font size
+fn glyph_width(face: usize, glyph: u16) -> f32 {
+    let face = &faces()[face];
+    f32::from(face.glyph_hor_advance(GlyphId(glyph)).unwrap_or(0)) * 1000.0 / f32::from(face.units_per_em())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TextStyle {
+    font: Font,
+    size: f32,
+    color: Color,
+    // Index into the writer's link targets
+    link: Option<usize>,
+    strike: bool,
+    // Inline code, drawn on a shaded background
+    code: bool,
+    // Baseline shift for footnote references
+    rise: f32,
+}
+
+impl TextStyle {
+    fn plain(font: Font, size: f32, color: Color) -> Self {
+        Self { font, size, color, link: None, strike: false, code: false, rise: 0.0 }
+    }
+}
+
+// Text in one style
+#[derive(Debug, Clone)]
+struct Span {
+    text: String,
+    style: TextStyle,
+}
+
+#[derive(Debug, Clone)]
+struct Fragment {
+    x: f32,
+    width: f32,
+    text: String,
+    style: TextStyle,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Line {
+    fragments: Vec<Fragment>,
+    width: f32,
+    // Largest font size on the line
+    size: f32,
+}
+
+impl Line {
+    fn height(&self) -> f32 {
+        self.size * LINE_SPACING
+    }
+
+    fn baseline(&self, top: f32) -> f32 {
+        top - self.size * 1.05
+    }
+
+    fn push(&mut self, text: &str, style: TextStyle, width: f32) {
+        self.size = self.size.max(style.size);
+        match self.fragments.last_mut() {
+            Some(last) if last.style == style => {
+                last.text.push_str(text);
+                last.width += width;
+            }
+            _ => self.fragments.push(Fragment { x: self.width, width, text: text.to_string(), style }),
+        }
+        self.width += width;
+    }
+
+    fn trim_end(&mut self) {
+        while let Some(last) = self.fragments.last_mut() {
+            while last.text.ends_with(' ') {
+                last.text.pop();
+                let space = last.style.font.text_width(" ", last.style.size);
+                last.width -= space;
+                self.width -= space;
+            }
+            if !last.text.is_empty() {
+                break;
+            }
+            self.fragments.pop();
+        }
+    }
+}
+
+// Words, runs of spaces and forced line breaks
+fn pieces(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let end = match first {
+            '\n' => 1,
+            ' ' => rest.find(|c| c != ' ').unwrap_or(rest.len()),
+            _ => rest.find([' ', '\n']).unwrap_or(rest.len()),
+        };
+        let (piece, remaining) = rest.split_at(end);
+        rest = remaining;
+        Some(piece)
+    })
+}
+
+// Greedy line breaking; words wider than a line are broken between characters
+fn break_lines(spans: &[Span], max_width: f32) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut line = Line::default();
+    let mut finish = |line: &mut Line, size: f32| {
+        let mut done = std::mem::take(line);
+        done.trim_end();
+        done.size = done.size.max(size);
+        lines.push(done);
+    };
+
+    for span in spans {
+        let style = span.style;
+        for piece in pieces(&span.text) {
+            if piece == "\n" {
+                finish(&mut line, style.size);
+                continue;
+            }
+            if piece.starts_with(' ') {
+                if !line.fragments.is_empty() {
+                    line.push(piece, style, style.font.text_width(piece, style.size));
+                }
+                continue;
+            }
+
+            let width = style.font.text_width(piece, style.size);
+            if line.width + width > max_width && !line.fragments.is_empty() {
+                finish(&mut line, style.size);
+            }
+            if width <= max_width {
+                line.push(piece, style, width);
+                continue;
+            }
+            for (index, c) in piece.char_indices() {
+                let character = &piece[index..index + c.len_utf8()];
+                let width = style.font.text_width(character, style.size);
+                if line.width + width > max_width && !line.fragments.is_empty() {
+                    finish(&mut line, style.size);
+                }
+                line.push(character, style, width);
+            }
+        }
+    }
+    if !line.fragments.is_empty() {
+        finish(&mut line, 0.0);
+    }
+    lines
+}
+
+// Width on one line, and of the widest word
+fn measure_spans(spans: &[Span]) -> (f32, f32) {
+    let mut total = 0.0f32;
+    let mut widest = 0.0f32;
+    for span in spans {
+        for piece in pieces(&span.text) {
+            let width = span.style.font.text_width(piece, span.style.size);
+            total += width;
+            if !piece.starts_with(' ') {
+                widest = widest.max(width);
+            }
+        }
+    }
+    (total, widest)
+}
+
+enum Op {
+    Text { x: f32, y: f32, style: TextStyle, text: String },
+    Fill { x: f32, y: f32, width: f32, height: f32, color: Color },
+    Line { from: (f32, f32), to: (f32, f32), width: f32, color: Color },
+    Frame { x: f32, y: f32, width: f32, height: f32, color: Color },
+    Image { index: usize, x: f32, y: f32, width: f32, height: f32 },
+}
+
+#[derive(Default)]
+struct Page {
+    ops: Vec<Op>,
+    // Clickable areas and the link target they lead to
+    links: Vec<(Rect, usize)>,
+}
+
+enum Marker {
+    Text(String),
+    Checkbox(bool),
+}
+
+struct TableRow {
+    cells: Vec<Vec<Span>>,
+    header: bool,
+}
+
+struct TableContext {
+    alignments: Vec<Alignment>,
+    rows: Vec<TableRow>,
+    cells: Vec<Vec<Span>>,
+}
+
+// A table row broken into lines for its column widths
+struct RowLayout {
+    cells: Vec<Vec<Line>>,
+    height: f32,
+    header: bool,
+}
+
+struct OutlineEntry {
+    level: u8,
+    title: String,
+    page: usize,
+    top: f32,
+}
+
+struct PdfImage {
+    width: u32,
+    height: u32,
+    kind: ImageKind,
+}
+
+enum ImageKind {
+    // Passed through for the reader to decode
+    Jpeg { data: Vec<u8>, components: u8 },
+    // Deflated 8-bit samples, with transparency as a separate soft mask
+    Samples { data: Vec<u8>, gray: bool, alpha: Option<Vec<u8>> },
+}
+
+// Walks the event stream once, buffering the inline content of each block and laying
+// blocks out top to bottom, starting a new page whenever the next line doesn't fit
+struct PdfWriter<'h> {
+    highlighter: &'h HighlightEngine,
+    base_dir: Option<&'h Path>,
+    page_width: f32,
+    page_height: f32,
+    pages: Vec<Page>,
+    // Top of the free space on the last page
+    y: f32,
+    // Space to leave before the next block, dropped at the top of a page
+    pending_gap: f32,
+
+    spans: Vec<Span>,
+    bold: usize,
+    italic: usize,
+    strike: usize,
+    link: Option<usize>,
+    heading: Option<HeadingLevel>,
+    // The next number of each open list, None for bullet lists
+    lists: Vec<Option<u64>>,
+    marker: Option<Marker>,
+    // Left edge and bar colour of each open quote
+    quotes: Vec<(f32, Color)>,
+    in_footnote: bool,
+    table: Option<TableContext>,
+    image: Option<(String, String)>,
+    code_block: Option<(CodeBlockInfo, String)>,
+    in_toc_marker: bool,
+
+    headings: Vec<TocEntry>,
+    next_heading: usize,
+    links: Vec<String>,
+    // Heading slugs and footnote labels, with the page and height they are at
+    anchors: HashMap<String, (usize, f32)>,
+    outline: Vec<OutlineEntry>,
+    footnote_numbers: HashMap<String, usize>,
+    footnotes_started: bool,
+    images: Vec<PdfImage>,
+    image_indexes: HashMap<String, Option<usize>>,
+}
+
+impl<'h> PdfWriter<'h> {
+    fn new(highlighter: &'h HighlightEngine, base_dir: Option<&'h Path>, page_size: PageSize, headings: Vec<TocEntry>) -> Self {
+        let (page_width, page_height) = page_size.dimensions();
+        Self {
+            highlighter,
+            base_dir,
+            page_width,
+            page_height,
+            pages: vec![Page::default()],
+            y: page_height - MARGIN,
+            pending_gap: 0.0,
+            spans: Vec::new(),
+            bold: 0,
+            italic: 0,
+            strike: 0,
+            link: None,
+            heading: None,
+            lists: Vec::new(),
+            marker: None,
+            quotes: Vec::new(),
+            in_footnote: false,
+            table: None,
+            image: None,
+            code_block: None,
+            in_toc_marker: false,
+            headings,
+            next_heading: 0,
+            links: Vec::new(),
+            anchors: HashMap::new(),
+            outline: Vec::new(),
+            footnote_numbers: HashMap::new(),
+            footnotes_started: false,
+            images: Vec::new(),
+            image_indexes: HashMap::new(),
+        }
+    }
+
+    fn write_events(&mut self, events: &[Event], alert_titles: &HashMap<usize, String>) {
+        for (index, event) in events.iter().enumerate() {
+            // Alt text is collected, not written
+            if let Some((_, alt)) = self.image.as_mut() {
+                match event {
+                    Event::End(TagEnd::Image) => self.end_image(),
+                    Event::Text(text) | Event::Code(text) => alt.push_str(text),
+                    _ => {}
+                }
+                continue;
+            }
+            if self.in_toc_marker {
+                self.in_toc_marker = !matches!(event, Event::End(TagEnd::Paragraph));
+                continue;
+            }
+            if let Some((_, code)) = self.code_block.as_mut() {
+                match event {
+                    Event::End(TagEnd::CodeBlock) => self.end_code_block(),
+                    Event::Text(text) => code.push_str(text),
+                    _ => {}
+                }
+                continue;
+            }
+
+            match event {
+                Event::Start(tag) => self.start_tag(tag, &events[index + 1..], alert_titles.get(&index)),
+                Event::End(tag) => self.end_tag(tag),
+                Event::Text(text) => self.push_text(text, false),
+                Event::Code(code) => self.push_text(code, true),
+                Event::InlineMath(latex) | Event::DisplayMath(latex) => self.push_math(latex),
+                Event::SoftBreak => self.push_text(" ", false),
+                Event::HardBreak => self.push_text("\n", false),
+                Event::InlineHtml(html) if is_line_break(html) => self.push_text("\n", false),
+                Event::Html(html) | Event::InlineHtml(html) if is_page_break(html) => {
+                    self.flush_paragraph(PARAGRAPH_GAP);
+                    self.page_break();
+                }
+                Event::Rule => self.rule(),
+                Event::FootnoteReference(label) => {
+                    let number = self.footnote_number(label);
+                    let mut style = self.text_style();
+                    style.rise = style.size * 0.35;
+                    style.size *= 0.7;
+                    style.link = self.add_link(&format!("#fn:{}", label));
+                    style.color = LINK;
+                    self.spans.push(Span { text: number.to_string(), style });
+                }
+                Event::TaskListMarker(checked) => self.marker = Some(Marker::Checkbox(*checked)),
+                // Raw HTML has no PDF equivalent
+                Event::Html(_) | Event::InlineHtml(_) => {}
+            }
+        }
+        self.flush_paragraph(0.0);
+    }
+
+    // `title` is the title of the alert a blockquote starting here stands for
+    fn start_tag(&mut self, tag: &Tag, rest: &[Event], title: Option<&String>) {
+        match tag {
+            Tag::Paragraph => {
+                // A list marker waiting for its item's first line stays pending
+                if !self.spans.is_empty() {
+                    self.flush_paragraph(PARAGRAPH_GAP);
+                }
+                if is_toc_marker(rest) {
+                    self.table_of_contents();
+                    self.in_toc_marker = true;
+                }
+            }
+            Tag::Heading { level, .. } => {
+                self.flush_paragraph(PARAGRAPH_GAP);
+                self.heading = Some(*level);
+                self.gap(if *level <= HeadingLevel::H2 { 14.0 } else { 10.0 });
+            }
+            Tag::BlockQuote(kind) => {
+                self.flush_paragraph(PARAGRAPH_GAP);
+                // Take the space before the quote now so its bar starts at its first line
+                self.gap(PARAGRAPH_GAP);
+                self.reserve(0.0);
+                let color = kind.map_or(BORDER, alert_color);
+                self.quotes.push((self.left(), color));
+                if let Some(kind) = kind {
+                    let mut style = self.text_style();
+                    style.font.bold = true;
+                    style.color = color;
+                    self.spans.push(Span { text: normalize(title.map_or(alert_title(*kind), String::as_str)), style });
+                    self.flush_paragraph(LIST_GAP);
+                }
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush_paragraph(PARAGRAPH_GAP);
+                let info = match kind {
+                    CodeBlockKind::Fenced(info) => CodeBlockInfo::parse(info),
+                    CodeBlockKind::Indented => CodeBlockInfo::default(),
+                };
+                self.code_block = Some((info, String::new()));
+            }
+            Tag::List(start) => {
+                self.flush_paragraph(LIST_GAP);
+                if self.lists.is_empty() {
+                    self.gap(PARAGRAPH_GAP);
+                }
+                self.lists.push(*start);
+            }
+            Tag::Item => {
+                self.flush_paragraph(LIST_GAP);
+                self.marker = Some(self.item_marker());
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.flush_paragraph(PARAGRAPH_GAP);
+                if !self.footnotes_started {
+                    self.footnotes_started = true;
+                    self.gap(PARAGRAPH_GAP);
+                    self.reserve(8.0);
+                    let (left, y) = (self.left(), self.y - 4.0);
+                    self.page().ops.push(Op::Line { from: (left, y), to: (left + 144.0, y), width: 0.6, color: BORDER });
+                    self.advance(8.0);
+                }
+                self.in_footnote = true;
+                let number = self.footnote_number(label);
+                self.reserve(FOOTNOTE_SIZE * LINE_SPACING);
+                self.anchors.insert(format!("fn:{}", label), (self.pages.len() - 1, self.y));
+                self.marker = Some(Marker::Text(format!("{}.", number)));
+            }
+            Tag::Table(alignments) => {
+                self.flush_paragraph(PARAGRAPH_GAP);
+                self.table = Some(TableContext { alignments: alignments.clone(), rows: Vec::new(), cells: Vec::new() });
+            }
+            Tag::TableHead => self.bold += 1,
+            Tag::Emphasis => self.italic += 1,
+            Tag::Strong => self.bold += 1,
+            Tag::Strikethrough => self.strike += 1,
+            Tag::Link { link_type, dest_url, .. } => self.link = self.add_link(&link_target(*link_type, dest_url)),
+            Tag::Image { dest_url, .. } => self.image = Some((dest_url.to_string(), String::new())),
+            _ => {}
+        }
+    }
+
+    fn end_tag(&mut self, tag: &TagEnd) {
+        match tag {
+            TagEnd::Paragraph => self.flush_paragraph(PARAGRAPH_GAP),
+            TagEnd::Heading(_) => self.end_heading(),
+            TagEnd::BlockQuote(_) => {
+                self.flush_paragraph(PARAGRAPH_GAP);
+                self.quotes.pop();
+                self.gap(PARAGRAPH_GAP);
+            }
+            TagEnd::List(_) => {
+                self.flush_paragraph(LIST_GAP);
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.gap(PARAGRAPH_GAP);
+                }
+            }
+            TagEnd::Item => self.flush_paragraph(LIST_GAP),
+            TagEnd::FootnoteDefinition => {
+                self.flush_paragraph(LIST_GAP * 2.0);
+                self.in_footnote = false;
+            }
+            TagEnd::TableCell => {
+                let spans = std::mem::take(&mut self.spans);
+                if let Some(table) = self.table.as_mut() {
+                    table.cells.push(spans);
+                }
+            }
+            TagEnd::TableHead | TagEnd::TableRow => {
+                let header = matches!(tag, TagEnd::TableHead);
+                if header {
+                    self.bold -= 1;
+                }
+                if let Some(table) = self.table.as_mut() {
+                    let cells = std::mem::take(&mut table.cells);
+                    table.rows.push(TableRow { cells, header });
+                }
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.place_table(table);
+                }
+                self.gap(PARAGRAPH_GAP);
+            }
+            TagEnd::Emphasis => self.italic -= 1,
+            TagEnd::Strong => self.bold -= 1,
+            TagEnd::Strikethrough => self.strike -= 1,
+            TagEnd::Link => self.link = None,
+            _ => {}
+        }
+    }
+
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().expect("the writer starts with a page")
+    }
+
+    fn content_top(&self) -> f32 {
+        self.page_height - MARGIN
+    }
+
+    fn left(&self) -> f32 {
+        MARGIN
+            + self.lists.len() as f32 * LIST_INDENT
+            + self.quotes.len() as f32 * QUOTE_INDENT
+            + if self.in_footnote { LIST_INDENT } else { 0.0 }
+    }
+
+    fn right(&self) -> f32 {
+        self.page_width - MARGIN
+    }
+
+    fn gap(&mut self, gap: f32) {
+        self.pending_gap = self.pending_gap.max(gap);
+    }
+
+    // Make room for content of the given height, on a new page if this one is full
+    fn reserve(&mut self, height: f32) {
+        let gap = std::mem::take(&mut self.pending_gap);
+        let at_top = self.y >= self.content_top();
+        if !at_top && self.y - gap - height < MARGIN {
+            self.new_page();
+        } else if !at_top {
+            self.advance(gap);
+        }
+    }
+
+    // Move down past placed content, continuing the bars of open quotes alongside it
+    fn advance(&mut self, height: f32) {
+        if height <= 0.0 {
+            return;
+        }
+        let y = self.y - height;
+        let bars: Vec<Op> = self.quotes.iter()
+            .map(|&(x, color)| Op::Fill { x, y, width: 3.0, height, color })
+            .collect();
+        self.page().ops.extend(bars);
+        self.y = y;
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Page::default());
+        self.y = self.content_top();
+        self.pending_gap = 0.0;
+    }
+
+    fn page_break(&mut self) {
+        if self.y < self.content_top() {
+            self.new_page();
+        }
+    }
+
+    fn text_style(&self) -> TextStyle {
+        let (size, color, bold) = match self.heading {
+            Some(level) => (heading_size(level), if level == HeadingLevel::H6 { MUTED } else { TEXT }, true),
+            None if self.in_footnote => (FOOTNOTE_SIZE, TEXT, false),
+            None if !self.quotes.is_empty() => (BODY_SIZE, MUTED, false),
+            None => (BODY_SIZE, TEXT, false),
+        };
+        TextStyle {
+            font: Font { mono: false, bold: bold || self.bold > 0, italic: self.italic > 0 },
+            size,
+            color: if self.link.is_some() { LINK } else { color },
+            link: self.link,
+            strike: self.strike > 0,
+            code: false,
+            rise: 0.0,
+        }
+    }
+
+    fn push_text(&mut self, text: &str, code: bool) {
+        let mut style = self.text_style();
+        if code {
+            style.font.mono = true;
+            style.code = true;
+            style.size *= 0.92;
+        }
+        self.spans.push(Span { text: normalize(text), style });
+    }
+
+    // Math is shown as its LaTeX source, set apart in oblique monospace
+    fn push_math(&mut self, latex: &str) {
+        let mut style = self.text_style();
+        style.font.mono = true;
+        style.font.italic = true;
+        style.size *= 0.92;
+        self.spans.push(Span { text: normalize(latex), style });
+    }
+
+    // Anchors within the document and web links are clickable; links to other files aren't
+    fn add_link(&mut self, target: &str) -> Option<usize> {
+        let clickable = ["#", "http://", "https://", "mailto:"].iter().any(|prefix| target.starts_with(prefix));
+        if !clickable {
+            return None;
+        }
+        self.links.push(target.to_string());
+        Some(self.links.len() - 1)
+    }
+
+    fn footnote_number(&mut self, label: &str) -> usize {
+        let next = self.footnote_numbers.len() + 1;
+        *self.footnote_numbers.entry(label.to_string()).or_insert(next)
+    }
+
+    fn item_marker(&mut self) -> Marker {
+        let depth = self.lists.len();
+        match self.lists.last_mut() {
+            Some(Some(number)) => {
+                let marker = format!("{}.", number);
+                *number += 1;
+                Marker::Text(marker)
+            }
+            _ => Marker::Text(["•", "–", "·"][depth.saturating_sub(1) % 3].to_string()),
+        }
+    }
+
+    fn flush_paragraph(&mut self, gap_after: f32) {
+        if self.spans.is_empty() && self.marker.is_none() {
+            return;
+        }
+        let spans = std::mem::take(&mut self.spans);
+        let left = self.left();
+        self.place_spans(&spans, left);
+        self.gap(gap_after);
+    }
+
+    fn place_spans(&mut self, spans: &[Span], left: f32) {
+        let width = self.right() - left;
+        let mut lines = break_lines(spans, width);
+        if lines.is_empty() {
+            // An empty list item still shows its marker
+            lines.push(Line { size: BODY_SIZE, ..Line::default() });
+        }
+        for line in &lines {
+            self.place_line(line, left, width, Alignment::None);
+        }
+    }
+
+    fn place_line(&mut self, line: &Line, left: f32, width: f32, alignment: Alignment) {
+        self.reserve(line.height());
+        let baseline = line.baseline(self.y);
+        if let Some(marker) = self.marker.take() {
+            self.draw_marker(marker, left, baseline, line.size);
+        }
+        self.draw_line(line, left, width, baseline, alignment);
+        self.advance(line.height());
+    }
+
+    fn draw_line(&mut self, line: &Line, left: f32, width: f32, baseline: f32, alignment: Alignment) {
+        let offset = match alignment {
+            Alignment::Center => (width - line.width) / 2.0,
+            Alignment::Right => width - line.width,
+            _ => 0.0,
+        };
+        for fragment in &line.fragments {
+            self.draw_fragment(fragment, left + offset, baseline);
+        }
+    }
+
+    fn draw_fragment(&mut self, fragment: &Fragment, origin: f32, baseline: f32) {
+        let x = origin + fragment.x;
+        let style = fragment.style;
+        let page = self.page();
+        if style.code {
+            page.ops.push(Op::Fill {
+                x: x - 1.5,
+                y: baseline - style.size * 0.3,
+                width: fragment.width + 3.0,
+                height: style.size * 1.25,
+                color: CODE_BACKGROUND,
+            });
+        }
+        page.ops.push(Op::Text { x, y: baseline + style.rise, style, text: fragment.text.clone() });
+        if style.strike {
+            let y = baseline + style.size * 0.3;
+            page.ops.push(Op::Line { from: (x, y), to: (x + fragment.width, y), width: 0.6, color: style.color });
+        }
+        if let Some(link) = style.link {
+            let rect = Rect::new(x, baseline - style.size * 0.25, x + fragment.width, baseline + style.rise + style.size * 0.9);
+            page.links.push((rect, link));
+        }
+    }
+
+    // List markers and task checkboxes sit in the indentation before the first line
+    fn draw_marker(&mut self, marker: Marker, left: f32, baseline: f32, size: f32) {
+        match marker {
+            Marker::Text(text) => {
+                let style = TextStyle::plain(Font::default(), size, TEXT);
+                let x = left - 5.0 - style.font.text_width(&text, size);
+                self.page().ops.push(Op::Text { x, y: baseline, style, text });
+            }
+            Marker::Checkbox(checked) => {
+                let side = size * 0.75;
+                let (x, y) = (left - 5.0 - side, baseline - size * 0.05);
+                let page = self.page();
+                page.ops.push(Op::Frame { x, y, width: side, height: side, color: MUTED });
+                if checked {
+                    let corner = (x + side * 0.42, y + side * 0.22);
+                    page.ops.push(Op::Line { from: (x + side * 0.2, y + side * 0.5), to: corner, width: 1.0, color: TEXT });
+                    page.ops.push(Op::Line { from: corner, to: (x + side * 0.82, y + side * 0.8), width: 1.0, color: TEXT });
+                }
+            }
+        }
+    }
+
+    fn title_block(&mut self, title: &str) {
+        let style = TextStyle::plain(Font { bold: true, ..Font::default() }, 24.0, TEXT);
+        self.place_spans(&[Span { text: normalize(title), style }], MARGIN);
+        self.gap(12.0);
+    }
+
+    fn end_heading(&mut self) {
+        let Some(level) = self.heading.take() else { return };
+        let spans = std::mem::take(&mut self.spans);
+        let left = self.left();
+        let width = self.right() - left;
+        let lines = break_lines(&spans, width);
+
+        // Keep the heading on the same page as the first lines after it
+        let height: f32 = lines.iter().map(Line::height).sum();
+        self.reserve(height + BODY_SIZE * LINE_SPACING * 2.0);
+        if let Some(entry) = self.headings.get(self.next_heading) {
+            let (page, top) = (self.pages.len() - 1, self.y);
+            self.anchors.insert(entry.slug.clone(), (page, top));
+            self.outline.push(OutlineEntry { level: entry.level, title: entry.text.clone(), page, top });
+        }
+        self.next_heading += 1;
+
+        for line in &lines {
+            self.place_line(line, left, width, Alignment::None);
+        }
+        if level <= HeadingLevel::H2 {
+            let y = self.y - 2.0;
+            let right = self.right();
+            self.page().ops.push(Op::Line { from: (left, y), to: (right, y), width: 0.6, color: BORDER });
+            self.advance(4.0);
+        }
+        self.gap(6.0);
+    }
+
+    // The document's headings as links, indented by level
+    fn table_of_contents(&mut self) {
+        let entries: Vec<(u8, String, String)> = self.headings.iter()
+            .map(|heading| (heading.level, heading.text.clone(), heading.slug.clone()))
+            .collect();
+        let top_level = entries.iter().map(|(level, _, _)| *level).min().unwrap_or(1);
+        for (level, text, slug) in entries {
+            let style = TextStyle { link: self.add_link(&format!("#{}", slug)), ..TextStyle::plain(Font::default(), BODY_SIZE, LINK) };
+            let left = self.left() + f32::from(level - top_level) * LIST_INDENT;
+            self.place_spans(&[Span { text: normalize(&text), style }], left);
+            self.gap(LIST_GAP);
+        }
+        self.gap(PARAGRAPH_GAP);
+    }
+
+    fn rule(&mut self) {
+        self.flush_paragraph(PARAGRAPH_GAP);
+        self.reserve(12.0);
+        let (left, right, y) = (self.left(), self.right(), self.y - 6.0);
+        self.page().ops.push(Op::Line { from: (left, y), to: (right, y), width: 1.0, color: BORDER });
+        self.advance(12.0);
+        self.gap(6.0);
+    }
+
+    // One shaded row per line, coloured like the HTML view; long lines wrap
+    fn end_code_block(&mut self) {
+        let Some((info, code)) = self.code_block.take() else { return };
+        let code = code.strip_suffix('\n').unwrap_or(&code);
+        let (left, right) = (self.left(), self.right());
+        let char_width = Font::MONO.text_width(" ", CODE_SIZE);
+        let columns = ((right - left - 2.0 * CODE_PADDING) / char_width).floor().max(1.0) as usize;
+
+        if let Some(title) = &info.title {
+            let style = TextStyle::plain(Font { bold: true, ..Font::MONO }, CODE_SIZE, TEXT);
+            self.code_row(&[(style, normalize(title))], CODE_TITLE_BACKGROUND, CODE_LINE_HEIGHT + 4.0);
+        }
+        self.code_row(&[], CODE_BACKGROUND, CODE_PADDING);
+
+        let styled = self.highlighter.styled_lines(code, info.language.as_deref());
+        for (index, line) in code.split('\n').enumerate() {
+            let regions: Vec<(TextStyle, &str)> = match styled.as_ref().and_then(|lines| lines.get(index)) {
+                Some(regions) => regions.iter()
+                    .map(|(style, text)| {
+                        let font = Font {
+                            mono: true,
+                            bold: style.font_style.contains(FontStyle::BOLD),
+                            italic: style.font_style.contains(FontStyle::ITALIC),
+                        };
+                        let color = [style.foreground.r, style.foreground.g, style.foreground.b].map(|c| f32::from(c) / 255.0);
+                        (TextStyle::plain(font, CODE_SIZE, color), text.trim_end_matches(['\n', '\r']))
+                    })
+                    .collect(),
+                None => vec![(TextStyle::plain(Font::MONO, CODE_SIZE, TEXT), line.trim_end_matches('\r'))],
+            };
+            let background = if info.is_line_highlighted(index + 1) { HIGHLIGHTED_LINE } else { CODE_BACKGROUND };
+            for row in wrap_code(&regions, columns) {
+                self.code_row(&row, background, CODE_LINE_HEIGHT);
+            }
+        }
+
+        self.code_row(&[], CODE_BACKGROUND, CODE_PADDING);
+        self.gap(PARAGRAPH_GAP);
+    }
+
+    fn code_row(&mut self, row: &[(TextStyle, String)], background: Color, height: f32) {
+        self.reserve(height);
+        let (left, right) = (self.left(), self.right());
+        let baseline = self.y - (height + CODE_SIZE) / 2.0 - CODE_SIZE * 0.1;
+        let y = self.y - height;
+        let page = self.page();
+        page.ops.push(Op::Fill { x: left, y, width: right - left, height, color: background });
+        let mut x = left + CODE_PADDING;
+        for (style, text) in row {
+            page.ops.push(Op::Text { x, y: baseline, style: *style, text: text.clone() });
+            x += style.font.text_width(text, CODE_SIZE);
+        }
+        self.advance(height);
+    }
+
+    fn place_table(&mut self, table: TableContext) {
+        let columns = table.alignments.len();
+        if columns == 0 || table.rows.is_empty() {
+            return;
+        }
+        let left = self.left();
+        let mut natural = vec![0.0f32; columns];
+        let mut minimum = vec![0.0f32; columns];
+        for row in &table.rows {
+            for (column, cell) in row.cells.iter().enumerate().take(columns) {
+                let (width, widest) = measure_spans(cell);
+                natural[column] = natural[column].max(width + 2.0 * CELL_PADDING);
+                minimum[column] = minimum[column].max(widest + 2.0 * CELL_PADDING);
+            }
+        }
+        let widths = column_widths(&natural, &minimum, self.right() - left);
+
+        let rows: Vec<RowLayout> = table.rows.iter()
+            .map(|row| {
+                let cells: Vec<Vec<Line>> = widths.iter().enumerate()
+                    .map(|(column, width)| row.cells.get(column)
+                        .map(|cell| break_lines(cell, width - 2.0 * CELL_PADDING))
+                        .unwrap_or_default())
+                    .collect();
+                let content = cells.iter()
+                    .map(|lines| lines.iter().map(Line::height).sum::<f32>())
+                    .fold(BODY_SIZE * LINE_SPACING, f32::max);
+                RowLayout { cells, height: content + 2.0 * CELL_PADDING, header: row.header }
+            })
+            .collect();
+
+        // The header row is repeated at the top of each page the table continues on
+        let header = rows.iter().find(|row| row.header);
+        for row in &rows {
+            let page_count = self.pages.len();
+            self.reserve(row.height);
+            if self.pages.len() != page_count && !row.header {
+                if let Some(header) = header {
+                    self.draw_row(header, left, &widths, &table.alignments);
+                    self.reserve(row.height);
+                }
+            }
+            self.draw_row(row, left, &widths, &table.alignments);
+        }
+    }
+
+    fn draw_row(&mut self, row: &RowLayout, left: f32, widths: &[f32], alignments: &[Alignment]) {
+        let top = self.y;
+        let bottom = top - row.height;
+        let mut x = left;
+        for (column, width) in widths.iter().enumerate() {
+            if row.header {
+                self.page().ops.push(Op::Fill { x, y: bottom, width: *width, height: row.height, color: CODE_BACKGROUND });
+            }
+            self.page().ops.push(Op::Frame { x, y: bottom, width: *width, height: row.height, color: BORDER });
+            let mut line_top = top - CELL_PADDING;
+            for line in &row.cells[column] {
+                let alignment = alignments.get(column).copied().unwrap_or(Alignment::None);
+                self.draw_line(line, x + CELL_PADDING, width - 2.0 * CELL_PADDING, line.baseline(line_top), alignment);
+                line_top -= line.height();
+            }
+            x += width;
+        }
+        self.advance(row.height);
+    }
+
+    fn end_image(&mut self) {
+        let Some((source, alt)) = self.image.take() else { return };
+        // Images in headings and tables would break up the layout around them
+        let index = if self.heading.is_some() || self.table.is_some() { None } else { self.load_image(&source) };
+        let Some(index) = index else {
+            // Remote, missing or unsupported images keep their alt text
+            let alt = if alt.is_empty() { source } else { alt };
+            self.italic += 1;
+            self.push_text(&format!("[{}]", alt), false);
+            self.italic -= 1;
+            return;
+        };
+
+        // Text before the image goes above it
+        self.flush_paragraph(4.0);
+        let image = &self.images[index];
+        let (left, right) = (self.left(), self.right());
+        let mut width = image.width as f32 * POINTS_PER_PIXEL;
+        let mut height = image.height as f32 * POINTS_PER_PIXEL;
+        let scale = 1.0f32
+            .min((right - left) / width)
+            .min((self.content_top() - MARGIN) * 0.85 / height);
+        width *= scale;
+        height *= scale;
+
+        self.reserve(height);
+        let y = self.y - height;
+        self.page().ops.push(Op::Image { index, x: left, y, width, height });
+        self.advance(height);
+        self.gap(4.0);
+    }
+
+    fn load_image(&mut self, source: &str) -> Option<usize> {
+        if let Some(index) = self.image_indexes.get(source) {
+            return *index;
+        }
+        let image = self.base_dir
+            .and_then(|base_dir| read_local_image(source, base_dir))
+            .and_then(|data| {
+                let header = read_header(&data)?;
+                decode_image(data, header)
+            });
+        let index = image.map(|image| {
+            self.images.push(image);
+            self.images.len() - 1
+        });
+        self.image_indexes.insert(source.to_string(), index);
+        index
+    }
+
+    fn add_running_text(&mut self, title: &str, options: &PdfOptions) {
+        let total = self.pages.len().to_string();
+        let style = TextStyle::plain(Font::default(), RUNNING_TEXT_SIZE, MUTED);
+        for (index, page) in self.pages.iter_mut().enumerate() {
+            let fill = |template: &str| normalize(
+                &template.replace("{title}", title).replace("{page}", &(index + 1).to_string()).replace("{pages}", &total),
+            );
+            if !options.header.is_empty() {
+                page.ops.push(Op::Text { x: MARGIN, y: self.page_height - MARGIN / 2.0, style, text: fill(&options.header) });
+            }
+            if !options.footer.is_empty() {
+                let text = fill(&options.footer);
+                let x = (self.page_width - style.font.text_width(&text, RUNNING_TEXT_SIZE)) / 2.0;
+                page.ops.push(Op::Text { x, y: MARGIN / 2.0 - RUNNING_TEXT_SIZE, style, text });
+            }
+        }
+    }
+
+    fn finish(mut self, title: &str, front_matter: Option<&FrontMatter>, options: &PdfOptions) -> Result<Vec<u8>, String> {
+        self.flush_paragraph(0.0);
+        let title = front_matter.and_then(|front_matter| front_matter.title.as_deref()).unwrap_or(title);
+        self.add_running_text(title, options);
+
+        let mut ids = Ref::new(1);
+        let catalog_id = ids.bump();
+        let page_tree_id = ids.bump();
+        let outline_id = ids.bump();
+        let info_id = ids.bump();
+        let font_ids: [[Ref; 6]; 4] = std::array::from_fn(|_| std::array::from_fn(|_| ids.bump()));
+        let image_ids: Vec<(Ref, Ref)> = self.images.iter().map(|_| (ids.bump(), ids.bump())).collect();
+        let page_ids: Vec<Ref> = self.pages.iter().map(|_| ids.bump()).collect();
+        let content_ids: Vec<Ref> = self.pages.iter().map(|_| ids.bump()).collect();
+        let outline_ids: Vec<Ref> = self.outline.iter().map(|_| ids.bump()).collect();
+        let image_names: Vec<String> = (0..self.images.len()).map(|index| format!("Im{}", index)).collect();
+
+        // Pages are drawn first to find the glyphs the fonts need
+        let mut used: UsedGlyphs = Default::default();
+        let contents: Vec<Vec<u8>> = self.pages.iter()
+            .map(|page| deflate(&render_ops(&page.ops, &image_names, &mut used)))
+            .collect();
+
+        let mut pdf = Pdf::new();
+        let mut catalog = pdf.catalog(catalog_id);
+        catalog.pages(page_tree_id);
+        if !self.outline.is_empty() {
+            catalog.outlines(outline_id);
+            catalog.page_mode(PageMode::UseOutlines);
+        }
+        catalog.finish();
+
+        let mut info = pdf.document_info(info_id);
+        info.title(TextStr(title));
+        if let Some(author) = front_matter.and_then(|front_matter| front_matter.author.as_deref()) {
+            info.author(TextStr(author));
+        }
+        if let Some(front_matter) = front_matter.filter(|front_matter| !front_matter.tags.is_empty()) {
+            info.keywords(TextStr(&front_matter.tags.join(", ")));
+        }
+        info.creator(TextStr("Markdown Viewer"));
+        info.finish();
+
+        // Every page shares the fonts and images through the page tree
+        let mut pages = pdf.pages(page_tree_id);
+        pages.kids(page_ids.iter().copied())
+            .count(page_ids.len() as i32)
+            .media_box(Rect::new(0.0, 0.0, self.page_width, self.page_height));
+        let mut resources = pages.resources();
+        let mut fonts = resources.fonts();
+        for (index, usage) in used.iter().enumerate() {
+            if !usage.glyphs.is_empty() {
+                fonts.pair(Name(FONT_NAMES[index]), font_ids[index][0]);
+            }
+        }
+        fonts.finish();
+        let mut x_objects = resources.x_objects();
+        for (name, (id, _)) in image_names.iter().zip(&image_ids) {
+            x_objects.pair(Name(name.as_bytes()), *id);
+        }
+        x_objects.finish();
+        resources.finish();
+        pages.finish();
+
+        for (index, usage) in used.iter().enumerate() {
+            if !usage.glyphs.is_empty() {
+                write_font(&mut pdf, font_ids[index], index, usage)?;
+            }
+        }
+
+        for (image, (id, mask_id)) in self.images.iter().zip(&image_ids) {
+            match &image.kind {
+                ImageKind::Jpeg { data, components } => {
+                    let mut x_object = pdf.image_xobject(*id, data);
+                    x_object.filter(Filter::DctDecode);
+                    x_object.width(image.width as i32).height(image.height as i32).bits_per_component(8);
+                    x_object.color_space_name(Name(if *components == 1 { b"DeviceGray" } else { b"DeviceRGB" }));
+                }
+                ImageKind::Samples { data, gray, alpha } => {
+                    let mut x_object = pdf.image_xobject(*id, data);
+                    x_object.filter(Filter::FlateDecode);
+                    x_object.width(image.width as i32).height(image.height as i32).bits_per_component(8);
+                    x_object.color_space_name(Name(if *gray { b"DeviceGray" } else { b"DeviceRGB" }));
+                    if alpha.is_some() {
+                        x_object.s_mask(*mask_id);
+                    }
+                    x_object.finish();
+                    if let Some(alpha) = alpha {
+                        let mut mask = pdf.image_xobject(*mask_id, alpha);
+                        mask.filter(Filter::FlateDecode);
+                        mask.width(image.width as i32).height(image.height as i32).bits_per_component(8);
+                        mask.color_space_name(Name(b"DeviceGray"));
+                    }
+                }
+            }
+        }
+
+        for (index, page) in self.pages.iter().enumerate() {
+            pdf.stream(content_ids[index], &contents[index]).filter(Filter::FlateDecode);
+
+            let mut writer = pdf.page(page_ids[index]);
+            writer.parent(page_tree_id).contents(content_ids[index]);
+            let mut annotations = writer.annotations();
+            for (rect, link) in &page.links {
+                let target = &self.links[*link];
+                match target.strip_prefix('#') {
+                    Some(anchor) => {
+                        // Links to headings that don't exist go nowhere
+                        let Some(&(page, top)) = self.anchors.get(anchor) else { continue };
+                        let mut annotation = annotations.push();
+                        annotation.subtype(AnnotationType::Link).rect(*rect).border(0.0, 0.0, 0.0, None);
+                        annotation.action().action_type(ActionType::GoTo).destination().page(page_ids[page]).xyz(0.0, top + 6.0, None);
+                    }
+                    None => {
+                        let mut annotation = annotations.push();
+                        annotation.subtype(AnnotationType::Link).rect(*rect).border(0.0, 0.0, 0.0, None);
+                        annotation.action().action_type(ActionType::Uri).uri(Str(target.as_bytes()));
+                    }
+                }
+            }
+        }
+
+        self.write_outline(&mut pdf, outline_id, &outline_ids, &page_ids);
+        Ok(pdf.finish())
+    }
+
+    // Bookmarks nest like the headings: each is a child of the closest earlier heading of a higher level
+    fn write_outline(&self, pdf: &mut Pdf, outline_id: Ref, ids: &[Ref], page_ids: &[Ref]) {
+        if self.outline.is_empty() {
+            return;
+        }
+        let mut parents: Vec<Option<usize>> = Vec::with_capacity(self.outline.len());
+        let mut open: Vec<usize> = Vec::new();
+        for (index, entry) in self.outline.iter().enumerate() {
+            while open.last().is_some_and(|&last| self.outline[last].level >= entry.level) {
+                open.pop();
+            }
+            parents.push(open.last().copied());
+            open.push(index);
+        }
+        let children = |parent: Option<usize>| -> Vec<usize> {
+            (0..self.outline.len()).filter(|&index| parents[index] == parent).collect()
+        };
+
+        let roots = children(None);
+        pdf.outline(outline_id)
+            .first(ids[roots[0]])
+            .last(ids[roots[roots.len() - 1]])
+            .count(self.outline.len() as i32);
+
+        for (index, entry) in self.outline.iter().enumerate() {
+            let siblings = children(parents[index]);
+            let position = siblings.iter().position(|&sibling| sibling == index).unwrap_or(0);
+            let descendants = self.outline[index + 1..].iter().take_while(|next| next.level > entry.level).count();
+
+            let mut item = pdf.outline_item(ids[index]);
+            item.title(TextStr(&entry.title));
+            item.parent(parents[index].map_or(outline_id, |parent| ids[parent]));
+            if position > 0 {
+                item.prev(ids[siblings[position - 1]]);
+            }
+            if let Some(&next) = siblings.get(position + 1) {
+                item.next(ids[next]);
+            }
+            if descendants > 0 {
+                let own = children(Some(index));
+                item.first(ids[own[0]]).last(ids[own[own.len() - 1]]).count(descendants as i32);
+            }
+            item.dest().page(page_ids[entry.page]).xyz(0.0, entry.top + 6.0, None);
+        }
+    }
+}
+
+// Column widths for a table: as wide as the content when it fits, otherwise squeezed
+// towards each column's widest word
+fn column_widths(natural: &[f32], minimum: &[f32], available: f32) -> Vec<f32> {
+    let total: f32 = natural.iter().sum();
+    if total <= available {
+        return natural.to_vec();
+    }
+    let minimum_total: f32 = minimum.iter().sum();
+    if minimum_total >= available {
+        return minimum.iter().map(|width| width * available / minimum_total).collect();
+    }
+    let flexible = total - minimum_total;
+    natural.iter().zip(minimum)
+        .map(|(natural, minimum)| minimum + (natural - minimum) / flexible * (available - minimum_total))
+        .collect()
+}
+
+// Split a line of highlighted code into rows of at most `columns` characters
+fn wrap_code(regions: &[(TextStyle, &str)], columns: usize) -> Vec<Vec<(TextStyle, String)>> {
+    let mut rows = vec![Vec::new()];
+    let mut column = 0;
+    for (style, text) in regions {
+        let mut expanded = String::new();
+        for c in text.chars() {
+            if c == '\t' {
+                let spaces = TAB_WIDTH - (column + expanded.chars().count()) % TAB_WIDTH;
+                expanded.extend(std::iter::repeat_n(' ', spaces));
+            } else {
+                expanded.push(c);
+            }
+        }
+        let text = normalize(&expanded);
+        let mut rest = text.as_str();
+        while !rest.is_empty() {
+            if column == columns {
+                rows.push(Vec::new());
+                column = 0;
+            }
+            let end = rest.char_indices().nth(columns - column).map_or(rest.len(), |(index, _)| index);
+            let (piece, remaining) = rest.split_at(end);
+            column += piece.chars().count();
+            if let Some(row) = rows.last_mut() {
+                row.push((*style, piece.to_string()));
+            }
+            rest = remaining;
+        }
+    }
+    rows
+}
+
+// The characters drawn with one face. Each gets its own CID, in order of first use, so
+// that characters the face has no glyph for still map back to their own text.
+#[derive(Default)]
+struct FaceUsage {
+    cids: HashMap<char, u16>,
+    // Characters and their glyphs, in CID order starting at 1
+    glyphs: Vec<(char, u16)>,
+}
+
+impl FaceUsage {
+    fn cid(&mut self, c: char, glyph: u16) -> u16 {
+        if let Some(&cid) = self.cids.get(&c) {
+            return cid;
+        }
+        // CID 0 is the missing glyph, for the unlikely document with more characters than CIDs
+        let Ok(cid) = u16::try_from(self.glyphs.len() + 1) else { return 0 };
+        self.glyphs.push((c, glyph));
+        self.cids.insert(c, cid);
+        cid
+    }
+}
+
+type UsedGlyphs = [FaceUsage; 4];
+
+fn render_ops(ops: &[Op], image_names: &[String], used: &mut UsedGlyphs) -> Vec<u8> {
+    let mut content = Content::new();
+    for op in ops {
+        match op {
+            Op::Text { x, y, style, text } => {
+                let [r, g, b] = style.color;
+                let slant = if style.font.italic { OBLIQUE_SLANT } else { 0.0 };
+                content.begin_text();
+                content.set_fill_rgb(r, g, b);
+                content.set_text_matrix([1.0, 0.0, slant, 1.0, *x, *y]);
+                for (face, glyphs) in glyph_runs(style.font, text, used) {
+                    content.set_font(Name(FONT_NAMES[face]), style.size);
+                    content.show(Str(&glyphs));
+                }
+                content.end_text();
+            }
+            Op::Fill { x, y, width, height, color } => {
+                let [r, g, b] = *color;
+                content.set_fill_rgb(r, g, b);
+                content.rect(*x, *y, *width, *height);
+                content.fill_nonzero();
+            }
+            Op::Line { from, to, width, color } => {
+                let [r, g, b] = *color;
+                content.set_stroke_rgb(r, g, b);
+                content.set_line_width(*width);
+                content.move_to(from.0, from.1);
+                content.line_to(to.0, to.1);
+                content.stroke();
+            }
+            Op::Frame { x, y, width, height, color } => {
+                let [r, g, b] = *color;
+                content.set_stroke_rgb(r, g, b);
+                content.set_line_width(0.6);
+                content.rect(*x, *y, *width, *height);
+                content.stroke();
+            }
+            Op::Image { index, x, y, width, height } => {
+                content.save_state();
+                content.transform([*width, 0.0, 0.0, *height, *x, *y]);
+                content.x_object(Name(image_names[*index].as_bytes()));
+                content.restore_state();
+            }
+        }
+    }
+    content.finish()
+}
+
+// Text as runs of two-byte CIDs, split where a character comes from another face
+fn glyph_runs(font: Font, text: &str, used: &mut UsedGlyphs) -> Vec<(usize, Vec<u8>)> {
+    let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+    for c in text.chars().filter(|&c| c != '\n') {
+        let (face, glyph) = font.glyph(c);
+        let cid = used[face].cid(c, glyph).to_be_bytes();
+        match runs.last_mut() {
+            Some((last, cids)) if *last == face => cids.extend_from_slice(&cid),
+            _ => runs.push((face, cid.to_vec())),
+        }
+    }
+    runs
+}
+
+// A face as a Type 0 font with Identity-H encoding, so the content streams hold CIDs,
+// a map from those to glyphs, and a ToUnicode map for searching and copying the text
+fn write_font(pdf: &mut Pdf, ids: [Ref; 6], index: usize, used: &FaceUsage) -> Result<(), String> {
+    let [font_id, cid_font_id, descriptor_id, cmap_id, gid_map_id, file_id] = ids;
+    let face = &faces()[index];
+    let scale = 1000.0 / f32::from(face.units_per_em());
+    let base_font = format!("{}+{}", subset_tag(used), BASE_FONTS[index]);
+    let system_info = SystemInfo { registry: Str(b"Adobe"), ordering: Str(b"Identity"), supplement: 0 };
+
+    pdf.type0_font(font_id)
+        .base_font(Name(base_font.as_bytes()))
+        .encoding_predefined(Name(b"Identity-H"))
+        .descendant_font(cid_font_id)
+        .to_unicode(cmap_id);
+
+    let mut cid_font = pdf.cid_font(cid_font_id);
+    cid_font.subtype(CidFontType::Type2)
+        .base_font(Name(base_font.as_bytes()))
+        .system_info(system_info)
+        .font_descriptor(descriptor_id)
+        .cid_to_gid_map_stream(gid_map_id);
+    cid_font.widths().consecutive(1, used.glyphs.iter().map(|&(_, glyph)| glyph_width(index, glyph)));
+    cid_font.finish();
+
+    let gid_map: Vec<u8> = std::iter::once(0)
+        .chain(used.glyphs.iter().map(|&(_, glyph)| glyph))
+        .flat_map(u16::to_be_bytes)
+        .collect();
+    pdf.stream(gid_map_id, &deflate(&gid_map)).filter(Filter::FlateDecode);
+
+    let bbox = face.global_bounding_box();
+    let mut flags = FontFlags::NON_SYMBOLIC;
+    flags.set(FontFlags::FIXED_PITCH, index >= 2);
+    pdf.font_descriptor(descriptor_id)
+        .name(Name(base_font.as_bytes()))
+        .flags(flags)
+        .bbox(Rect::new(
+            f32::from(bbox.x_min) * scale,
+            f32::from(bbox.y_min) * scale,
+            f32::from(bbox.x_max) * scale,
+            f32::from(bbox.y_max) * scale,
+        ))
+        .italic_angle(0.0)
+        .ascent(f32::from(face.ascender()) * scale)
+        .descent(f32::from(face.descender()) * scale)
+        .cap_height(f32::from(face.capital_height().unwrap_or(face.ascender())) * scale)
+        .stem_v(if index % 2 == 1 { 120.0 } else { 80.0 })
+        .font_file2(file_id);
+
+    let mut cmap = UnicodeCmap::new(Name(b"Custom"), system_info);
+    for (cid, &(c, _)) in (1u16..).zip(&used.glyphs) {
+        cmap.pair(cid, c);
+    }
+    pdf.cmap(cmap_id, &cmap.finish());
+
+    // The missing glyph is always kept
+    let glyphs: Vec<u16> = std::iter::once(0).chain(used.glyphs.iter().map(|&(_, glyph)| glyph)).collect();
+    let subset = subsetter::subset(FONT_FILES[index], 0, subsetter::Profile::pdf(&glyphs))
+        .map_err(|e| format!("Failed to embed font {}: {}", BASE_FONTS[index], e))?;
+    pdf.stream(file_id, &deflate(&subset))
+        .filter(Filter::FlateDecode)
+        .pair(Name(b"Length1"), subset.len() as i32);
+    Ok(())
+}
+
+// Subset fonts are named with a tag of six capital letters, here derived from the glyphs kept
+fn subset_tag(used: &FaceUsage) -> String {
+    let mut hash = used.glyphs.iter().fold(5381u32, |hash, &(_, glyph)| hash.wrapping_mul(33) ^ u32::from(glyph));
+    (0..6)
+        .map(|_| {
+            let letter = char::from(b'A' + (hash % 26) as u8);
+            hash /= 26;
+            letter
+        })
+        .collect()
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(data, 6)
+}
+
+// JPEGs are embedded as they are; PNGs are decoded to raw samples. GIFs aren't supported.
+fn decode_image(data: Vec<u8>, header: ImageHeader) -> Option<PdfImage> {
+    if header.width == 0 || header.height == 0 || u64::from(header.width) * u64::from(header.height) > MAX_IMAGE_PIXELS {
+        return None;
+    }
+    match header.format {
+        ImageFormat::Jpeg if matches!(header.components, 1 | 3) => Some(PdfImage {
+            width: header.width,
+            height: header.height,
+            kind: ImageKind::Jpeg { data, components: header.components },
+        }),
+        ImageFormat::Png => decode_png(&data),
+        _ => None,
+    }
+}
+
+fn decode_png(data: &[u8]) -> Option<PdfImage> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    // Palettes, low bit depths and tRNS chunks become 8-bit gray or RGB with alpha
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().ok()?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).ok()?;
+    pixels.truncate(frame.buffer_size());
+
+    let (channels, gray) = match frame.color_type {
+        png::ColorType::Grayscale => (1, true),
+        png::ColorType::GrayscaleAlpha => (2, true),
+        png::ColorType::Rgb => (3, false),
+        png::ColorType::Rgba => (4, false),
+        png::ColorType::Indexed => return None,
+    };
+    let (color, alpha) = if channels % 2 == 0 {
+        let mut color = Vec::with_capacity(pixels.len());
+        let mut alpha = Vec::with_capacity(pixels.len() / channels);
+        for pixel in pixels.chunks_exact(channels) {
+            color.extend_from_slice(&pixel[..channels - 1]);
+            alpha.push(pixel[channels - 1]);
+        }
+        // Fully opaque images don't need a mask
+        (color, Some(alpha).filter(|alpha| alpha.iter().any(|&value| value != 255)))
+    } else {
+        (pixels, None)
+    };
+
+    Some(PdfImage {
+        width: frame.width,
+        height: frame.height,
+        kind: ImageKind::Samples { data: deflate(&color), gray, alpha: alpha.map(|alpha| deflate(&alpha)) },
+    })
+}
+
+// Explicit page breaks, as written for HTML printing or by other markdown tools
+fn is_page_break(html: &str) -> bool {
+    let html = html.to_ascii_lowercase().replace(' ', "");
+    ["page-break-before:always", "page-break-after:always", "break-before:page", "break-after:page", "<!--pagebreak-->"]
+        .iter()
+        .any(|marker| html.contains(marker))
+}
+
+fn heading_size(level: HeadingLevel) -> f32 {
+    match level {
+        HeadingLevel::H1 => 20.0,
+        HeadingLevel::H2 => 16.0,
+        HeadingLevel::H3 => 13.5,
+        HeadingLevel::H4 => 11.5,
+        HeadingLevel::H5 => 10.5,
+        HeadingLevel::H6 => 10.0,
+    }
+}
+
+fn alert_color(kind: BlockQuoteKind) -> Color {
+    match kind {
+        BlockQuoteKind::Note => rgb(0x0969DA),
+        BlockQuoteKind::Tip => rgb(0x1A7F37),
+        BlockQuoteKind::Important => rgb(0x8250DF),
+        BlockQuoteKind::Warning => rgb(0x9A6700),
+        BlockQuoteKind::Caution => rgb(0xCF222E),
+    }
+}
+
+// Text as it is typeset: tabs and other spaces become plain spaces, and zero-width and
+// control characters are dropped
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            '\n' => Some(c),
+            '\t' | '\u{2000}'..='\u{200A}' | '\u{202F}' => Some(' '),
+            '\u{200B}'..='\u{200D}' | '\u{FEFF}' => None,
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(markdown: &str) -> Result<Vec<u8>, String> {
+        export_pdf(markdown, None, "doc", &HighlightEngine::new(), MarkdownOptions::default(), &PdfOptions::default())
+    }
+
+    fn contains(pdf: &[u8], text: &[u8]) -> bool {
+        pdf.windows(text.len()).any(|window| window == text)
+    }
+
+    #[test]
+    fn embeds_subset_fonts_with_unicode_maps() {
+        let pdf = export("# Café\n\nПривет → ✓ ≤ π, “quotes” and `code`\n").unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        for key in [&b"/Identity-H"[..], b"/CIDFontType2", b"/CIDToGIDMap", b"/ToUnicode", b"/FontFile2", b"+DejaVuSans-Bold", b"+DejaVuSansMono"] {
+            assert!(contains(&pdf, key), "missing {}", String::from_utf8_lossy(key));
+        }
+        assert!(!contains(&pdf, b"/Type1"));
+        // Only the glyphs used are kept
+        assert!(pdf.len() < 150_000, "{} bytes", pdf.len());
+    }
+
+    #[test]
+    fn writes_characters_no_font_has() {
+        assert!(export("世界 🎉\n").is_ok());
+        assert_eq!(Font::default().glyph('世'), (0, 0));
+
+        // Each still gets its own CID, so the text survives copying
+        let mut used = UsedGlyphs::default();
+        let runs = glyph_runs(Font::default(), "世界世", &mut used);
+        assert_eq!(runs, vec![(0, vec![0, 1, 0, 2, 0, 1])]);
+        assert_eq!(used[0].glyphs, vec![('世', 0), ('界', 0)]);
+    }
+
+    #[test]
+    fn falls_back_to_sans_for_characters_mono_lacks() {
+        assert_eq!(Font::MONO.glyph('😀').0, 0);
+        assert_eq!(Font { bold: true, ..Font::MONO }.glyph('😀').0, 1);
+        assert_eq!(Font::MONO.glyph('π').0, 2);
+
+        let mut used = UsedGlyphs::default();
+        let runs = glyph_runs(Font::MONO, "a😀b", &mut used);
+        let faces: Vec<usize> = runs.iter().map(|(face, _)| *face).collect();
+        assert_eq!(faces, vec![2, 0, 2]);
+    }
+
+    #[test]
+    fn measures_text_with_the_font_metrics() {
+        let width = Font::default().text_width("ii", 10.0);
+        assert!(width > 0.0);
+        assert!(Font::default().text_width("WW", 10.0) > width);
+        assert_eq!(Font::MONO.text_width("ii", 10.0), Font::MONO.text_width("WW", 10.0));
+        assert_eq!(Font { italic: true, ..Font::default() }.text_width("ii", 10.0), width);
+    }
+
+    #[test]
+    fn links_email_addresses_with_mailto() {
+        let pdf = export("Write to a@b.com or <c@d.org>.\n").unwrap();
+        assert!(contains(&pdf, b"/URI (mailto:a@b.com)"));
+        assert!(contains(&pdf, b"/URI (mailto:c@d.org)"));
+    }
+
+    #[test]
+    fn titles_alerts() {
+        let highlighter = HighlightEngine::new();
+        let mut writer = PdfWriter::new(&highlighter, None, PageSize::Letter, Vec::new());
+        let document = document_events(":::warning Mind the gap\nb\n:::\n\n> [!TIP]\n> c\n", MarkdownOptions::default());
+        writer.write_events(&document.events, &document.alert_titles);
+        let titles: Vec<&str> = writer.pages[0].ops.iter()
+            .filter_map(|op| match op {
+                Op::Text { style, text, .. } if style.font.bold => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(titles, ["Mind the gap", "Tip"]);
+    }
+
+    #[test]
+    fn normalizes_spaces_and_drops_invisible_characters() {
+        assert_eq!(normalize("a\u{200B}b\tc\u{2003}d\u{7}\n"), "ab c d\n");
+    }
+
+    #[test]
+    fn wraps_code_by_characters() {
+        let style = TextStyle::plain(Font::MONO, CODE_SIZE, TEXT);
+        let rows: Vec<Vec<String>> = wrap_code(&[(style, "αβγδε\tx")], 3).into_iter()
+            .map(|row| row.into_iter().map(|(_, text)| text).collect())
+            .collect();
+        assert_eq!(rows, vec![vec!["αβγ"], vec!["δε "], vec!["  x"]]);
+    }
+
+    #[test]
+    fn tags_subsets_with_six_capitals() {
+        let mut used = FaceUsage::default();
+        used.cid('a', 68);
+        let tag = subset_tag(&used);
+        assert_eq!(tag.len(), 6);
+        assert!(tag.chars().all(|c| c.is_ascii_uppercase()));
+    }
+}
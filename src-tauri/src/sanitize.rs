@@ -0,0 +1,405 @@
+use std::cell::RefCell;
+use lol_html::html_content::Element;
+use lol_html::{doc_comments, element, rewrite_str, RewriteStrSettings};
+use serde::{Deserialize, Serialize};
+
+// Upper bound on recorded report entries so hostile documents can't bloat the IPC payload
+const MAX_REPORT_ITEMS: usize = 500;
+
+// Elements removed together with everything inside them
+const DANGEROUS_TAGS: &[&str] = &[
+    "script", "style", "iframe", "frame", "frameset", "object", "embed", "applet",
+    "noscript", "noembed", "noframes", "template", "textarea", "select", "title",
+    "xmp", "base", "link", "meta", "form", "button",
+];
+
+// Formatting produced by pulldown-cmark for ordinary markdown
+const STRICT_TAGS: &[&str] = &[
+    "p", "br", "strong", "em", "b", "i", "u", "s", "del", "ins",
+    "ul", "ol", "li", "dl", "dt", "dd",
+    "h1", "h2", "h3", "h4", "h5", "h6",
+    "blockquote", "pre", "code", "kbd", "samp", "var",
+    "table", "thead", "tbody", "tfoot", "tr", "th", "td",
+    "a", "img", "div", "span", "hr", "sup", "sub", "input", "aside",
+];
+
+// MathML Core, produced for `$...$` and `$$...$$` math
+const MATHML_TAGS: &[&str] = &[
+    "math", "semantics", "annotation", "mrow", "mi", "mn", "mo", "ms", "mtext", "mspace",
+    "mfrac", "msqrt", "mroot", "mstyle", "merror", "mpadded", "mphantom",
+    "msub", "msup", "msubsup", "munder", "mover", "munderover", "mmultiscripts",
+    "mprescripts", "none", "mtable", "mtr", "mtd",
+];
+
+const MATHML_ATTRIBUTES: &[&str] = &[
+    "xmlns", "display", "encoding", "mathvariant", "displaystyle", "scriptlevel",
+    "stretchy", "symmetric", "largeop", "movablelimits", "accent", "accentunder",
+    "lspace", "rspace", "minsize", "maxsize", "form", "fence", "separator",
+    "linethickness", "width", "height", "depth", "voffset", "mathcolor", "mathbackground",
+    "mathsize", "columnalign", "rowalign", "columnspacing", "rowspacing", "columnlines",
+    "rowlines", "columnspan", "rowspan",
+];
+
+// Extra HTML commonly embedded in README files
+const STANDARD_TAGS: &[&str] = &[
+    "figure", "figcaption", "details", "summary", "mark", "abbr", "cite", "q",
+    "small", "caption", "colgroup", "col", "picture", "source",
+];
+
+// Inline SVG, matching the set the frontend keeps for Mermaid output
+const RELAXED_TAGS: &[&str] = &[
+    "svg", "g", "path", "text", "tspan", "rect", "circle", "ellipse", "line",
+    "polyline", "polygon", "defs", "marker",
+];
+
+const GLOBAL_ATTRIBUTES: &[&str] = &[
+    "class", "id", "title", "lang", "dir", "align", "role", "aria-hidden",
+    "data-lang", "data-title", "data-highlight-lines", "data-source-line",
+];
+
+const ELEMENT_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "rel", "target", "name"]),
+    ("img", &["src", "alt", "width", "height"]),
+    ("input", &["type", "checked", "disabled"]),
+    ("ol", &["start", "type"]),
+    ("li", &["value"]),
+    ("td", &["colspan", "rowspan"]),
+    ("th", &["colspan", "rowspan", "scope"]),
+    ("col", &["span"]),
+    ("colgroup", &["span"]),
+    ("details", &["open"]),
+    ("source", &["srcset", "media", "type"]),
+];
+
+const SVG_ATTRIBUTES: &[&str] = &[
+    "viewbox", "xmlns", "x", "y", "x1", "y1", "x2", "y2", "cx", "cy", "r", "rx", "ry",
+    "width", "height", "fill", "stroke", "stroke-width", "d", "transform", "points",
+    "text-anchor", "font-family", "font-size", "font-weight", "marker-end", "marker-start",
+];
+
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "srcset"];
+
+// Properties emitted by syntect, table alignment and math error markers
+const STRICT_STYLE_PROPERTIES: &[&str] = &[
+    "color", "background-color", "font-weight", "font-style", "text-decoration", "text-align",
+    "border-color",
+];
+
+const STANDARD_STYLE_PROPERTIES: &[&str] = &[
+    "margin", "margin-top", "margin-bottom", "margin-left", "margin-right",
+    "padding", "padding-top", "padding-bottom", "padding-left", "padding-right",
+    "border", "border-style", "border-width", "border-radius",
+    "width", "height", "max-width", "max-height", "vertical-align",
+    "font-size", "font-family", "line-height", "white-space",
+];
+
+const RELAXED_STYLE_PROPERTIES: &[&str] = &[
+    "display", "float", "clear", "opacity", "fill", "stroke", "stroke-width", "text-anchor",
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SanitizeLevel {
+    // Plain markdown output only: no inline styling beyond highlighting, web links only
+    Strict,
+    // Common README HTML, local and data: images
+    #[default]
+    Standard,
+    // Adds inline SVG and layout styles
+    Relaxed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StrippedKind {
+    Element,
+    Attribute,
+    Url,
+    StyleProperty,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StrippedItem {
+    pub kind: StrippedKind,
+    pub element: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SanitizeReport {
+    pub level: SanitizeLevel,
+    pub stripped: Vec<StrippedItem>,
+    pub truncated: bool,
+}
+
+impl SanitizeReport {
+    fn record(&mut self, kind: StrippedKind, element: &str, detail: &str) {
+        if self.stripped.len() >= MAX_REPORT_ITEMS {
+            self.truncated = true;
+            return;
+        }
+        self.stripped.push(StrippedItem {
+            kind,
+            element: element.to_string(),
+            detail: detail.chars().take(200).collect(),
+        });
+    }
+}
+
+impl SanitizeLevel {
+    fn allows_tag(self, tag: &str) -> bool {
+        let relaxed = self == SanitizeLevel::Relaxed;
+        STRICT_TAGS.contains(&tag)
+            || MATHML_TAGS.contains(&tag)
+            || (self != SanitizeLevel::Strict && STANDARD_TAGS.contains(&tag))
+            || (relaxed && RELAXED_TAGS.contains(&tag))
+    }
+
+    fn allows_attribute(self, tag: &str, attribute: &str) -> bool {
+        if GLOBAL_ATTRIBUTES.contains(&attribute) {
+            return true;
+        }
+        if attribute == "style" {
+            return true; // Individual properties are filtered separately
+        }
+        if MATHML_TAGS.contains(&tag) && MATHML_ATTRIBUTES.contains(&attribute) {
+            return true;
+        }
+        if self == SanitizeLevel::Relaxed && RELAXED_TAGS.contains(&tag) && SVG_ATTRIBUTES.contains(&attribute) {
+            return true;
+        }
+        ELEMENT_ATTRIBUTES.iter()
+            .find(|(name, _)| *name == tag)
+            .is_some_and(|(_, attributes)| attributes.contains(&attribute))
+    }
+
+    fn allows_style_property(self, property: &str) -> bool {
+        STRICT_STYLE_PROPERTIES.contains(&property)
+            || (self != SanitizeLevel::Strict && STANDARD_STYLE_PROPERTIES.contains(&property))
+            || (self == SanitizeLevel::Relaxed && RELAXED_STYLE_PROPERTIES.contains(&property))
+    }
+
+    fn allows_url(self, attribute: &str, url: &str) -> bool {
+        // Browsers ignore embedded whitespace and control characters in schemes
+        let normalized: String = url.chars()
+            .filter(|c| !c.is_whitespace() && !c.is_control())
+            .collect::<String>()
+            .to_lowercase();
+
+        let scheme = match normalized.find(':') {
+            Some(colon) if !normalized[..colon].contains(['/', '?', '#']) => &normalized[..colon],
+            _ => return true, // Relative URL or fragment
+        };
+
+        match scheme {
+            "http" | "https" | "mailto" => true,
+            "file" | "tel" => self != SanitizeLevel::Strict,
+            "data" => {
+                self != SanitizeLevel::Strict
+                    && attribute == "src"
+                    && ["data:image/png", "data:image/jpeg", "data:image/gif", "data:image/webp", "data:image/svg+xml"]
+                        .iter()
+                        .any(|prefix| normalized.starts_with(prefix))
+            }
+            _ => false,
+        }
+    }
+}
+
+// Filter a style attribute down to allowed properties with safe values
+fn filter_style(style: &str, level: SanitizeLevel, tag: &str, report: &mut SanitizeReport) -> String {
+    let mut kept = Vec::new();
+    for declaration in style.split(';') {
+        let declaration = declaration.trim();
+        if declaration.is_empty() {
+            continue;
+        }
+        let Some((property, value)) = declaration.split_once(':') else {
+            report.record(StrippedKind::StyleProperty, tag, declaration);
+            continue;
+        };
+        let property = property.trim().to_lowercase();
+        let value = value.trim();
+        let value_lower = value.to_lowercase();
+
+        let unsafe_value = ["url(", "expression(", "javascript:", "vbscript:", "@import", "/*"]
+            .iter()
+            .any(|pattern| value_lower.contains(pattern))
+            || value.contains(['\\', '<', '>', '&', '"']);
+
+        if !level.allows_style_property(&property) || unsafe_value {
+            report.record(StrippedKind::StyleProperty, tag, declaration);
+            continue;
+        }
+        kept.push(format!("{}:{}", property, value));
+    }
+    kept.join(";")
+}
+
+fn sanitize_element(el: &mut Element, level: SanitizeLevel, report: &mut SanitizeReport) {
+    let tag = el.tag_name();
+
+    if DANGEROUS_TAGS.contains(&tag.as_str()) && !level.allows_tag(&tag) {
+        report.record(StrippedKind::Element, &tag, &tag);
+        el.remove();
+        return;
+    }
+
+    if !level.allows_tag(&tag) {
+        report.record(StrippedKind::Element, &tag, &tag);
+        el.remove_and_keep_content();
+        return;
+    }
+
+    // Only read-only task list checkboxes are allowed
+    if tag == "input" {
+        let is_checkbox = el.get_attribute("type")
+            .is_some_and(|value| value.eq_ignore_ascii_case("checkbox"));
+        if !is_checkbox {
+            report.record(StrippedKind::Element, &tag, "non-checkbox input");
+            el.remove();
+            return;
+        }
+        if !el.has_attribute("disabled") {
+            let _ = el.set_attribute("disabled", "");
+        }
+    }
+
+    let attributes: Vec<(String, String)> = el.attributes().iter()
+        .map(|attribute| (attribute.name(), attribute.value()))
+        .collect();
+
+    for (name, raw_value) in attributes {
+        let value = html_escape::decode_html_entities(&raw_value).to_string();
+
+        if !level.allows_attribute(&tag, &name) {
+            report.record(StrippedKind::Attribute, &tag, &name);
+            el.remove_attribute(&name);
+            continue;
+        }
+
+        if URL_ATTRIBUTES.contains(&name.as_str()) && !level.allows_url(&name, &value) {
+            report.record(StrippedKind::Url, &tag, &value);
+            el.remove_attribute(&name);
+            continue;
+        }
+
+        if name == "style" {
+            let filtered = filter_style(&value, level, &tag, report);
+            if filtered.is_empty() {
+                el.remove_attribute(&name);
+            } else if filtered != value {
+                let _ = el.set_attribute(&name, &filtered);
+            }
+        }
+    }
+
+    // Links leaving the document never get access to the viewer window
+    if tag == "a" && el.has_attribute("target") {
+        let _ = el.set_attribute("rel", "noopener noreferrer");
+    }
+}
+
+// Sanitize rendered HTML against the allowlist for the given level
+pub fn sanitize_html(html: &str, level: SanitizeLevel) -> Result<(String, SanitizeReport), String> {
+    let report = RefCell::new(SanitizeReport { level, ..SanitizeReport::default() });
+
+    let output = rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("*", |el| {
+                sanitize_element(el, level, &mut report.borrow_mut());
+                Ok(())
+            }),
+        ],
+        document_content_handlers: vec![
+            // Comments are invisible anyway, so drop them without reporting
+            doc_comments!(|comment| {
+                comment.remove();
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| format!("Failed to sanitize HTML: {}", e))?;
+
+    Ok((output, report.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(html: &str, level: SanitizeLevel) -> String {
+        sanitize_html(html, level).unwrap().0
+    }
+
+    #[test]
+    fn removes_dangerous_elements_with_their_content_at_every_level() {
+        for level in [SanitizeLevel::Strict, SanitizeLevel::Standard, SanitizeLevel::Relaxed] {
+            assert_eq!(clean("<p>a<script>alert(1)</script>b</p>", level), "<p>ab</p>");
+            assert_eq!(clean("<iframe src=\"x\">inside</iframe>", level), "");
+            assert_eq!(clean("<p onclick=\"x()\">a</p>", level), "<p>a</p>");
+            assert_eq!(clean("<a href=\"javascript:alert(1)\">a</a>", level), "<a>a</a>");
+            assert_eq!(clean("<a href=\"java\tscript:x\">a</a>", level), "<a>a</a>");
+        }
+    }
+
+    #[test]
+    fn strict_keeps_only_markdown_output() {
+        assert_eq!(clean("<details><summary>s</summary>d</details>", SanitizeLevel::Strict), "sd");
+        assert_eq!(clean("<img src=\"file:///etc/x.png\">", SanitizeLevel::Strict), "<img>");
+        assert_eq!(clean("<img src=\"data:image/png;base64,AA\">", SanitizeLevel::Strict), "<img>");
+        assert_eq!(clean("<span style=\"color:red;margin:4px\">a</span>", SanitizeLevel::Strict),
+            "<span style=\"color:red\">a</span>");
+        assert_eq!(clean("<a href=\"https://example.com\">a</a>", SanitizeLevel::Strict),
+            "<a href=\"https://example.com\">a</a>");
+    }
+
+    #[test]
+    fn standard_allows_readme_html_and_local_images() {
+        assert_eq!(clean("<details open><summary>s</summary>d</details>", SanitizeLevel::Standard),
+            "<details open><summary>s</summary>d</details>");
+        assert_eq!(clean("<img src=\"data:image/png;base64,AA\">", SanitizeLevel::Standard),
+            "<img src=\"data:image/png;base64,AA\">");
+        assert_eq!(clean("<a href=\"data:text/html,x\">a</a>", SanitizeLevel::Standard), "<a>a</a>");
+        assert_eq!(clean("<span style=\"margin:4px;display:none\">a</span>", SanitizeLevel::Standard),
+            "<span style=\"margin:4px\">a</span>");
+        assert_eq!(clean("<svg><path d=\"M0\"></path></svg>", SanitizeLevel::Standard), "");
+    }
+
+    #[test]
+    fn relaxed_allows_inline_svg_and_layout_styles() {
+        assert_eq!(clean("<svg viewBox=\"0 0 1 1\"><path d=\"M0\"></path></svg>", SanitizeLevel::Relaxed),
+            "<svg viewBox=\"0 0 1 1\"><path d=\"M0\"></path></svg>");
+        assert_eq!(clean("<div style=\"display:none;background:url(x)\">a</div>", SanitizeLevel::Relaxed),
+            "<div style=\"display:none\">a</div>");
+    }
+
+    #[test]
+    fn keeps_math_and_read_only_checkboxes() {
+        assert_eq!(clean("<math display=\"block\"><mi>x</mi></math>", SanitizeLevel::Strict),
+            "<math display=\"block\"><mi>x</mi></math>");
+        assert_eq!(clean("<input type=\"checkbox\" checked>", SanitizeLevel::Strict),
+            "<input type=\"checkbox\" checked disabled=\"\">");
+        assert_eq!(clean("<input type=\"text\">", SanitizeLevel::Relaxed), "");
+    }
+
+    #[test]
+    fn protects_new_windows_and_drops_comments() {
+        assert_eq!(clean("<a href=\"https://x.org\" target=\"_blank\">a</a><!-- c -->", SanitizeLevel::Standard),
+            "<a href=\"https://x.org\" target=\"_blank\" rel=\"noopener noreferrer\">a</a>");
+    }
+
+    #[test]
+    fn reports_what_was_stripped() {
+        let (_, report) = sanitize_html("<p onclick=\"x\"><script></script><a href=\"vbscript:x\">a</a></p>", SanitizeLevel::Standard).unwrap();
+        let kinds: Vec<StrippedKind> = report.stripped.iter().map(|item| item.kind).collect();
+        assert_eq!(kinds, vec![StrippedKind::Attribute, StrippedKind::Element, StrippedKind::Url]);
+        assert_eq!(report.level, SanitizeLevel::Standard);
+        assert!(!report.truncated);
+
+        let (_, report) = sanitize_html(&"<blink></blink>".repeat(MAX_REPORT_ITEMS + 1), SanitizeLevel::Strict).unwrap();
+        assert_eq!(report.stripped.len(), MAX_REPORT_ITEMS);
+        assert!(report.truncated);
+    }
+}
@@ -0,0 +1,271 @@
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::highlight::HighlightEngine;
+use crate::markdown::{RenderedBlock, RenderedDocument};
+use crate::source_map::strip_source_lines;
+use crate::standalone::{embed_diagrams, inline_images, StandaloneHtml};
+use crate::ExportTheme;
+
+// Paragraphs starting with one of these turn the rest of their slide into speaker notes
+const NOTE_PREFIXES: &[&str] = &["Note:", "Notes:"];
+
+// One slide of a presentation and the lines of the document it came from
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Slide {
+    pub html: String,
+    pub notes: Option<String>,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+// Group the top-level blocks of a document into slides. Documents with `---` rules are
+// split at the rules; documents without any start a slide at every `#` and `##` heading.
+pub fn split_slides(blocks: &[RenderedBlock]) -> Vec<Slide> {
+    let split_at_rules = blocks.iter().any(|block| is_rule(&block.html));
+    let mut slides: Vec<Slide> = Vec::new();
+    let mut slide_ended = true;
+
+    for block in blocks {
+        if split_at_rules && is_rule(&block.html) {
+            slide_ended = true;
+            continue;
+        }
+        if !split_at_rules && starts_section(&block.html) {
+            slide_ended = true;
+        }
+
+        if slide_ended {
+            slides.push(Slide { html: String::new(), notes: None, start_line: block.start_line, end_line: block.end_line });
+            slide_ended = false;
+        }
+        let Some(slide) = slides.last_mut() else { continue };
+        slide.end_line = block.end_line;
+        match (&mut slide.notes, strip_note_prefix(&block.html)) {
+            (Some(notes), _) => notes.push_str(&block.html),
+            (None, Some(notes)) => slide.notes = Some(notes),
+            (None, None) => slide.html.push_str(&block.html),
+        }
+    }
+
+    slides
+}
+
+// A slide deck in a single HTML file that needs nothing beside it: local images are
+// inlined and diagrams replaced by their rendered SVG, like standalone_html does for
+// documents. Arrow keys, space and Home/End navigate, N shows notes and F goes full screen.
+pub fn slide_deck_html(
+    document: RenderedDocument,
+    base_file: Option<&Path>,
+    title: &str,
+    diagrams: &[Option<String>],
+    highlighter: &HighlightEngine,
+) -> Result<StandaloneHtml, String> {
+    let slides = split_slides(document.blocks.as_deref().unwrap_or_default());
+    let mut sections = String::new();
+    for (index, slide) in slides.iter().enumerate() {
+        sections.push_str(&format!("<section class=\"slide\" id=\"slide-{}\">\n<div class=\"slide-content\">\n{}</div>\n", index + 1, slide.html));
+        if let Some(notes) = &slide.notes {
+            sections.push_str(&format!("<aside class=\"notes\">\n{}</aside>\n", notes));
+        }
+        sections.push_str("</section>\n");
+    }
+
+    let html = strip_source_lines(&sections).into_owned();
+    let (html, skipped_images) = match base_file.and_then(Path::parent) {
+        Some(base_dir) => inline_images(&html, base_dir)?,
+        None => (html, Vec::new()),
+    };
+    let html = embed_diagrams(&html, diagrams)?;
+
+    let title = document.front_matter.as_ref()
+        .and_then(|front_matter| front_matter.title.as_deref())
+        .unwrap_or(title);
+    let html = format!(
+        concat!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n",
+            "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\n",
+            "<title>{}</title>\n<style>{}{}</style>\n</head>\n<body>\n<main class=\"deck\">\n{}</main>\n",
+            "<div class=\"slide-counter\"></div>\n<script>{}</script>\n</body>\n</html>\n",
+        ),
+        html_escape::encode_text(title), DECK_CSS, highlighter.theme_css(ExportTheme::Light), html, DECK_SCRIPT
+    );
+    Ok(StandaloneHtml { html, skipped_images })
+}
+
+fn is_rule(html: &str) -> bool {
+    html.trim_start().starts_with("<hr")
+}
+
+fn starts_section(html: &str) -> bool {
+    let html = html.trim_start();
+    html.starts_with("<h1") || html.starts_with("<h2")
+}
+
+// The paragraph without its `Note:` prefix, when it starts with one
+fn strip_note_prefix(html: &str) -> Option<String> {
+    let html = html.trim_start();
+    if !html.starts_with("<p") {
+        return None;
+    }
+    let content_start = html.find('>')? + 1;
+    let content = &html[content_start..];
+    let prefix = NOTE_PREFIXES.iter().find(|prefix| content.starts_with(*prefix))?;
+    let rest = content[prefix.len()..].trim_start();
+    // A prefix on a line of its own leaves an empty paragraph behind
+    if rest.starts_with("</p>") {
+        return Some(String::new());
+    }
+    Some(format!("{}{}", &html[..content_start], rest))
+}
+
+// Slides are laid out at 960x540 and scaled to fit the window, so they look the same on
+// any screen. Code colours are added from the light highlighting theme.
+const DECK_CSS: &str = "
+* { box-sizing: border-box; }
+html, body { margin: 0; height: 100%; overflow: hidden; background: #1a1a1a; }
+body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif; color: #333; line-height: 1.5; }
+.slide { display: none; position: absolute; left: 50%; top: 50%; width: 960px; height: 540px; margin: -270px 0 0 -480px; flex-direction: column; background: #fff; transform: scale(var(--slide-scale, 1)); overflow: hidden; }
+.slide.active { display: flex; }
+.slide-content { display: flex; flex: 1; flex-direction: column; justify-content: center; padding: 40px 64px; font-size: 24px; }
+.slide-content > :first-child { margin-top: 0; }
+h1, h2, h3, h4, h5, h6 { margin: 0.6em 0 0.4em; line-height: 1.2; font-weight: 600; }
+h1 { font-size: 2.2em; }
+h2 { font-size: 1.6em; }
+h3 { font-size: 1.25em; }
+p, ul, ol, table, pre, blockquote { margin: 0 0 0.6em; }
+ul, ol { padding-left: 1.4em; }
+a { color: #007bff; text-decoration: none; }
+img, svg { max-width: 100%; max-height: 400px; }
+code { font-family: 'Monaco', 'Consolas', 'Courier New', monospace; font-size: 0.85em; background: #f1f3f5; padding: 0.1em 0.25em; border-radius: 0.2em; }
+pre { padding: 0.6em 0.9em; border-radius: 0.4em; overflow: hidden; background: #f8f9fa; }
+pre code { background: none; padding: 0; }
+pre .highlighted-line { display: block; margin: 0 -0.9em; padding: 0 0.9em; background: rgba(255, 213, 79, 0.25); }
+blockquote { padding-left: 1em; border-left: 0.2em solid #ced4da; color: #555; }
+table { border-collapse: collapse; font-size: 0.85em; }
+th, td { border: 1px solid #dee2e6; padding: 0.3em 0.6em; text-align: left; }
+th { background: #f8f9fa; }
+.markdown-alert { padding: 0.4em 0.9em; border-left: 0.25em solid var(--alert-color, #0969da); }
+.markdown-alert-title { font-weight: 600; color: var(--alert-color, #0969da); }
+.markdown-alert-tip { --alert-color: #1a7f37; }
+.markdown-alert-important { --alert-color: #8250df; }
+.markdown-alert-warning { --alert-color: #9a6700; }
+.markdown-alert-caution { --alert-color: #d1242f; }
+.math-display { display: block; text-align: center; }
+.mermaid-diagram-container { text-align: center; }
+.notes { display: none; position: absolute; left: 0; right: 0; bottom: 0; max-height: 40%; overflow: auto; padding: 12px 24px; font-size: 16px; background: rgba(255, 248, 197, 0.97); border-top: 1px solid #e6d97a; }
+.show-notes .notes { display: block; }
+.slide-counter { position: fixed; right: 16px; bottom: 12px; color: #aaa; font-size: 14px; }
+@media print {
+  html, body { height: auto; overflow: visible; background: #fff; }
+  .slide { display: flex; position: relative; left: 0; top: 0; height: auto; min-height: 540px; margin: 0; transform: none; page-break-after: always; border: 1px solid #ddd; }
+  .notes { display: block; position: static; }
+  .slide-counter { display: none; }
+}
+";
+
+const DECK_SCRIPT: &str = "
+(function () {
+  var slides = document.querySelectorAll('.slide');
+  var counter = document.querySelector('.slide-counter');
+  var current = 0;
+  function fit() {
+    var scale = Math.min(window.innerWidth / 960, window.innerHeight / 540);
+    document.documentElement.style.setProperty('--slide-scale', scale);
+  }
+  function show(index) {
+    if (slides.length === 0) return;
+    current = Math.max(0, Math.min(slides.length - 1, index));
+    slides.forEach(function (slide, i) { slide.classList.toggle('active', i === current); });
+    counter.textContent = (current + 1) + ' / ' + slides.length;
+    history.replaceState(null, '', '#' + (current + 1));
+  }
+  document.addEventListener('keydown', function (event) {
+    switch (event.key) {
+      case 'ArrowRight': case 'ArrowDown': case 'PageDown': case ' ': case 'Enter': show(current + 1); break;
+      case 'ArrowLeft': case 'ArrowUp': case 'PageUp': case 'Backspace': show(current - 1); break;
+      case 'Home': show(0); break;
+      case 'End': show(slides.length - 1); break;
+      case 'n': case 'N': document.body.classList.toggle('show-notes'); break;
+      case 'f': case 'F':
+        if (document.fullscreenElement) document.exitFullscreen();
+        else document.documentElement.requestFullscreen();
+        break;
+      default: return;
+    }
+    event.preventDefault();
+  });
+  window.addEventListener('resize', fit);
+  fit();
+  show((parseInt(location.hash.slice(1), 10) || 1) - 1);
+})();
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sanitize::SanitizeReport;
+
+    fn blocks(html: &[&str]) -> Vec<RenderedBlock> {
+        html.iter().enumerate()
+            .map(|(index, html)| RenderedBlock { html: format!("{}\n", html), start_line: index * 2 + 1, end_line: index * 2 + 1 })
+            .collect()
+    }
+
+    #[test]
+    fn splits_at_rules_when_there_are_any() {
+        let slides = split_slides(&blocks(&["<h1>Title</h1>", "<p>a</p>", "<hr />", "<h2>Next</h2>", "<h2>Same</h2>"]));
+        assert_eq!(slides.len(), 2);
+        assert_eq!(slides[0].html, "<h1>Title</h1>\n<p>a</p>\n");
+        assert_eq!((slides[0].start_line, slides[0].end_line), (1, 3));
+        assert_eq!(slides[1].html, "<h2>Next</h2>\n<h2>Same</h2>\n");
+        assert_eq!((slides[1].start_line, slides[1].end_line), (7, 9));
+    }
+
+    #[test]
+    fn splits_at_top_headings_without_rules() {
+        let slides = split_slides(&blocks(&["<p>cover</p>", "<h1>A</h1>", "<h3>a.1</h3>", "<h2 id=\"b\">B</h2>", "<p>b</p>"]));
+        let html: Vec<&str> = slides.iter().map(|slide| slide.html.as_str()).collect();
+        assert_eq!(html, ["<p>cover</p>\n", "<h1>A</h1>\n<h3>a.1</h3>\n", "<h2 id=\"b\">B</h2>\n<p>b</p>\n"]);
+        assert!(split_slides(&[]).is_empty());
+        assert!(split_slides(&blocks(&["<hr />", "<hr />"])).is_empty());
+    }
+
+    #[test]
+    fn moves_note_paragraphs_and_what_follows_into_notes() {
+        let slides = split_slides(&blocks(&[
+            "<h1>A</h1>", "<p data-source-line=\"3-3\">Note: say hello</p>", "<ul><li>point</li></ul>", "<h1>B</h1>",
+        ]));
+        assert_eq!(slides[0].html, "<h1>A</h1>\n");
+        assert_eq!(slides[0].notes.as_deref(), Some("<p data-source-line=\"3-3\">say hello</p>\n<ul><li>point</li></ul>\n"));
+        assert_eq!(slides[1].notes, None);
+    }
+
+    #[test]
+    fn handles_note_prefixes_on_their_own_line() {
+        assert_eq!(strip_note_prefix("<p>Notes:</p>"), Some(String::new()));
+        assert_eq!(strip_note_prefix("<p>Notes:\nremember</p>"), Some("<p>remember</p>".to_string()));
+        assert_eq!(strip_note_prefix("<p>Noted: no</p>"), None);
+        assert_eq!(strip_note_prefix("<h2>Note: heading</h2>"), None);
+    }
+
+    #[test]
+    fn writes_a_deck_with_notes() {
+        let document = RenderedDocument {
+            html: String::new(),
+            toc: Vec::new(),
+            front_matter: None,
+            sanitize_report: SanitizeReport::default(),
+            dependencies: Vec::new(),
+            blocks: Some(blocks(&["<h1 data-source-line=\"1-1\">Hi</h1>", "<p>Note: psst</p>", "<h1>Bye</h1>"])),
+        };
+        let deck = slide_deck_html(document, None, "Talk <1>", &[], &HighlightEngine::new()).unwrap();
+        assert!(deck.html.contains("<title>Talk &lt;1&gt;</title>"));
+        assert!(deck.html.contains("<section class=\"slide\" id=\"slide-1\">\n<div class=\"slide-content\">\n<h1>Hi</h1>\n</div>\n<aside class=\"notes\">\n<p>psst</p>\n</aside>\n</section>"));
+        assert!(deck.html.contains("id=\"slide-2\""));
+        assert!(!deck.html.contains("data-source-line"));
+        assert!(deck.skipped_images.is_empty());
+    }
+}
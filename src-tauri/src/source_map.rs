@@ -0,0 +1,285 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ops::Range;
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use pulldown_cmark::{Alignment, CowStr, Event, Tag, TagEnd};
+use serde::Serialize;
+
+use crate::markdown::{LineIndex, SourceEvent};
+
+// Block elements that earlier stages already turned into raw HTML
+const PRERENDERED_BLOCK_TAGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6", "pre", "aside"];
+
+// Where a source line ended up in the rendered document
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceLocation {
+    // Value of the element's data-source-line attribute, e.g. "12-14"
+    pub source_line: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    // Closest heading at or above the line, for documents rendered without line data
+    pub heading_id: Option<String>,
+}
+
+// Line span of a block, ignoring the blank lines lists and the like take into their range
+pub fn line_span(range: &Range<usize>, source: &str, line_index: &LineIndex) -> (usize, usize) {
+    let end = range.start + source[range.clone()].trim_end().len();
+    (line_index.line_of(range.start), line_index.line_of(end.saturating_sub(1).max(range.start)))
+}
+
+// Give every block element a `data-source-line="start-end"` attribute. Start tags are
+// written here instead of by pulldown-cmark, since its writer has no way to add attributes.
+pub fn annotate_source_lines<'a>(
+    events: Vec<SourceEvent<'a>>,
+    source: &str,
+    line_index: &LineIndex,
+) -> Vec<SourceEvent<'a>> {
+    let attribute = |range: &Range<usize>| {
+        let (start, end) = line_span(range, source, line_index);
+        format!(" data-source-line=\"{}-{}\"", start, end)
+    };
+
+    let toc_markers = toc_marker_paragraphs(&events);
+    let mut output = Vec::with_capacity(events.len());
+    let mut in_html_block = false;
+    let mut table = TableState::default();
+
+    for (index, (event, range)) in events.into_iter().enumerate() {
+        let html = match &event {
+            Event::Start(Tag::HtmlBlock) => {
+                in_html_block = true;
+                None
+            }
+            Event::End(TagEnd::HtmlBlock) => {
+                in_html_block = false;
+                None
+            }
+            // Raw HTML from the document is left alone
+            Event::Html(html) if !in_html_block => with_attribute(html, &attribute(&range)),
+            Event::Start(Tag::Paragraph) if !toc_markers.contains(&index) => Some(format!("<p{}>", attribute(&range))),
+            Event::Start(Tag::BlockQuote(None)) => Some(format!("<blockquote{}>\n", attribute(&range))),
+            Event::Start(Tag::List(Some(1))) => Some(format!("<ol{}>\n", attribute(&range))),
+            Event::Start(Tag::List(Some(start))) => Some(format!("<ol start=\"{}\"{}>\n", start, attribute(&range))),
+            Event::Start(Tag::List(None)) => Some(format!("<ul{}>\n", attribute(&range))),
+            Event::Start(Tag::Item) => Some(format!("<li{}>", attribute(&range))),
+            Event::Rule => Some(format!("<hr{} />\n", attribute(&range))),
+            Event::Start(Tag::Table(alignments)) => {
+                table = TableState { alignments: alignments.clone(), ..TableState::default() };
+                Some(format!("<table{}>", attribute(&range)))
+            }
+            Event::Start(Tag::TableHead) => {
+                table.in_head = true;
+                table.cell_index = 0;
+                Some(format!("<thead><tr{}>", attribute(&range)))
+            }
+            Event::End(TagEnd::TableHead) => {
+                table.in_head = false;
+                Some("</tr></thead><tbody>\n".to_string())
+            }
+            Event::Start(Tag::TableRow) => {
+                table.cell_index = 0;
+                Some(format!("<tr{}>", attribute(&range)))
+            }
+            Event::End(TagEnd::TableRow) => Some("</tr>\n".to_string()),
+            Event::Start(Tag::TableCell) => Some(table.cell_start()),
+            Event::End(TagEnd::TableCell) => {
+                table.cell_index += 1;
+                Some(if table.in_head { "</th>" } else { "</td>" }.to_string())
+            }
+            Event::End(TagEnd::Table) => Some("</tbody></table>\n".to_string()),
+            _ => None,
+        };
+
+        match html {
+            Some(html) => output.push((Event::Html(CowStr::from(html)), range)),
+            None => output.push((event, range)),
+        }
+    }
+    output
+}
+
+// `[TOC]` paragraphs must stay exactly the `<p>[TOC]</p>` that TOC_MARKER looks for
+fn toc_marker_paragraphs(events: &[SourceEvent]) -> HashSet<usize> {
+    let mut markers = HashSet::new();
+    for (index, (event, _)) in events.iter().enumerate() {
+        if !matches!(event, Event::Start(Tag::Paragraph)) {
+            continue;
+        }
+        let mut text = String::new();
+        for (inner, _) in &events[index + 1..] {
+            match inner {
+                Event::Text(content) => text.push_str(content),
+                Event::End(TagEnd::Paragraph) => {
+                    if text == "[TOC]" {
+                        markers.insert(index);
+                    }
+                    break;
+                }
+                _ => break,
+            }
+        }
+    }
+    markers
+}
+
+// Mirrors pulldown-cmark's table output, which depends on the column alignments
+#[derive(Default)]
+struct TableState {
+    alignments: Vec<Alignment>,
+    in_head: bool,
+    cell_index: usize,
+}
+
+impl TableState {
+    fn cell_start(&self) -> String {
+        let tag = if self.in_head { "th" } else { "td" };
+        let style = match self.alignments.get(self.cell_index) {
+            Some(Alignment::Left) => " style=\"text-align: left\"",
+            Some(Alignment::Center) => " style=\"text-align: center\"",
+            Some(Alignment::Right) => " style=\"text-align: right\"",
+            _ => "",
+        };
+        format!("<{}{}>", tag, style)
+    }
+}
+
+// Insert the attribute into a prerendered heading, code block or alert start tag
+fn with_attribute(html: &str, attribute: &str) -> Option<String> {
+    let tag_name_end = html.strip_prefix('<')?
+        .find(|c: char| !c.is_ascii_alphanumeric())? + 1;
+    if !PRERENDERED_BLOCK_TAGS.contains(&&html[1..tag_name_end]) {
+        return None;
+    }
+    Some(format!("{}{}{}", &html[..tag_name_end], attribute, &html[tag_name_end..]))
+}
+
+// The HTML without its data-source-line attributes, so blocks that only moved compare equal
+pub fn strip_source_lines(html: &str) -> Cow<'_, str> {
+    const ATTRIBUTE: &str = " data-source-line=\"";
+    if !html.contains(ATTRIBUTE) {
+        return Cow::Borrowed(html);
+    }
+    let mut stripped = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(ATTRIBUTE) {
+        stripped.push_str(&rest[..start]);
+        let value = &rest[start + ATTRIBUTE.len()..];
+        rest = value.find('"').map_or("", |end| &value[end + 1..]);
+    }
+    stripped.push_str(rest);
+    Cow::Owned(stripped)
+}
+
+// Find the innermost element rendered from a line. Blank lines between blocks resolve to the next block.
+pub fn locate_line(html: &str, line: usize) -> Result<Option<SourceLocation>, String> {
+    let elements = RefCell::new(Vec::new());
+    let headings = RefCell::new(Vec::new());
+
+    rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("[data-source-line]", |el| {
+                let value = el.get_attribute("data-source-line").unwrap_or_default();
+                if let Some((start, end)) = value.split_once('-') {
+                    if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                        elements.borrow_mut().push((start, end, value.clone()));
+                        if let Some(id) = el.get_attribute("id").filter(|_| is_heading(&el.tag_name())) {
+                            headings.borrow_mut().push((start, id));
+                        }
+                    }
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| format!("Failed to read source lines: {}", e))?;
+
+    let elements = elements.into_inner();
+    // Smallest span wins; among equal spans the later (nested) element
+    let containing = elements.iter()
+        .filter(|(start, end, _)| (*start..=*end).contains(&line))
+        .max_by_key(|(start, end, _)| std::cmp::Reverse(end - start))
+        .or_else(|| elements.iter().filter(|(start, _, _)| *start > line).min_by_key(|(start, _, _)| *start))
+        .or_else(|| elements.last());
+
+    let heading_id = headings.into_inner().into_iter()
+        .rfind(|(start, _)| *start <= line)
+        .map(|(_, id)| id);
+
+    Ok(containing.map(|(start_line, end_line, source_line)| SourceLocation {
+        source_line: source_line.clone(),
+        start_line: *start_line,
+        end_line: *end_line,
+        heading_id,
+    }))
+}
+
+fn is_heading(tag_name: &str) -> bool {
+    matches!(tag_name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{html, Options, Parser};
+
+    fn render(markdown: &str) -> String {
+        let events = Parser::new_ext(markdown, Options::ENABLE_TABLES).into_offset_iter().collect();
+        let events = annotate_source_lines(events, markdown, &LineIndex::new(markdown));
+        let mut output = String::new();
+        html::push_html(&mut output, events.into_iter().map(|(event, _)| event));
+        output
+    }
+
+    #[test]
+    fn annotates_block_elements() {
+        let html = render("Para\ngraph\n\n- a\n- b\n\n---\n");
+        assert_eq!(html, "<p data-source-line=\"1-2\">Para\ngraph</p>\n\
+<ul data-source-line=\"4-5\">\n<li data-source-line=\"4-4\">a</li>\n<li data-source-line=\"5-5\">b</li>\n</ul>\n\
+<hr data-source-line=\"7-7\" />\n");
+    }
+
+    #[test]
+    fn matches_pulldown_cmark_table_output() {
+        let markdown = "| a | b |\n|:--|--:|\n| 1 | 2 |\n";
+        let mut plain = String::new();
+        html::push_html(&mut plain, Parser::new_ext(markdown, Options::ENABLE_TABLES));
+        assert_eq!(strip_source_lines(&render(markdown)), plain);
+    }
+
+    #[test]
+    fn leaves_toc_markers_and_raw_html_alone() {
+        assert_eq!(render("[TOC]\n"), "<p>[TOC]</p>\n");
+        assert_eq!(render("<div>\nraw\n</div>\n"), "<div>\nraw\n</div>\n");
+        assert_eq!(with_attribute("<h2 id=\"x\">", " a"), Some("<h2 a id=\"x\">".to_string()));
+        assert_eq!(with_attribute("<div>", " a"), None);
+    }
+
+    #[test]
+    fn strips_source_line_attributes() {
+        assert_eq!(strip_source_lines("<p data-source-line=\"3-4\">a</p><li data-source-line=\"5-5\">"), "<p>a</p><li>");
+        assert!(matches!(strip_source_lines("<p>a</p>"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn locates_the_innermost_block_for_a_line() {
+        let html = "<h1 id=\"intro\" data-source-line=\"1-1\">Intro</h1>\
+<ul data-source-line=\"3-5\"><li data-source-line=\"4-4\">a</li></ul>\
+<p data-source-line=\"8-9\">b</p>";
+        let location = locate_line(html, 4).unwrap().unwrap();
+        assert_eq!((location.source_line.as_str(), location.start_line, location.end_line), ("4-4", 4, 4));
+        assert_eq!(location.heading_id.as_deref(), Some("intro"));
+
+        // Blank lines resolve to the next block, lines past the end to the last one
+        assert_eq!(locate_line(html, 6).unwrap().unwrap().source_line, "8-9");
+        assert_eq!(locate_line(html, 20).unwrap().unwrap().source_line, "8-9");
+        assert!(locate_line("<p>plain</p>", 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn ignores_trailing_blank_lines_in_spans() {
+        let source = "- a\n- b\n\n\nnext";
+        assert_eq!(line_span(&(0..10), source, &LineIndex::new(source)), (1, 2));
+    }
+}
@@ -0,0 +1,202 @@
+use std::cell::RefCell;
+use std::path::Path;
+
+use base64::Engine;
+use lol_html::html_content::ContentType;
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use serde::Serialize;
+
+use crate::highlight::HighlightEngine;
+use crate::images::{read_local_image, sniff_mime_type};
+use crate::links::percent_decode;
+use crate::markdown::RenderedDocument;
+use crate::source_map::strip_source_lines;
+use crate::{export_html_document, ExportTheme};
+
+// Base64 grows images by a third, so these keep exported files loadable in a browser
+const MAX_INLINE_IMAGE_SIZE: usize = 10 * 1024 * 1024;
+const MAX_INLINE_TOTAL_SIZE: usize = 40 * 1024 * 1024;
+
+// An exported HTML file that needs nothing beside it to display
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StandaloneHtml {
+    pub html: String,
+    // Sources of local images left as links: missing, not an image, or over the size limits
+    pub skipped_images: Vec<String>,
+}
+
+// Wrap a rendered document in the export template with local images inlined as data URIs
+// and diagrams replaced by their rendered SVG. The stylesheet for the highlighted code
+// comes from the selected themes of the export theme.
+pub fn standalone_html(
+    document: RenderedDocument,
+    base_file: Option<&Path>,
+    title: &str,
+    theme: ExportTheme,
+    diagrams: &[Option<String>],
+    highlighter: &HighlightEngine,
+) -> Result<StandaloneHtml, String> {
+    let html = strip_source_lines(&document.html).into_owned();
+    let (html, skipped_images) = match base_file.and_then(Path::parent) {
+        Some(base_dir) => inline_images(&html, base_dir)?,
+        None => (html, Vec::new()),
+    };
+    let html = embed_diagrams(&html, diagrams)?;
+    let html = export_html_document(html, title.to_string(), Some(document.toc), document.front_matter, Some(theme), highlighter)?;
+    Ok(StandaloneHtml { html, skipped_images })
+}
+
+// Replace the `src` of every local image with a data URI. Remote images and data URIs
+// are left alone.
+pub fn inline_images(html: &str, base_dir: &Path) -> Result<(String, Vec<String>), String> {
+    let skipped = RefCell::new(Vec::new());
+    let total = RefCell::new(0usize);
+
+    let html = rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("img[src]", |el| {
+                let src = el.get_attribute("src").unwrap_or_default();
+                let src = html_escape::decode_html_entities(&src).to_string();
+                if is_remote(&src) {
+                    return Ok(());
+                }
+
+                // The renderer percent-encodes paths, which the file system doesn't
+                let data = read_local_image(&src, base_dir).or_else(|| read_local_image(&percent_decode(&src), base_dir));
+                let data_uri = data.and_then(|data| {
+                    let mime_type = sniff_mime_type(&data)?;
+                    let mut total = total.borrow_mut();
+                    if data.len() > MAX_INLINE_IMAGE_SIZE || *total + data.len() > MAX_INLINE_TOTAL_SIZE {
+                        return None;
+                    }
+                    *total += data.len();
+                    Some(format!("data:{};base64,{}", mime_type, base64::engine::general_purpose::STANDARD.encode(&data)))
+                });
+                match data_uri {
+                    Some(data_uri) => el.set_attribute("src", &data_uri)?,
+                    None => skipped.borrow_mut().push(src),
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| format!("Failed to embed images: {}", e))?;
+
+    Ok((html, skipped.into_inner()))
+}
+
+fn is_remote(src: &str) -> bool {
+    src.starts_with("data:") || src.split_once("://").is_some_and(|(scheme, _)| !scheme.eq_ignore_ascii_case("file"))
+}
+
+// Swap mermaid code blocks for the SVG the viewer rendered them to, in document order.
+// Blocks without a rendering (a failed diagram, or more blocks than renderings) stay code.
+pub fn embed_diagrams(html: &str, diagrams: &[Option<String>]) -> Result<String, String> {
+    if diagrams.is_empty() {
+        return Ok(html.to_string());
+    }
+    let next = RefCell::new(diagrams.iter());
+
+    let html = rewrite_str(html, RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("pre[data-lang=\"mermaid\"]", |el| {
+                if let Some(Some(svg)) = next.borrow_mut().next() {
+                    el.replace(&format!("<div class=\"mermaid-diagram-container\">{}</div>", svg), ContentType::Html);
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    }).map_err(|e| format!("Failed to embed diagrams: {}", e))?;
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x01\0\0\0\x01";
+
+    fn data_uri(data: &[u8]) -> String {
+        format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(data))
+    }
+
+    // A PNG of the given size, with zero padding after the header
+    fn png_of_size(size: usize) -> Vec<u8> {
+        let mut data = PNG.to_vec();
+        data.resize(size, 0);
+        data
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-standalone-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn inlines_local_images() {
+        let dir = temp_dir("inline");
+        fs::write(dir.join("a&b.png"), PNG).unwrap();
+        fs::write(dir.join("My Image.png"), PNG).unwrap();
+        fs::write(dir.join("notes.txt"), "not an image").unwrap();
+
+        let html = concat!(
+            "<img src=\"a&amp;b.png\" alt=\"x\"><img src=\"My%20Image.png\">",
+            "<img src=\"notes.txt\"><img src=\"missing.png\">",
+        );
+        let (html, skipped) = inline_images(html, &dir).unwrap();
+        assert_eq!(html, format!(
+            "<img src=\"{}\" alt=\"x\"><img src=\"{}\"><img src=\"notes.txt\"><img src=\"missing.png\">",
+            data_uri(PNG), data_uri(PNG)
+        ));
+        assert_eq!(skipped, vec!["notes.txt", "missing.png"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn leaves_remote_images_and_data_uris_alone() {
+        let dir = temp_dir("remote");
+        let html = format!(
+            "<img src=\"https://example.com/a.png\"><img src=\"{}\"><img src=\"HTTP://example.com/b.png\">",
+            data_uri(PNG)
+        );
+        assert_eq!(inline_images(&html, &dir).unwrap(), (html.clone(), Vec::new()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn skips_images_over_the_size_limits() {
+        let dir = temp_dir("limits");
+        fs::write(dir.join("huge.png"), png_of_size(MAX_INLINE_IMAGE_SIZE + 1)).unwrap();
+        // Four of these all but fill the total, leaving no room for the fifth or even a small one
+        let large_size = MAX_INLINE_TOTAL_SIZE / 4 - 1;
+        fs::write(dir.join("large.png"), png_of_size(large_size)).unwrap();
+        fs::write(dir.join("small.png"), PNG).unwrap();
+
+        let html = format!("<img src=\"huge.png\">{}<img src=\"small.png\">", "<img src=\"large.png\">".repeat(5));
+        let (html, skipped) = inline_images(&html, &dir).unwrap();
+        assert_eq!(skipped, vec!["huge.png", "large.png", "small.png"]);
+        assert_eq!(html.matches("data:image/png;base64,").count(), 4);
+        assert!(html.starts_with("<img src=\"huge.png\">"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn embeds_diagrams_in_document_order() {
+        let block = |code: &str| format!("<pre data-lang=\"mermaid\"><code>{}</code></pre>", code);
+        let html = format!("{}<p>between</p>{}{}{}", block("one"), block("two"), block("three"), block("four"));
+        let diagrams = [Some("<svg>1</svg>".to_string()), None, Some("<svg>3</svg>".to_string())];
+
+        // A failed diagram and blocks past the last rendering stay code
+        assert_eq!(embed_diagrams(&html, &diagrams).unwrap(), format!(
+            "<div class=\"mermaid-diagram-container\"><svg>1</svg></div><p>between</p>{}<div class=\"mermaid-diagram-container\"><svg>3</svg></div>{}",
+            block("two"), block("four")
+        ));
+        assert_eq!(embed_diagrams(&html, &[]).unwrap(), html);
+        assert_eq!(embed_diagrams("<pre data-lang=\"rust\">x</pre>", &diagrams).unwrap(), "<pre data-lang=\"rust\">x</pre>");
+    }
+}
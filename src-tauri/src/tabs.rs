@@ -0,0 +1,565 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::markdown::RenderedBlock;
+use crate::validate_file_path;
+
+pub type TabId = u32;
+
+const DEFAULT_ZOOM: f64 = 100.0;
+// Quiet period used to coalesce the burst of events a single save produces
+pub const DEFAULT_WATCH_DEBOUNCE_MS: u64 = 300;
+const MIN_WATCH_DEBOUNCE_MS: u64 = 50;
+const MAX_WATCH_DEBOUNCE_MS: u64 = 5000;
+// A file that never stops changing (a log being written) is still reported once this
+// many quiet periods have passed since the first event
+const MAX_DEBOUNCE_PERIODS: u32 = 10;
+
+// What the frontend needs to restore a tab
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TabInfo {
+    pub id: TabId,
+    // None for documents without a file, such as the sample or dropped content
+    pub file_path: Option<String>,
+    pub title: String,
+    pub scroll_top: f64,
+    pub zoom: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TabList {
+    pub tabs: Vec<TabInfo>,
+    pub active_tab: Option<TabId>,
+}
+
+// Payload of `file-changed` and `file-deleted` events
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileChangedEvent {
+    pub tab_id: TabId,
+    pub file_path: String,
+    // Set when an embedded file such as an image changed rather than the document
+    pub dependency: Option<String>,
+}
+
+// Payload of `file-moved` events; the tab already shows the new path
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMovedEvent {
+    pub tab_id: TabId,
+    pub old_path: String,
+    pub new_path: String,
+}
+
+// What a burst of file system events amounted to for the watched file
+#[derive(Debug, PartialEq)]
+enum WatchOutcome {
+    Changed,
+    Moved(PathBuf),
+    Deleted,
+}
+
+struct Tab {
+    info: TabInfo,
+    // Dropping the watch stops watching the tab's files
+    watch: Option<FileWatch>,
+    // Last block render of the tab's file, which the next refresh is diffed against
+    rendered_blocks: Option<Vec<RenderedBlock>>,
+}
+
+// A tab's watcher, covering the document's folder and the folders of the files it embeds
+struct FileWatch {
+    watcher: RecommendedWatcher,
+    document_dir: PathBuf,
+    dependency_dirs: HashSet<PathBuf>,
+    // Shared with the watcher thread
+    dependencies: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl FileWatch {
+    fn set_dependencies(&mut self, dependencies: &[PathBuf]) {
+        let dirs: HashSet<PathBuf> = dependencies.iter()
+            .filter_map(|path| path.parent())
+            .filter(|dir| *dir != self.document_dir)
+            .map(Path::to_path_buf)
+            .collect();
+
+        for dir in self.dependency_dirs.difference(&dirs) {
+            self.watcher.unwatch(dir).ok();
+        }
+        for dir in dirs.difference(&self.dependency_dirs) {
+            if let Err(e) = self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                eprintln!("Failed to watch {}: {}", dir.display(), e);
+            }
+        }
+
+        self.dependency_dirs = dirs;
+        *self.dependencies.lock().unwrap() = dependencies.iter().cloned().collect();
+    }
+}
+
+// Open documents, each with its own file watcher
+pub struct TabManager {
+    tabs: Vec<Tab>,
+    active_tab: Option<TabId>,
+    next_id: TabId,
+    // Shared with the watcher threads so a new delay applies to tabs that are already open
+    watch_debounce_ms: Arc<AtomicU64>,
+}
+
+impl Default for TabManager {
+    fn default() -> Self {
+        Self {
+            tabs: Vec::new(),
+            active_tab: None,
+            next_id: 0,
+            watch_debounce_ms: Arc::new(AtomicU64::new(DEFAULT_WATCH_DEBOUNCE_MS)),
+        }
+    }
+}
+
+// Global state for open tabs
+pub type TabState = Arc<Mutex<TabManager>>;
+
+impl TabManager {
+    pub fn list(&self) -> TabList {
+        TabList {
+            tabs: self.tabs.iter().map(|tab| tab.info.clone()).collect(),
+            active_tab: self.active_tab,
+        }
+    }
+
+    fn tab_mut(&mut self, tab_id: TabId) -> Result<&mut Tab, String> {
+        self.tabs.iter_mut()
+            .find(|tab| tab.info.id == tab_id)
+            .ok_or_else(|| format!("No tab with id {}", tab_id))
+    }
+
+    // Open a document in a new tab, or switch to the tab that already shows it
+    pub fn open<R: Runtime>(&mut self, file_path: Option<String>, title: String, app_handle: &AppHandle<R>) -> Result<TabInfo, String> {
+        if let Some(path) = &file_path {
+            if let Some(tab) = self.tabs.iter().find(|tab| tab.info.file_path.as_ref() == Some(path)) {
+                self.active_tab = Some(tab.info.id);
+                return Ok(tab.info.clone());
+            }
+        }
+
+        self.next_id += 1;
+        let id = self.next_id;
+        let watch = file_path.as_deref()
+            .map(|path| watch_file(id, path, self.watch_debounce_ms.clone(), app_handle.clone()))
+            .transpose()?;
+
+        let info = TabInfo { id, file_path, title, scroll_top: 0.0, zoom: DEFAULT_ZOOM };
+        self.tabs.push(Tab { info: info.clone(), watch, rendered_blocks: None });
+        self.active_tab = Some(id);
+        Ok(info)
+    }
+
+    // Show a different file in an existing tab (link navigation)
+    pub fn navigate<R: Runtime>(&mut self, tab_id: TabId, file_path: String, title: String, app_handle: &AppHandle<R>) -> Result<TabInfo, String> {
+        let debounce = self.watch_debounce_ms.clone();
+        let tab = self.tab_mut(tab_id)?;
+        if tab.info.file_path.as_ref() != Some(&file_path) {
+            tab.watch = Some(watch_file(tab_id, &file_path, debounce, app_handle.clone())?);
+            tab.rendered_blocks = None;
+            tab.info.file_path = Some(file_path);
+            tab.info.scroll_top = 0.0;
+        }
+        tab.info.title = title;
+        Ok(tab.info.clone())
+    }
+
+    // Close a tab and return the tab that becomes active
+    pub fn close(&mut self, tab_id: TabId) -> Option<TabId> {
+        let Some(index) = self.tabs.iter().position(|tab| tab.info.id == tab_id) else {
+            return self.active_tab;
+        };
+        self.tabs.remove(index);
+
+        if self.active_tab == Some(tab_id) {
+            // Prefer the tab to the right, like browsers do
+            self.active_tab = self.tabs.get(index)
+                .or_else(|| self.tabs.last())
+                .map(|tab| tab.info.id);
+        }
+        self.active_tab
+    }
+
+    pub fn activate(&mut self, tab_id: TabId) -> Result<TabInfo, String> {
+        let info = self.tab_mut(tab_id)?.info.clone();
+        self.active_tab = Some(tab_id);
+        Ok(info)
+    }
+
+    pub fn update_view(&mut self, tab_id: TabId, scroll_top: f64, zoom: f64) -> Result<(), String> {
+        let tab = self.tab_mut(tab_id)?;
+        tab.info.scroll_top = scroll_top.max(0.0);
+        tab.info.zoom = zoom;
+        Ok(())
+    }
+
+    // Keep showing a file that was renamed or moved while open, without losing the reader's place
+    fn follow_move<R: Runtime>(&mut self, tab_id: TabId, new_path: String, app_handle: &AppHandle<R>) -> Result<TabInfo, String> {
+        let debounce = self.watch_debounce_ms.clone();
+        let tab = self.tab_mut(tab_id)?;
+        tab.watch = Some(watch_file(tab_id, &new_path, debounce, app_handle.clone())?);
+        if let Some(name) = Path::new(&new_path).file_name() {
+            tab.info.title = name.to_string_lossy().to_string();
+        }
+        tab.info.file_path = Some(new_path);
+        Ok(tab.info.clone())
+    }
+
+    // The tab, as long as it still shows the given document
+    fn tab_showing(&mut self, tab_id: TabId, document_path: &Path) -> Option<&mut Tab> {
+        self.tabs.iter_mut()
+            .find(|tab| tab.info.id == tab_id && tab.info.file_path.as_deref().map(Path::new) == Some(document_path))
+    }
+
+    // Replace the embedded files watched for a tab
+    pub fn set_dependencies(&mut self, tab_id: TabId, document_path: &Path, dependencies: &[PathBuf]) {
+        if let Some(watch) = self.tab_showing(tab_id, document_path).and_then(|tab| tab.watch.as_mut()) {
+            watch.set_dependencies(dependencies);
+        }
+    }
+
+    // Store a tab's latest block render and return the one it replaces
+    pub fn replace_rendered_blocks(&mut self, tab_id: TabId, document_path: &Path, blocks: Option<Vec<RenderedBlock>>) -> Option<Vec<RenderedBlock>> {
+        let tab = self.tab_showing(tab_id, document_path)?;
+        std::mem::replace(&mut tab.rendered_blocks, blocks)
+    }
+
+    // Returns the delay actually used after clamping
+    pub fn set_watch_debounce(&self, debounce_ms: u64) -> u64 {
+        let debounce_ms = debounce_ms.clamp(MIN_WATCH_DEBOUNCE_MS, MAX_WATCH_DEBOUNCE_MS);
+        self.watch_debounce_ms.store(debounce_ms, Ordering::Relaxed);
+        debounce_ms
+    }
+}
+
+// Watch a tab's file and report, once the events of a save have settled,
+// `file-changed`, `file-moved` or `file-deleted` tagged with the tab id
+fn watch_file<R: Runtime>(tab_id: TabId, file_path: &str, debounce_ms: Arc<AtomicU64>, app_handle: AppHandle<R>) -> Result<FileWatch, String> {
+    let (sender, receiver) = mpsc::channel::<Event>();
+
+    let mut watcher = notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
+        match res {
+            Ok(event) => {
+                if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+                    sender.send(event).ok();
+                }
+            }
+            Err(e) => {
+                eprintln!("Watch error: {:?}", e);
+            }
+        }
+    }).map_err(|e| format!("Failed to create watcher: {}", e))?;
+
+    // Watch the file's parent directory, so saves that replace the file are seen too
+    let parent = Path::new(file_path).parent().ok_or("File has no parent directory")?;
+    watcher.watch(parent, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Failed to watch directory: {}", e))?;
+
+    // The thread ends once the watcher, and with it the sender, is dropped
+    let watched_path = PathBuf::from(file_path);
+    let dependencies = Arc::new(Mutex::new(HashSet::new()));
+    let watched_dependencies = dependencies.clone();
+    std::thread::spawn(move || {
+        while let Ok(first) = receiver.recv() {
+            let delay = Duration::from_millis(debounce_ms.load(Ordering::Relaxed));
+            let events = collect_burst(&receiver, first, delay);
+            match classify_events(&events, &watched_path) {
+                Some(WatchOutcome::Changed) => emit_file_event(&app_handle, "file-changed", tab_id, &watched_path, None),
+                Some(WatchOutcome::Moved(new_path)) => follow_moved_file(&app_handle, tab_id, &watched_path, &new_path),
+                Some(WatchOutcome::Deleted) => emit_file_event(&app_handle, "file-deleted", tab_id, &watched_path, None),
+                None => {
+                    let dependencies = watched_dependencies.lock().unwrap();
+                    let changed = events.iter()
+                        .flat_map(|event| &event.paths)
+                        .find(|path| dependencies.contains(*path));
+                    if let Some(dependency) = changed {
+                        emit_file_event(&app_handle, "file-changed", tab_id, &watched_path, Some(dependency));
+                    }
+                }
+            }
+        }
+    });
+
+    Ok(FileWatch {
+        watcher,
+        document_dir: parent.to_path_buf(),
+        dependency_dirs: HashSet::new(),
+        dependencies,
+    })
+}
+
+// The events that follow the first until a quiet period of `delay`, or until
+// MAX_DEBOUNCE_PERIODS of them have passed
+fn collect_burst(receiver: &mpsc::Receiver<Event>, first: Event, delay: Duration) -> Vec<Event> {
+    let deadline = Instant::now() + delay * MAX_DEBOUNCE_PERIODS;
+    let mut events = vec![first];
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(delay.min(remaining)) {
+            Ok(event) => events.push(event),
+            Err(_) => break,
+        }
+    }
+    events
+}
+
+fn classify_events(events: &[Event], path: &Path) -> Option<WatchOutcome> {
+    if !events.iter().any(|event| event.paths.iter().any(|event_path| event_path == path)) {
+        return None;
+    }
+
+    // Editors that save by renaming a temporary file over the original (or by moving the
+    // original aside first) leave a file at the path, so that is just a change
+    if path.exists() {
+        return Some(WatchOutcome::Changed);
+    }
+    Some(match renamed_to(events, path) {
+        Some(new_path) => WatchOutcome::Moved(new_path),
+        None => WatchOutcome::Deleted,
+    })
+}
+
+// Where a rename took the file. Some platforms report both paths in one event,
+// others report the two halves separately, paired by a tracker id.
+fn renamed_to(events: &[Event], path: &Path) -> Option<PathBuf> {
+    let mut trackers = Vec::new();
+    let mut destination = None;
+
+    for event in events {
+        let EventKind::Modify(ModifyKind::Name(mode)) = event.kind else { continue };
+        match (mode, event.paths.as_slice()) {
+            (RenameMode::Both, [from, to]) if from == path => destination = Some(to.clone()),
+            (RenameMode::From, [from]) if from == path => trackers.extend(event.attrs.tracker()),
+            (RenameMode::To, [to]) if event.attrs.tracker().is_some_and(|tracker| trackers.contains(&tracker)) => {
+                destination = Some(to.clone());
+            }
+            _ => {}
+        }
+    }
+    destination.filter(|destination| destination.is_file())
+}
+
+fn emit_file_event<R: Runtime>(app_handle: &AppHandle<R>, event: &str, tab_id: TabId, path: &Path, dependency: Option<&Path>) {
+    let payload = FileChangedEvent {
+        tab_id,
+        file_path: path.to_string_lossy().to_string(),
+        dependency: dependency.map(|dependency| dependency.to_string_lossy().to_string()),
+    };
+    app_handle.emit(event, &payload).ok();
+}
+
+// Point the tab at the file's new location. Renames to something the viewer won't open
+// (such as a `.bak` extension) count as deletions.
+fn follow_moved_file<R: Runtime>(app_handle: &AppHandle<R>, tab_id: TabId, old_path: &Path, new_path: &Path) {
+    let Ok(validated) = validate_file_path(&new_path.to_string_lossy()) else {
+        emit_file_event(app_handle, "file-deleted", tab_id, old_path, None);
+        return;
+    };
+
+    let state = app_handle.state::<TabState>();
+    let mut tabs = state.lock().unwrap();
+    match tabs.follow_move(tab_id, validated.to_string_lossy().to_string(), app_handle) {
+        Ok(TabInfo { file_path: Some(new_path), .. }) => {
+            let payload = FileMovedEvent { tab_id, old_path: old_path.to_string_lossy().to_string(), new_path };
+            app_handle.emit("file-moved", &payload).ok();
+        }
+        Ok(_) => {}
+        // The tab was closed in the meantime
+        Err(e) => eprintln!("Failed to follow moved file: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+    use std::fs;
+
+    fn event(kind: EventKind, paths: &[&Path]) -> Event {
+        paths.iter().fold(Event::new(kind), |event, path| event.add_path(path.to_path_buf()))
+    }
+
+    fn rename(mode: RenameMode, path: &Path, tracker: usize) -> Event {
+        event(EventKind::Modify(ModifyKind::Name(mode)), &[path]).set_tracker(tracker)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-tabs-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // A folder with `a.md` and `b.md`
+    fn documents(name: &str) -> (PathBuf, String, String) {
+        let dir = temp_dir(name).canonicalize().unwrap();
+        for file in ["a.md", "b.md"] {
+            fs::write(dir.join(file), "# x\n").unwrap();
+        }
+        let path = |file: &str| dir.join(file).to_string_lossy().to_string();
+        let (a, b) = (path("a.md"), path("b.md"));
+        (dir, a, b)
+    }
+
+    #[test]
+    fn opens_each_file_in_one_tab() {
+        let (dir, a, b) = documents("open");
+        let app = tauri::test::mock_app();
+        let mut tabs = TabManager::default();
+
+        let first = tabs.open(Some(a.clone()), "a.md".to_string(), app.handle()).unwrap();
+        let second = tabs.open(Some(b), "b.md".to_string(), app.handle()).unwrap();
+        assert_ne!(first.id, second.id);
+        assert_eq!(tabs.list().active_tab, Some(second.id));
+
+        // Opening a file again switches to its tab
+        let again = tabs.open(Some(a), "a.md".to_string(), app.handle()).unwrap();
+        assert_eq!(again.id, first.id);
+        assert_eq!(tabs.list().active_tab, Some(first.id));
+        assert_eq!(tabs.list().tabs.len(), 2);
+        assert!(tabs.tabs.iter().all(|tab| tab.watch.is_some()));
+
+        // Documents without a file always get a tab of their own
+        let sample = tabs.open(None, "Sample".to_string(), app.handle()).unwrap();
+        let another = tabs.open(None, "Sample".to_string(), app.handle()).unwrap();
+        assert_ne!(sample.id, another.id);
+        assert_eq!((sample.scroll_top, sample.zoom), (0.0, DEFAULT_ZOOM));
+        assert!(tabs.tabs[2].watch.is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn activates_a_neighbour_when_closing() {
+        let app = tauri::test::mock_app();
+        let mut tabs = TabManager::default();
+        let ids: Vec<TabId> = ["one", "two", "three"].iter()
+            .map(|title| tabs.open(None, title.to_string(), app.handle()).unwrap().id)
+            .collect();
+
+        // The tab to the right takes over, or the last one at the end
+        tabs.activate(ids[1]).unwrap();
+        assert_eq!(tabs.close(ids[1]), Some(ids[2]));
+        assert_eq!(tabs.close(ids[2]), Some(ids[0]));
+
+        // Closing an inactive or unknown tab keeps the active one
+        let four = tabs.open(None, "four".to_string(), app.handle()).unwrap().id;
+        assert_eq!(tabs.close(ids[0]), Some(four));
+        assert_eq!(tabs.close(99), Some(four));
+        assert_eq!(tabs.close(four), None);
+        assert!(tabs.list().tabs.is_empty());
+        assert!(tabs.activate(four).is_err());
+    }
+
+    #[test]
+    fn keeps_scroll_and_zoom_per_tab() {
+        let (dir, a, b) = documents("view");
+        let app = tauri::test::mock_app();
+        let mut tabs = TabManager::default();
+        let first = tabs.open(Some(a), "a.md".to_string(), app.handle()).unwrap().id;
+        let second = tabs.open(None, "Sample".to_string(), app.handle()).unwrap().id;
+
+        tabs.update_view(first, 420.0, 150.0).unwrap();
+        tabs.update_view(second, -5.0, 80.0).unwrap();
+        let restored = tabs.activate(first).unwrap();
+        assert_eq!((restored.scroll_top, restored.zoom), (420.0, 150.0));
+        let restored = tabs.activate(second).unwrap();
+        assert_eq!((restored.scroll_top, restored.zoom), (0.0, 80.0));
+        assert!(tabs.update_view(99, 0.0, 100.0).is_err());
+
+        // Following a link starts the new file at the top but keeps the zoom
+        let navigated = tabs.navigate(first, b.clone(), "b.md".to_string(), app.handle()).unwrap();
+        assert_eq!(navigated.file_path, Some(b));
+        assert_eq!((navigated.scroll_top, navigated.zoom), (0.0, 150.0));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn treats_rename_replace_saves_as_changes() {
+        let dir = temp_dir("vim");
+        let file = dir.join("notes.md");
+        let backup = dir.join("notes.md~");
+        fs::write(&file, "new").unwrap();
+
+        // Vim moves the original aside, writes a new file and removes the backup
+        let events = [
+            event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&file, &backup]),
+            event(EventKind::Create(CreateKind::File), &[&file]),
+            event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &[&file]),
+            event(EventKind::Remove(RemoveKind::File), &[&backup]),
+        ];
+        assert_eq!(classify_events(&events, &file), Some(WatchOutcome::Changed));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn follows_files_moved_away() {
+        let dir = temp_dir("move");
+        let file = dir.join("notes.md");
+        let moved = dir.join("renamed.md");
+        fs::write(&moved, "x").unwrap();
+
+        let both = [event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&file, &moved])];
+        assert_eq!(classify_events(&both, &file), Some(WatchOutcome::Moved(moved.clone())));
+
+        // The two halves paired by tracker, with an unrelated rename in between
+        let halves = [
+            rename(RenameMode::From, &file, 7),
+            rename(RenameMode::To, &dir.join("other.md"), 8),
+            rename(RenameMode::To, &moved, 7),
+        ];
+        assert_eq!(renamed_to(&halves, &file), Some(moved.clone()));
+        assert_eq!(classify_events(&halves, &file), Some(WatchOutcome::Moved(moved)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_deleted_and_unrelated_files() {
+        let dir = temp_dir("remove");
+        let file = dir.join("notes.md");
+
+        assert_eq!(classify_events(&[event(EventKind::Remove(RemoveKind::File), &[&file])], &file), Some(WatchOutcome::Deleted));
+        // Moved to a path that no longer exists either
+        let gone = [event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[&file, &dir.join("gone.md")])];
+        assert_eq!(classify_events(&gone, &file), Some(WatchOutcome::Deleted));
+        assert_eq!(classify_events(&[event(EventKind::Create(CreateKind::File), &[&dir.join("other.md")])], &file), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bounds_the_debounce_of_a_file_that_keeps_changing() {
+        let (sender, receiver) = mpsc::channel();
+        let delay = Duration::from_millis(20);
+        std::thread::spawn(move || {
+            while sender.send(Event::new(EventKind::Any)).is_ok() {
+                std::thread::sleep(Duration::from_millis(2));
+            }
+        });
+
+        let started = Instant::now();
+        let events = collect_burst(&receiver, Event::new(EventKind::Any), delay);
+        assert!(events.len() > 1);
+        assert!(started.elapsed() < delay * MAX_DEBOUNCE_PERIODS * 5);
+    }
+
+    #[test]
+    fn ends_a_burst_after_a_quiet_period() {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(Event::new(EventKind::Any)).unwrap();
+        let events = collect_burst(&receiver, Event::new(EventKind::Any), Duration::from_millis(10));
+        assert_eq!(events.len(), 2);
+    }
+}
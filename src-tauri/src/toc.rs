@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+// Marker paragraph replaced by the table of contents on export
+pub const TOC_MARKER: &str = "<p>[TOC]</p>";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    pub slug: String,
+    pub line: usize,
+    #[serde(default)]
+    pub children: Vec<TocEntry>,
+}
+
+// GitHub-compatible heading slugs with duplicate disambiguation
+#[derive(Default)]
+pub struct Slugger {
+    seen: HashMap<String, usize>,
+}
+
+impl Slugger {
+    pub fn slug(&mut self, text: &str) -> String {
+        let base = slugify(text);
+        let mut candidate = base.clone();
+
+        // Same scheme as GitHub: `intro`, `intro-1`, `intro-2`, ...
+        while self.seen.contains_key(&candidate) {
+            let count = self.seen.entry(base.clone()).or_insert(0);
+            *count += 1;
+            candidate = format!("{}-{}", base, count);
+        }
+        self.seen.insert(candidate.clone(), 0);
+        candidate
+    }
+
+    // Reserve an explicit id so generated slugs don't collide with it
+    pub fn reserve(&mut self, id: &str) {
+        self.seen.entry(id.to_string()).or_insert(0);
+    }
+}
+
+// Lowercase, drop punctuation, turn spaces into hyphens
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+// Nest a flat, document-ordered heading list under its parent headings
+pub fn build_toc(flat: Vec<TocEntry>) -> Vec<TocEntry> {
+    let mut roots: Vec<TocEntry> = Vec::new();
+    let mut stack: Vec<TocEntry> = Vec::new();
+
+    for entry in flat {
+        while stack.last().is_some_and(|open| open.level >= entry.level) {
+            let finished = stack.pop().unwrap();
+            attach(&mut roots, &mut stack, finished);
+        }
+        stack.push(entry);
+    }
+    while let Some(finished) = stack.pop() {
+        attach(&mut roots, &mut stack, finished);
+    }
+
+    roots
+}
+
+fn attach(roots: &mut Vec<TocEntry>, stack: &mut [TocEntry], entry: TocEntry) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(entry),
+        None => roots.push(entry),
+    }
+}
+
+// Render the table of contents as nested lists of anchor links
+pub fn render_toc_html(toc: &[TocEntry]) -> String {
+    if toc.is_empty() {
+        return String::new();
+    }
+    let mut html = String::from("<nav class=\"table-of-contents\">\n");
+    render_toc_list(toc, &mut html);
+    html.push_str("</nav>\n");
+    html
+}
+
+fn render_toc_list(entries: &[TocEntry], html: &mut String) {
+    html.push_str("<ul>\n");
+    for entry in entries {
+        html.push_str(&format!(
+            "<li><a href=\"#{}\">{}</a>",
+            html_escape::encode_double_quoted_attribute(&entry.slug),
+            html_escape::encode_text(&entry.text)
+        ));
+        if !entry.children.is_empty() {
+            html.push('\n');
+            render_toc_list(&entry.children, html);
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: u8, text: &str) -> TocEntry {
+        TocEntry { level, text: text.to_string(), slug: slugify(text), line: 0, children: Vec::new() }
+    }
+
+    #[test]
+    fn slugifies_like_github() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  API_v2 - Overview  "), "api_v2---overview");
+        assert_eq!(slugify("Café Crème"), "café-crème");
+        assert_eq!(slugify("C++ & Rust?"), "c--rust");
+        assert_eq!(slugify("!!!"), "");
+    }
+
+    #[test]
+    fn numbers_duplicate_slugs() {
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.slug("Intro"), "intro");
+        assert_eq!(slugger.slug("Intro"), "intro-1");
+        assert_eq!(slugger.slug("Intro-1"), "intro-1-1");
+        assert_eq!(slugger.slug("Intro"), "intro-2");
+    }
+
+    #[test]
+    fn skips_reserved_ids() {
+        let mut slugger = Slugger::default();
+        slugger.reserve("setup");
+        slugger.reserve("setup-1");
+        assert_eq!(slugger.slug("Setup"), "setup-2");
+    }
+
+    #[test]
+    fn nests_headings_under_their_parents() {
+        let toc = build_toc(vec![entry(1, "A"), entry(2, "B"), entry(3, "C"), entry(2, "D"), entry(1, "E"), entry(3, "F")]);
+        assert_eq!(toc.iter().map(|e| e.text.as_str()).collect::<Vec<_>>(), ["A", "E"]);
+        assert_eq!(toc[0].children.iter().map(|e| e.text.as_str()).collect::<Vec<_>>(), ["B", "D"]);
+        assert_eq!(toc[0].children[0].children[0].text, "C");
+        assert_eq!(toc[1].children[0].text, "F");
+    }
+
+    #[test]
+    fn renders_escaped_nested_lists() {
+        assert_eq!(render_toc_html(&[]), "");
+        let html = render_toc_html(&build_toc(vec![entry(1, "<A>"), entry(2, "B")]));
+        assert_eq!(html, "<nav class=\"table-of-contents\">\n<ul>\n<li><a href=\"#a\">&lt;A&gt;</a>\n<ul>\n<li><a href=\"#b\">B</a></li>\n</ul>\n</li>\n</ul>\n</nav>\n");
+    }
+}
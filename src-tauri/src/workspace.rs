@@ -0,0 +1,346 @@
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::WalkBuilder;
+use notify::event::{CreateKind, ModifyKind, RemoveKind};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::markdown::first_heading;
+use crate::{check_forbidden_path, has_markdown_extension};
+
+// Keep huge folders (home directories, monorepos) from stalling the UI
+pub const MAX_WORKSPACE_FILES: usize = 5000;
+// Only the start of each file is read to find its title
+const MAX_TITLE_SCAN_BYTES: u64 = 64 * 1024;
+// Quiet period used to coalesce bursts of file system events into one rescan
+const RESCAN_DELAY: Duration = Duration::from_millis(250);
+
+// A directory or markdown file in the workspace tree
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceEntry {
+    pub name: String,
+    pub path: String,
+    pub is_dir: bool,
+    pub title: Option<String>,
+    pub children: Vec<WorkspaceEntry>,
+    // Set on the root when the scan stopped at MAX_WORKSPACE_FILES
+    pub truncated: bool,
+}
+
+impl WorkspaceEntry {
+    fn directory(name: String, path: &Path) -> Self {
+        Self { name, path: path.to_string_lossy().to_string(), is_dir: true, title: None, children: Vec::new(), truncated: false }
+    }
+
+    // Insert a file below this directory, creating intermediate directories as needed
+    fn insert(&mut self, parent: &Path, relative: &[String], file: WorkspaceEntry) {
+        let Some((first, rest)) = relative.split_first() else {
+            self.children.push(file);
+            return;
+        };
+
+        let directory_path = parent.join(first);
+        let index = match self.children.iter().position(|child| child.is_dir && child.name == *first) {
+            Some(index) => index,
+            None => {
+                self.children.push(WorkspaceEntry::directory(first.clone(), &directory_path));
+                self.children.len() - 1
+            }
+        };
+        self.children[index].insert(&directory_path, rest, file);
+    }
+
+    // Directories first, then case-insensitive by name
+    fn sort(&mut self) {
+        self.children.sort_by(|a, b| match (a.is_dir, b.is_dir) {
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        });
+        self.children.iter_mut().for_each(WorkspaceEntry::sort);
+    }
+}
+
+// An open folder together with the watcher that keeps its tree current. Without a
+// watcher (the system ran out of watches) the tree is only read once.
+pub struct Workspace {
+    pub root: PathBuf,
+    pub tree: WorkspaceEntry,
+    _watcher: Option<RecommendedWatcher>,
+}
+
+// Global state for the open workspace, next to TabState
+pub type WorkspaceState = Arc<Mutex<Option<Workspace>>>;
+
+// Same rules as validate_file_path, but for a directory
+pub fn validate_directory_path(dir_path: &str) -> Result<PathBuf, String> {
+    if dir_path.contains("..") {
+        return Err("Path traversal detected: '..' not allowed in folder paths".to_string());
+    }
+
+    let canonical_path = Path::new(dir_path).canonicalize()
+        .map_err(|e| format!("Invalid folder path: {}", e))?;
+    check_forbidden_path(&canonical_path)?;
+
+    if !canonical_path.is_dir() {
+        return Err(format!("Path is not a folder: {}", canonical_path.display()));
+    }
+    Ok(canonical_path)
+}
+
+// Walk the folder for markdown files, honouring .gitignore and hidden files
+pub fn scan_workspace(root: &Path) -> WorkspaceEntry {
+    let root_name = root.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| root.to_string_lossy().to_string());
+    let mut tree = WorkspaceEntry::directory(root_name, root);
+
+    let walker = WalkBuilder::new(root)
+        .require_git(false)
+        .filter_entry(|entry| check_forbidden_path(entry.path()).is_ok())
+        .build();
+
+    let mut file_count = 0;
+    for entry in walker.flatten() {
+        let path = entry.path();
+        if !entry.file_type().is_some_and(|file_type| file_type.is_file()) || !has_markdown_extension(path) {
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(root) else { continue };
+
+        let mut components: Vec<String> = relative.components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        let Some(name) = components.pop() else { continue };
+        if file_count == MAX_WORKSPACE_FILES {
+            tree.truncated = true;
+            break;
+        }
+
+        let file = WorkspaceEntry {
+            name,
+            path: path.to_string_lossy().to_string(),
+            is_dir: false,
+            title: read_title(path),
+            children: Vec::new(),
+            truncated: false,
+        };
+        tree.insert(root, &components, file);
+        file_count += 1;
+    }
+
+    tree.sort();
+    tree
+}
+
+fn read_title(path: &Path) -> Option<String> {
+    let mut bytes = Vec::new();
+    File::open(path).ok()?.take(MAX_TITLE_SCAN_BYTES).read_to_end(&mut bytes).ok()?;
+    first_heading(&String::from_utf8_lossy(&bytes))
+}
+
+// Open a folder, returning its tree and keeping it updated through a recursive watcher.
+// Changes are pushed to the frontend as `workspace-changed` events; if the folder
+// can't be watched the tree is returned without live updates.
+pub fn open_workspace(root: PathBuf, app_handle: AppHandle) -> Result<Workspace, String> {
+    let (sender, receiver) = mpsc::channel::<()>();
+    let filter = Arc::new(Mutex::new(PathFilter::new(&root)));
+
+    let event_filter = filter.clone();
+    let watcher = notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
+        match res {
+            Ok(event) => {
+                if is_tree_change(&event, &event_filter.lock().unwrap()) {
+                    sender.send(()).ok();
+                }
+            }
+            Err(e) => {
+                eprintln!("Workspace watch error: {:?}", e);
+            }
+        }
+    })
+        .and_then(|mut watcher| watcher.watch(&root, RecursiveMode::Recursive).map(|_| watcher))
+        .map_err(|e| eprintln!("Workspace {} won't update live: {}", root.display(), e))
+        .ok();
+
+    // The thread ends once the watcher, and with it the sender, is dropped
+    let watched_root = root.clone();
+    std::thread::spawn(move || {
+        while receiver.recv().is_ok() {
+            while receiver.recv_timeout(RESCAN_DELAY).is_ok() {}
+
+            // The ignore files may have been among the changes
+            *filter.lock().unwrap() = PathFilter::new(&watched_root);
+            let tree = scan_workspace(&watched_root);
+            let state = app_handle.state::<WorkspaceState>();
+            let mut workspace_guard = state.lock().unwrap();
+            if let Some(workspace) = workspace_guard.as_mut().filter(|workspace| workspace.root == watched_root) {
+                workspace.tree = tree.clone();
+                app_handle.emit("workspace-changed", &tree).ok();
+            }
+        }
+    });
+
+    let tree = scan_workspace(&root);
+    Ok(Workspace { root, tree, _watcher: watcher })
+}
+
+// The scan's rules for skipping paths, as far as the watcher can check them per event:
+// hidden files and folders, and the root's .gitignore and .ignore files. Ignore files
+// further down still apply to the rescan itself.
+struct PathFilter {
+    root: PathBuf,
+    ignore: Gitignore,
+}
+
+impl PathFilter {
+    fn new(root: &Path) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for path in [root.join(".gitignore"), root.join(".ignore")] {
+            if path.is_file() {
+                if let Some(e) = builder.add(&path) {
+                    eprintln!("Failed to read {}: {}", path.display(), e);
+                }
+            }
+        }
+        let ignore = builder.build().unwrap_or_else(|_| Gitignore::empty());
+        Self { root: root.to_path_buf(), ignore }
+    }
+
+    // Whether the path is one the scan would visit
+    fn includes(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else { return false };
+        let hidden = |name: &std::ffi::OsStr| name.to_string_lossy().starts_with('.');
+        let hidden_parent = relative.parent().is_some_and(|parent| parent.iter().any(hidden));
+        if hidden_parent || (relative.file_name().is_some_and(hidden) && !is_ignore_file(path)) {
+            return false;
+        }
+        relative.as_os_str().is_empty() || !self.ignore.matched_path_or_any_parents(path, path.is_dir()).is_ignore()
+    }
+}
+
+fn is_ignore_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == ".gitignore" || name == ".ignore")
+}
+
+// Whether an event path is, or was, a folder. Paths gone by the time the event arrives
+// are judged by name: folders seldom have an extension, editors' temporary files do.
+fn is_directory_event(path: &Path, kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Create(CreateKind::Folder) | EventKind::Remove(RemoveKind::Folder) => true,
+        EventKind::Create(CreateKind::File) | EventKind::Remove(RemoveKind::File) => false,
+        _ if path.exists() => path.is_dir(),
+        _ => path.extension().is_none(),
+    }
+}
+
+// Markdown files and folders appearing, disappearing or being renamed, edits to markdown
+// files (titles may change) and edits to ignore files, on paths the scan doesn't skip.
+// Other modifications, such as a folder's timestamp changing, leave the tree as it is.
+fn is_tree_change(event: &Event, filter: &PathFilter) -> bool {
+    let relevant = |path: &PathBuf| has_markdown_extension(path) || is_ignore_file(path);
+    event.paths.iter().any(|path| filter.includes(path) && match event.kind {
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)) => {
+            relevant(path) || is_directory_event(path, &event.kind)
+        }
+        EventKind::Modify(_) => relevant(path),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{DataChange, MetadataKind, RenameMode};
+    use std::fs;
+
+    // A folder with `.gitignore` (ignoring `target/`), `notes/a.md`, `target/b.md` and `.git/`
+    fn fixture(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("markdown-viewer-workspace-{}-{}", name, std::process::id()));
+        for folder in ["notes", "target", ".git"] {
+            fs::create_dir_all(dir.join(folder)).unwrap();
+        }
+        fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        fs::write(dir.join("notes").join("a.md"), "# Alpha\n").unwrap();
+        fs::write(dir.join("target").join("b.md"), "# Beta\n").unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn event(kind: EventKind, paths: &[PathBuf]) -> Event {
+        paths.iter().fold(Event::new(kind), |event, path| event.add_path(path.clone()))
+    }
+
+    #[test]
+    fn scans_markdown_files_outside_ignored_folders() {
+        let dir = fixture("scan");
+        let tree = scan_workspace(&dir);
+        assert!(!tree.truncated);
+        assert_eq!(tree.children.len(), 1);
+        assert_eq!(tree.children[0].name, "notes");
+        assert_eq!(tree.children[0].children[0].title.as_deref(), Some("Alpha"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn filters_paths_the_scan_skips() {
+        let dir = fixture("filter");
+        let filter = PathFilter::new(&dir);
+        assert!(filter.includes(&dir));
+        assert!(filter.includes(&dir.join("notes").join("a.md")));
+        assert!(filter.includes(&dir.join("notes").join("new.md")));
+        assert!(filter.includes(&dir.join(".gitignore")));
+        assert!(!filter.includes(&dir.join("target").join("b.md")));
+        assert!(!filter.includes(&dir.join("target").join("debug").join("c.md")));
+        assert!(!filter.includes(&dir.join(".git").join("index")));
+        assert!(!filter.includes(&dir.join(".obsidian").join("workspace.md")));
+        assert!(!filter.includes(&std::env::temp_dir().join("elsewhere.md")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn counts_markdown_and_folder_changes() {
+        let dir = fixture("changes");
+        let filter = PathFilter::new(&dir);
+        let markdown = dir.join("notes").join("a.md");
+
+        assert!(is_tree_change(&event(EventKind::Create(CreateKind::File), &[dir.join("notes").join("new.md")]), &filter));
+        assert!(is_tree_change(&event(EventKind::Create(CreateKind::Folder), &[dir.join("drafts")]), &filter));
+        assert!(is_tree_change(&event(EventKind::Remove(RemoveKind::Any), &[dir.join("old-folder")]), &filter));
+        assert!(is_tree_change(&event(EventKind::Modify(ModifyKind::Data(DataChange::Content)), std::slice::from_ref(&markdown)), &filter));
+        assert!(is_tree_change(&event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &[dir.join(".gitignore")]), &filter));
+        // An editor saving through a temporary file and renaming it into place
+        let temporary = dir.join("notes").join("a.md.tmp");
+        assert!(is_tree_change(&event(EventKind::Modify(ModifyKind::Name(RenameMode::Both)), &[temporary, markdown]), &filter));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_other_changes() {
+        let dir = fixture("noise");
+        let filter = PathFilter::new(&dir);
+
+        // Ignored and hidden folders
+        assert!(!is_tree_change(&event(EventKind::Create(CreateKind::File), &[dir.join("target").join("c.md")]), &filter));
+        assert!(!is_tree_change(&event(EventKind::Modify(ModifyKind::Any), &[dir.join(".git").join("index")]), &filter));
+        // A folder's timestamp changing, and a temporary file that is already gone
+        assert!(!is_tree_change(&event(EventKind::Modify(ModifyKind::Metadata(MetadataKind::WriteTime)), &[dir.join("notes")]), &filter));
+        assert!(!is_tree_change(&event(EventKind::Modify(ModifyKind::Data(DataChange::Any)), &[dir.join("notes").join(".a.md.swp")]), &filter));
+        assert!(!is_tree_change(&event(EventKind::Modify(ModifyKind::Any), &[dir.join("notes").join("a.md~tmp")]), &filter));
+        // Files that aren't markdown
+        assert!(!is_tree_change(&event(EventKind::Create(CreateKind::File), &[dir.join("notes").join("image.png")]), &filter));
+        assert!(!is_tree_change(&event(EventKind::Remove(RemoveKind::Any), &[dir.join("notes").join("build.log")]), &filter));
+        assert!(!is_tree_change(&event(EventKind::Access(notify::event::AccessKind::Any), &[dir.join("notes").join("a.md")]), &filter));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -8,7 +8,11 @@ const { writeFile, BaseDirectory } = window.__TAURI__.fs;
 let currentFilePath = null;
 let isInitialized = false;
 let currentMarkdownContent = '';
+// Rust HTML of each top-level block on screen, in order; empty when the document isn't split
+let currentBlockHTML = [];
 let currentTitle = 'Untitled';
+// Title for exports: front matter title, or the file name without extension
+let currentDocumentTitle = 'Untitled';
 let mermaidInitialized = false;
 
 // Find in page variables
@@ -20,6 +24,68 @@ let searchTerm = '';
 let isSearchDialogVisible = false;
 let originalContentHTML = '';
 
+// Server-side sanitizer strictness: 'strict', 'standard' or 'relaxed'
+const SANITIZE_LEVEL_KEY = 'markdownViewer.sanitizeLevel';
+function getSanitizeLevel() {
+  const level = localStorage.getItem(SANITIZE_LEVEL_KEY);
+  return ['strict', 'standard', 'relaxed'].includes(level) ? level : 'standard';
+}
+
+// Markdown extension overrides, e.g. {"smartPunctuation": true}; null keeps GitHub defaults
+const MARKDOWN_OPTIONS_KEY = 'markdownViewer.markdownOptions';
+function getMarkdownOptions() {
+  try {
+    const options = JSON.parse(localStorage.getItem(MARKDOWN_OPTIONS_KEY));
+    return options && typeof options === 'object' ? options : null;
+  } catch (e) {
+    return null;
+  }
+}
+
+// Milliseconds to wait for a save's file system events to settle before reloading
+const WATCH_DEBOUNCE_KEY = 'markdownViewer.watchDebounceMs';
+async function applyWatchDebounce() {
+  const debounceMs = parseInt(localStorage.getItem(WATCH_DEBOUNCE_KEY), 10);
+  if (Number.isFinite(debounceMs)) {
+    await invoke('set_watch_debounce', { debounceMs });
+  }
+}
+
+// Syntax highlighting themes by colour scheme, e.g. {"light": "InspiredGitHub", "dark": "base16-ocean.dark"}
+const HIGHLIGHT_THEMES_KEY = 'markdownViewer.highlightThemes';
+// What the backend uses for a colour scheme without a saved theme
+const DEFAULT_HIGHLIGHT_THEMES = { light: 'InspiredGitHub', dark: 'base16-ocean.dark' };
+function getHighlightThemes() {
+  try {
+    const themes = JSON.parse(localStorage.getItem(HIGHLIGHT_THEMES_KEY));
+    return themes && typeof themes === 'object' ? themes : {};
+  } catch (e) {
+    return {};
+  }
+}
+
+// Code blocks carry scope classes only; the stylesheet from the backend colours them and
+// switches with the system colour scheme, so a new theme needs no re-render
+async function applyHighlightThemes() {
+  const { light = null, dark = null } = getHighlightThemes();
+  let css;
+  try {
+    css = await invoke('set_highlight_themes', { light, dark });
+  } catch (error) {
+    // A saved theme that no longer exists, such as a deleted .tmTheme file
+    console.warn('Error applying code themes, using the defaults:', error);
+    css = await invoke('set_highlight_themes', { light: null, dark: null });
+  }
+
+  let style = document.querySelector('#code-theme-style');
+  if (!style) {
+    style = document.createElement('style');
+    style.id = 'code-theme-style';
+    document.head.appendChild(style);
+  }
+  style.textContent = css;
+}
+
 // Security: HTML sanitization function
 function sanitizeHTML(html) {
   if (typeof DOMPurify !== 'undefined') {
@@ -30,16 +96,33 @@ function sanitizeHTML(html) {
         'h1', 'h2', 'h3', 'h4', 'h5', 'h6',
         'blockquote', 'pre', 'code', 'kbd', 'samp', 'var',
         'table', 'thead', 'tbody', 'tfoot', 'tr', 'th', 'td',
-        'a', 'img', 'figure', 'figcaption',
+        'a', 'img', 'figure', 'figcaption', 'input',
         'div', 'span', 'hr', 'sup', 'sub',
-        'details', 'summary',
+        'details', 'summary', 'aside',
+        // MathML produced by the Rust math renderer
+        'math', 'semantics', 'annotation', 'mrow', 'mi', 'mn', 'mo', 'ms', 'mtext', 'mspace',
+        'mfrac', 'msqrt', 'mroot', 'mstyle', 'merror', 'mpadded', 'mphantom',
+        'msub', 'msup', 'msubsup', 'munder', 'mover', 'munderover', 'mmultiscripts', 'mprescripts', 'none',
+        'mtable', 'mtr', 'mtd',
         // Mermaid diagram containers (but not script tags)
         'svg', 'g', 'path', 'text', 'rect', 'circle', 'ellipse', 'line', 'polyline', 'polygon'
       ],
       ALLOWED_ATTR: [
         'href', 'title', 'alt', 'src', 'width', 'height',
-        'class', 'id', 'style', 'target', 'rel',
+        'class', 'id', 'style', 'target', 'rel', 'role', 'aria-hidden',
         'data-search-result',
+        // Read-only task list checkboxes (enforced by the Rust sanitizer)
+        'type', 'checked', 'disabled',
+        // Code block metadata from fenced info strings
+        'data-lang', 'data-title', 'data-highlight-lines',
+        // Link targets resolved by the Rust side
+        'data-link-kind', 'data-link-path', 'data-link-anchor',
+        // Source line ranges of block elements
+        'data-source-line',
+        // MathML presentation attributes
+        'display', 'encoding', 'mathvariant', 'displaystyle', 'scriptlevel', 'stretchy', 'fence',
+        'separator', 'lspace', 'rspace', 'largeop', 'movablelimits', 'accent', 'accentunder',
+        'columnalign', 'rowalign', 'columnspacing', 'rowspacing', 'linethickness', 'minsize', 'maxsize',
         // SVG attributes for Mermaid
         'viewBox', 'xmlns', 'x', 'y', 'cx', 'cy', 'r', 'rx', 'ry',
         'fill', 'stroke', 'stroke-width', 'd', 'transform',
@@ -49,7 +132,7 @@ function sanitizeHTML(html) {
       ALLOW_UNKNOWN_PROTOCOLS: false,
       ALLOWED_URI_REGEXP: /^(?:(?:(?:f|ht)tps?|mailto|tel|callto|cid|xmpp|file|data):|[^a-z]|[a-z+.\-]+(?:[^a-z+.\-:]|$))/i,
       // Keep safe URL protocols only
-      FORBID_TAGS: ['script', 'object', 'embed', 'form', 'input', 'button', 'textarea', 'select', 'iframe'],
+      FORBID_TAGS: ['script', 'object', 'embed', 'form', 'button', 'textarea', 'select', 'iframe'],
       FORBID_ATTR: ['onerror', 'onload', 'onclick', 'onmouseover', 'onfocus', 'onblur', 'onchange', 'onsubmit']
     });
   } else {
@@ -166,6 +249,10 @@ const MAX_ZOOM = 200;
 const ZOOM_STEP = 10;
 let zoomLevelsByFile = new Map(); // Store zoom levels per file
 
+// Outline sidebar
+let currentToc = [];
+const TOC_VISIBLE_KEY = 'markdownViewer.tocVisible';
+
 // DOM elements
 let openFileBtn;
 let fileInput;
@@ -174,7 +261,8 @@ let markdownViewer;
 let markdownContent;
 let exportHtmlBtn;
 let exportDocxBtn;
-let printPdfBtn;
+let exportPdfBtn;
+let exportEpubBtn;
 let exportButtonGroup;
 let exportDropdownBtn;
 let exportDropdownMenu;
@@ -613,7 +701,7 @@ function updateRecentFilesUI() {
         removeFromRecentFiles(filePath);
       } else {
         hideRecentFiles();
-        loadMarkdownFile(filePath).catch(error => {
+        openFileInTab(filePath).catch(error => {
           console.error('Failed to load recent file:', error);
           alert('Failed to open file. It may have been moved or deleted.');
           removeFromRecentFiles(filePath);
@@ -720,6 +808,402 @@ function hideZoomControls() {
   }
 }
 
+// Outline sidebar built from the heading tree returned by the Rust renderer
+function renderTocSidebar(toc) {
+  currentToc = toc || [];
+  const tocList = document.querySelector('#toc-list');
+  const tocToggleBtn = document.querySelector('#toc-toggle-btn');
+  tocList.replaceChildren();
+  
+  if (currentToc.length === 0) {
+    tocToggleBtn.style.display = 'none';
+    setTocVisible(false, false);
+    return;
+  }
+  
+  tocList.appendChild(buildTocList(currentToc));
+  tocToggleBtn.style.display = 'inline-flex';
+  setTocVisible(localStorage.getItem(TOC_VISIBLE_KEY) === 'true', false);
+}
+
+function buildTocList(entries) {
+  const list = document.createElement('ul');
+  for (const entry of entries) {
+    const item = document.createElement('li');
+    const link = document.createElement('a');
+    link.href = `#${entry.slug}`;
+    link.textContent = entry.text;
+    link.title = `Line ${entry.line}`;
+    link.className = `toc-level-${entry.level}`;
+    link.addEventListener('click', (event) => {
+      event.preventDefault();
+      scrollToAnchor(entry.slug);
+    });
+    item.appendChild(link);
+    if (entry.children && entry.children.length > 0) {
+      item.appendChild(buildTocList(entry.children));
+    }
+    list.appendChild(item);
+  }
+  return list;
+}
+
+function scrollToAnchor(slug) {
+  const target = document.getElementById(slug);
+  if (target && markdownContent.contains(target)) {
+    target.scrollIntoView({ behavior: 'smooth', block: 'start' });
+  }
+}
+
+function setTocVisible(visible, remember = true) {
+  const sidebar = document.querySelector('#toc-sidebar');
+  sidebar.style.display = visible ? 'block' : 'none';
+  document.querySelector('#toc-toggle-btn').classList.toggle('active', visible);
+  if (remember) {
+    localStorage.setItem(TOC_VISIBLE_KEY, String(visible));
+  }
+}
+
+function toggleToc() {
+  const sidebar = document.querySelector('#toc-sidebar');
+  setTocVisible(sidebar.style.display === 'none');
+}
+
+// Back/forward history for link navigation between documents, kept per tab
+const navigationHistories = new Map();
+
+function activeHistory() {
+  if (!navigationHistories.has(activeTabId)) {
+    navigationHistories.set(activeTabId, { back: [], forward: [] });
+  }
+  return navigationHistories.get(activeTabId);
+}
+
+function currentLocation() {
+  return currentFilePath ? { path: currentFilePath, scrollTop: markdownViewer.scrollTop } : null;
+}
+
+async function navigateTo(path, anchor = null) {
+  const history = activeHistory();
+  const from = currentLocation();
+  if (from) {
+    history.back.push(from);
+    history.forward = [];
+  }
+  await showLocation({ path, anchor });
+}
+
+async function showLocation(location) {
+  if (location.path !== currentFilePath) {
+    await navigateActiveTab(location.path);
+  }
+  if (location.anchor) {
+    scrollToAnchor(location.anchor);
+  } else if (location.scrollTop !== undefined) {
+    markdownViewer.scrollTop = location.scrollTop;
+  }
+  updateHistoryButtons();
+}
+
+async function goBack() {
+  const history = activeHistory();
+  const target = history.back.pop();
+  if (!target) return;
+  const from = currentLocation();
+  if (from) history.forward.push(from);
+  await showLocation(target);
+}
+
+async function goForward() {
+  const history = activeHistory();
+  const target = history.forward.pop();
+  if (!target) return;
+  const from = currentLocation();
+  if (from) history.back.push(from);
+  await showLocation(target);
+}
+
+function updateHistoryButtons() {
+  const history = activeHistory();
+  document.querySelector('#back-btn').disabled = history.back.length === 0;
+  document.querySelector('#forward-btn').disabled = history.forward.length === 0;
+}
+
+// Open documents live in tabs managed by the Rust side, which also watches their files.
+// Markdown for tabs without a file (the sample, dropped content) is kept here.
+let activeTabId = null;
+const tabDocuments = new Map();
+const changedTabs = new Set();
+const missingTabs = new Set();
+
+function fileNameOf(filePath) {
+  return filePath.split(/[\\/]/).pop();
+}
+
+async function openFileInTab(filePath) {
+  await saveActiveTabView();
+  const tab = await invoke('open_tab', { filePath, title: fileNameOf(filePath) });
+  await showTab(tab);
+}
+
+async function openContentInTab(markdownText, title) {
+  await saveActiveTabView();
+  const tab = await invoke('open_tab', { filePath: null, title });
+  tabDocuments.set(tab.id, markdownText);
+  await showTab(tab);
+}
+
+// Link navigation replaces the document in the active tab
+async function navigateActiveTab(filePath) {
+  if (activeTabId === null) {
+    await openFileInTab(filePath);
+    return;
+  }
+  const tab = await invoke('navigate_tab', { tabId: activeTabId, filePath, title: fileNameOf(filePath) });
+  await loadMarkdownFile(tab.filePath);
+  await renderTabBar();
+}
+
+async function showTab(tab) {
+  activeTabId = tab.id;
+  changedTabs.delete(tab.id);
+  removeFileNotice();
+  
+  if (tab.filePath) {
+    await loadMarkdownFile(tab.filePath);
+  } else {
+    await loadMarkdownContent(tabDocuments.get(tab.id) || '', tab.title);
+  }
+  updateZoomLevel(tab.zoom);
+  markdownViewer.scrollTop = tab.scrollTop;
+  
+  updateHistoryButtons();
+  await renderTabBar();
+}
+
+async function switchToTab(tabId) {
+  if (tabId === activeTabId) return;
+  try {
+    await saveActiveTabView();
+    const tab = await invoke('activate_tab', { tabId });
+    await showTab(tab);
+  } catch (error) {
+    console.error('Error switching tab:', error);
+  }
+}
+
+async function closeTab(tabId) {
+  try {
+    const nextTabId = await invoke('close_tab', { tabId });
+    tabDocuments.delete(tabId);
+    changedTabs.delete(tabId);
+    missingTabs.delete(tabId);
+    navigationHistories.delete(tabId);
+    
+    if (tabId !== activeTabId) {
+      await renderTabBar();
+    } else if (nextTabId !== null) {
+      const tab = await invoke('activate_tab', { tabId: nextTabId });
+      await showTab(tab);
+    } else {
+      activeTabId = null;
+      showWelcomeScreen();
+      await renderTabBar();
+    }
+  } catch (error) {
+    console.error('Error closing tab:', error);
+  }
+}
+
+async function cycleTabs(step) {
+  const { tabs } = await invoke('list_tabs');
+  if (tabs.length < 2) return;
+  const index = tabs.findIndex(tab => tab.id === activeTabId);
+  const next = tabs[(index + step + tabs.length) % tabs.length];
+  await switchToTab(next.id);
+}
+
+// Remember where the reader was before leaving the active tab
+async function saveActiveTabView() {
+  if (activeTabId === null) return;
+  try {
+    await invoke('update_tab_view', {
+      tabId: activeTabId,
+      scrollTop: markdownViewer.scrollTop,
+      zoom: currentZoomLevel
+    });
+  } catch (error) {
+    console.error('Error saving tab view:', error);
+  }
+}
+
+function showWelcomeScreen() {
+  leaveDiffView();
+  currentFilePath = null;
+  currentMarkdownContent = '';
+  markdownContent.innerHTML = '';
+  markdownViewer.style.display = 'none';
+  welcomeScreen.style.display = 'flex';
+  exportButtonGroup.style.display = 'none';
+  document.querySelector('#present-btn').style.display = 'none';
+  document.querySelector('#compare-btn').style.display = 'none';
+  document.querySelector('#history-button-group').style.display = 'none';
+  hideZoomControls();
+  renderTocSidebar([]);
+  markActiveWorkspaceFile();
+  updateHistoryButtons();
+  document.title = 'Markdown Viewer';
+}
+
+async function renderTabBar() {
+  const tabBar = document.querySelector('#tab-bar');
+  const { tabs } = await invoke('list_tabs');
+  tabBar.replaceChildren();
+  tabBar.style.display = tabs.length > 0 ? 'flex' : 'none';
+  
+  for (const tab of tabs) {
+    const tabElement = document.createElement('div');
+    tabElement.className = 'tab';
+    tabElement.classList.toggle('active', tab.id === activeTabId);
+    tabElement.classList.toggle('changed', changedTabs.has(tab.id));
+    tabElement.classList.toggle('missing', missingTabs.has(tab.id));
+    tabElement.title = tab.filePath || tab.title;
+    tabElement.addEventListener('click', () => switchToTab(tab.id));
+    tabElement.addEventListener('auxclick', (event) => {
+      if (event.button === 1) closeTab(tab.id);
+    });
+    
+    const label = document.createElement('span');
+    label.className = 'tab-title';
+    label.textContent = tab.title;
+    
+    const closeButton = document.createElement('button');
+    closeButton.className = 'tab-close';
+    closeButton.title = 'Close Tab';
+    closeButton.textContent = '×';
+    closeButton.addEventListener('click', (event) => {
+      event.stopPropagation();
+      closeTab(tab.id);
+    });
+    
+    tabElement.append(label, closeButton);
+    tabBar.appendChild(tabElement);
+  }
+}
+
+// Links carry data-link-* attributes describing where they point
+async function handleContentLinkClick(event) {
+  const link = event.target.closest('a[href]');
+  if (!link || !markdownContent.contains(link)) return;
+  event.preventDefault();
+  
+  const { linkKind, linkPath, linkAnchor } = link.dataset;
+  try {
+    switch (linkKind) {
+      case 'anchor':
+        if (currentFilePath) {
+          await navigateTo(currentFilePath, linkAnchor);
+        } else if (linkAnchor) {
+          scrollToAnchor(linkAnchor);
+        }
+        break;
+      case 'markdown':
+        await navigateTo(linkPath, linkAnchor);
+        break;
+      case 'file':
+        await window.__TAURI__.opener.revealItemInDir(linkPath);
+        break;
+      case 'external':
+        await window.__TAURI__.opener.openUrl(link.getAttribute('href'));
+        break;
+      default:
+        console.warn('Link target not found:', link.getAttribute('href'));
+    }
+  } catch (error) {
+    console.error('Error following link:', error);
+  }
+}
+
+// Folder workspace: a file tree built by the Rust side and kept current by its watcher
+let workspaceTree = null;
+
+async function openFolder() {
+  try {
+    const dirPath = await open({ directory: true });
+    if (dirPath) {
+      const tree = await invoke('open_workspace', { dirPath });
+      renderWorkspaceTree(tree);
+    }
+  } catch (error) {
+    console.error('Error opening folder:', error);
+    alert('Failed to open folder: ' + error);
+  }
+}
+
+async function closeWorkspace() {
+  try {
+    await invoke('close_workspace');
+  } catch (error) {
+    console.error('Error closing folder:', error);
+  }
+  renderWorkspaceTree(null);
+}
+
+function renderWorkspaceTree(tree) {
+  workspaceTree = tree;
+  const sidebar = document.querySelector('#workspace-sidebar');
+  const treeNav = document.querySelector('#workspace-tree');
+  treeNav.replaceChildren();
+  
+  if (!tree) {
+    sidebar.style.display = 'none';
+    return;
+  }
+  
+  document.querySelector('#workspace-name').textContent = tree.name;
+  document.querySelector('#workspace-name').title = tree.path;
+  // The scan stops at a file limit; say so rather than show a silently partial tree
+  document.querySelector('#workspace-truncated').style.display = tree.truncated ? 'block' : 'none';
+  treeNav.appendChild(buildWorkspaceList(tree.children));
+  sidebar.style.display = 'block';
+  markActiveWorkspaceFile();
+}
+
+function buildWorkspaceList(entries) {
+  const list = document.createElement('ul');
+  for (const entry of entries) {
+    const item = document.createElement('li');
+    if (entry.isDir) {
+      const details = document.createElement('details');
+      details.open = true;
+      const summary = document.createElement('summary');
+      summary.textContent = entry.name;
+      details.appendChild(summary);
+      details.appendChild(buildWorkspaceList(entry.children));
+      item.appendChild(details);
+    } else {
+      const link = document.createElement('a');
+      link.href = '#';
+      link.textContent = entry.title || entry.name;
+      link.title = entry.path;
+      link.dataset.path = entry.path;
+      link.addEventListener('click', (event) => {
+        event.preventDefault();
+        openFileInTab(entry.path);
+      });
+      item.appendChild(link);
+    }
+    list.appendChild(item);
+  }
+  return list;
+}
+
+function markActiveWorkspaceFile() {
+  document.querySelectorAll('#workspace-tree a').forEach(link => {
+    link.classList.toggle('active', link.dataset.path === currentFilePath);
+  });
+}
+
 async function openFile() {
   try {
     const filePath = await open({
@@ -732,7 +1216,7 @@ async function openFile() {
     });
 
     if (filePath) {
-      await loadMarkdownFile(filePath);
+      await openFileInTab(filePath);
     }
   } catch (error) {
     console.error('Error opening file:', error);
@@ -1032,7 +1516,7 @@ gantt
 
     // Store sample markdown for DOCX export
     window.sampleMarkdownContent = sampleMarkdown;
-    await loadMarkdownContent(sampleMarkdown);
+    await openContentInTab(sampleMarkdown, 'Sample');
   } catch (error) {
     console.error('Error opening sample file:', error);
     alert('Failed to load sample content: ' + error.message);
@@ -1041,11 +1525,10 @@ gantt
 
 async function loadMarkdownContent(markdownText, fileName = 'Sample') {
   try {
-    // Stop watching previous file since this is content-based
-    if (currentFilePath) {
-      await stopWatchingFile();
-      currentFilePath = null;
-    }
+    leaveDiffView();
+    currentFilePath = null;
+    document.querySelector('#compare-btn').style.display = 'none';
+    document.querySelector('#history-button-group').style.display = 'none';
     
     // Clear search if active
     if (isSearchDialogVisible) {
@@ -1058,15 +1541,25 @@ async function loadMarkdownContent(markdownText, fileName = 'Sample') {
     markdownViewer.style.display = 'block';
 
     // Call Tauri command to parse markdown
-    const htmlContent = await invoke('parse_markdown', { markdownContent: markdownText });
+    const rendered = await invoke('parse_markdown', {
+      markdownContent: markdownText,
+      sanitizeLevel: getSanitizeLevel(),
+      markdownOptions: getMarkdownOptions()
+    });
+    const htmlContent = rendered.html;
     
     // Store current content for export
     currentMarkdownContent = htmlContent;
     currentTitle = fileName;
+    currentDocumentTitle = documentTitleFor(rendered, fileName);
     
     // Display the parsed HTML with sanitization
     const sanitizedHTML = sanitizeHTML(htmlContent);
     markdownContent.innerHTML = sanitizedHTML;
+    currentBlockHTML = [];
+    renderFrontMatter(rendered.frontMatter);
+    showSanitizeReport(rendered.sanitizeReport);
+    renderTocSidebar(rendered.toc);
     
     // Add image error handling
     setupImageErrorHandling();
@@ -1082,6 +1575,7 @@ async function loadMarkdownContent(markdownText, fileName = 'Sample') {
     
     // Show export button group
     exportButtonGroup.style.display = 'inline-flex';
+    document.querySelector('#present-btn').style.display = 'inline-flex';
     
     // Show zoom controls
     showZoomControls();
@@ -1090,7 +1584,7 @@ async function loadMarkdownContent(markdownText, fileName = 'Sample') {
     resetZoom();
     
     // Update window title
-    document.title = `Markdown Viewer - ${fileName}`;
+    document.title = `Markdown Viewer - ${currentDocumentTitle}`;
     
   } catch (error) {
     console.error('Error loading markdown content:', error);
@@ -1106,10 +1600,7 @@ async function loadMarkdownContent(markdownText, fileName = 'Sample') {
 
 async function loadMarkdownFile(filePath) {
   try {
-    // Stop watching previous file
-    if (currentFilePath) {
-      await stopWatchingFile();
-    }
+    leaveDiffView();
     
     // Clear search if active
     if (isSearchDialogVisible) {
@@ -1122,16 +1613,34 @@ async function loadMarkdownFile(filePath) {
     markdownViewer.style.display = 'block';
 
     // Call Tauri command to read and parse markdown
-    const htmlContent = await invoke('read_markdown_file', { filePath });
+    // The tab id lets the backend watch the images this document embeds
+    const rendered = await invoke('read_markdown_file', {
+      filePath,
+      sanitizeLevel: getSanitizeLevel(),
+      markdownOptions: getMarkdownOptions(),
+      tabId: activeTabId
+    });
+    const htmlContent = rendered.html;
     
     // Store current content for export
     currentMarkdownContent = htmlContent;
     currentTitle = filePath.split(/[\\/]/).pop();
+    currentDocumentTitle = documentTitleFor(rendered, currentTitle);
     
     // Display the parsed HTML with sanitization
-    const sanitizedHTML = sanitizeHTML(htmlContent);
-    markdownContent.innerHTML = sanitizedHTML;
+    // Files arrive split into blocks so later changes can be patched in place
+    if (rendered.blocks) {
+      markdownContent.replaceChildren(...rendered.blocks.map(buildBlockElement));
+      currentBlockHTML = rendered.blocks.map(block => block.html);
+    } else {
+      markdownContent.innerHTML = sanitizeHTML(htmlContent);
+      currentBlockHTML = [];
+    }
+    renderFrontMatter(rendered.frontMatter);
+    showSanitizeReport(rendered.sanitizeReport);
+    renderTocSidebar(rendered.toc);
     currentFilePath = filePath;
+    markActiveWorkspaceFile();
     
     // Add image error handling
     setupImageErrorHandling();
@@ -1147,6 +1656,9 @@ async function loadMarkdownFile(filePath) {
     
     // Show export button group
     exportButtonGroup.style.display = 'inline-flex';
+    document.querySelector('#present-btn').style.display = 'inline-flex';
+    document.querySelector('#compare-btn').style.display = 'inline-flex';
+    updateGitInfo(filePath);
     
     // Show zoom controls
     showZoomControls();
@@ -1155,14 +1667,11 @@ async function loadMarkdownFile(filePath) {
     restoreZoomForFile(filePath);
     
     // Update window title
-    document.title = `Markdown Viewer - ${currentTitle}`;
+    document.title = `Markdown Viewer - ${currentDocumentTitle}`;
     
     // Add to recent files
     addToRecentFiles(filePath, currentTitle);
     
-    // Start watching the file for changes
-    await startWatchingFile(filePath);
-    
   } catch (error) {
     console.error('Error loading markdown file:', error);
     markdownContent.innerHTML = `
@@ -1174,12 +1683,98 @@ async function loadMarkdownFile(filePath) {
   }
 }
 
+// Tell the reader when the Rust sanitizer removed something from the document
+function showSanitizeReport(report) {
+  markdownViewer.querySelectorAll('.sanitize-notice').forEach(notice => notice.remove());
+  
+  if (!report || !report.stripped || report.stripped.length === 0) {
+    return;
+  }
+  
+  console.warn('Sanitizer removed content:', report);
+  
+  const notice = document.createElement('div');
+  notice.className = 'sanitize-notice';
+  
+  const count = report.stripped.length;
+  const summary = document.createElement('span');
+  summary.textContent = `${count}${report.truncated ? '+' : ''} potentially unsafe item${count === 1 ? ' was' : 's were'} removed from this document.`;
+  
+  const details = report.stripped
+    .slice(0, 20)
+    .map(item => `${item.kind}: <${item.element}> ${item.detail}`)
+    .join('\n');
+  notice.title = details;
+  
+  const dismiss = document.createElement('button');
+  dismiss.className = 'sanitize-notice-dismiss';
+  dismiss.textContent = '×';
+  dismiss.title = 'Dismiss';
+  dismiss.addEventListener('click', () => notice.remove());
+  
+  notice.appendChild(summary);
+  notice.appendChild(dismiss);
+  // Kept outside the content element so it never ends up in exports
+  markdownViewer.insertBefore(notice, markdownContent);
+}
+
+// Metadata card built from front matter returned by the Rust renderer
+function renderFrontMatter(frontMatter) {
+  if (!frontMatter) {
+    return;
+  }
+  
+  const { title, author, date, tags } = frontMatter;
+  if (!title && !author && !date && (!tags || tags.length === 0)) {
+    return;
+  }
+  
+  const card = document.createElement('header');
+  card.className = 'front-matter';
+  
+  if (title) {
+    const titleElement = document.createElement('div');
+    titleElement.className = 'front-matter-title';
+    titleElement.textContent = title;
+    card.appendChild(titleElement);
+  }
+  
+  const details = [author, date].filter(Boolean);
+  if (details.length > 0) {
+    const detailsElement = document.createElement('div');
+    detailsElement.className = 'front-matter-details';
+    detailsElement.textContent = details.join(' · ');
+    card.appendChild(detailsElement);
+  }
+  
+  if (tags && tags.length > 0) {
+    const tagsElement = document.createElement('div');
+    tagsElement.className = 'front-matter-tags';
+    for (const tag of tags) {
+      const tagElement = document.createElement('span');
+      tagElement.className = 'front-matter-tag';
+      tagElement.textContent = tag;
+      tagsElement.appendChild(tagElement);
+    }
+    card.appendChild(tagsElement);
+  }
+  
+  markdownContent.prepend(card);
+}
+
+function documentTitleFor(rendered, fileName) {
+  if (rendered.frontMatter && rendered.frontMatter.title) {
+    return rendered.frontMatter.title;
+  }
+  return fileName.replace(/\.(md|markdown|mdown|mkd)$/i, '');
+}
+
 async function checkLaunchArgs() {
   try {
     // First check if there's a file opened via "Open With" (macOS RunEvent::Opened)
     const openedFile = await invoke('get_opened_file');
     if (openedFile) {
-      await loadMarkdownFile(openedFile);
+      await openLaunchedFile(openedFile);
       return true;
     }
     
@@ -1190,7 +1785,7 @@ async function checkLaunchArgs() {
     for (let i = 1; i < args.length; i++) {
       const arg = args[i];
       if (arg.match(/\.(md|markdown|mdown|mkd)$/i)) {
-        await loadMarkdownFile(arg);
+        await openFileInTab(arg);
         return true;
       }
     }
@@ -1282,7 +1877,7 @@ async function setupDragAndDrop() {
             
             if (markdownFile) {
               console.log('Loading markdown file:', markdownFile);
-              loadMarkdownFile(markdownFile);
+              openFileInTab(markdownFile);
             } else if (droppedFiles.length > 0) {
               console.log('Non-markdown files dropped:', droppedFiles);
               alert('Please drop a markdown file (.md, .markdown, .mdown, .mkd)');
@@ -1340,19 +1935,7 @@ async function setupDragAndDrop() {
         if (markdownFile) {
           // For native file API, read the content directly
           const content = await markdownFile.text();
-          const htmlContent = await invoke('parse_markdown', { markdownContent: content });
-          
-          // Stop watching previous file
-          if (currentFilePath) {
-            await stopWatchingFile();
-            currentFilePath = null;
-          }
-          
-          // Show content
-          welcomeScreen.style.display = 'none';
-          markdownViewer.style.display = 'block';
-          markdownContent.innerHTML = htmlContent;
-          document.title = `Markdown Viewer - ${markdownFile.name}`;
+          await openContentInTab(content, markdownFile.name);
           
           console.log('✅ Loaded dropped file via native API');
         } else if (files.length > 0) {
@@ -1368,83 +1951,553 @@ async function setupDragAndDrop() {
   }
 }
 
-async function startWatchingFile(filePath) {
-  try {
-    await invoke('start_watching_file', { filePath });
-    console.log('Started watching file:', filePath);
-  } catch (error) {
-    console.error('Error starting file watcher:', error);
+// Each tab's file is watched on the Rust side; background tabs are only marked as changed
+async function handleFileChange({ tabId, filePath, dependency }) {
+  console.log('File changed:', dependency || filePath);
+  missingTabs.delete(tabId);
+  if (tabId === activeTabId) {
+    removeFileNotice();
+    await refreshMarkdownFile(filePath);
+    if (dependency) {
+      refreshLocalImage(dependency);
+    }
+  } else {
+    changedTabs.add(tabId);
+    await renderTabBar();
   }
 }
 
-async function stopWatchingFile() {
-  try {
-    await invoke('stop_watching_file');
-    console.log('Stopped watching file');
-  } catch (error) {
-    console.error('Error stopping file watcher:', error);
-  }
+function buildBlockElement(block) {
+  const element = document.createElement('div');
+  element.className = 'md-block';
+  element.innerHTML = sanitizeHTML(block.html);
+  element.dataset.startLine = block.startLine;
+  element.dataset.endLine = block.endLine;
+  return element;
 }
 
-async function handleFileChange(filePath) {
-  console.log('File changed:', filePath);
-  if (filePath === currentFilePath) {
-    // Reload the file content
+// Re-render a changed file and patch only the blocks that differ, keeping the reader's place
+async function refreshMarkdownFile(filePath) {
+  // A comparison against the changed file is redone instead
+  if (activeDiff && activeDiff.newPath === filePath) {
+    await showDiffView(filePath, activeDiff.source);
+    return;
+  }
+  
+  const oldBlocks = Array.from(markdownContent.querySelectorAll(':scope > .md-block'));
+  if (filePath !== currentFilePath || oldBlocks.length !== currentBlockHTML.length) {
+    const scrollTop = markdownViewer.scrollTop;
     await loadMarkdownFile(filePath);
+    markdownViewer.scrollTop = scrollTop;
+    return;
   }
-}
-
-function initializeMermaid() {
-  if (!window.mermaid || mermaidInitialized) return;
   
+  let update;
   try {
-    mermaid.initialize({
-      startOnLoad: false,
-      theme: 'default',
-      securityLevel: 'loose',
-      htmlLabels: true,
-      flowchart: {
-        useMaxWidth: true,
-        htmlLabels: true
-      },
-      sequence: {
-        useMaxWidth: true,
-        wrap: true
-      },
-      gantt: {
-        useMaxWidth: true,
-        fontSize: 12,
-        sectionFontSize: 13,
-        barHeight: 24,
-        barGap: 6,
-        leftPadding: 100,
-        rightPadding: 100,
-        topPadding: 50,
-        titleTopMargin: 25
-      }
+    update = await invoke('refresh_markdown_file', {
+      filePath,
+      sanitizeLevel: getSanitizeLevel(),
+      markdownOptions: getMarkdownOptions(),
+      tabId: activeTabId
     });
-    mermaidInitialized = true;
-    console.log('✅ Mermaid initialized successfully');
   } catch (error) {
-    console.error('❌ Error initializing Mermaid:', error);
-  }
-}
-
-async function processMermaidDiagrams() {
-  if (!window.mermaid) {
-    console.log('⚠️ Mermaid not available, skipping diagram processing');
+    console.error('Error refreshing markdown file:', error);
     return;
   }
   
-  // Initialize Mermaid if not done already
-  initializeMermaid();
+  if (isSearchDialogVisible) {
+    hideSearchDialog();
+  }
+  const anchor = captureScrollAnchor();
+  
+  // Kept blocks stay in the DOM (with their rendered diagrams); new ones go in between
+  const keptIndexes = new Set(update.blocks.map(block => block.oldIndex).filter(index => index !== null));
+  const start = document.createComment('');
+  markdownContent.insertBefore(start, oldBlocks[0] || null);
+  oldBlocks.forEach((element, index) => {
+    if (!keptIndexes.has(index)) element.remove();
+  });
   
-  try {
-    // Find all code blocks with mermaid class
-    const mermaidBlocks = markdownContent.querySelectorAll('code.language-mermaid, pre code.language-mermaid');
-    console.log(`Found ${mermaidBlocks.length} Mermaid diagram(s)`);
+  let previous = start;
+  const blockHTML = [];
+  for (const block of update.blocks) {
+    let element;
+    if (block.oldIndex !== null) {
+      element = oldBlocks[block.oldIndex];
+      // The block may have moved; its content is the same, so every line shifts alike
+      const lineShift = block.startLine - Number(element.dataset.startLine);
+      element.dataset.startLine = block.startLine;
+      element.dataset.endLine = block.endLine;
+      element.querySelectorAll('[data-source-line]').forEach(node => {
+        node.dataset.sourceLine = shiftSourceLines(node.dataset.sourceLine, lineShift);
+      });
+      blockHTML.push(currentBlockHTML[block.oldIndex].replace(
+        /data-source-line="([\d-]+)"/g,
+        (_, range) => `data-source-line="${shiftSourceLines(range, lineShift)}"`
+      ));
+    } else {
+      element = buildBlockElement(block);
+      setupImageErrorHandling(element);
+      blockHTML.push(block.html);
+    }
+    if (previous.nextSibling !== element) {
+      previous.after(element);
+    }
+    previous = element;
+  }
+  start.remove();
+  
+  currentBlockHTML = blockHTML;
+  currentMarkdownContent = blockHTML.join('');
+  markdownContent.querySelectorAll(':scope > .front-matter').forEach(card => card.remove());
+  renderFrontMatter(update.frontMatter);
+  showSanitizeReport(update.sanitizeReport);
+  renderTocSidebar(update.toc);
+  
+  await processMermaidDiagrams();
+  await applySyntaxHighlighting();
+  originalContentHTML = markdownContent.innerHTML;
+  
+  restoreScrollAnchor(anchor);
+  highlightChanges(update.changes);
+}
+
+function shiftSourceLines(range, lineShift) {
+  return range.split('-').map(line => Number(line) + lineShift).join('-');
+}
+
+// The first element at the top of the viewport, and where it was
+function captureScrollAnchor() {
+  const viewerTop = markdownViewer.getBoundingClientRect().top;
+  for (const element of markdownContent.querySelectorAll(':scope > .md-block > *')) {
+    const top = element.getBoundingClientRect().top;
+    if (top >= viewerTop) {
+      return { element, top };
+    }
+  }
+  return null;
+}
+
+// Scroll so the anchor stays put when blocks above it grew or shrank
+function restoreScrollAnchor(anchor) {
+  if (!anchor || !anchor.element.isConnected) return;
+  markdownViewer.scrollTop += anchor.element.getBoundingClientRect().top - anchor.top;
+}
+
+// Briefly highlight edited blocks, and mark where blocks were removed, using the change map
+function highlightChanges(changes) {
+  const blocks = Array.from(markdownContent.querySelectorAll(':scope > .md-block'));
+  for (const change of changes) {
+    const targets = blocks.filter(block => {
+      const startLine = Number(block.dataset.startLine);
+      const endLine = Number(block.dataset.endLine);
+      return change.kind === 'removed'
+        ? startLine === change.startLine
+        : startLine <= change.endLine && endLine >= change.startLine;
+    });
+    const className = change.kind === 'removed' ? 'block-removed-before' : 'block-changed';
     
-    for (let i = 0; i < mermaidBlocks.length; i++) {
+    // Blocks use display: contents, so the highlight goes on their children
+    for (const block of targets) {
+      for (const child of block.children) {
+        child.classList.add(className);
+        child.addEventListener('animationend', () => child.classList.remove(className), { once: true });
+      }
+    }
+  }
+}
+
+// Comparison shown in place of the active document, if any
+let activeDiff = null;
+
+async function compareWithFile() {
+  if (!currentFilePath) return;
+  try {
+    const oldPath = await open({
+      title: `Compare ${fileNameOf(currentFilePath)} with`,
+      filters: [
+        {
+          name: 'Markdown',
+          extensions: ['md', 'markdown', 'mdown', 'mkd']
+        }
+      ]
+    });
+    if (oldPath) {
+      await showDiffView(currentFilePath, { oldPath, label: fileNameOf(oldPath) });
+    }
+  } catch (error) {
+    console.error('Error comparing files:', error);
+    alert('Failed to compare files: ' + error);
+  }
+}
+
+// Render the older version on the left and the current file on the right, changes marked.
+// The older version is either another file ({ oldPath }) or a git revision ({ revision }).
+async function showDiffView(newPath, source) {
+  const options = { sanitizeLevel: getSanitizeLevel(), markdownOptions: getMarkdownOptions() };
+  const diff = source.revision
+    ? await invoke('diff_with_revision', { filePath: newPath, revision: source.revision, ...options })
+    : await invoke('diff_markdown_files', { oldPath: source.oldPath, newPath, ...options });
+  
+  if (isSearchDialogVisible) {
+    hideSearchDialog();
+  }
+  const scrollTop = activeDiff ? markdownViewer.scrollTop : 0;
+  activeDiff = { newPath, source, html: diff.html };
+  markdownContent.innerHTML = sanitizeHTML(diff.html);
+  currentBlockHTML = [];
+  renderTocSidebar([]);
+  setupImageErrorHandling();
+  originalContentHTML = markdownContent.innerHTML;
+  markdownViewer.scrollTop = scrollTop;
+  
+  const { modified, added, removed } = diff.stats;
+  removeFileNotice();
+  const notice = document.createElement('div');
+  notice.className = 'file-notice diff-notice';
+  const text = document.createElement('span');
+  text.textContent = `Comparing ${source.label} with ${fileNameOf(newPath)}: `
+    + `${modified} changed, ${added} added, ${removed} removed`;
+  const close = document.createElement('button');
+  close.className = 'btn btn-secondary';
+  close.textContent = 'Close Comparison';
+  close.addEventListener('click', () => loadMarkdownFile(newPath));
+  notice.append(text, close);
+  markdownViewer.insertBefore(notice, markdownContent);
+}
+
+// Git repository of the open file, if it is in one
+let currentGitInfo = null;
+
+async function updateGitInfo(filePath) {
+  let gitInfo = null;
+  try {
+    gitInfo = await invoke('get_git_info', { filePath });
+  } catch (error) {
+    console.error('Error reading git repository:', error);
+  }
+  if (filePath !== currentFilePath) return;
+  
+  currentGitInfo = gitInfo;
+  hideHistoryMenu();
+  document.querySelector('#history-button-group').style.display =
+    gitInfo && gitInfo.tracked ? 'inline-flex' : 'none';
+}
+
+// List the commits that changed the open file; picking one compares it with the file on disk
+async function showHistoryMenu() {
+  if (!currentFilePath || !currentGitInfo) return;
+  const filePath = currentFilePath;
+  const name = fileNameOf(filePath);
+  const menu = document.querySelector('#history-menu');
+  
+  const addItem = (label, detail, source) => {
+    const item = document.createElement('button');
+    item.className = 'dropdown-item';
+    const title = document.createElement('span');
+    title.className = 'history-item-title';
+    title.textContent = label;
+    const info = document.createElement('span');
+    info.className = 'history-item-detail';
+    info.textContent = detail;
+    item.append(title, info);
+    item.addEventListener('click', async () => {
+      hideHistoryMenu();
+      try {
+        await showDiffView(filePath, source);
+      } catch (error) {
+        console.error('Error comparing with revision:', error);
+        alert('Failed to compare with revision: ' + error);
+      }
+    });
+    menu.appendChild(item);
+  };
+  
+  let history;
+  try {
+    history = await invoke('get_file_history', { filePath, limit: 50 });
+  } catch (error) {
+    console.error('Error reading file history:', error);
+    return;
+  }
+  
+  menu.replaceChildren();
+  const branch = currentGitInfo.branch ? ` on ${currentGitInfo.branch}` : '';
+  addItem(
+    currentGitInfo.modified ? 'Uncommitted changes' : 'Compare with HEAD',
+    `Last commit${branch}`,
+    { revision: 'HEAD', label: `${name}@HEAD` }
+  );
+  for (const commit of history) {
+    const date = new Date(commit.time * 1000).toLocaleDateString();
+    addItem(
+      commit.summary || commit.shortId,
+      `${commit.shortId} · ${commit.author} · ${date}`,
+      { revision: commit.id, label: `${name}@${commit.shortId}` }
+    );
+  }
+  menu.classList.add('show');
+}
+
+function hideHistoryMenu() {
+  document.querySelector('#history-menu').classList.remove('show');
+}
+
+// Themes grouped by the colour scheme they suit, plus importing TextMate themes
+async function showCodeThemeMenu() {
+  const menu = document.querySelector('#code-theme-menu');
+  let themes;
+  try {
+    themes = await invoke('list_highlight_themes');
+  } catch (error) {
+    console.error('Error listing code themes:', error);
+    return;
+  }
+
+  const saved = getHighlightThemes();
+  const addHeading = (label) => {
+    const heading = document.createElement('div');
+    heading.className = 'code-theme-heading';
+    heading.textContent = label;
+    menu.appendChild(heading);
+  };
+  const addItem = (label, selected, action) => {
+    const item = document.createElement('button');
+    item.className = selected ? 'dropdown-item selected' : 'dropdown-item';
+    item.textContent = label;
+    item.addEventListener('click', async () => {
+      hideCodeThemeMenu();
+      await action();
+    });
+    menu.appendChild(item);
+  };
+  const addDivider = () => {
+    const divider = document.createElement('div');
+    divider.className = 'code-theme-divider';
+    menu.appendChild(divider);
+  };
+
+  menu.replaceChildren();
+  for (const [scheme, label] of [['light', 'Light mode'], ['dark', 'Dark mode']]) {
+    addHeading(label);
+    const current = saved[scheme] || DEFAULT_HIGHLIGHT_THEMES[scheme];
+    for (const theme of themes.filter(theme => theme.dark === (scheme === 'dark'))) {
+      const name = theme.custom ? `${theme.name} (custom)` : theme.name;
+      addItem(name, theme.name === current, () => selectCodeTheme(scheme, theme.name));
+    }
+  }
+  addDivider();
+  addItem('Import .tmTheme…', false, importCodeTheme);
+  addItem('Reset to Defaults', false, async () => {
+    localStorage.removeItem(HIGHLIGHT_THEMES_KEY);
+    await applyHighlightThemes();
+  });
+  menu.classList.add('show');
+}
+
+function hideCodeThemeMenu() {
+  document.querySelector('#code-theme-menu').classList.remove('show');
+}
+
+async function selectCodeTheme(scheme, name) {
+  const themes = getHighlightThemes();
+  themes[scheme] = name;
+  localStorage.setItem(HIGHLIGHT_THEMES_KEY, JSON.stringify(themes));
+  try {
+    await applyHighlightThemes();
+  } catch (error) {
+    console.error('Error applying code theme:', error);
+    alert('Failed to apply code theme: ' + (error.message || error));
+  }
+}
+
+// Imported themes are kept by the backend and used for the colour scheme matching
+// their background
+async function importCodeTheme() {
+  try {
+    const filePath = await open({
+      multiple: false,
+      filters: [
+        {
+          name: 'TextMate Theme',
+          extensions: ['tmTheme']
+        }
+      ]
+    });
+    if (!filePath) {
+      return;
+    }
+
+    const name = await invoke('import_highlight_theme', { filePath });
+    const themes = await invoke('list_highlight_themes');
+    const theme = themes.find(theme => theme.name === name);
+    await selectCodeTheme(theme && theme.dark ? 'dark' : 'light', name);
+  } catch (error) {
+    console.error('Error importing code theme:', error);
+    alert('Failed to import code theme: ' + (error.message || error));
+  }
+}
+
+function leaveDiffView() {
+  if (activeDiff) {
+    activeDiff = null;
+    markdownViewer.querySelectorAll('.diff-notice').forEach(notice => notice.remove());
+  }
+}
+
+// Scroll to the element rendered from a source line, or to a heading
+async function revealSourceLocation({ line, anchor }) {
+  if (line && currentFilePath) {
+    try {
+      const location = await invoke('locate_source_line', {
+        filePath: currentFilePath,
+        line,
+        markdownOptions: getMarkdownOptions()
+      });
+      // Nested elements can share a range; the last one is the innermost
+      const targets = location
+        ? markdownContent.querySelectorAll(`[data-source-line="${location.sourceLine}"]`)
+        : [];
+      const target = targets[targets.length - 1];
+      if (target) {
+        target.scrollIntoView({ block: 'center' });
+        target.classList.add('source-line-target');
+        target.addEventListener('animationend', () => target.classList.remove('source-line-target'), { once: true });
+        return;
+      }
+      if (location && location.headingId) {
+        scrollToAnchor(location.headingId);
+      }
+    } catch (error) {
+      console.error('Error locating source line:', error);
+    }
+  } else if (anchor) {
+    scrollToAnchor(anchor);
+  }
+}
+
+// A file from the command line or the OS, possibly with a line or heading to show
+async function openLaunchedFile(openedFile) {
+  await openFileInTab(openedFile.filePath);
+  await revealSourceLocation(openedFile);
+}
+
+// The webview caches file:// images, so an edited image needs a fresh URL
+function refreshLocalImage(imagePath) {
+  const source = `file://${imagePath}`;
+  markdownContent.querySelectorAll('img').forEach(img => {
+    if (img.getAttribute('src') === source) {
+      img.setAttribute('src', `${source}?v=${Date.now()}`);
+    }
+  });
+}
+
+// The tab keeps showing the last rendered content so nothing is lost while the file is gone
+async function handleFileDeleted({ tabId, filePath }) {
+  console.log('File deleted:', filePath);
+  missingTabs.add(tabId);
+  if (tabId === activeTabId) {
+    showFileNotice(`${fileNameOf(filePath)} was deleted or moved out of its folder. Showing the last loaded version.`);
+  }
+  await renderTabBar();
+}
+
+// The tab already points at the new path on the Rust side
+async function handleFileMoved({ tabId, oldPath, newPath }) {
+  console.log('File moved:', oldPath, '->', newPath);
+  missingTabs.delete(tabId);
+  removeFromRecentFiles(oldPath);
+  
+  const history = navigationHistories.get(tabId);
+  if (history) {
+    for (const location of [...history.back, ...history.forward]) {
+      if (location.path === oldPath) location.path = newPath;
+    }
+  }
+  
+  if (tabId === activeTabId) {
+    // Reload so relative links and images resolve against the new location
+    removeFileNotice();
+    const scrollTop = markdownViewer.scrollTop;
+    await loadMarkdownFile(newPath);
+    markdownViewer.scrollTop = scrollTop;
+  }
+  await renderTabBar();
+}
+
+function showFileNotice(message) {
+  removeFileNotice();
+  const notice = document.createElement('div');
+  notice.className = 'file-notice';
+  
+  const text = document.createElement('span');
+  text.textContent = message;
+  
+  const dismiss = document.createElement('button');
+  dismiss.className = 'sanitize-notice-dismiss';
+  dismiss.textContent = '×';
+  dismiss.title = 'Dismiss';
+  dismiss.addEventListener('click', () => notice.remove());
+  
+  notice.append(text, dismiss);
+  markdownViewer.insertBefore(notice, markdownContent);
+}
+
+function removeFileNotice() {
+  markdownViewer.querySelectorAll('.file-notice').forEach(notice => notice.remove());
+}
+
+function initializeMermaid() {
+  if (!window.mermaid || mermaidInitialized) return;
+  
+  try {
+    mermaid.initialize({
+      startOnLoad: false,
+      theme: 'default',
+      securityLevel: 'loose',
+      htmlLabels: true,
+      flowchart: {
+        useMaxWidth: true,
+        htmlLabels: true
+      },
+      sequence: {
+        useMaxWidth: true,
+        wrap: true
+      },
+      gantt: {
+        useMaxWidth: true,
+        fontSize: 12,
+        sectionFontSize: 13,
+        barHeight: 24,
+        barGap: 6,
+        leftPadding: 100,
+        rightPadding: 100,
+        topPadding: 50,
+        titleTopMargin: 25
+      }
+    });
+    mermaidInitialized = true;
+    console.log('✅ Mermaid initialized successfully');
+  } catch (error) {
+    console.error('❌ Error initializing Mermaid:', error);
+  }
+}
+
+async function processMermaidDiagrams(root = markdownContent) {
+  if (!window.mermaid) {
+    console.log('⚠️ Mermaid not available, skipping diagram processing');
+    return;
+  }
+  
+  // Initialize Mermaid if not done already
+  initializeMermaid();
+  
+  try {
+    // Find all code blocks with mermaid class
+    const mermaidBlocks = root.querySelectorAll('code.language-mermaid, pre code.language-mermaid');
+    console.log(`Found ${mermaidBlocks.length} Mermaid diagram(s)`);
+    
+    for (let i = 0; i < mermaidBlocks.length; i++) {
       const block = mermaidBlocks[i];
       const diagramText = block.textContent;
       const diagramId = `mermaid-diagram-${Date.now()}-${i}`;
@@ -1462,6 +2515,9 @@ async function processMermaidDiagrams() {
         
         // Replace the code block with the rendered diagram
         const preElement = block.closest('pre') || block;
+        if (preElement.dataset.sourceLine) {
+          container.dataset.sourceLine = preElement.dataset.sourceLine;
+        }
         preElement.parentNode.replaceChild(container, preElement);
         
         console.log(`✅ Rendered Mermaid diagram ${i + 1}`);
@@ -1471,6 +2527,7 @@ async function processMermaidDiagrams() {
         
         // Create error fallback
         const errorDiv = document.createElement('div');
+        errorDiv.className = 'mermaid-diagram-error';
         errorDiv.style.border = '2px dashed #dc3545';
         errorDiv.style.borderRadius = '0.5rem';
         errorDiv.style.padding = '1rem';
@@ -1567,8 +2624,8 @@ async function applySyntaxHighlighting() {
   }
 }
 
-function setupImageErrorHandling() {
-  const images = markdownContent.querySelectorAll('img');
+function setupImageErrorHandling(root = markdownContent) {
+  const images = root.querySelectorAll('img');
   images.forEach(img => {
     img.addEventListener('error', function() {
       console.warn('Image failed to load:', img.src);
@@ -1628,7 +2685,10 @@ async function exportHtml() {
     }
 
     // Generate filename based on current title
-    const defaultName = currentTitle.replace(/\.(md|markdown|mdown|mkd)$/i, '') + '.html';
+    const stem = name => name.replace(/\.(md|markdown|mdown|mkd)$/i, '');
+    const defaultName = activeDiff
+      ? `${stem(activeDiff.source.label).replace(/[^\w.-]+/g, '-')}-vs-${stem(fileNameOf(activeDiff.newPath))}.html`
+      : stem(currentTitle) + '.html';
     console.log('Default filename:', defaultName);
     
     console.log('Opening save dialog...');
@@ -1648,12 +2708,31 @@ async function exportHtml() {
     if (filePath) {
       console.log('Generating enhanced HTML document...');
       
-      // Get the current rendered content
-      const contentElement = document.querySelector('#markdown-content');
-      const renderedContent = contentElement ? contentElement.innerHTML : currentMarkdownContent;
-      
-      // Create self-contained HTML document with embedded CSS and resources
-      const htmlDocument = generateSelfContainedHtml(renderedContent, currentTitle);
+      // Documents are rendered again by the backend with local images and diagrams
+      // embedded, so the file keeps working once moved or mailed. Comparisons are
+      // wrapped as they are shown. The viewer follows the system colour scheme, and so
+      // does the exported file.
+      let htmlDocument;
+      let skippedImages = [];
+      if (activeDiff) {
+        htmlDocument = await invoke('export_html', {
+          content: activeDiff.html,
+          title: `${activeDiff.source.label} vs ${fileNameOf(activeDiff.newPath)}`,
+          theme: 'auto'
+        });
+      } else {
+        const exported = await invoke('export_standalone_html', {
+          filePath: currentFilePath,
+          markdownContent: currentFilePath ? null : tabDocuments.get(activeTabId),
+          title: currentDocumentTitle,
+          sanitizeLevel: getSanitizeLevel(),
+          markdownOptions: getMarkdownOptions(),
+          diagrams: renderedDiagrams(),
+          theme: 'auto'
+        });
+        htmlDocument = exported.html;
+        skippedImages = exported.skippedImages;
+      }
       
       console.log('Enhanced HTML document generated, length:', htmlDocument.length);
       
@@ -1662,506 +2741,458 @@ async function exportHtml() {
       await writeFile(filePath, new TextEncoder().encode(htmlDocument));
       
       console.log('File written successfully');
-      alert(`Enhanced HTML exported successfully to: ${filePath}`);
+      const skippedNote = skippedImages.length > 0
+        ? `\n\nThese images could not be embedded and will only show next to the original:\n${skippedImages.join('\n')}`
+        : '';
+      alert(`Enhanced HTML exported successfully to: ${filePath}${skippedNote}`);
     } else {
       console.log('No file path selected');
     }
   } catch (error) {
     console.error('Error exporting HTML:', error);
     console.error('Error details:', error.message, error.stack);
-    alert('Failed to export HTML: ' + error.message);
+    alert('Failed to export HTML: ' + (error.message || error));
   }
 }
 
-function generateSelfContainedHtml(content, title) {
-  // Get the current CSS styles
-  const cssStyles = Array.from(document.styleSheets)
-    .map(sheet => {
-      try {
-        return Array.from(sheet.cssRules)
-          .map(rule => rule.cssText)
-          .join('\n');
-      } catch (e) {
-        // Handle CORS issues with external stylesheets
-        return '';
-      }
-    })
-    .join('\n');
+// Rendered diagrams in document order, null where rendering failed, so the exporter
+// can put each one in place of its code block
+function renderedDiagrams() {
+  return Array.from(markdownContent.querySelectorAll('.mermaid-diagram-container, .mermaid-diagram-error'))
+    .map(element => element.classList.contains('mermaid-diagram-container') ? element.innerHTML : null);
+}
 
-  // Additional CSS for better standalone display
-  const additionalCSS = `
-    /* Enhanced standalone styles */
-    body {
-      font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, 'Helvetica Neue', Arial, sans-serif;
-      line-height: 1.6;
-      color: #333;
-      background-color: #fff;
-      margin: 0;
-      padding: 2rem;
-      max-width: 900px;
-      margin: 0 auto;
-    }
-    
-    /* Print styles */
-    @media print {
-      body { margin: 1rem; padding: 0; }
-      .content { box-shadow: none; }
+async function exportDocx() {
+  try {
+    if (!currentMarkdownContent) {
+      throw new Error('No content to export');
     }
-    
-    /* Ensure images are responsive */
-    img { max-width: 100%; height: auto; }
-    
-    /* Table improvements */
-    table {
-      border-collapse: collapse;
-      width: 100%;
-      margin: 1rem 0;
+
+    // Files are read again by the backend; unsaved tabs send their markdown
+    const markdownContent = currentFilePath ? null : tabDocuments.get(activeTabId);
+    if (!currentFilePath && markdownContent === undefined) {
+      throw new Error('Could not retrieve original markdown content');
     }
-    
-    th, td {
-      border: 1px solid #ddd;
-      padding: 0.5rem;
-      text-align: left;
+
+    const defaultName = currentTitle.replace(/\.(md|markdown|mdown|mkd)$/i, '') + '.docx';
+    const filePath = await save({
+      title: 'Export as DOCX',
+      defaultPath: defaultName,
+      filters: [
+        {
+          name: 'Word Document',
+          extensions: ['docx']
+        }
+      ]
+    });
+
+    if (!filePath) {
+      return;
     }
+
+    // The document is built natively and arrives as raw bytes
+    const docx = await invoke('export_docx', {
+      filePath: currentFilePath,
+      markdownContent,
+      title: currentDocumentTitle,
+      markdownOptions: getMarkdownOptions()
+    });
+    await writeFile(filePath, new Uint8Array(docx));
     
-    th {
-      background-color: #f5f5f5;
-      font-weight: bold;
-    }
+    console.log('✅ DOCX exported successfully to:', filePath);
+    alert(`DOCX exported successfully to: ${filePath}`);
     
-    /* Code improvements */
-    pre, code {
-      background-color: #f5f5f5;
-      border-radius: 3px;
-    }
+  } catch (error) {
+    console.error('❌ Error exporting DOCX:', error);
+    const message = error.message || error;
     
-    pre {
-      padding: 1rem;
-      overflow-x: auto;
+    if (message === 'No content to export') {
+      alert('No content to export. Please load a markdown file first.');
+    } else {
+      alert('Failed to export DOCX: ' + (message || 'Unknown error'));
     }
-    
-    code {
-      padding: 0.2rem 0.4rem;
-      font-family: 'Monaco', 'Consolas', 'Courier New', monospace;
+  }
+}
+
+async function exportPdf() {
+  try {
+    if (!currentMarkdownContent) {
+      throw new Error('No content to export');
     }
-    
-    /* Mermaid diagram styling */
-    .mermaid-diagram-container {
-      background: #fafafa;
-      border: 1px solid #e1e4e8;
-      border-radius: 6px;
-      padding: 1rem;
-      margin: 1rem 0;
-      text-align: center;
+
+    // Files are read again by the backend; unsaved tabs send their markdown
+    const markdownContent = currentFilePath ? null : tabDocuments.get(activeTabId);
+    if (!currentFilePath && markdownContent === undefined) {
+      throw new Error('Could not retrieve original markdown content');
     }
-    
-    .mermaid-diagram-container svg {
-      max-width: 100%;
-      height: auto;
+
+    const defaultName = currentTitle.replace(/\.(md|markdown|mdown|mkd)$/i, '') + '.pdf';
+    const filePath = await save({
+      title: 'Export as PDF',
+      defaultPath: defaultName,
+      filters: [
+        {
+          name: 'PDF Document',
+          extensions: ['pdf']
+        }
+      ]
+    });
+
+    if (!filePath) {
+      return;
     }
-  `;
 
-  // Create the complete HTML document
-  const htmlDocument = `<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>${escapeHtml(title)}</title>
-    <style>
-${cssStyles}
-${additionalCSS}
-    </style>
-    <!-- Mermaid for diagrams -->
-    <script src="https://unpkg.com/mermaid@10.6.1/dist/mermaid.min.js"></script>
-    <script>
-        // Initialize Mermaid when page loads
-        document.addEventListener('DOMContentLoaded', function() {
-            if (typeof mermaid !== 'undefined') {
-                mermaid.initialize({
-                    startOnLoad: true,
-                    theme: 'default',
-                    securityLevel: 'loose'
-                });
-            }
-        });
-    </script>
-</head>
-<body>
-    <div class="content">
-        ${content}
-    </div>
-    
-    <!-- Footer with generation info -->
-    <footer style="margin-top: 3rem; padding-top: 1rem; border-top: 1px solid #eee; font-size: 0.875rem; color: #666; text-align: center;">
-        <p>Generated from ${escapeHtml(title)} on ${new Date().toLocaleString()}</p>
-        <p>Created with <a href="https://github.com/vanzan01/markdown-viewer" style="color: #007bff; text-decoration: none;">Markdown Viewer</a></p>
-    </footer>
-</body>
-</html>`;
+    // Laid out and paginated natively, so no browser or print dialog is involved
+    const pdf = await invoke('export_pdf', {
+      filePath: currentFilePath,
+      markdownContent,
+      title: currentDocumentTitle,
+      markdownOptions: getMarkdownOptions()
+    });
+    await writeFile(filePath, new Uint8Array(pdf));
 
-  return htmlDocument;
-}
+    console.log('✅ PDF exported successfully to:', filePath);
+    alert(`PDF exported successfully to: ${filePath}`);
 
-function escapeHtml(text) {
-  const div = document.createElement('div');
-  div.textContent = text;
-  return div.innerHTML;
-}
+  } catch (error) {
+    console.error('❌ Error exporting PDF:', error);
+    const message = error.message || error;
 
-// Additional secure HTML escaping for content injection
-function secureEscapeHtml(text) {
-  if (typeof text !== 'string') {
-    return '';
+    if (message === 'No content to export') {
+      alert('No content to export. Please load a markdown file first.');
+    } else {
+      alert('Failed to export PDF: ' + (message || 'Unknown error'));
+    }
   }
-  
-  return text
-    .replace(/&/g, '&amp;')
-    .replace(/</g, '&lt;')
-    .replace(/>/g, '&gt;')
-    .replace(/"/g, '&quot;')
-    .replace(/'/g, '&#x27;')
-    .replace(/\//g, '&#x2F;')
-    .replace(/`/g, '&#x60;')
-    .replace(/=/g, '&#x3D;');
 }
 
-async function printToPdf() {
+async function exportEpub() {
   try {
     if (!currentMarkdownContent) {
-      alert('No content to print. Please load a markdown file first.');
-      return;
+      throw new Error('No content to export');
     }
 
-    console.log('Print to PDF function called');
-    
-    // Check if we're on macOS where window.print() doesn't work properly
-    const isMac = navigator.platform.toUpperCase().includes('MAC') || 
-                  navigator.userAgent.toUpperCase().includes('MAC');
-    
-    if (isMac) {
-      // For macOS, export HTML and open in browser where print works properly
-      try {
-        const content = document.querySelector('.content');
-        if (!content) {
-          alert('No content found to export');
-          return;
-        }
-        
-        // Get the content safely
-        const contentHtml = content.innerHTML;
-        
-        // Validate content size before processing
-        if (contentHtml.length > 10 * 1024 * 1024) { // 10MB limit
-          alert('Content too large to export safely');
-          return;
-        }
-        
-        // Create full HTML document with embedded styles and secure content injection
-        const fullHtml = `<!DOCTYPE html>
-<html>
-<head>
-  <meta charset="UTF-8">
-  <title>${secureEscapeHtml('Markdown Document')}</title>
-  <style>
-    body {
-      font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Oxygen, Ubuntu, Cantarell, sans-serif;
-      font-size: 14px;
-      line-height: 1.6;
-      color: #333;
-      max-width: 800px;
-      margin: 0 auto;
-      padding: 2rem;
-      background: white;
-    }
-    
-    h1, h2, h3, h4, h5, h6 {
-      margin-top: 1.5rem;
-      margin-bottom: 0.75rem;
-      font-weight: 600;
-      line-height: 1.2;
-    }
-    
-    h1 { font-size: 2rem; }
-    h2 { font-size: 1.5rem; }
-    h3 { font-size: 1.25rem; }
-    
-    p { margin-bottom: 1rem; }
-    
-    ul, ol { margin-bottom: 1rem; padding-left: 2rem; }
-    li { margin-bottom: 0.25rem; }
-    
-    code {
-      background: #f5f5f5;
-      padding: 0.2rem 0.4rem;
-      border-radius: 3px;
-      font-family: 'SF Mono', Monaco, 'Cascadia Code', monospace;
-      font-size: 0.9em;
-    }
-    
-    pre {
-      background: #f5f5f5;
-      padding: 1rem;
-      border-radius: 5px;
-      overflow-x: auto;
-      margin-bottom: 1rem;
-    }
-    
-    pre code { background: none; padding: 0; }
-    
-    table {
-      border-collapse: collapse;
-      width: 100%;
-      margin-bottom: 1rem;
-    }
-    
-    th, td {
-      border: 1px solid #ddd;
-      padding: 0.5rem;
-      text-align: left;
-    }
-    
-    th { background: #f5f5f5; font-weight: 600; }
-    
-    img {
-      max-width: 100%;
-      height: auto;
-      display: block;
-      margin: 1rem auto;
-    }
-    
-    blockquote {
-      border-left: 4px solid #ddd;
-      padding-left: 1rem;
-      margin: 1rem 0;
-      color: #666;
-    }
-    
-    a { color: #0066cc; text-decoration: underline; }
-    
-    hr {
-      border: none;
-      border-top: 1px solid #ddd;
-      margin: 2rem 0;
-    }
-    
-    @media print {
-      body { padding: 0; max-width: none; }
-      h1, h2, h3, h4, h5, h6 { page-break-after: avoid; }
-      pre, blockquote, table, img { page-break-inside: avoid; }
-    }
-  </style>
-</head>
-<body>
-  ${contentHtml}
-</body>
-</html>`;
-        
-        await invoke('save_temp_html_and_open', { htmlContent: fullHtml });
-        alert('Document opened in browser. Use File → Print to save as PDF.');
-        return;
-        
-      } catch (error) {
-        console.error('Failed to open in browser:', error);
-        alert('Failed to open in browser. Using fallback print dialog.');
-        // Fall through to window.print() fallback
-      }
-    }
-    
-    // Reset zoom to 100% for printing to ensure consistent layout
-    const originalZoom = currentZoomLevel;
-    if (currentZoomLevel !== 100) {
-      updateZoomLevel(100);
+    // Files are read again by the backend; unsaved tabs send their markdown
+    const markdownContent = currentFilePath ? null : tabDocuments.get(activeTabId);
+    if (!currentFilePath && markdownContent === undefined) {
+      throw new Error('Could not retrieve original markdown content');
     }
-    
-    // Add print-specific styles for better PDF output
-    const printStyles = document.createElement('style');
-    printStyles.id = 'print-styles';
-    printStyles.textContent = `
-      @media print {
-        /* Hide UI elements */
-        .header { display: none !important; }
-        .controls { display: none !important; }
-        .zoom-controls { display: none !important; }
-        
-        /* Optimize content for print */
-        .app { height: auto !important; }
-        .main { display: block !important; overflow: visible !important; }
-        .viewer { overflow: visible !important; display: block !important; }
-        .content { 
-          max-width: none !important; 
-          margin: 0 !important; 
-          padding: 1rem !important;
-          transform: none !important;
-          width: 100% !important;
-        }
-        
-        /* Improve text rendering */
-        body { 
-          font-size: 12pt !important; 
-          line-height: 1.4 !important;
-          color: black !important;
-          background: white !important;
-        }
-        
-        /* Table improvements */
-        table { 
-          border-collapse: collapse !important;
-          page-break-inside: avoid;
-        }
-        
-        /* Code block improvements */
-        pre, code {
-          background: #f5f5f5 !important;
-          border: 1px solid #ddd !important;
-          page-break-inside: avoid;
-        }
-        
-        /* Image handling */
-        img {
-          max-width: 100% !important;
-          page-break-inside: avoid;
-        }
-        
-        /* Heading improvements */
-        h1, h2, h3, h4, h5, h6 {
-          page-break-after: avoid;
-          color: black !important;
-        }
-        
-        /* Mermaid diagrams */
-        .mermaid-diagram-container {
-          background: white !important;
-          border: 1px solid #ddd !important;
-          page-break-inside: avoid;
+
+    const defaultName = currentTitle.replace(/\.(md|markdown|mdown|mkd)$/i, '') + '.epub';
+    const filePath = await save({
+      title: 'Export as EPUB',
+      defaultPath: defaultName,
+      filters: [
+        {
+          name: 'EPUB Book',
+          extensions: ['epub']
         }
-      }
-    `;
-    
-    document.head.appendChild(printStyles);
-    
-    // Trigger print dialog
-    window.print();
-    
-    // Clean up: remove print styles and restore zoom
-    setTimeout(() => {
-      const printStylesElement = document.getElementById('print-styles');
-      if (printStylesElement) {
-        printStylesElement.remove();
-      }
-      
-      // Restore original zoom level
-      if (originalZoom !== 100) {
-        updateZoomLevel(originalZoom);
-      }
-    }, 1000);
-    
+      ]
+    });
+
+    if (!filePath) {
+      return;
+    }
+
+    const epub = await invoke('export_epub', {
+      filePath: currentFilePath,
+      markdownContent,
+      title: currentDocumentTitle,
+      sanitizeLevel: getSanitizeLevel(),
+      markdownOptions: getMarkdownOptions()
+    });
+    await writeFile(filePath, new Uint8Array(epub));
+
+    console.log('✅ EPUB exported successfully to:', filePath);
+    alert(`EPUB exported successfully to: ${filePath}`);
+
   } catch (error) {
-    console.error('Error printing to PDF:', error);
-    alert('Failed to print: ' + error.message);
+    console.error('❌ Error exporting EPUB:', error);
+    const message = error.message || error;
+
+    if (message === 'No content to export') {
+      alert('No content to export. Please load a markdown file first.');
+    } else {
+      alert('Failed to export EPUB: ' + (message || 'Unknown error'));
+    }
   }
 }
 
-async function exportDocx() {
+async function exportSlideDeck() {
   try {
-    console.log('🔄 Starting DOCX export using Pandoc WebAssembly...');
-    
-    // Input validation
     if (!currentMarkdownContent) {
       throw new Error('No content to export');
     }
 
-    // Check if DOCX generator is ready
-    if (!window.docxReady || !window.generateDocxFromMarkdown) {
-      throw new Error('DOCX generator not loaded');
+    // Files are read again by the backend; unsaved tabs send their markdown
+    const markdownContent = currentFilePath ? null : tabDocuments.get(activeTabId);
+    if (!currentFilePath && markdownContent === undefined) {
+      throw new Error('Could not retrieve original markdown content');
     }
 
-    console.log('📄 Getting original markdown content...');
-    
-    // Get the original markdown content (stored when file was loaded)
-    // We need the raw markdown, not the rendered HTML
-    const markdownText = await getOriginalMarkdownContent();
-    
-    if (!markdownText) {
-      throw new Error('Could not retrieve original markdown content');
+    const defaultName = currentTitle.replace(/\.(md|markdown|mdown|mkd)$/i, '') + '-slides.html';
+    const filePath = await save({
+      title: 'Export as Slides',
+      defaultPath: defaultName,
+      filters: [
+        {
+          name: 'HTML',
+          extensions: ['html']
+        }
+      ]
+    });
+
+    if (!filePath) {
+      return;
     }
 
-    console.log('🔄 Converting markdown to DOCX using docx.js...');
-    
-    // Generate filename without extension
-    const titleWithoutExt = currentTitle.replace(/\.(md|markdown|mdown|mkd)$/i, '');
-    
-    // Use the docx.js library to generate DOCX
-    const uint8Array = await window.generateDocxFromMarkdown(markdownText, titleWithoutExt);
+    const exported = await invoke('export_slide_deck', {
+      filePath: currentFilePath,
+      markdownContent,
+      title: currentDocumentTitle,
+      sanitizeLevel: getSanitizeLevel(),
+      markdownOptions: getMarkdownOptions(),
+      diagrams: renderedDiagrams()
+    });
+    await writeFile(filePath, new TextEncoder().encode(exported.html));
+
+    console.log('✅ Slide deck exported successfully to:', filePath);
+    const skippedNote = exported.skippedImages.length > 0
+      ? `\n\nThese images could not be embedded and will only show next to the original:\n${exported.skippedImages.join('\n')}`
+      : '';
+    alert(`Slide deck exported successfully to: ${filePath}${skippedNote}`);
 
-    console.log('📊 Final DOCX size:', uint8Array.length, 'bytes');
+  } catch (error) {
+    console.error('❌ Error exporting slide deck:', error);
+    const message = error.message || error;
 
-    if (uint8Array.length === 0) {
-      throw new Error('Generated DOCX is empty');
+    if (message === 'No content to export') {
+      alert('No content to export. Please load a markdown file first.');
+    } else {
+      alert('Failed to export slide deck: ' + (message || 'Unknown error'));
     }
+  }
+}
 
-    // Generate filename
-    const defaultName = currentTitle.replace(/\.(md|markdown|mdown|mkd)$/i, '') + '.docx';
-    console.log('📁 Default filename:', defaultName);
-    
-    // Show save dialog
+// Every markdown file of the open folder as one book, a chapter per file
+async function exportWorkspaceEpub() {
+  if (!workspaceTree) {
+    return;
+  }
+
+  try {
     const filePath = await save({
-      title: 'Export as DOCX',
-      defaultPath: defaultName,
+      title: 'Export Folder as EPUB',
+      defaultPath: workspaceTree.name + '.epub',
       filters: [
         {
-          name: 'Word Document',
-          extensions: ['docx']
+          name: 'EPUB Book',
+          extensions: ['epub']
         }
       ]
     });
 
     if (!filePath) {
-      console.log('❌ No file path selected');
       return;
     }
 
-    console.log('📁 Selected file path:', filePath);
-    
-    // Save file using Tauri v2 API
-    console.log('🔄 Writing DOCX file...');
-    await writeFile(filePath, uint8Array);
-    
-    console.log('✅ DOCX exported successfully to:', filePath);
-    alert(`DOCX exported successfully to: ${filePath}`);
-    
+    const epub = await invoke('export_folder_epub', {
+      dirPath: workspaceTree.path,
+      title: workspaceTree.name,
+      sanitizeLevel: getSanitizeLevel(),
+      markdownOptions: getMarkdownOptions()
+    });
+    await writeFile(filePath, new Uint8Array(epub));
+
+    console.log('✅ Folder EPUB exported successfully to:', filePath);
+    alert(`EPUB exported successfully to: ${filePath}`);
+
   } catch (error) {
-    console.error('❌ Error exporting DOCX:', error);
-    console.error('Error details:', error.message);
-    
-    if (error.message && error.message.includes('not loaded')) {
-      alert('Pandoc WebAssembly library not loaded. Please refresh the page and try again.');
-    } else if (error.message && error.message.includes('No content')) {
-      alert('No content to export. Please load a markdown file first.');
-    } else {
-      alert('Failed to export DOCX: ' + (error.message || 'Unknown error'));
+    console.error('❌ Error exporting folder EPUB:', error);
+    alert('Failed to export EPUB: ' + (error.message || error));
+  }
+}
+
+// Presentation mode: the document split into slides by the Rust side and shown over
+// the whole window. A presenter view in a second window follows along through events.
+let presentation = null;
+const SLIDE_WIDTH = 960;
+const SLIDE_HEIGHT = 540;
+
+async function startPresentation() {
+  if (!currentMarkdownContent || activeDiff || presentation) {
+    return;
+  }
+
+  try {
+    const slides = await invoke('render_slides', {
+      filePath: currentFilePath,
+      markdownContent: currentFilePath ? null : tabDocuments.get(activeTabId),
+      sanitizeLevel: getSanitizeLevel(),
+      markdownOptions: getMarkdownOptions()
+    });
+    if (slides.length === 0) {
+      alert('This document has nothing to present.');
+      return;
     }
+
+    const stage = document.querySelector('#presentation-stage');
+    stage.innerHTML = '';
+    for (const slide of slides) {
+      const section = document.createElement('section');
+      section.className = 'presentation-slide content';
+      section.innerHTML = sanitizeHTML(slide.html);
+      stage.appendChild(section);
+    }
+    await processMermaidDiagrams(stage);
+    setupImageErrorHandling(stage);
+
+    // Start on the slide the reader had scrolled to
+    const line = firstVisibleSourceLine();
+    const appWindow = window.__TAURI__.window.getCurrentWindow();
+    presentation = {
+      slides,
+      index: Math.max(0, slides.findIndex(slide => slide.endLine >= line)),
+      wasFullscreen: await appWindow.isFullscreen()
+    };
+    document.querySelector('#presentation').style.display = 'flex';
+    fitPresentation();
+    showSlide(presentation.index);
+    await appWindow.setFullscreen(true);
+  } catch (error) {
+    console.error('❌ Error starting presentation:', error);
+    alert('Failed to start presentation: ' + (error.message || error));
   }
 }
 
-// Helper function to get original markdown content
-async function getOriginalMarkdownContent() {
-  // If we have a current file path, re-read the file to get original markdown
-  if (currentFilePath) {
-    try {
-      const content = await invoke('read_file_content', { filePath: currentFilePath });
-      return content;
-    } catch (error) {
-      console.warn('Could not re-read file content:', error);
+async function stopPresentation() {
+  if (!presentation) {
+    return;
+  }
+
+  const { wasFullscreen } = presentation;
+  presentation = null;
+  document.querySelector('#presentation').style.display = 'none';
+  document.querySelector('#presentation-stage').innerHTML = '';
+  try {
+    await window.__TAURI__.event.emit('presentation-ended');
+    if (!wasFullscreen) {
+      await window.__TAURI__.window.getCurrentWindow().setFullscreen(false);
     }
+  } catch (error) {
+    console.error('Error leaving presentation:', error);
   }
-  
-  // For sample content, use the current markdown content
-  if (currentTitle === 'Sample' && currentMarkdownContent) {
-    return currentMarkdownContent;
+}
+
+function showSlide(index) {
+  if (!presentation) {
+    return;
+  }
+
+  presentation.index = Math.max(0, Math.min(presentation.slides.length - 1, index));
+  const sections = document.querySelectorAll('#presentation-stage .presentation-slide');
+  sections.forEach((section, i) => section.classList.toggle('active', i === presentation.index));
+  document.querySelector('#presentation-counter').textContent =
+    `${presentation.index + 1} / ${presentation.slides.length}`;
+  window.__TAURI__.event.emit('presentation-slide', { index: presentation.index })
+    .catch(error => console.error('Error updating presenter view:', error));
+}
+
+// Slides are laid out at a fixed size and scaled to the window, so they keep their layout
+function fitPresentation() {
+  const stage = document.querySelector('#presentation-stage');
+  const scale = Math.min(stage.clientWidth / SLIDE_WIDTH, stage.clientHeight / SLIDE_HEIGHT);
+  stage.style.setProperty('--slide-scale', scale);
+}
+
+// Line of the first block at the top of the viewer, so modes can keep the reader's place
+function firstVisibleSourceLine() {
+  const viewerTop = markdownViewer.getBoundingClientRect().top;
+  for (const element of markdownContent.querySelectorAll('[data-source-line]')) {
+    if (element.getBoundingClientRect().bottom > viewerTop) {
+      return parseInt(element.dataset.sourceLine, 10);
+    }
+  }
+  return 1;
+}
+
+function handlePresentationKeydown(event) {
+  switch (event.key) {
+    case 'ArrowRight': case 'ArrowDown': case 'PageDown': case ' ': case 'Enter':
+      showSlide(presentation.index + 1);
+      break;
+    case 'ArrowLeft': case 'ArrowUp': case 'PageUp': case 'Backspace':
+      showSlide(presentation.index - 1);
+      break;
+    case 'Home':
+      showSlide(0);
+      break;
+    case 'End':
+      showSlide(presentation.slides.length - 1);
+      break;
+    case 'Escape':
+      stopPresentation();
+      break;
+    case 's': case 'S':
+      openPresenterView();
+      break;
+    default:
+      return;
+  }
+  event.preventDefault();
+}
+
+// Clicks advance the slides; links are followed outside the presentation, or to the
+// slide holding their anchor
+async function handlePresentationClick(event) {
+  const link = event.target.closest('a[href]');
+  if (!link) {
+    showSlide(presentation.index + 1);
+    return;
+  }
+  event.preventDefault();
+
+  const { linkKind, linkPath, linkAnchor } = link.dataset;
+  try {
+    if (linkKind === 'anchor' && linkAnchor) {
+      const stage = document.querySelector('#presentation-stage');
+      const target = stage.querySelector(`#${CSS.escape(linkAnchor)}`);
+      const section = target && target.closest('.presentation-slide');
+      if (section) {
+        showSlide(Array.from(stage.children).indexOf(section));
+      }
+    } else if (linkKind === 'file') {
+      await window.__TAURI__.opener.revealItemInDir(linkPath);
+    } else if (linkKind === 'external') {
+      await window.__TAURI__.opener.openUrl(link.getAttribute('href'));
+    }
+  } catch (error) {
+    console.error('Error following link:', error);
   }
-  
-  throw new Error('Could not retrieve original markdown content');
+}
+
+async function openPresenterView() {
+  try {
+    await invoke('open_presenter_window');
+  } catch (error) {
+    console.error('Error opening presenter view:', error);
+    alert('Failed to open presenter view: ' + (error.message || error));
+  }
+}
+
+// The presenter view gets slides as they are shown here, diagrams already rendered
+function sendPresentationState() {
+  if (!presentation) {
+    return;
+  }
+
+  const sections = document.querySelectorAll('#presentation-stage .presentation-slide');
+  window.__TAURI__.event.emit('presentation-state', {
+    title: currentDocumentTitle,
+    slides: presentation.slides.map((slide, i) => ({ html: sections[i].innerHTML, notes: slide.notes })),
+    index: presentation.index,
+    codeThemeCss: document.querySelector('#code-theme-style')?.textContent || ''
+  }).catch(error => console.error('Error sending presentation to presenter view:', error));
 }
 
 window.addEventListener("DOMContentLoaded", async () => {
@@ -2174,7 +3205,8 @@ window.addEventListener("DOMContentLoaded", async () => {
   markdownContent = document.querySelector('#markdown-content');
   exportHtmlBtn = document.querySelector('#export-html-btn');
   exportDocxBtn = document.querySelector('#export-docx-btn');
-  printPdfBtn = document.querySelector('#print-pdf-btn');
+  exportPdfBtn = document.querySelector('#export-pdf-btn');
+  exportEpubBtn = document.querySelector('#export-epub-btn');
   exportButtonGroup = document.querySelector('#export-button-group');
   exportDropdownBtn = document.querySelector('#export-dropdown-btn');
   exportDropdownMenu = document.querySelector('#export-dropdown-menu');
@@ -2183,6 +3215,19 @@ window.addEventListener("DOMContentLoaded", async () => {
   openFileBtn.addEventListener('click', openFile);
   document.querySelector('#recent-files-btn').addEventListener('click', showRecentFiles);
   document.querySelector('#sample-btn').addEventListener('click', openSampleFile);
+  document.querySelector('#open-folder-btn').addEventListener('click', openFolder);
+  document.querySelector('#workspace-close-btn').addEventListener('click', closeWorkspace);
+  document.querySelector('#workspace-epub-btn').addEventListener('click', exportWorkspaceEpub);
+  
+  // Link navigation and history
+  markdownContent.addEventListener('click', handleContentLinkClick);
+  document.querySelector('#back-btn').addEventListener('click', goBack);
+  document.querySelector('#forward-btn').addEventListener('click', goForward);
+  document.addEventListener('mouseup', (event) => {
+    // Mouse side buttons
+    if (event.button === 3) goBack();
+    else if (event.button === 4) goForward();
+  });
   
   // Welcome screen button event listeners (secure alternative to inline onclick)
   document.querySelector('#welcome-open-btn').addEventListener('click', openFile);
@@ -2195,9 +3240,17 @@ window.addEventListener("DOMContentLoaded", async () => {
     hideExportDropdown();
     exportDocx();
   });
-  printPdfBtn.addEventListener('click', () => {
+  exportPdfBtn.addEventListener('click', () => {
+    hideExportDropdown();
+    exportPdf();
+  });
+  exportEpubBtn.addEventListener('click', () => {
+    hideExportDropdown();
+    exportEpub();
+  });
+  document.querySelector('#export-slides-btn').addEventListener('click', () => {
     hideExportDropdown();
-    printToPdf();
+    exportSlideDeck();
   });
   
   // Export dropdown functionality
@@ -2223,10 +3276,58 @@ window.addEventListener("DOMContentLoaded", async () => {
   document.querySelector('#zoom-out-btn').addEventListener('click', zoomOut);
   document.querySelector('#zoom-reset-btn').addEventListener('click', resetZoom);
   
+  // Outline sidebar toggle
+  document.querySelector('#toc-toggle-btn').addEventListener('click', toggleToc);
+  document.querySelector('#compare-btn').addEventListener('click', compareWithFile);
+  
+  // Presentation mode
+  document.querySelector('#present-btn').addEventListener('click', startPresentation);
+  document.querySelector('#presentation-stage').addEventListener('click', handlePresentationClick);
+  document.querySelector('#presentation-exit-btn').addEventListener('click', stopPresentation);
+  window.addEventListener('resize', () => {
+    if (presentation) fitPresentation();
+  });
+  document.querySelector('#history-btn').addEventListener('click', (event) => {
+    event.stopPropagation();
+    if (document.querySelector('#history-menu').classList.contains('show')) {
+      hideHistoryMenu();
+    } else {
+      showHistoryMenu();
+    }
+  });
+  document.addEventListener('click', (event) => {
+    if (!document.querySelector('#history-button-group').contains(event.target)) {
+      hideHistoryMenu();
+    }
+  });
+  document.querySelector('#code-theme-btn').addEventListener('click', (event) => {
+    event.stopPropagation();
+    if (document.querySelector('#code-theme-menu').classList.contains('show')) {
+      hideCodeThemeMenu();
+    } else {
+      showCodeThemeMenu();
+    }
+  });
+  document.addEventListener('click', (event) => {
+    if (!document.querySelector('#code-theme-button-group').contains(event.target)) {
+      hideCodeThemeMenu();
+    }
+  });
+  
   // Global keyboard shortcuts
   document.addEventListener('keydown', (event) => {
+    // Slides take every key while presenting
+    if (presentation) {
+      handlePresentationKeydown(event);
+      return;
+    }
+    // F5 to present the document
+    if (event.key === 'F5') {
+      event.preventDefault();
+      startPresentation();
+    }
     // Ctrl+F for find in page
-    if (event.ctrlKey && event.key === 'f') {
+    else if (event.ctrlKey && event.key === 'f') {
       event.preventDefault();
       showSearchDialog();
     }
@@ -2248,6 +3349,24 @@ window.addEventListener("DOMContentLoaded", async () => {
       event.preventDefault();
       resetZoom();
     }
+    // History navigation
+    else if (event.altKey && event.key === 'ArrowLeft') {
+      event.preventDefault();
+      goBack();
+    }
+    else if (event.altKey && event.key === 'ArrowRight') {
+      event.preventDefault();
+      goForward();
+    }
+    // Tabs
+    else if (event.ctrlKey && event.key === 'w' && activeTabId !== null) {
+      event.preventDefault();
+      closeTab(activeTabId);
+    }
+    else if (event.ctrlKey && event.key === 'Tab') {
+      event.preventDefault();
+      cycleTabs(event.shiftKey ? -1 : 1);
+    }
   });
   
   // Add manual drag and drop debugging
@@ -2271,14 +3390,35 @@ window.addEventListener("DOMContentLoaded", async () => {
   await listen('file-changed', (event) => {
     handleFileChange(event.payload);
   });
+  await listen('file-deleted', (event) => {
+    handleFileDeleted(event.payload);
+  });
+  await listen('file-moved', (event) => {
+    handleFileMoved(event.payload);
+  });
+  await applyWatchDebounce();
+  await applyHighlightThemes().catch(error => console.error('Error applying code themes:', error));
+  
+  // The presenter view asks for the running presentation once loaded, then drives it
+  await listen('presenter-ready', () => {
+    sendPresentationState();
+  });
+  await listen('presentation-navigate', (event) => {
+    showSlide(event.payload.index);
+  });
+  
+  // Listen for workspace tree updates from the folder watcher
+  await listen('workspace-changed', (event) => {
+    renderWorkspaceTree(event.payload);
+  });
   
   // Listen for file opened via OS "Open With" events
   await listen('file-opened-via-os', async (event) => {
-    const filePath = event.payload;
-    if (filePath) {
+    const openedFile = event.payload;
+    if (openedFile) {
       // Small delay to ensure UI is ready
       setTimeout(async () => {
-        await loadMarkdownFile(filePath);
+        await openLaunchedFile(openedFile);
       }, 100);
     }
   });
@@ -0,0 +1,118 @@
+// Presenter view: the current and next slide, speaker notes and a timer for the
+// presentation running in the main window. Slides arrive as events from the main window,
+// and navigating here asks the main window to change slide.
+const { emit, listen } = window.__TAURI__.event;
+
+const SLIDE_WIDTH = 960;
+const SLIDE_HEIGHT = 540;
+
+let slides = [];
+let currentIndex = 0;
+let timerStart = null;
+
+function sanitizeHTML(html) {
+  if (typeof DOMPurify === 'undefined') {
+    const div = document.createElement('div');
+    div.textContent = html;
+    return div.innerHTML;
+  }
+  return DOMPurify.sanitize(html);
+}
+
+// Show a slide in one of the frames, or leave the frame empty past the last slide
+function showInFrame(frame, slide) {
+  frame.querySelector('.presentation-slide')?.remove();
+  if (!slide) {
+    return;
+  }
+  const section = document.createElement('section');
+  section.className = 'presentation-slide content';
+  section.innerHTML = sanitizeHTML(slide.html);
+  frame.appendChild(section);
+  fitFrame(frame);
+}
+
+function fitFrame(frame) {
+  const scale = Math.min(frame.clientWidth / SLIDE_WIDTH, frame.clientHeight / SLIDE_HEIGHT);
+  frame.style.setProperty('--slide-scale', scale);
+}
+
+function render() {
+  showInFrame(document.querySelector('#presenter-current'), slides[currentIndex]);
+  showInFrame(document.querySelector('#presenter-next'), slides[currentIndex + 1]);
+  const notes = slides[currentIndex]?.notes;
+  document.querySelector('#presenter-notes').innerHTML = notes ? sanitizeHTML(notes) : '';
+  document.querySelector('#presenter-counter').textContent =
+    slides.length > 0 ? `Slide ${currentIndex + 1} of ${slides.length}` : '';
+}
+
+function navigate(index) {
+  if (slides.length === 0) {
+    return;
+  }
+  const target = Math.max(0, Math.min(slides.length - 1, index));
+  emit('presentation-navigate', { index: target })
+    .catch(error => console.error('Error changing slide:', error));
+}
+
+function updateTimer() {
+  const elapsed = timerStart === null ? 0 : Math.floor((Date.now() - timerStart) / 1000);
+  const minutes = String(Math.floor(elapsed / 60)).padStart(2, '0');
+  const seconds = String(elapsed % 60).padStart(2, '0');
+  document.querySelector('#presenter-timer').textContent = `${minutes}:${seconds}`;
+}
+
+window.addEventListener("DOMContentLoaded", async () => {
+  document.querySelector('#presenter-prev-btn').addEventListener('click', () => navigate(currentIndex - 1));
+  document.querySelector('#presenter-next-btn').addEventListener('click', () => navigate(currentIndex + 1));
+  document.querySelector('#presenter-reset-btn').addEventListener('click', () => {
+    timerStart = Date.now();
+    updateTimer();
+  });
+
+  document.addEventListener('keydown', (event) => {
+    switch (event.key) {
+      case 'ArrowRight': case 'ArrowDown': case 'PageDown': case ' ': case 'Enter':
+        navigate(currentIndex + 1);
+        break;
+      case 'ArrowLeft': case 'ArrowUp': case 'PageUp': case 'Backspace':
+        navigate(currentIndex - 1);
+        break;
+      case 'Home':
+        navigate(0);
+        break;
+      case 'End':
+        navigate(slides.length - 1);
+        break;
+      default:
+        return;
+    }
+    event.preventDefault();
+  });
+
+  window.addEventListener('resize', () => {
+    document.querySelectorAll('.presenter-frame').forEach(fitFrame);
+  });
+  setInterval(updateTimer, 1000);
+
+  await listen('presentation-state', (event) => {
+    slides = event.payload.slides;
+    currentIndex = event.payload.index;
+    document.title = `Presenter View - ${event.payload.title}`;
+    document.querySelector('#code-theme-style').textContent = event.payload.codeThemeCss;
+    if (timerStart === null) {
+      timerStart = Date.now();
+    }
+    render();
+  });
+  await listen('presentation-slide', (event) => {
+    currentIndex = event.payload.index;
+    render();
+  });
+  await listen('presentation-ended', async () => {
+    await window.__TAURI__.webviewWindow.getCurrentWebviewWindow().close();
+  });
+
+  // Ask the main window for the slides now that the listeners are in place
+  await emit('presenter-ready');
+});